@@ -0,0 +1,215 @@
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::cpu::Cpu;
+use crate::ram::Ram;
+
+// something a condition can look at
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operand {
+    Mem(u16),
+    V(usize),
+    I,
+    Pc,
+    Dt,
+    St,
+    Frame
+}
+
+impl Operand {
+    // mem[3E0], v3 or v[3], i, pc, dt, st or frame. addresses and
+    // register numbers are hex like everywhere else
+    fn parse(text: &str) -> Option<Operand> {
+        if let Some(addr) = text.strip_prefix("mem[").and_then(|rest| rest.strip_suffix(']')) {
+            return u16::from_str_radix(addr, 16).ok().map(Operand::Mem);
+        }
+        if let Some(reg) = text.strip_prefix('v') {
+            let reg = reg.strip_prefix('[').and_then(|reg| reg.strip_suffix(']')).unwrap_or(reg);
+            return usize::from_str_radix(reg, 16).ok().filter(|&reg| reg < 16).map(Operand::V);
+        }
+        match text {
+            "i" => Some(Operand::I),
+            "pc" => Some(Operand::Pc),
+            "dt" => Some(Operand::Dt),
+            "st" => Some(Operand::St),
+            "frame" => Some(Operand::Frame),
+            _ => None
+        }
+    }
+
+    fn read(&self, cpu: &Cpu, ram: &Ram) -> u64 {
+        match *self {
+            Operand::Mem(addr) => ram.memory().get(addr as usize).map_or(0, |&byte| byte as u64),
+            Operand::V(reg) => cpu.reg_gpr()[reg] as u64,
+            Operand::I => cpu.reg_i() as u64,
+            Operand::Pc => cpu.reg_pc() as u64,
+            Operand::Dt => cpu.reg_dt() as u64,
+            Operand::St => cpu.reg_st() as u64,
+            Operand::Frame => cpu.frames()
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+impl Compare {
+    fn parse(text: &str) -> Option<Compare> {
+        match text {
+            "==" => Some(Compare::Equal),
+            "!=" => Some(Compare::NotEqual),
+            "<" => Some(Compare::Less),
+            "<=" => Some(Compare::LessOrEqual),
+            ">" => Some(Compare::Greater),
+            ">=" => Some(Compare::GreaterOrEqual),
+            _ => None
+        }
+    }
+
+    fn holds(&self, a: u64, b: u64) -> bool {
+        match self {
+            Compare::Equal => a == b,
+            Compare::NotEqual => a != b,
+            Compare::Less => a < b,
+            Compare::LessOrEqual => a <= b,
+            Compare::Greater => a > b,
+            Compare::GreaterOrEqual => a >= b
+        }
+    }
+}
+
+// one comparison like "mem[3E0] >= 5"
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Condition {
+    operand: Operand,
+    compare: Compare,
+    value: u64
+}
+
+// conditions joined by &&, e.g. "v3 == 0 && frame < 600". values are
+// decimal, or hex with a 0x prefix
+fn parse_conditions(text: &str) -> Result<Vec<Condition>, String> {
+    text.split("&&").map(|part| {
+        let words: Vec<&str> = part.split_whitespace().collect();
+        let (operand, compare, value) = match words[..] {
+            [operand, compare, value] => (operand, compare, value),
+            _ => return Err(format!("Could not parse condition {}: needs OPERAND COMPARE VALUE", part.trim()))
+        };
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse()
+        };
+        Ok(Condition {
+            operand: Operand::parse(operand).ok_or_else(|| format!("Could not parse condition {}: unknown operand {}", part.trim(), operand))?,
+            compare: Compare::parse(compare).ok_or_else(|| format!("Could not parse condition {}: unknown comparison {}", part.trim(), compare))?,
+            value: value.map_err(|e| format!("Could not parse condition {}: {}", part.trim(), e))?
+        })
+    }).collect()
+}
+
+// an entry of the trigger file
+#[derive(Deserialize)]
+struct TriggerFile {
+    name: String,
+    when: String
+}
+
+struct Trigger {
+    name: String,
+    conditions: Vec<Condition>,
+    unlocked: bool
+}
+
+// a rom's achievements, each unlocking the first frame all its conditions
+// hold. the trigger file is a json list of {"name": ..., "when": ...}
+pub struct Achievements {
+    triggers: Vec<Trigger>
+}
+
+impl Achievements {
+    // where the trigger file for the rom with this sha1 is kept
+    pub fn path(sha1: &str) -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("achievements").join(format!("{}.json", sha1)))
+    }
+
+    pub fn load(path: &Path) -> Result<Achievements, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Achievements::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Achievements, String> {
+        let entries: Vec<TriggerFile> = serde_json::from_str(json)
+            .map_err(|e| format!("Could not parse achievements: {}", e))?;
+        let triggers = entries.into_iter().map(|entry| {
+            Ok(Trigger {
+                conditions: parse_conditions(&entry.when)?,
+                name: entry.name,
+                unlocked: false
+            })
+        }).collect::<Result<Vec<Trigger>, String>>()?;
+        Ok(Achievements {
+            triggers
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    // call once per frame, returns the names of the ones unlocked just now
+    pub fn update(&mut self, cpu: &Cpu, ram: &Ram) -> Vec<String> {
+        let mut unlocked = Vec::new();
+        for trigger in self.triggers.iter_mut().filter(|trigger| !trigger.unlocked) {
+            let met = trigger.conditions.iter().all(|condition| {
+                condition.compare.holds(condition.operand.read(cpu, ram), condition.value)
+            });
+            if met {
+                trigger.unlocked = true;
+                unlocked.push(trigger.name.clone());
+            }
+        }
+        unlocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Achievements;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn achievements_unlock_once_when_their_conditions_hold() {
+        let json = r#"[
+            {"name": "five", "when": "v3 == 5 && mem[200] == 0x63"},
+            {"name": "never", "when": "v[3] > 5"}
+        ]"#;
+        let mut achievements = Achievements::from_json(json).unwrap();
+        // LD V3, 05
+        let mut chip8 = Chip8::with_rom(&[0x63, 0x05]).unwrap();
+        assert!(achievements.update(&chip8.cpu, &chip8.ram).is_empty());
+
+        chip8.step().unwrap();
+        assert_eq!(achievements.update(&chip8.cpu, &chip8.ram), vec!["five"]);
+        assert!(achievements.update(&chip8.cpu, &chip8.ram).is_empty());
+
+        assert!(Achievements::from_json(r#"[{"name": "bad", "when": "v3 = 5"}]"#).is_err());
+    }
+}
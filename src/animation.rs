@@ -0,0 +1,150 @@
+use crate::present::Palette;
+use crate::spec::TIMER_HZ;
+
+// how far a flash takes the unlit color towards the lit one, out of 256
+const FLASH_AMOUNT: u32 = 64;
+// seconds for the hue to go all the way round when none are given
+const DEFAULT_HUE_PERIOD: u32 = 60;
+
+// where the machine is when a frame is shown, for animations to follow
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Moment {
+    pub frame: u64,
+    // the buzzer is sounding
+    pub sound: bool
+}
+
+// changes the palette a frame is drawn with. the presentation layer asks
+// once per frame, with the palette the user picked as base, so animations
+// never build on their own output
+pub trait PaletteAnimation {
+    fn animate(&mut self, base: &Palette, moment: Moment) -> Palette;
+}
+
+// the built in animations, for --palette-animation
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct PaletteEffects {
+    // seconds for a full turn of the color wheel
+    pub hue_period: Option<u32>,
+    // the background lights up while the buzzer sounds
+    pub flash: bool
+}
+
+impl PaletteEffects {
+    // a list of hue-shift, optionally hue-shift:SECONDS, and flash, or none
+    pub fn parse(text: &str) -> Option<PaletteEffects> {
+        let mut effects = PaletteEffects::default();
+        if text == "none" {
+            return Some(effects);
+        }
+        for name in text.split(',') {
+            match name.trim().split_once(':') {
+                Some(("hue-shift", seconds)) => {
+                    effects.hue_period = Some(seconds.parse().ok().filter(|&seconds| seconds > 0)?);
+                },
+                None if name.trim() == "hue-shift" => effects.hue_period = Some(DEFAULT_HUE_PERIOD),
+                None if name.trim() == "flash" => effects.flash = true,
+                _ => return None
+            }
+        }
+        Some(effects)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == PaletteEffects::default()
+    }
+}
+
+impl PaletteAnimation for PaletteEffects {
+    fn animate(&mut self, base: &Palette, moment: Moment) -> Palette {
+        let mut palette = *base;
+        if let Some(period) = self.hue_period {
+            // machine time rather than the wall clock, so turbo and pauses
+            // carry the colors along with the game
+            let frames = period as u64 * TIMER_HZ as u64;
+            let degrees = (moment.frame % frames) as f32 * 360.0 / frames as f32;
+            for color in [&mut palette.off, &mut palette.on, &mut palette.border, &mut palette.plane2, &mut palette.both] {
+                *color = rotate_hue(*color, degrees);
+            }
+        }
+        if self.flash && moment.sound {
+            palette.off = mix(palette.off, palette.on, FLASH_AMOUNT);
+            palette.border = mix(palette.border, palette.on, FLASH_AMOUNT);
+        }
+        palette
+    }
+}
+
+// the same color with its hue turned round by degrees, keeping saturation
+// and value
+fn rotate_hue(color: u32, degrees: f32) -> u32 {
+    let channels = [(color >> 16) & 0xFF, (color >> 8) & 0xFF, color & 0xFF];
+    let max = *channels.iter().max().unwrap() as f32;
+    let min = *channels.iter().min().unwrap() as f32;
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return color;
+    }
+    let [red, green, blue] = channels.map(|channel| channel as f32);
+    let hue = if max == red {
+        (green - blue) / chroma
+    } else if max == green {
+        (blue - red) / chroma + 2.0
+    } else {
+        (red - green) / chroma + 4.0
+    };
+    let hue = (hue * 60.0 + degrees).rem_euclid(360.0) / 60.0;
+
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (red, green, blue) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x)
+    };
+    let channel = |value: f32| ((value + min).round() as u32).min(0xFF);
+    channel(red) << 16 | channel(green) << 8 | channel(blue)
+}
+
+// amount out of 256 of the way from one color to the other
+fn mix(from: u32, to: u32, amount: u32) -> u32 {
+    let mut color = 0;
+    for shift in [0, 8, 16] {
+        let a = (from >> shift) & 0xFF;
+        let b = (to >> shift) & 0xFF;
+        color |= ((a * (256 - amount) + b * amount) / 256) << shift;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Moment,
+        PaletteAnimation,
+        PaletteEffects
+    };
+    use crate::present::Palette;
+
+    #[test]
+    fn hues_turn_with_machine_time_and_sound_flashes() {
+        assert_eq!(PaletteEffects::parse("hue-shift:0"), None);
+        let mut effects = PaletteEffects::parse("hue-shift:3, flash").unwrap();
+        assert_eq!(effects.hue_period, Some(3));
+
+        let base = Palette {
+            on: 0xFF0000,
+            ..Palette::default()
+        };
+        let start = effects.animate(&base, Moment { frame: 0, sound: false });
+        assert_eq!(start, base);
+        // a third of the way round red is green, and black stays black
+        // until the buzzer lights it up
+        let third = effects.animate(&base, Moment { frame: 60, sound: false });
+        assert_eq!((third.on, third.off), (0x00FF00, 0x000000));
+        let beep = effects.animate(&base, Moment { frame: 180, sound: true });
+        assert_eq!((beep.on, beep.off), (0xFF0000, 0x3F0000));
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::VecDeque;
+
+#[cfg(feature = "desktop")]
+use rodio::{
+    Device,
+    Sink
+};
+#[cfg(feature = "desktop")]
+use rodio::source::SineWave;
+
+use crate::spec::TIMER_HZ;
+
+pub const DEFAULT_FREQUENCY: u32 = 440;
+
+// loudness of the captured square wave, out of 1.0
+const CAPTURE_AMPLITUDE: f32 = 0.25;
+
+// where the buzzer ends up. start_beep/stop_beep are called on every timer
+// tick, so implementations have to be fine with repeated calls
+pub trait AudioSink {
+    fn start_beep(&mut self);
+    fn stop_beep(&mut self);
+    fn set_frequency(&mut self, hz: u32);
+}
+
+// sine wave beep on the default output device
+#[cfg(feature = "desktop")]
+pub struct RodioSink {
+    device: Device,
+    sink: Sink,
+    playing: bool
+}
+
+#[cfg(feature = "desktop")]
+impl RodioSink {
+    pub fn new() -> Option<RodioSink> {
+        let device = rodio::default_output_device()?;
+        let sink = RodioSink::tone(&device, DEFAULT_FREQUENCY);
+        Some(RodioSink {
+            device,
+            sink,
+            playing: false
+        })
+    }
+
+    fn tone(device: &Device, hz: u32) -> Sink {
+        let sink = Sink::new(device);
+        sink.append(SineWave::new(hz));
+        sink.pause();
+        sink
+    }
+}
+
+#[cfg(feature = "desktop")]
+impl AudioSink for RodioSink {
+    fn start_beep(&mut self) {
+        if !self.playing {
+            self.sink.play();
+            self.playing = true;
+        }
+    }
+
+    fn stop_beep(&mut self) {
+        if self.playing {
+            self.sink.pause();
+            self.playing = false;
+        }
+    }
+
+    fn set_frequency(&mut self, hz: u32) {
+        // a sine source can't be retuned, so swap in a new one
+        self.sink.stop();
+        self.sink = RodioSink::tone(&self.device, hz);
+        if self.playing {
+            self.sink.play();
+        }
+    }
+}
+
+// for runs nobody listens to
+pub struct SilentSink;
+
+impl AudioSink for SilentSink {
+    fn start_beep(&mut self) {}
+    fn stop_beep(&mut self) {}
+    fn set_frequency(&mut self, _hz: u32) {}
+}
+
+// generates the buzzer as mono f32 pcm for embedders that own the output
+// device (libretro, WebAudio) instead of playing it. every start_beep or
+// stop_beep adds one frame of samples, so it expects exactly one call per
+// timer tick, which is what tick_timers does
+pub struct CaptureSink {
+    sample_rate: u32,
+    frequency: u32,
+    // position in the square wave's period, kept across frames so the wave
+    // doesn't click at frame boundaries
+    phase: f32,
+    samples: VecDeque<f32>,
+    capacity: usize
+}
+
+impl CaptureSink {
+    // keeps at most capacity samples, dropping the oldest when the
+    // embedder falls behind
+    pub fn new(sample_rate: u32, capacity: usize) -> CaptureSink {
+        CaptureSink {
+            sample_rate,
+            frequency: DEFAULT_FREQUENCY,
+            phase: 0.0,
+            samples: VecDeque::with_capacity(capacity),
+            capacity
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // samples waiting to be played
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // moves up to out.len() samples into out, oldest first, and returns how
+    // many were written
+    pub fn drain(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.samples.len());
+        for (slot, sample) in out.iter_mut().zip(self.samples.drain(..count)) {
+            *slot = sample;
+        }
+        count
+    }
+
+    fn push_frame(&mut self, beeping: bool) {
+        let frame_samples = self.sample_rate / TIMER_HZ;
+        let step = self.frequency as f32 / self.sample_rate as f32;
+        for _ in 0..frame_samples {
+            let sample = if !beeping {
+                0.0
+            } else if self.phase < 0.5 {
+                CAPTURE_AMPLITUDE
+            } else {
+                -CAPTURE_AMPLITUDE
+            };
+            self.phase = (self.phase + step) % 1.0;
+
+            if self.samples.len() == self.capacity {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+        }
+    }
+}
+
+impl AudioSink for CaptureSink {
+    fn start_beep(&mut self) {
+        self.push_frame(true);
+    }
+
+    fn stop_beep(&mut self) {
+        self.push_frame(false);
+    }
+
+    fn set_frequency(&mut self, hz: u32) {
+        self.frequency = hz;
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::AudioSink;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum AudioEvent {
+        Start,
+        Stop,
+        Frequency(u32)
+    }
+
+    // records every call together with the tick it happened on. tests move
+    // the clock forward themselves so timings are exact
+    pub struct MockAudioSink {
+        pub now: u64,
+        pub events: Vec<(u64, AudioEvent)>
+    }
+
+    impl Default for MockAudioSink {
+        fn default() -> MockAudioSink {
+            MockAudioSink::new()
+        }
+    }
+
+    impl MockAudioSink {
+        pub fn new() -> MockAudioSink {
+            MockAudioSink {
+                now: 0,
+                events: Vec::new()
+            }
+        }
+
+        // collapses the repeated start/stop calls into (start, end) ticks,
+        // a beep still sounding has no end
+        pub fn beeps(&self) -> Vec<(u64, Option<u64>)> {
+            let mut beeps = Vec::new();
+            let mut started = None;
+            for &(time, event) in &self.events {
+                match event {
+                    AudioEvent::Start if started.is_none() => started = Some(time),
+                    AudioEvent::Stop => {
+                        if let Some(start) = started.take() {
+                            beeps.push((start, Some(time)));
+                        }
+                    },
+                    _ => ()
+                }
+            }
+            if let Some(start) = started {
+                beeps.push((start, None));
+            }
+            beeps
+        }
+
+        pub fn frequencies(&self) -> Vec<u32> {
+            self.events.iter()
+                .filter_map(|&(_, event)| match event {
+                    AudioEvent::Frequency(hz) => Some(hz),
+                    _ => None
+                })
+                .collect()
+        }
+    }
+
+    impl AudioSink for MockAudioSink {
+        fn start_beep(&mut self) {
+            self.events.push((self.now, AudioEvent::Start));
+        }
+
+        fn stop_beep(&mut self) {
+            self.events.push((self.now, AudioEvent::Stop));
+        }
+
+        fn set_frequency(&mut self, hz: u32) {
+            self.events.push((self.now, AudioEvent::Frequency(hz)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AudioSink,
+        CaptureSink
+    };
+    use super::mock::MockAudioSink;
+    use crate::cpu::Cpu;
+    use crate::ram::Ram;
+    use crate::PROGRAM_START_ADDR;
+
+    fn run_ticks(cpu: &mut Cpu, audio: &mut MockAudioSink, ticks: u64) {
+        for _ in 0..ticks {
+            cpu.tick_timers(audio);
+            audio.now += 1;
+        }
+    }
+
+    fn load_program(ram: &mut Ram, program: &[u16]) {
+        for (i, opcode) in program.iter().enumerate() {
+            let addr = PROGRAM_START_ADDR + 2 * i as u16;
+            ram.write_byte(addr, (opcode >> 8) as u8);
+            ram.write_byte(addr + 1, *opcode as u8);
+        }
+    }
+
+    #[test]
+    fn silent_when_st_is_zero() {
+        let mut cpu = Cpu::new();
+        let mut audio = MockAudioSink::new();
+        run_ticks(&mut cpu, &mut audio, 10);
+        assert!(audio.beeps().is_empty());
+    }
+
+    #[test]
+    fn beep_lasts_st_ticks() {
+        for &st in &[1u8, 2, 5, 60, 255] {
+            let mut cpu = Cpu::new();
+            let mut audio = MockAudioSink::new();
+            cpu.set_reg_st(st);
+            run_ticks(&mut cpu, &mut audio, st as u64 + 10);
+            assert_eq!(audio.beeps(), vec![(0, Some(st as u64))], "ST = {}", st);
+            assert_eq!(cpu.reg_st(), 0);
+        }
+    }
+
+    #[test]
+    fn fx18_starts_a_beep() {
+        let mut cpu = Cpu::new();
+        let mut ram = Ram::new();
+        let mut audio = MockAudioSink::new();
+        // V3 = 5, ST = V3
+        load_program(&mut ram, &[0x6305, 0xF318]);
+        cpu.run_instruction(&mut ram).unwrap();
+        cpu.run_instruction(&mut ram).unwrap();
+        assert_eq!(cpu.reg_st(), 5);
+
+        run_ticks(&mut cpu, &mut audio, 8);
+        assert_eq!(audio.beeps(), vec![(0, Some(5))]);
+    }
+
+    #[test]
+    fn reloading_st_extends_the_beep() {
+        let mut cpu = Cpu::new();
+        let mut audio = MockAudioSink::new();
+        cpu.set_reg_st(3);
+        run_ticks(&mut cpu, &mut audio, 2);
+        cpu.set_reg_st(4);
+        run_ticks(&mut cpu, &mut audio, 10);
+        assert_eq!(audio.beeps(), vec![(0, Some(6))]);
+    }
+
+    #[test]
+    fn separate_beeps_stay_separate() {
+        let mut cpu = Cpu::new();
+        let mut audio = MockAudioSink::new();
+        cpu.set_reg_st(2);
+        run_ticks(&mut cpu, &mut audio, 5);
+        cpu.set_reg_st(1);
+        run_ticks(&mut cpu, &mut audio, 5);
+        assert_eq!(audio.beeps(), vec![(0, Some(2)), (5, Some(6))]);
+    }
+
+    #[test]
+    fn delay_timer_does_not_beep() {
+        let mut cpu = Cpu::new();
+        let mut audio = MockAudioSink::new();
+        cpu.set_reg_dt(10);
+        run_ticks(&mut cpu, &mut audio, 4);
+        assert_eq!(cpu.reg_dt(), 6);
+        assert!(audio.beeps().is_empty());
+    }
+
+    #[test]
+    fn frequency_changes_are_recorded() {
+        let mut audio = MockAudioSink::new();
+        audio.set_frequency(440);
+        audio.now = 3;
+        audio.set_frequency(880);
+        assert_eq!(audio.frequencies(), vec![440, 880]);
+    }
+
+    #[test]
+    fn capture_sink_renders_a_frame_per_tick() {
+        let mut cpu = Cpu::new();
+        let mut audio = CaptureSink::new(48000, 48000);
+        audio.set_frequency(1000);
+        cpu.set_reg_st(2);
+        for _ in 0..3 {
+            cpu.tick_timers(&mut audio);
+        }
+        assert_eq!(audio.len(), 3 * 800);
+
+        let mut out = vec![0.0; 3 * 800];
+        assert_eq!(audio.drain(&mut out), 3 * 800);
+        assert!(audio.is_empty());
+        // two frames of a 1kHz square wave, 24 samples high then 24 low,
+        // then a silent frame
+        assert!(out[..24].iter().all(|&sample| sample > 0.0));
+        assert!(out[24..48].iter().all(|&sample| sample < 0.0));
+        assert!(out[1600..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn capture_sink_drops_the_oldest_samples_when_full() {
+        let mut audio = CaptureSink::new(6000, 150);
+        audio.start_beep();
+        audio.stop_beep();
+        assert_eq!(audio.len(), 150);
+
+        let mut out = vec![1.0; 200];
+        assert_eq!(audio.drain(&mut out), 150);
+        assert!(out[50..150].iter().all(|&sample| sample == 0.0));
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::sync::mpsc::{
+    self,
+    Sender
+};
+use std::thread::{
+    self,
+    JoinHandle
+};
+use std::time::{
+    Duration,
+    Instant
+};
+
+use crate::state::State;
+
+// one save for the writer thread, and how many of the rom's to keep after it
+struct PendingSave {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    rom_path: String,
+    keep: usize
+}
+
+// saves the machine every interval next to the rom as ROM.autosave-N.state,
+// N counting up, and keeps the newest `keep` of them. the files are written
+// on a thread of their own so a slow disk doesn't drop frames, each to a
+// temporary file first so a crash mid-write can't leave a torn one behind
+pub struct Autosave {
+    interval: Duration,
+    keep: usize,
+    rom_path: String,
+    next: u64,
+    last: Instant,
+    writer: Option<(Sender<PendingSave>, JoinHandle<()>)>
+}
+
+impl Autosave {
+    pub fn new(rom_path: &str, interval: Duration, keep: usize) -> Autosave {
+        let (sender, receiver) = mpsc::channel::<PendingSave>();
+        let handle = thread::spawn(move || {
+            for save in receiver {
+                let temp = save.path.with_extension("state.tmp");
+                match fs::write(&temp, &save.bytes).and_then(|()| fs::rename(&temp, &save.path)) {
+                    Ok(()) => Autosave::prune(&save.rom_path, save.keep),
+                    Err(e) => println!("Could not write {}: {}", save.path.display(), e)
+                }
+            }
+        });
+
+        let mut autosave = Autosave {
+            interval,
+            keep: keep.max(1),
+            rom_path: String::new(),
+            next: 0,
+            last: Instant::now(),
+            writer: Some((sender, handle))
+        };
+        autosave.set_rom(rom_path);
+        autosave
+    }
+
+    // carries on numbering after the autosaves the rom already has
+    pub fn set_rom(&mut self, rom_path: &str) {
+        self.rom_path = rom_path.to_string();
+        self.next = Autosave::numbers(rom_path).last().map_or(0, |&n| n + 1);
+        self.last = Instant::now();
+    }
+
+    pub fn due(&self, now: Instant) -> bool {
+        now.duration_since(self.last) >= self.interval
+    }
+
+    pub fn save(&mut self, state: &State, now: Instant) {
+        let path = Autosave::path(&self.rom_path, self.next);
+        if let Some((sender, _)) = &self.writer {
+            // the thread only ends once the sender is dropped
+            let _ = sender.send(PendingSave {
+                path,
+                bytes: state.to_bytes(),
+                rom_path: self.rom_path.clone(),
+                keep: self.keep
+            });
+        }
+        self.next += 1;
+        self.last = now;
+    }
+
+    // waits for the saves still queued to be written
+    pub fn finish(mut self) {
+        if let Some((sender, handle)) = self.writer.take() {
+            drop(sender);
+            let _ = handle.join();
+        }
+    }
+
+    // the newest autosave of the rom, if it has any
+    pub fn latest(rom_path: &str) -> Option<PathBuf> {
+        Autosave::numbers(rom_path).last().map(|&n| Autosave::path(rom_path, n))
+    }
+
+    // removes all but the newest keep of the rom's autosaves, older ones
+    // included in case a run before kept more
+    fn prune(rom_path: &str, keep: usize) {
+        let numbers = Autosave::numbers(rom_path);
+        let expired = numbers.len().saturating_sub(keep);
+        for &n in &numbers[..expired] {
+            // already gone is fine
+            let _ = fs::remove_file(Autosave::path(rom_path, n));
+        }
+    }
+
+    fn path(rom_path: &str, n: u64) -> PathBuf {
+        PathBuf::from(format!("{}.autosave-{}.state", rom_path, n))
+    }
+
+    // the numbers of the rom's autosaves, oldest first
+    fn numbers(rom_path: &str) -> Vec<u64> {
+        let path = Path::new(rom_path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+        let prefix = format!("{}.autosave-", path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default());
+        let mut numbers: Vec<u64> = fs::read_dir(dir).map(|entries| {
+            entries.filter_map(|entry| {
+                let name = entry.ok()?.file_name().to_string_lossy().into_owned();
+                name.strip_prefix(&prefix)?.strip_suffix(".state")?.parse().ok()
+            }).collect()
+        }).unwrap_or_default();
+        numbers.sort_unstable();
+        numbers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{
+        Duration,
+        Instant
+    };
+
+    use super::Autosave;
+    use crate::cpu::Cpu;
+    use crate::ram::Ram;
+
+    #[test]
+    fn autosaves_roll_over_keeping_the_newest() {
+        let dir = std::env::temp_dir().join(format!("rust8-autosave-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8").to_string_lossy().into_owned();
+        let state = Cpu::new().save_state(&Ram::new());
+
+        let mut autosave = Autosave::new(&rom, Duration::from_secs(30), 2);
+        let start = Instant::now();
+        assert!(!autosave.due(start));
+        assert!(autosave.due(start + Duration::from_secs(31)));
+        for _ in 0..3 {
+            autosave.save(&state, Instant::now());
+        }
+        autosave.finish();
+        assert_eq!(Autosave::numbers(&rom), vec![1, 2]);
+        assert_eq!(Autosave::latest(&rom), Some(Autosave::path(&rom, 2)));
+
+        // a new session carries on from the newest
+        let mut autosave = Autosave::new(&rom, Duration::from_secs(30), 2);
+        autosave.save(&state, Instant::now());
+        autosave.finish();
+        assert_eq!(Autosave::numbers(&rom), vec![2, 3]);
+
+        // keeping fewer than last time drops the extra older ones too
+        let mut autosave = Autosave::new(&rom, Duration::from_secs(30), 1);
+        autosave.save(&state, Instant::now());
+        autosave.finish();
+        assert_eq!(Autosave::numbers(&rom), vec![4]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs::File;
+
+use crate::WIDTH;
+use crate::HEIGHT;
+
+// background artwork drawn around the game, which is placed inside the
+// cutout rectangle (x, y, width, height) in image pixels
+pub struct Bezel {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+    pub cutout: (usize, usize, usize, usize)
+}
+
+impl Bezel {
+    pub fn load(path: &str, cutout: Option<(usize, usize, usize, usize)>) -> Result<Bezel, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Could not open bezel {}: {}", path, e))?;
+
+        let mut decoder = png::Decoder::new(file);
+        // always decode to 8 bit gray/rgb with or without alpha
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()
+            .map_err(|e| format!("Could not decode bezel {}: {}", path, e))?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)
+            .map_err(|e| format!("Could not decode bezel {}: {}", path, e))?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::RGB => 3,
+            png::ColorType::RGBA => 4,
+            png::ColorType::Indexed => return Err(format!("Could not expand palette of bezel {}", path))
+        };
+
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let line = &data[y * info.line_size..];
+            for x in 0..width {
+                let px = &line[x * channels..(x + 1) * channels];
+                let (r, g, b, a) = match channels {
+                    1 => (px[0], px[0], px[0], 255),
+                    2 => (px[0], px[0], px[0], px[1]),
+                    3 => (px[0], px[1], px[2], 255),
+                    _ => (px[0], px[1], px[2], px[3])
+                };
+                // transparent parts of the artwork show black
+                let scale = |c: u8| (c as u32 * a as u32 / 255) & 0xFF;
+                pixels.push((scale(r) << 16) | (scale(g) << 8) | scale(b));
+            }
+        }
+
+        // default to the middle 80% of the image
+        let cutout = cutout.unwrap_or((width / 10, height / 10, width * 8 / 10, height * 8 / 10));
+        let (x, y, w, h) = cutout;
+        if x + w > width || y + h > height {
+            return Err(format!("Bezel cutout {}x{}+{}+{} does not fit the {}x{} image", w, h, x, y, width, height));
+        }
+        if w < WIDTH || h < HEIGHT {
+            return Err(format!("Bezel cutout must be at least {}x{}", WIDTH, HEIGHT));
+        }
+
+        Ok(Bezel {
+            width,
+            height,
+            pixels,
+            cutout
+        })
+    }
+}
+
+// parses WIDTHxHEIGHT+X+Y
+pub fn parse_cutout(spec: &str) -> Option<(usize, usize, usize, usize)> {
+    let (size, offset) = spec.split_at(spec.find('+')?);
+    let mut size = size.split('x');
+    let w = size.next()?.parse().ok()?;
+    let h = size.next()?.parse().ok()?;
+    let mut offset = offset[1..].split('+');
+    let x = offset.next()?.parse().ok()?;
+    let y = offset.next()?.parse().ok()?;
+    if size.next().is_some() || offset.next().is_some() {
+        return None;
+    }
+    Some((x, y, w, h))
+}
\ No newline at end of file
@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::config;
+use crate::cpu::Cpu;
+use crate::labels::MemoryLabels;
+use crate::ram::Ram;
+use crate::spec;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timer {
+    Delay,
+    Sound
+}
+
+impl Timer {
+    pub fn parse(name: &str) -> Option<Timer> {
+        match name {
+            "dt" => Some(Timer::Delay),
+            "st" => Some(Timer::Sound),
+            _ => None
+        }
+    }
+
+    fn read(&self, cpu: &Cpu) -> u8 {
+        match self {
+            Timer::Delay => cpu.reg_dt(),
+            Timer::Sound => cpu.reg_st()
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Timer::Delay => "DT",
+            Timer::Sound => "ST"
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Breakpoint {
+    // stop when this frame starts, frames count timer ticks from boot
+    Frame(u64),
+    // stop on the tick a running timer runs out
+    TimerExpired(Timer),
+    // stop after a frame that wrote this address
+    Write(u16),
+    // stop after a frame that read this address, instruction fetches
+    // included
+    Read(u16)
+}
+
+// an address in ram written in hex, with or without 0x
+pub fn parse_addr(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).ok()
+        .filter(|&addr| (addr as usize) < spec::RAM_SIZE)
+}
+
+impl Breakpoint {
+    // frame:600, dt, st, write:3E0 or read:3E0, addresses in hex
+    pub fn parse(text: &str) -> Option<Breakpoint> {
+        if let Some(timer) = Timer::parse(text) {
+            return Some(Breakpoint::TimerExpired(timer));
+        }
+        let (kind, value) = text.split_once(':')?;
+        match kind {
+            "frame" => value.parse().ok().map(Breakpoint::Frame),
+            "write" => parse_addr(value).map(Breakpoint::Write),
+            "read" => parse_addr(value).map(Breakpoint::Read),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Frame(frame) => write!(f, "frame:{}", frame),
+            Breakpoint::TimerExpired(timer) => write!(f, "{}", timer.name().to_lowercase()),
+            Breakpoint::Write(addr) => write!(f, "write:{:03X}", addr),
+            Breakpoint::Read(addr) => write!(f, "read:{:03X}", addr)
+        }
+    }
+}
+
+impl TryFrom<String> for Breakpoint {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Breakpoint, String> {
+        Breakpoint::parse(&text).ok_or_else(|| format!("Could not parse breakpoint {}", text))
+    }
+}
+
+impl From<Breakpoint> for String {
+    fn from(breakpoint: Breakpoint) -> String {
+        breakpoint.to_string()
+    }
+}
+
+// breakpoints that go together, e.g. everything about drawing
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct BreakpointGroup {
+    pub enabled: bool,
+    pub breakpoints: Vec<Breakpoint>
+}
+
+// a rom's named breakpoint groups, kept between runs so a long debugging
+// session can switch whole sets on and off instead of retyping them
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct BreakpointGroups {
+    groups: BTreeMap<String, BreakpointGroup>
+}
+
+impl BreakpointGroups {
+    // where the groups for the rom with this sha1 are kept
+    pub fn path(sha1: &str) -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("breakpoints").join(format!("{}.json", sha1)))
+    }
+
+    // no file yet means no groups
+    pub fn load(path: &Path) -> Result<BreakpointGroups, String> {
+        if !path.exists() {
+            return Ok(BreakpointGroups::default());
+        }
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).expect("Could not serialize breakpoint groups.");
+        fs::write(path, json)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    pub fn groups(&self) -> &BTreeMap<String, BreakpointGroup> {
+        &self.groups
+    }
+
+    // adds to the group, making it if needed. new groups start enabled
+    pub fn add(&mut self, name: &str, breakpoints: &[Breakpoint]) {
+        let group = self.groups.entry(name.to_string()).or_insert_with(|| BreakpointGroup {
+            enabled: true,
+            breakpoints: Vec::new()
+        });
+        for breakpoint in breakpoints {
+            if !group.breakpoints.contains(breakpoint) {
+                group.breakpoints.push(*breakpoint);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        self.groups.remove(name).map(|_| ()).ok_or_else(|| format!("No breakpoint group {}", name))
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let group = self.groups.get_mut(name).ok_or_else(|| format!("No breakpoint group {}", name))?;
+        group.enabled = enabled;
+        Ok(())
+    }
+
+    // the breakpoints of every enabled group
+    pub fn enabled(&self) -> Vec<Breakpoint> {
+        self.groups.values()
+            .filter(|group| group.enabled)
+            .flat_map(|group| group.breakpoints.iter().copied())
+            .collect()
+    }
+}
+
+// breakpoints that fire on frame and timer events rather than addresses,
+// checked once per frame right after the timers tick
+pub struct FrameBreakpoints {
+    breakpoints: Vec<Breakpoint>,
+    frame: u64,
+    last_timers: Option<(u8, u8)>
+}
+
+impl FrameBreakpoints {
+    pub fn new(breakpoints: Vec<Breakpoint>) -> FrameBreakpoints {
+        FrameBreakpoints {
+            breakpoints,
+            frame: 0,
+            last_timers: None
+        }
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // starts counting frames again, e.g. after a reboot
+    pub fn reset(&mut self) {
+        self.frame = 0;
+        self.last_timers = None;
+    }
+
+    // call once per frame after tick_timers, returns why to stop if a
+    // breakpoint was hit. memory breakpoints trace ram's accesses, starting
+    // from the first frame after they're set or ram is replaced
+    // addresses are named by labels where they have one
+    pub fn update(&mut self, cpu: &Cpu, ram: &mut Ram, labels: &MemoryLabels) -> Option<String> {
+        self.frame += 1;
+        let last_timers = self.last_timers.replace((cpu.reg_dt(), cpu.reg_st()));
+        let watches_memory = self.breakpoints.iter()
+            .any(|breakpoint| matches!(breakpoint, Breakpoint::Write(_) | Breakpoint::Read(_)));
+        let accesses = if watches_memory && ram.tracing() {
+            ram.take_trace()
+        } else {
+            if watches_memory {
+                ram.start_trace();
+            }
+            Vec::new()
+        };
+
+        let mut hits = Vec::new();
+        for breakpoint in &self.breakpoints {
+            match *breakpoint {
+                Breakpoint::Frame(frame) => {
+                    if frame == self.frame {
+                        hits.push(format!("frame {}", frame));
+                    }
+                },
+                Breakpoint::TimerExpired(timer) => {
+                    let before = match (timer, last_timers) {
+                        (Timer::Delay, Some((dt, _))) => dt,
+                        (Timer::Sound, Some((_, st))) => st,
+                        (_, None) => 0
+                    };
+                    if before > 0 && timer.read(cpu) == 0 {
+                        hits.push(format!("{} reached 0 on frame {}", timer.name(), self.frame));
+                    }
+                },
+                Breakpoint::Write(addr) => {
+                    if accesses.contains(&(addr, true)) {
+                        hits.push(format!("write to {} on frame {}", labels.describe(addr), self.frame));
+                    }
+                },
+                Breakpoint::Read(addr) => {
+                    if accesses.contains(&(addr, false)) {
+                        hits.push(format!("read of {} on frame {}", labels.describe(addr), self.frame));
+                    }
+                }
+            }
+        }
+
+        if hits.is_empty() {
+            None
+        } else {
+            Some(hits.join(", "))
+        }
+    }
+}
+
+// pauses before the first instruction of each opcode class, e.g. the first
+// DRW whatever its operands, to walk a rom through every instruction it uses
+pub struct FirstOpcodeBreak {
+    seen: Vec<u16>
+}
+
+impl Default for FirstOpcodeBreak {
+    fn default() -> FirstOpcodeBreak {
+        FirstOpcodeBreak::new()
+    }
+}
+
+impl FirstOpcodeBreak {
+    pub fn new() -> FirstOpcodeBreak {
+        FirstOpcodeBreak {
+            seen: Vec::new()
+        }
+    }
+
+    // forgets the classes seen so far, e.g. after loading another rom
+    pub fn reset(&mut self) {
+        self.seen.clear();
+    }
+
+    // call before running the instruction at PC, returns why to stop if
+    // it's the first of its class. words that aren't instructions never stop
+    pub fn update(&mut self, cpu: &Cpu, ram: &Ram) -> Option<String> {
+        let memory = ram.memory();
+        let pc = cpu.reg_pc() as usize;
+        if pc + 1 >= memory.len() {
+            return None;
+        }
+        let instruction = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+
+        let opcode = spec::opcode_of(instruction)?;
+        if self.seen.contains(&opcode) {
+            return None;
+        }
+        self.seen.push(opcode);
+        Some(format!("first {} at {:03X} ({} of {} opcode classes)",
+            spec::disassemble(instruction), pc, self.seen.len(), spec::OPCODES.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Breakpoint,
+        BreakpointGroups,
+        FirstOpcodeBreak,
+        FrameBreakpoints,
+        Timer
+    };
+    use crate::audio::SilentSink;
+    use crate::chip8::Chip8;
+    use crate::labels::MemoryLabels;
+
+    #[test]
+    fn breakpoints_parse_and_print_the_same() {
+        for text in ["frame:600", "dt", "st", "write:3E0", "read:FFF"] {
+            assert_eq!(Breakpoint::parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(Breakpoint::parse("read:0x3e0"), Some(Breakpoint::Read(0x3E0)));
+        assert_eq!(Breakpoint::parse("st"), Some(Breakpoint::TimerExpired(Timer::Sound)));
+
+        // past the end of ram, and not breakpoints at all
+        assert_eq!(Breakpoint::parse("write:1000"), None);
+        assert_eq!(Breakpoint::parse("read:FFFF"), None);
+        assert_eq!(Breakpoint::parse("frame:-1"), None);
+        assert_eq!(Breakpoint::parse("jump:200"), None);
+    }
+
+    #[test]
+    fn frame_and_timer_breakpoints_stop_on_their_frame() {
+        // LD V0, 02  LD DT, V0  JP 204
+        let mut chip8 = Chip8::with_rom(&[0x60, 0x02, 0xF0, 0x15, 0x12, 0x04]).unwrap();
+        let mut breakpoints = FrameBreakpoints::new(vec![Breakpoint::Frame(1), Breakpoint::TimerExpired(Timer::Delay)]);
+        let mut hits = Vec::new();
+        for _ in 0..4 {
+            (0..3).try_for_each(|_| chip8.step()).unwrap();
+            chip8.cpu.tick_timers(&mut SilentSink);
+            if let Some(hit) = breakpoints.update(&chip8.cpu, &mut chip8.ram, &MemoryLabels::default()) {
+                hits.push(hit);
+            }
+        }
+        assert_eq!(hits, vec!["frame 1", "DT reached 0 on frame 2"]);
+        assert_eq!(breakpoints.frame(), 4);
+    }
+
+    #[test]
+    fn first_opcode_break_stops_once_per_class() {
+        // LD V0, 01  LD V1, 02  ADD V0, 01  JP 204
+        let mut chip8 = Chip8::with_rom(&[0x60, 0x01, 0x61, 0x02, 0x70, 0x01, 0x12, 0x04]).unwrap();
+        let mut first_opcodes = FirstOpcodeBreak::new();
+        let mut hits = Vec::new();
+        for _ in 0..10 {
+            if let Some(hit) = first_opcodes.update(&chip8.cpu, &chip8.ram) {
+                hits.push(hit);
+            }
+            chip8.step().unwrap();
+        }
+
+        assert_eq!(hits.len(), 3);
+        assert!(hits[0].starts_with("first LD V0, 01 at 200"));
+        assert!(hits[1].starts_with("first ADD V0, 01 at 204"));
+        assert!(hits[2].starts_with("first JP 204 at 206"));
+    }
+
+    #[test]
+    fn enabled_breakpoint_groups_stop_on_memory_writes() {
+        let mut groups = BreakpointGroups::default();
+        groups.add("score", &[Breakpoint::parse("write:300").unwrap()]);
+        groups.add("draw", &[Breakpoint::parse("read:400").unwrap(), Breakpoint::Frame(1)]);
+        groups.set_enabled("draw", false).unwrap();
+        let json = serde_json::to_string(&groups).unwrap();
+        assert!(json.contains("\"write:300\""));
+        assert_eq!(serde_json::from_str::<BreakpointGroups>(&json).unwrap(), groups);
+
+        // LD I, 300, then LD B, V0 the frame after, then loop
+        let mut chip8 = Chip8::with_rom(&[0xA3, 0x00, 0xF0, 0x33, 0x12, 0x04]).unwrap();
+        let mut breakpoints = FrameBreakpoints::new(groups.enabled());
+        let mut hits = Vec::new();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+            if let Some(hit) = breakpoints.update(&chip8.cpu, &mut chip8.ram, &MemoryLabels::default()) {
+                hits.push(hit);
+            }
+        }
+        assert_eq!(hits, vec!["write to 300 on frame 2"]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use minifb::{
+    Key,
+    KeyRepeat,
+    Scale,
+    Window,
+    WindowOptions
+};
+
+use rust8::overlay::{
+    self,
+    Overlay
+};
+use rust8::romdb;
+use rust8::stats::UsageStats;
+use rust8::timing::FRAME_TIME;
+use rust8::{
+    HEIGHT,
+    PX_OFF,
+    PX_ON,
+    WIDTH
+};
+
+use crate::kiosk;
+use crate::shutdown::Shutdown;
+
+const HEADER: &str = "PICK A ROM, ENTER TO PLAY";
+
+// the order roms are listed in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Sort {
+    Title,
+    // needs usage stats, see `rust8 stats`
+    Recent,
+    MostPlayed
+}
+
+impl Sort {
+    pub fn parse(text: &str) -> Option<Sort> {
+        match text {
+            "title" => Some(Sort::Title),
+            "recent" => Some(Sort::Recent),
+            "most-played" => Some(Sort::MostPlayed),
+            _ => None
+        }
+    }
+}
+
+// a rom of the directory and the name it's listed under
+struct Entry {
+    path: PathBuf,
+    sha1: String,
+    title: String
+}
+
+// .ch8 and .c8 files, and files without an extension like the roms in data/
+fn is_rom(path: &Path) -> bool {
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    kiosk::is_rom(path) && matches!(extension.as_deref(), None | Some("ch8") | Some("c8"))
+}
+
+// the roms in dir by title, known roms by their title in the rom database
+fn list(dir: &str) -> Result<Vec<Entry>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Could not read rom directory {}: {}", dir, e))?;
+    let mut roms: Vec<Entry> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_rom(path))
+        .map(|path| {
+            let sha1 = fs::read(&path).map(|data| romdb::sha1(&data)).unwrap_or_default();
+            let title = romdb::lookup(&sha1)
+                .map(|info| info.title.to_uppercase())
+                .unwrap_or_else(|| kiosk::rom_title(&path));
+            Entry {
+                path,
+                sha1,
+                title
+            }
+        })
+        .collect();
+    roms.sort_by(|a, b| a.title.cmp(&b.title));
+    Ok(roms)
+}
+
+// puts the roms played most recently or for longest first, the ones never
+// played stay after them by title
+fn sort_by_stats(roms: &mut [Entry], sort: Sort, stats: &UsageStats) {
+    let key = |rom: &Entry| stats.get(&rom.sha1).map(|played| match sort {
+        Sort::Recent => played.last_played,
+        Sort::MostPlayed => played.seconds,
+        Sort::Title => 0
+    });
+    // stable, so ties keep their title order
+    roms.sort_by_key(|rom| Reverse(key(rom)));
+}
+
+// shows the roms of dir in a window the size of the game's and lets the
+// user pick one with the arrow keys, page up and down, home and end, and
+// enter. None when escape is pressed, the window is closed or shutdown is
+// requested
+pub fn pick(dir: &str, sort: Sort, scale: usize, shutdown: &Shutdown) -> Result<Option<String>, String> {
+    let mut roms = list(dir)?;
+    if roms.is_empty() {
+        return Err(format!("No roms found in {}, give a rom or point --rom-dir at some", dir));
+    }
+    if sort != Sort::Title {
+        match UsageStats::path().map(|path| UsageStats::enabled(&path)) {
+            Some(Ok(Some(stats))) => sort_by_stats(&mut roms, sort, &stats),
+            Some(Err(e)) => println!("{}, listing roms by title", e),
+            _ => println!("Usage stats are off, listing roms by title. `rust8 stats --enable` turns them on")
+        }
+    }
+
+    let width = WIDTH * scale;
+    let height = HEIGHT * scale;
+    let mut window = Window::new(
+        "chip-8 rust - pick a rom",
+        width,
+        height,
+        WindowOptions {
+            scale: Scale::X1,
+            ..WindowOptions::default()
+        }
+    ).map_err(|e| format!("Could not open window: {}", e))?;
+    window.limit_update_rate(Some(FRAME_TIME));
+
+    let text_scale = (scale / 4).max(1);
+    let line_height = (overlay::CELL_HEIGHT + 2) * text_scale;
+    // the header takes the first line
+    let rows = (height / line_height).saturating_sub(1).max(1);
+    let mut buffer = vec![PX_OFF; width * height];
+    let mut selected = 0;
+    let mut top = 0;
+
+    while window.is_open() && !shutdown.requested() {
+        let last = roms.len() - 1;
+        if window.is_key_pressed(Key::Escape, KeyRepeat::No) {
+            return Ok(None);
+        }
+        if window.is_key_pressed(Key::Enter, KeyRepeat::No) {
+            return Ok(Some(roms[selected].path.to_string_lossy().into_owned()));
+        }
+        if window.is_key_pressed(Key::Up, KeyRepeat::Yes) {
+            selected = selected.saturating_sub(1);
+        }
+        if window.is_key_pressed(Key::Down, KeyRepeat::Yes) {
+            selected = (selected + 1).min(last);
+        }
+        if window.is_key_pressed(Key::PageUp, KeyRepeat::Yes) {
+            selected = selected.saturating_sub(rows);
+        }
+        if window.is_key_pressed(Key::PageDown, KeyRepeat::Yes) {
+            selected = (selected + rows).min(last);
+        }
+        if window.is_key_pressed(Key::Home, KeyRepeat::No) {
+            selected = 0;
+        }
+        if window.is_key_pressed(Key::End, KeyRepeat::No) {
+            selected = last;
+        }
+        // keep the selection on screen
+        if selected < top {
+            top = selected;
+        }
+        if selected >= top + rows {
+            top = selected + 1 - rows;
+        }
+
+        buffer.iter_mut().for_each(|pixel| *pixel = PX_OFF);
+        let mut overlay = Overlay::new(&mut buffer, width, height);
+        overlay.draw_text(text_scale, text_scale, HEADER, PX_ON, text_scale);
+        for (row, rom) in roms.iter().enumerate().skip(top).take(rows) {
+            let y = (row - top + 1) * line_height;
+            let color = if row == selected {
+                overlay.fill_rect(0, y, width, line_height, PX_ON);
+                PX_OFF
+            } else {
+                PX_ON
+            };
+            overlay.draw_text(2 * text_scale, y + text_scale, &rom.title, color, text_scale);
+        }
+        window.update_with_buffer(&buffer, width, height)
+            .map_err(|e| format!("Could not draw rom browser: {}", e))?;
+    }
+    Ok(None)
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+use serde::Serialize;
+
+use rust8::romdb;
+use rust8::score::HighScores;
+
+use crate::kiosk;
+
+// one distinct rom and every file in the directory that holds it
+#[derive(Serialize)]
+pub struct CatalogEntry {
+    pub sha1: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub known: bool,
+    pub size: usize,
+    pub files: Vec<String>,
+    pub high_score: Option<u32>
+}
+
+// hashes every rom in dir and groups files with identical contents, named
+// from the rom database where possible. sorted by title
+pub fn build(dir: &str) -> Result<Vec<CatalogEntry>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Could not read catalog directory {}: {}", dir, e))?;
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && kiosk::is_rom(path))
+        .collect();
+    roms.sort();
+
+    // a catalog without scores is still worth having
+    let high_scores = HighScores::path()
+        .and_then(|path| HighScores::load(&path).ok())
+        .unwrap_or_default();
+
+    let mut by_hash: BTreeMap<String, CatalogEntry> = BTreeMap::new();
+    for path in &roms {
+        let data = fs::read(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let sha1 = romdb::sha1(&data);
+        let file = file_name(path);
+
+        let entry = by_hash.entry(sha1.clone()).or_insert_with(|| {
+            let info = romdb::lookup(&sha1);
+            CatalogEntry {
+                title: info.map(|info| info.title.to_string()).unwrap_or_else(|| kiosk::rom_title(path)),
+                author: info.map(|info| info.author.to_string()),
+                known: info.is_some(),
+                size: data.len(),
+                files: Vec::new(),
+                high_score: high_scores.get(&sha1).map(|game| game.best),
+                sha1
+            }
+        });
+        entry.files.push(file);
+    }
+
+    let mut catalog: Vec<CatalogEntry> = by_hash.into_values().collect();
+    catalog.sort_by(|a, b| a.title.cmp(&b.title));
+    Ok(catalog)
+}
+
+pub fn write(path: &str, catalog: &[CatalogEntry]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(catalog).expect("Could not serialize catalog.");
+    fs::write(path, json)
+        .map_err(|e| format!("Could not write {}: {}", path, e))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
@@ -0,0 +1,513 @@
+use crate::audio::{
+    AudioSink,
+    SilentSink
+};
+use crate::cpu::Cpu;
+use crate::display::Frame;
+use crate::error::Chip8Error;
+use crate::present::Palette;
+use crate::ram::Ram;
+use crate::romdb;
+use crate::screenshot::Image;
+use crate::state::State;
+use crate::spec::{
+    self,
+    MAX_ROM_SIZE,
+    OP_LD_KEY,
+    OP_SKNP,
+    OP_SKP,
+    PROGRAM_START_ADDR
+};
+
+// copies rom to the program start address
+pub fn load_rom(ram: &mut Ram, rom: &[u8]) -> Result<(), Chip8Error> {
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(Chip8Error::RomTooLarge { size: rom.len(), max: MAX_ROM_SIZE });
+    }
+    for (i, &byte) in rom.iter().enumerate() {
+        ram.write_byte(PROGRAM_START_ADDR + i as u16, byte);
+    }
+    Ok(())
+}
+
+// instructions per frame unless set otherwise, the desktop default
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 9;
+
+// runs up to frames frames of instructions_per_frame instructions as fast
+// as it can, with no keys held and the buzzer silent, e.g. to get past a
+// title screen. until_input stops at the first instruction that looks at
+// the keypad instead. returns how many frames were run
+pub fn fast_forward(cpu: &mut Cpu, ram: &mut Ram, frames: u64, instructions_per_frame: usize, until_input: bool) -> Result<u64, Chip8Error> {
+    for frame in 0..frames {
+        for _ in 0..instructions_per_frame {
+            if until_input && reads_keypad(cpu, ram) {
+                return Ok(frame);
+            }
+            cpu.run_instruction(ram)?;
+            if cpu.halted().is_some() {
+                return Ok(frame);
+            }
+        }
+        cpu.tick_timers(&mut SilentSink);
+    }
+    Ok(frames)
+}
+
+// a frame's instructions with whatever keys the cpu holds, then one timer
+// tick. a halt or an error, which is kept as the halt reason, ends the
+// frame's instructions early. Chip8::run_frame and anything going back
+// over its frames, e.g. a netplay rollback, share it
+pub fn run_frame(cpu: &mut Cpu, ram: &mut Ram, instructions_per_frame: usize, audio: &mut dyn AudioSink) {
+    for _ in 0..instructions_per_frame {
+        if cpu.run_instruction(ram).is_err() || cpu.halted().is_some() {
+            break;
+        }
+    }
+    cpu.tick_timers(audio);
+}
+
+// whether the instruction at pc checks or waits for a key
+fn reads_keypad(cpu: &Cpu, ram: &Ram) -> bool {
+    let memory = ram.memory();
+    let pc = cpu.reg_pc() as usize;
+    if pc + 1 >= memory.len() {
+        return false;
+    }
+    let instruction = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+    matches!(spec::opcode_of(instruction), Some(OP_SKP) | Some(OP_SKNP) | Some(OP_LD_KEY))
+}
+
+// a whole machine, cpu and memory together
+pub struct Chip8 {
+    pub cpu: Cpu,
+    pub ram: Ram,
+    instructions_per_frame: usize,
+    beeping: bool
+}
+
+// remembers whether the last timer tick sounded the buzzer
+struct Buzzer(bool);
+
+impl AudioSink for Buzzer {
+    fn start_beep(&mut self) {
+        self.0 = true;
+    }
+
+    fn stop_beep(&mut self) {
+        self.0 = false;
+    }
+
+    fn set_frequency(&mut self, _hz: u32) {}
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
+}
+
+impl Chip8 {
+    pub fn new() -> Chip8 {
+        Chip8 {
+            cpu: Cpu::new(),
+            ram: Ram::new(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            beeping: false
+        }
+    }
+
+    // fresh machine with rom loaded at the program start address
+    pub fn with_rom(rom: &[u8]) -> Result<Chip8, Chip8Error> {
+        let mut chip8 = Chip8::new();
+        load_rom(&mut chip8.ram, rom)?;
+        Ok(chip8)
+    }
+
+    // as with_rom, set up with the rom's quirks if the rom database knows it
+    pub fn with_rom_quirks(rom: &[u8]) -> Result<Chip8, Chip8Error> {
+        let mut chip8 = Chip8::with_rom(rom)?;
+        chip8.use_known_quirks(rom);
+        Ok(chip8)
+    }
+
+    // the quirks the rom database has for rom, the ones set stay otherwise
+    pub fn use_known_quirks(&mut self, rom: &[u8]) {
+        if let Some(info) = romdb::lookup(&romdb::sha1(rom)) {
+            self.cpu.set_quirks(info.quirks);
+        }
+    }
+
+    // starts over with rom as with_rom would, keeping the speed and memory
+    // policy. the cpu's other settings are the caller's to set again. a rom
+    // that can't be loaded leaves the machine as it was
+    pub fn reset(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let mut chip8 = Chip8::with_rom(rom)?;
+        chip8.ram.set_policy(self.ram.policy());
+        chip8.instructions_per_frame = self.instructions_per_frame;
+        *self = chip8;
+        Ok(())
+    }
+
+    // machine picked up exactly where a saved state left off, from the
+    // contents of a state file. lets tests start in the middle of a game
+    pub fn from_state(bytes: &[u8]) -> Result<Chip8, String> {
+        let json = std::str::from_utf8(bytes)
+            .map_err(|e| format!("Invalid state json: {}", e))?;
+        let state = State::from_json(json)?;
+
+        let mut chip8 = Chip8::new();
+        chip8.load_state(&state)?;
+        Ok(chip8)
+    }
+
+    pub fn save_state(&self) -> State {
+        self.cpu.save_state(&self.ram)
+    }
+
+    pub fn load_state(&mut self, state: &State) -> Result<(), String> {
+        self.cpu.load_state(&mut self.ram, state)
+    }
+
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        self.cpu.run_instruction(&mut self.ram)
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: usize) {
+        self.instructions_per_frame = instructions;
+    }
+
+    // one display refresh: holds keys, runs the frame's instructions, ticks
+    // the timers once and returns the display. frontends call this at 60Hz
+    // and start or stop their buzzer by beeping()
+    pub fn run_frame(&mut self, keys: &[bool; 16]) -> &Frame {
+        for (key, &held) in keys.iter().enumerate() {
+            self.cpu.set_key(key, held);
+        }
+        let mut buzzer = Buzzer(self.beeping);
+        run_frame(&mut self.cpu, &mut self.ram, self.instructions_per_frame, &mut buzzer);
+        self.beeping = buzzer.0;
+        self.cpu.gfx()
+    }
+
+    // whether the buzzer sounded on the last frame
+    pub fn beeping(&self) -> bool {
+        self.beeping
+    }
+
+    // the display as it looks in palette, scale times its size, e.g. for
+    // Image::write_png
+    pub fn framebuffer_image(&self, palette: &Palette, scale: usize) -> Image {
+        Image::new(self.cpu.gfx(), palette, scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fast_forward,
+        Chip8
+    };
+    use crate::audio::SilentSink;
+    use crate::state::State;
+    use crate::error::Chip8Error;
+    use crate::ram::MemoryPolicy;
+    use crate::quirks::Quirks;
+    use crate::spec::MAX_ROM_SIZE;
+
+    // breakout mid-game: the ball is heading up and right, about to hit the
+    // bottom row of bricks
+    const BREAKOUT: &[u8] = include_bytes!("../tests/fixtures/breakout.state.json");
+
+    fn run_frames(chip8: &mut Chip8, frames: usize) {
+        let mut audio = SilentSink;
+        for _ in 0..frames {
+            for _ in 0..9 {
+                chip8.step().unwrap();
+            }
+            chip8.cpu.tick_timers(&mut audio);
+        }
+    }
+
+    fn lit_in_row(chip8: &Chip8, row: usize) -> usize {
+        chip8.save_state().display[row].matches('#').count()
+    }
+
+    #[test]
+    fn from_state_restores_the_saved_machine() {
+        let chip8 = Chip8::from_state(BREAKOUT).unwrap();
+        let state = State::from_json(std::str::from_utf8(BREAKOUT).unwrap()).unwrap();
+        assert_eq!(chip8.save_state(), state);
+    }
+
+    #[test]
+    fn from_state_rejects_garbage() {
+        assert!(Chip8::from_state(b"not a state").is_err());
+        assert!(Chip8::from_state(&[0xFF, 0xFE]).is_err());
+    }
+
+    #[test]
+    fn breakout_ball_breaks_a_brick_and_bounces() {
+        let mut chip8 = Chip8::from_state(BREAKOUT).unwrap();
+        let bricks = lit_in_row(&chip8, 13);
+        // V5 is the ball's vertical direction, V4 the horizontal one is
+        // picked at random on each bounce
+        assert_eq!(chip8.cpu.reg_gpr()[5], 0xFF);
+
+        run_frames(&mut chip8, 30);
+
+        assert_eq!(chip8.cpu.reg_gpr()[5], 0x01);
+        assert!(lit_in_row(&chip8, 13) < bricks);
+        assert_eq!(chip8.cpu.halted(), None);
+    }
+
+    #[test]
+    fn counts_frames_and_cycles() {
+        let mut chip8 = Chip8::with_rom(&[0x12, 0x00]).unwrap();
+        run_frames(&mut chip8, 3);
+        assert_eq!(chip8.cpu.frames(), 3);
+        assert_eq!(chip8.cpu.cycles(), 27);
+
+        // a halted machine executes nothing, so nothing more is counted
+        let mut chip8 = Chip8::with_rom(&[0x00, 0x00]).unwrap();
+        run_frames(&mut chip8, 1);
+        assert_eq!(chip8.cpu.cycles(), 0);
+    }
+
+    #[test]
+    fn journal_records_ram_writes_until_cleared() {
+        // LD V0, 7B  LD I, 300  LD B, V0
+        let mut chip8 = Chip8::with_rom(&[0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33]).unwrap();
+        chip8.cpu.start_journal();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+
+        let writes: Vec<(u16, u8, u8, u16)> = chip8.cpu.journal().iter()
+            .map(|write| (write.addr, write.old, write.new, write.pc))
+            .collect();
+        assert_eq!(writes, vec![(0x300, 0, 1, 0x204), (0x301, 0, 2, 0x204), (0x302, 0, 3, 0x204)]);
+
+        chip8.cpu.clear_journal();
+        assert!(chip8.cpu.journal().is_empty());
+    }
+
+    #[test]
+    fn waiting_on_dt_spots_a_delay_loop() {
+        // LD V0, 05  LD DT, V0  LD V1, DT  SE V1, 00  JP 204
+        let mut chip8 = Chip8::with_rom(&[0x60, 0x05, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04]).unwrap();
+        chip8.step().unwrap();
+        assert!(!chip8.cpu.waiting_on_dt(&chip8.ram));
+        chip8.step().unwrap();
+        assert!(chip8.cpu.waiting_on_dt(&chip8.ram));
+
+        // in the middle of the loop, and once DT has run out
+        chip8.step().unwrap();
+        assert!(!chip8.cpu.waiting_on_dt(&chip8.ram));
+        chip8.cpu.set_reg_dt(0);
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        assert!(!chip8.cpu.waiting_on_dt(&chip8.ram));
+    }
+
+    #[test]
+    fn fx29_points_i_at_the_digit_sprite() {
+        // LD V3, 0A  LD F, V3  DRW V0, V0, 5
+        let mut chip8 = Chip8::with_rom(&[0x63, 0x0A, 0xF3, 0x29, 0xD0, 0x05]).unwrap();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.cpu.reg_i(), 10 * 5);
+
+        // the top row of "A" is F0
+        let top: Vec<bool> = (0..8).map(|x| chip8.cpu.gfx().lit(x, 0)).collect();
+        assert_eq!(top, vec![true, true, true, true, false, false, false, false]);
+        assert_eq!(chip8.cpu.reg_gpr()[0xF], 0);
+    }
+
+    #[test]
+    fn run_frame_runs_a_frame_of_instructions_and_ticks_once() {
+        // LD V0, 02  LD ST, V0  JP 204
+        let mut chip8 = Chip8::with_rom(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+        chip8.set_instructions_per_frame(2);
+        let mut keys = [false; 16];
+
+        chip8.run_frame(&keys);
+        assert_eq!(chip8.cpu.reg_pc(), 0x204);
+        assert_eq!(chip8.cpu.reg_st(), 1);
+        assert!(chip8.beeping());
+
+        keys[0] = true;
+        chip8.run_frame(&keys);
+        assert_eq!(chip8.cpu.keys()[0], 1);
+        assert_eq!(chip8.cpu.reg_st(), 0);
+        assert!(chip8.beeping());
+        assert_eq!(chip8.cpu.frames(), 2);
+
+        chip8.run_frame(&keys);
+        assert!(!chip8.beeping());
+    }
+
+    #[test]
+    fn roms_too_big_for_ram_are_refused() {
+        assert_eq!(Chip8::with_rom(&vec![0; MAX_ROM_SIZE + 1]).err(), Some(Chip8Error::RomTooLarge { size: MAX_ROM_SIZE + 1, max: MAX_ROM_SIZE }));
+    }
+
+    #[test]
+    fn memory_policy_decides_what_happens_past_the_end_of_ram() {
+        // LD I, FFF; LD V0, 123; LD B, V0 writes the digits to FFF, 1000, 1001
+        let rom = [0xAF, 0xFF, 0x60, 0x7B, 0xF0, 0x33];
+        let run = |policy| {
+            let mut chip8 = Chip8::with_rom(&rom).unwrap();
+            chip8.ram.set_policy(policy);
+            let result = (0..3).try_for_each(|_| chip8.step());
+            (result, chip8.ram.memory()[0xFFF], chip8.ram.memory()[0x000], chip8.ram.memory()[0x001])
+        };
+        assert_eq!(run(MemoryPolicy::Checked).0, Err(Chip8Error::OutOfBoundsAccess { addr: 0x1000, pc: 0x204 }));
+        assert_eq!(run(MemoryPolicy::Mirrored), (Ok(()), 1, 2, 3));
+        assert_eq!(run(MemoryPolicy::Clamped).1, 3);
+    }
+
+    #[test]
+    fn pc_comes_round_past_the_end_of_ram() {
+        // JP FFE to LD V0, 01 in the last two bytes, then on to 000
+        let run = |policy, jump: u16| {
+            let mut chip8 = Chip8::with_rom(&[0x10 | (jump >> 8) as u8, jump as u8]).unwrap();
+            chip8.ram.set_policy(policy);
+            chip8.ram.write_byte(0xFFF, 0x01);
+            chip8.ram.write_byte(jump, 0x60);
+            let result = (0..2).try_for_each(|_| chip8.step());
+            (result, chip8.cpu.reg_pc(), chip8.cpu.reg_gpr()[0])
+        };
+        for policy in [MemoryPolicy::Checked, MemoryPolicy::Mirrored, MemoryPolicy::Clamped] {
+            assert_eq!(run(policy, 0xFFE), (Ok(()), 0x000, 0x01));
+        }
+
+        // JP FFF to an LD V0 straddling the end, which reads its operand
+        // as the policy says
+        assert_eq!(run(MemoryPolicy::Checked, 0xFFF).0, Err(Chip8Error::OutOfBoundsAccess { addr: 0x1000, pc: 0xFFF }));
+        assert_eq!(run(MemoryPolicy::Mirrored, 0xFFF), (Ok(()), 0x001, 0xF0));
+        assert_eq!(run(MemoryPolicy::Clamped, 0xFFF), (Ok(()), 0x001, 0x60));
+    }
+
+    #[test]
+    fn running_off_the_program_from_the_last_byte_of_ram() {
+        // JP FFF, where the jump on to empty memory straddles the end. its
+        // second byte is the font's first, F0, mirrored and FFF clamped
+        let run = |policy| {
+            let mut chip8 = Chip8::with_rom(&[0x1F, 0xFF]).unwrap();
+            chip8.ram.set_policy(policy);
+            chip8.ram.write_byte(0xFFF, 0x13);
+            (0..3).for_each(|_| chip8.step().unwrap());
+            chip8.cpu.halted().map(String::from)
+        };
+        assert_eq!(run(MemoryPolicy::Mirrored), Some(String::from("Ran off the program: null opcode 0000 at 03F0, reached from 0FFF (JP 3F0)")));
+        assert_eq!(run(MemoryPolicy::Clamped), Some(String::from("Ran off the program: null opcode 0000 at 0313, reached from 0FFF (JP 313)")));
+    }
+
+    #[test]
+    fn fx55_and_fx65_store_and_load_v0_to_vx() {
+        // LD V0, 1; LD V1, 2; LD V2, 3; LD I, 300; LD [I], V2; LD V0, 0;
+        // LD V1, 0; LD V2, 0; LD V1, [I]
+        let rom = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xA3, 0x00, 0xF2, 0x55, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xF1, 0x65];
+        let mut chip8 = Chip8::with_rom(&rom).unwrap();
+        (0..5).try_for_each(|_| chip8.step()).unwrap();
+        assert_eq!(&chip8.ram.memory()[0x300..0x304], &[1, 2, 3, 0]);
+        assert_eq!(chip8.cpu.reg_pc(), 0x20A);
+        (0..4).try_for_each(|_| chip8.step()).unwrap();
+        // only V0 and V1 come back, V2 is left alone
+        assert_eq!(&chip8.cpu.reg_gpr()[..3], &[1, 2, 0]);
+        assert_eq!(chip8.cpu.reg_pc(), 0x212);
+    }
+
+    #[test]
+    fn shl_and_subn_set_vx_and_vf() {
+        // LD V0, 88; SHL V0; LD V1, 10; LD V2, 30; SUBN V1, V2
+        let rom = [0x60, 0x88, 0x80, 0x0E, 0x61, 0x10, 0x62, 0x30, 0x81, 0x27];
+        let mut chip8 = Chip8::with_rom(&rom).unwrap();
+        (0..2).try_for_each(|_| chip8.step()).unwrap();
+        // the bit shifted out is the top one
+        assert_eq!((chip8.cpu.reg_gpr()[0], chip8.cpu.reg_gpr()[0xF]), (0x10, 1));
+        (0..3).try_for_each(|_| chip8.step()).unwrap();
+        // VY minus VX goes in VX, VY is left alone
+        assert_eq!(&chip8.cpu.reg_gpr()[1..3], &[0x20, 0x30]);
+        assert_eq!(chip8.cpu.reg_gpr()[0xF], 1);
+    }
+
+    #[test]
+    fn quirks_pick_which_interpreter_to_behave_like() {
+        // LD V0, 5; LD V1, 3; SHR V0, V1; LD I, 300; LD [I], V1
+        let rom = [0x60, 0x05, 0x61, 0x03, 0x80, 0x16, 0xA3, 0x00, 0xF1, 0x55];
+        let run = |quirks| {
+            let mut chip8 = Chip8::with_rom(&rom).unwrap();
+            chip8.cpu.set_quirks(quirks);
+            (0..5).try_for_each(|_| chip8.step()).unwrap();
+            (chip8.cpu.reg_gpr()[0], chip8.cpu.reg_i(), chip8.ram.memory()[0x301])
+        };
+        assert_eq!(run(Quirks::default()), (2, 0x300, 3));
+        assert_eq!(run(Quirks::VIP), (1, 0x302, 3));
+        assert_eq!(run(Quirks::SCHIP), (2, 0x300, 3));
+
+        // LD V3, 4; JP V0, 300, which jumps to 300 plus V3 with jump-vx
+        let jump = |quirks| {
+            let mut chip8 = Chip8::with_rom(&[0x63, 0x04, 0xB3, 0x00]).unwrap();
+            chip8.cpu.set_quirks(quirks);
+            (0..2).try_for_each(|_| chip8.step()).unwrap();
+            chip8.cpu.reg_pc()
+        };
+        assert_eq!(jump(Quirks::default()), 0x300);
+        assert_eq!(jump(Quirks::SCHIP), 0x304);
+    }
+
+    #[test]
+    fn strict_mode_stops_on_what_the_spec_leaves_undefined() {
+        let run = |rom: &[u8], strict| {
+            let mut chip8 = Chip8::with_rom(rom).unwrap();
+            chip8.cpu.set_strict(strict);
+            (0..rom.len() / 2).try_for_each(|_| chip8.step())
+        };
+        // 5XY1 is only loosely SE VX, VY
+        assert_eq!(run(&[0x50, 0x11], false), Ok(()));
+        assert_eq!(run(&[0x50, 0x11], true), Err(Chip8Error::InvalidOpcode { opcode: 0x5011, pc: 0x200 }));
+        // LD I, 000; LD [I], V0 writes over the interpreter
+        assert_eq!(run(&[0xA0, 0x00, 0xF0, 0x55], false), Ok(()));
+        assert_eq!(run(&[0xA0, 0x00, 0xF0, 0x55], true), Err(Chip8Error::ReservedWrite { addr: 0x000, pc: 0x202 }));
+        // LD V0, 20; SKP V0 checks a key that doesn't exist
+        assert_eq!(run(&[0x60, 0x20, 0xE0, 0x9E], false), Ok(()));
+        assert_eq!(run(&[0x60, 0x20, 0xE0, 0x9E], true), Err(Chip8Error::InvalidKey { key: 0x20, pc: 0x202 }));
+    }
+
+    #[test]
+    fn reset_starts_another_rom_keeping_the_speed_and_memory_policy() {
+        let mut chip8 = Chip8::with_rom(&[0x60, 0x05]).unwrap();
+        chip8.set_instructions_per_frame(3);
+        chip8.ram.set_policy(MemoryPolicy::Mirrored);
+        chip8.step().unwrap();
+        assert_eq!(chip8.cpu.reg_gpr()[0], 5);
+
+        chip8.reset(&[0x61, 0x07]).unwrap();
+        assert_eq!(chip8.cpu.reg_pc(), 0x200);
+        assert_eq!(chip8.cpu.reg_gpr()[0], 0);
+        assert_eq!(chip8.ram.policy(), MemoryPolicy::Mirrored);
+        chip8.run_frame(&[false; 16]);
+        assert_eq!(chip8.cpu.reg_gpr()[1], 7);
+        assert_eq!(chip8.cpu.cycles(), 1);
+
+        // too big to load, so the machine carries on as it was
+        assert!(chip8.reset(&vec![0; MAX_ROM_SIZE + 1]).is_err());
+        assert_eq!(chip8.cpu.reg_gpr()[1], 7);
+    }
+
+    #[test]
+    fn fast_boot_runs_frames_until_the_rom_looks_at_the_keypad() {
+        // count V0 up to 3, one loop a frame, then wait on a key
+        let rom = [0x70, 0x01, 0x30, 0x03, 0x12, 0x00, 0xE0, 0x9E, 0x12, 0x06];
+        let mut chip8 = Chip8::with_rom(&rom).unwrap();
+        assert_eq!(fast_forward(&mut chip8.cpu, &mut chip8.ram, 60, 3, true), Ok(2));
+        assert_eq!(chip8.cpu.reg_pc(), 0x206);
+        assert_eq!(chip8.cpu.frames(), 2);
+
+        let mut chip8 = Chip8::with_rom(&rom).unwrap();
+        assert_eq!(fast_forward(&mut chip8.cpu, &mut chip8.ram, 10, 3, false), Ok(10));
+        assert_eq!(chip8.cpu.frames(), 10);
+    }
+}
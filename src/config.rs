@@ -0,0 +1,44 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// where per user files like macros live: $XDG_CONFIG_HOME/rust8, falling
+// back to ~/.config/rust8. None when neither variable is set
+pub fn dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+    Some(base.join("rust8"))
+}
+
+// reads a json file of the config directory, no file yet is the same as
+// the default
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e))
+    };
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+}
+
+// writes value as json, making the directory first if needed
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Could not serialize {}: {}", path.display(), e))?;
+    fs::write(path, json)
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
@@ -1,17 +1,42 @@
-use rand::Rng;
-use::minifb::{
-    Key,
-    Window
+use std::fmt;
+
+use serde::{
+    Deserialize,
+    Serialize
 };
+
 use crate::ram::Ram;
+use crate::audio::AudioSink;
+use crate::display::Frame;
+use crate::error::Chip8Error;
+use crate::input::InputSource;
+use crate::opcode_log::{
+    InvalidOpcode,
+    InvalidOpcodeLog,
+    InvalidOpcodePolicy
+};
+use crate::quirks::Quirks;
+use crate::rng::{
+    RngSource,
+    ThreadRng
+};
+use crate::state::{
+    self,
+    State,
+    STATE_VERSION
+};
 
-use crate::PROGRAM_START_ADDR;
-use crate::NUM_GPR;
-use crate::HEIGHT;
-use crate::WIDTH;
-use crate::PX_OFF;
-use crate::PX_ON;
+use crate::spec::{
+    HEIGHT,
+    NUM_GPR,
+    NUM_KEYS,
+    PROGRAM_START_ADDR,
+    STACK_DEPTH,
+    WIDTH
+};
+use crate::spec;
 
+#[derive(Clone)]
 pub struct Cpu {
     // 16 8 bit general purpose registers
     reg_gpr: [u8; NUM_GPR],
@@ -30,29 +55,114 @@ pub struct Cpu {
     // 8 bit stack pointer
     sp: u8,
 
-    // stack
-    stack: [u8; 16],
+    // stack of return addresses
+    stack: [u16; STACK_DEPTH],
 
-    // 8 bit graphics (gfx) array
-    gfx: [u32; 64 * 32],
+    // the display
+    gfx: Frame,
 
     // keyboard handling
-    keys: [u8; 16]
+    keys: [u8; NUM_KEYS],
+
+    // key seen going down while FX0A waits, it completes on release
+    waiting_key: Option<u8>,
+
+    // give up on FX0A after this many frames, for runs nobody is playing
+    key_wait_timeout: Option<(u32, KeyWaitTimeout)>,
+    // frames the current FX0A has been waiting, None when not waiting
+    key_wait_frames: Option<u32>,
+
+    // address of the last instruction executed, to explain where a crash came from
+    last_pc: Option<u16>,
+
+    // why the machine stopped, it executes nothing more once this is set
+    halt: Option<String>,
+
+    // frames and instructions run since boot, for tools pacing or timing
+    // a run. loading a state doesn't reset them
+    frames: u64,
+    cycles: u64,
+    // cycles at the last timer tick, and how many the frame before it ran,
+    // for the millisecond clock
+    tick_cycles: u64,
+    last_frame_cycles: u64,
+
+    // writes to ram made since the journal was last cleared, only while
+    // journaling
+    journal: Option<Vec<RamWrite>>,
+
+    // random numbers for CXNN
+    rng: Box<dyn RngSource>,
+
+    // which interpreter's behaviour to copy where they differ
+    quirks: Quirks,
+
+    // treat everything the spec leaves undefined as an error, see set_strict
+    strict: bool,
+    extensions: Option<Extensions>,
+    // set when invalid opcodes are logged and skipped rather than stopping
+    // the machine
+    opcode_log: Option<InvalidOpcodeLog>
+}
+
+// what FX0A does once the key wait timeout runs out
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum KeyWaitTimeout {
+    // act as if this key was pressed and released
+    Press(u8),
+    // stop waiting and halt the machine
+    Abort
+}
+
+// opcodes beyond chip-8 a rom can be given, see spec::EXTENSION_OPCODES
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Extensions {
+    // FXF1 reads a millisecond clock, for smoother animation than DT allows
+    Rust8
+}
+
+impl Extensions {
+    pub fn parse(text: &str) -> Option<Extensions> {
+        match text {
+            "rust8" => Some(Extensions::Rust8),
+            _ => None
+        }
+    }
+}
 
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
+    }
 }
 
 impl Cpu {
     pub fn new() -> Cpu {
         Cpu {
-            reg_gpr: [0; 16],
+            reg_gpr: [0; NUM_GPR],
             reg_i: 0,
             reg_pc: PROGRAM_START_ADDR,
             sp: 0,
-            stack: [0; 16],
+            stack: [0; STACK_DEPTH],
             reg_dt: 0,
             reg_st: 0,
-            gfx: [PX_OFF; 64 * 32],
-            keys: [0; 16]
+            gfx: Frame::new(WIDTH, HEIGHT),
+            keys: [0; NUM_KEYS],
+            waiting_key: None,
+            key_wait_timeout: None,
+            key_wait_frames: None,
+            last_pc: None,
+            halt: None,
+            frames: 0,
+            cycles: 0,
+            tick_cycles: 0,
+            last_frame_cycles: 0,
+            journal: None,
+            rng: Box::new(ThreadRng),
+            quirks: Quirks::default(),
+            strict: false,
+            extensions: None,
+            opcode_log: None
         }
     }
 
@@ -64,12 +174,28 @@ impl Cpu {
         self.reg_st
     }
 
+    pub fn reg_gpr(&self) -> [u8; NUM_GPR] {
+        self.reg_gpr
+    }
+
+    pub fn reg_i(&self) -> u16 {
+        self.reg_i
+    }
+
     pub fn reg_pc(&self) -> u16 {
         self.reg_pc
     }
 
-    pub fn gfx(&self) -> [u32; 2048] {
-        self.gfx
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn gfx(&self) -> &Frame {
+        &self.gfx
+    }
+
+    pub fn keys(&self) -> [u8; 16] {
+        self.keys
     }
 
     pub fn set_reg_dt(&mut self, reg_dt: u8) {
@@ -79,6 +205,281 @@ impl Cpu {
         self.reg_st = reg_st;
     }
 
+    // e.g. a SeededRng so a run can be repeated exactly
+    pub fn set_rng(&mut self, rng: Box<dyn RngSource>) {
+        self.rng = rng;
+    }
+
+    // where RND would carry on from, if it can be repeated, see RngSource
+    pub fn rng_seed(&self) -> Option<u64> {
+        self.rng.seed()
+    }
+
+    // the key FX0A saw go down and waits to see released. saved states
+    // leave it out
+    pub fn waiting_key(&self) -> Option<u8> {
+        self.waiting_key
+    }
+
+    pub fn set_waiting_key(&mut self, key: Option<u8>) {
+        self.waiting_key = key;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // strict mode stops on what other interpreters may do differently:
+    // instructions only matching an opcode loosely (5XY1, 0NNN), writes
+    // below PROGRAM_START_ADDR and key checks on values past F. pair it
+    // with MemoryPolicy::Checked for accesses past the end of ram
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn set_extensions(&mut self, extensions: Option<Extensions>) {
+        self.extensions = extensions;
+    }
+
+    pub fn extensions(&self) -> Option<Extensions> {
+        self.extensions
+    }
+
+    // strict mode stops on invalid opcodes whatever the policy
+    pub fn set_invalid_opcode_policy(&mut self, policy: InvalidOpcodePolicy) {
+        self.opcode_log = match policy {
+            InvalidOpcodePolicy::Stop => None,
+            InvalidOpcodePolicy::Skip => Some(InvalidOpcodeLog::new())
+        };
+    }
+
+    pub fn invalid_opcode_policy(&self) -> InvalidOpcodePolicy {
+        match self.opcode_log {
+            None => InvalidOpcodePolicy::Stop,
+            Some(_) => InvalidOpcodePolicy::Skip
+        }
+    }
+
+    // the log lines for invalid opcodes skipped since the last call, call
+    // once a frame
+    pub fn take_opcode_log(&mut self) -> Vec<String> {
+        let frames = self.frames;
+        self.opcode_log.as_mut().map(|log| log.take(frames)).unwrap_or_default()
+    }
+
+    // the log lines left once the machine stops running
+    pub fn finish_opcode_log(&mut self) -> Vec<String> {
+        self.opcode_log.as_mut().map(InvalidOpcodeLog::finish).unwrap_or_default()
+    }
+
+    pub fn set_key_wait_timeout(&mut self, frames: u32, action: KeyWaitTimeout) {
+        self.key_wait_timeout = Some((frames, action));
+    }
+
+    pub fn clear_key_wait_timeout(&mut self) {
+        self.key_wait_timeout = None;
+    }
+
+    pub fn key_wait_timeout(&self) -> Option<(u32, KeyWaitTimeout)> {
+        self.key_wait_timeout
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // milliseconds since boot as the program sees them: the whole frames
+    // run, plus the part of this one its instructions so far make up, going
+    // by how many the last frame ran. tied to instructions rather than the
+    // host clock so a replay reads the same times
+    pub fn millis(&self) -> u64 {
+        let per_frame = self.last_frame_cycles.max(1);
+        let into_frame = (self.cycles - self.tick_cycles).min(per_frame);
+        (self.frames * 1000 + into_frame * 1000 / per_frame) / spec::TIMER_HZ as u64
+    }
+
+    // starts recording ram writes, see journal
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    // the writes recorded since the last clear_journal, oldest first
+    pub fn journal(&self) -> &[RamWrite] {
+        self.journal.as_deref().unwrap_or(&[])
+    }
+
+    // forgets the recorded writes, e.g. at the start of every frame
+    pub fn clear_journal(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+    }
+
+    // true when PC is at the top of a loop that only polls the delay timer,
+    //   FX07      LD VX, DT
+    //   3X00      SE VX, 00
+    //   1NNN      JP back to the FX07
+    // and DT hasn't run out yet. nothing the loop does can be seen until
+    // the next timer tick, so a caller may skip straight to it
+    pub fn waiting_on_dt(&self, ram: &Ram) -> bool {
+        let memory = ram.memory();
+        let pc = self.reg_pc as usize;
+        if self.reg_dt == 0 || pc + 5 >= memory.len() {
+            return false;
+        }
+        let word = |addr: usize| (memory[addr] as u16) << 8 | memory[addr + 1] as u16;
+        let (load, skip, jump) = (word(pc), word(pc + 2), word(pc + 4));
+
+        let x = load & 0x0F00;
+        load & 0xF0FF == spec::OP_LD_DT
+            && skip == spec::OP_SE_NN | x
+            && jump == spec::OP_JP | self.reg_pc
+    }
+
+    // the reason the machine stopped, if it has
+    pub fn halted(&self) -> Option<&str> {
+        self.halt.as_deref()
+    }
+
+    // counts both timers down once, the buzzer sounds while ST is non-zero
+    pub fn tick_timers(&mut self, audio: &mut dyn AudioSink) {
+        self.frames += 1;
+        self.last_frame_cycles = self.cycles - self.tick_cycles;
+        self.tick_cycles = self.cycles;
+        if let Some(frames) = &mut self.key_wait_frames {
+            *frames += 1;
+        }
+        if self.reg_dt > 0 {
+            self.reg_dt -= 1;
+        }
+        if self.reg_st > 0 {
+            audio.start_beep();
+            self.reg_st -= 1;
+        } else {
+            audio.stop_beep();
+        }
+    }
+
+    pub fn save_state(&self, ram: &Ram) -> State {
+        State {
+            version: STATE_VERSION,
+            v: self.reg_gpr,
+            i: self.reg_i,
+            pc: self.reg_pc,
+            sp: self.sp,
+            stack: self.stack,
+            dt: self.reg_dt,
+            st: self.reg_st,
+            keys: self.keys,
+            display: state::encode_display(self.gfx.pixels()),
+            ram: state::encode_ram(ram.memory())
+        }
+    }
+
+    pub fn load_state(&mut self, ram: &mut Ram, state: &State) -> Result<(), String> {
+        // decode everything up front so a bad state leaves the machine untouched
+        let pixels = state.display_pixels()?;
+        let memory = state.ram_bytes()?;
+
+        self.reg_gpr = state.v;
+        self.reg_i = state.i;
+        self.reg_pc = state.pc;
+        self.sp = state.sp;
+        self.stack = state.stack;
+        self.reg_dt = state.dt;
+        self.reg_st = state.st;
+        self.keys = state.keys;
+        self.waiting_key = None;
+        self.key_wait_frames = None;
+        self.last_pc = None;
+        self.halt = None;
+        self.clear_journal();
+        for (index, &on) in pixels.iter().enumerate() {
+            self.gfx.set(index % WIDTH, index / WIDTH, on);
+        }
+        ram.load_memory(&memory);
+
+        Ok(())
+    }
+
+    // the registers together with the code around PC, see MachineDump
+    pub fn dump<'a>(&'a self, ram: &'a Ram) -> MachineDump<'a> {
+        MachineDump {
+            cpu: self,
+            ram
+        }
+    }
+
+    fn null_opcode_report(&self, ram: &Ram) -> String {
+        let from = match self.last_pc {
+            Some(last_pc) => {
+                // read the way it was fetched, through the memory policy,
+                // but without showing up in a trace
+                let byte = |addr: u16| ram.resolve(addr).map_or(0, |addr| ram.memory()[addr as usize]);
+                let last = (byte(last_pc) as u16) << 8 | byte(last_pc.wrapping_add(1)) as u16;
+                format!("reached from {:04X} ({})", last_pc, spec::disassemble(last))
+            },
+            None => String::from("as the first instruction")
+        };
+        format!("Ran off the program: null opcode 0000 at {:04X}, {}", self.reg_pc, from)
+    }
+
+    // on to the next instruction. PC is 12 bits, so past FFE it comes
+    // round to the start of memory whatever the memory policy
+    fn advance_pc(&mut self) {
+        self.reg_pc = self.reg_pc.wrapping_add(2) & 0x0FFF;
+    }
+
+    // program reads from ram go through here, for the out of bounds error
+    fn read_byte(&self, ram: &mut Ram, addr: u16) -> Result<u8, Chip8Error> {
+        ram.read_byte(addr).ok_or(Chip8Error::OutOfBoundsAccess { addr: addr as usize, pc: self.reg_pc })
+    }
+
+    // program writes to ram go through here so they can be journaled
+    fn write_byte(&mut self, ram: &mut Ram, addr: u16, value: u8) -> Result<(), Chip8Error> {
+        let addr = ram.resolve(addr).ok_or(Chip8Error::OutOfBoundsAccess { addr: addr as usize, pc: self.reg_pc })?;
+        if self.strict && addr < PROGRAM_START_ADDR {
+            return Err(Chip8Error::ReservedWrite { addr, pc: self.reg_pc });
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.push(RamWrite {
+                addr,
+                old: ram.memory()[addr as usize],
+                new: value,
+                pc: self.reg_pc
+            });
+        }
+        ram.write_byte(addr, value);
+        Ok(())
+    }
+
+    // only the low nibble of a key counts, strict mode wants nothing else set
+    fn key_index(&self, key: u8) -> Result<usize, Chip8Error> {
+        if self.strict && key as usize >= NUM_KEYS {
+            return Err(Chip8Error::InvalidKey { key, pc: self.reg_pc });
+        }
+        Ok((key & 0x0F) as usize)
+    }
+
+    // the VIP's 8XY1, 8XY2 and 8XY3 leave VF cleared
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.reg_gpr[0xF] = 0;
+        }
+    }
+
     pub fn get_reg_vx(&self, opcode: u16) -> u8 {
         return self.reg_gpr[((opcode & 0x0F00) >> 8) as usize];
     }
@@ -95,39 +496,73 @@ impl Cpu {
         self.reg_gpr[((opcode & 0x00F0) >> 4) as usize] = value;
     }
 
-    pub fn handle_keypress(&mut self, window: &Window) -> [u8; 16] {
+    // reads the keypad fresh from input
+    pub fn handle_keypress(&mut self, input: &mut dyn InputSource) -> [u8; 16] {
         self.keys = [0; 16];
-        window.get_keys().map(|keys_received| {
-            for k in keys_received {
-                match k {
-                    Key::Key1 => self.keys[0x1] = 1,
-                    Key::Key2 => self.keys[0x2] = 1,
-                    Key::Key3 => self.keys[0x3] = 1,
-                    Key::Key4 => self.keys[0xC] = 1,
-                    Key::Q => self.keys[0x4] = 1,
-                    Key::W => self.keys[0x5] = 1,
-                    Key::E => self.keys[0x6] = 1,
-                    Key::R => self.keys[0xD] = 1,
-                    Key::A => self.keys[0x7] = 1,
-                    Key::S => self.keys[0x8] = 1,
-                    Key::D => self.keys[0x9] = 1,
-                    Key::F => self.keys[0xE] = 1,
-                    Key::Z => self.keys[0xA] = 1,
-                    Key::X => self.keys[0x0] = 1,
-                    Key::C => self.keys[0xB] = 1,
-                    Key::V => self.keys[0xF] = 1,
-                    _ => () // noop
+        input.poll(&mut self.keys);
+        self.keys
+    }
+
+    // presses or releases one key directly, for frontends that get key
+    // events rather than something to poll. the key stays as set until the
+    // next handle_keypress
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if key < NUM_KEYS {
+            self.keys[key] = pressed as u8;
+        }
+    }
+
+    // adds the keys held on another source on top of handle_keypress
+    pub fn merge_keypress(&mut self, input: &mut dyn InputSource) -> [u8; 16] {
+        input.poll(&mut self.keys);
+        self.keys
+    }
+
+    // runs the instruction at PC. an error also halts the machine, with the
+    // error as the reason, except invalid opcodes being skipped, see
+    // set_invalid_opcode_policy
+    pub fn run_instruction(&mut self, ram: &mut Ram) -> Result<(), Chip8Error> {
+        let result = self.execute(ram);
+        if let Err(Chip8Error::InvalidOpcode { opcode, pc }) = result {
+            if self.opcode_log.is_some() && !self.strict {
+                let entry = InvalidOpcode::new(opcode, pc, ram, self.quirks, self.frames);
+                if let Some(log) = &mut self.opcode_log {
+                    log.record(&entry);
                 }
+                self.reg_pc = pc;
+                self.advance_pc();
+                return Ok(());
             }
-        });
-        self.keys
+        }
+        if let Err(e) = &result {
+            self.halt = Some(e.to_string());
+        }
+        result
     }
 
-    pub fn run_instruction(&mut self, ram: &mut Ram) {
+    fn execute(&mut self, ram: &mut Ram) -> Result<(), Chip8Error> {
+        if self.halt.is_some() {
+            return Ok(());
+        }
         // fetch opcode Big Endian
-        let hi = ram.read_byte(self.reg_pc) as u16;
-        let lo = ram.read_byte(self.reg_pc+1) as u16;
+        let hi = self.read_byte(ram, self.reg_pc)? as u16;
+        let lo = self.read_byte(ram, self.reg_pc.wrapping_add(1))? as u16;
         let instruction: u16 = (hi << 8) | lo;
+
+        // a zero word is empty memory, not a real instruction. the program
+        // jumped or fell off its end, so stop instead of clearing the screen
+        // over and over
+        if instruction == 0x0000 {
+            self.halt = Some(self.null_opcode_report(ram));
+            return Ok(());
+        }
+        let extension = self.extensions.and(spec::extension_opcode_of(instruction));
+        if self.strict && spec::opcode_of(instruction).is_none() && extension.is_none() {
+            return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc });
+        }
+        self.last_pc = Some(self.reg_pc);
+        self.cycles += 1;
+
         // decode and execute the opcode
         let reg_vx = self.get_reg_vx(instruction);
         let reg_vy = self.get_reg_vy(instruction);
@@ -139,19 +574,20 @@ impl Cpu {
             0x0000 => match instruction & 0x000F {
                 0x0000 => {
                     // 0x00E0: clear screen
-                    for index in 0..2048 {
-                        self.gfx[index] = PX_OFF;
-                    }
-                    self.reg_pc += 2;
+                    self.gfx.clear();
+                    self.advance_pc();
                 },
                 0x000E => {
                     // 0x00EE: return from subroutine
                     // restores program counter and then removes stack address
+                    if self.sp == 0 {
+                        return Err(Chip8Error::StackUnderflow { opcode: instruction, pc: self.reg_pc });
+                    }
                     self.sp -= 1;
-                    self.reg_pc = self.stack[self.sp as usize] as u16;
-                    self.reg_pc += 2;
+                    self.reg_pc = self.stack[self.sp as usize];
+                    self.advance_pc();
                 },
-                _ => println!("Invalid opcode {}", instruction)
+                _ => return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc })
             },
             0x1000 => {
                 // 0x1NNN: jumps to address NNN
@@ -159,62 +595,68 @@ impl Cpu {
             },
             0x2000 => {
                 // 0x2NNN: calls subroutine at NNN
-                self.stack[self.sp as usize] = self.reg_pc as u8;
+                if self.sp as usize >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { opcode: instruction, pc: self.reg_pc });
+                }
+                self.stack[self.sp as usize] = self.reg_pc;
                 self.sp += 1;
                 self.reg_pc = instruction & 0x0FFF;
             },
             0x3000 => {
                 // 0x3XNN: skips the next instruction if VX === NNN
                 if reg_vx == nn {
-                    self.reg_pc += 2;
+                    self.advance_pc();
                 }
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0x4000 => {
                 // 0x4XNN: skips the next instruction if VX !== NNN
                 if reg_vx != nn {
-                    self.reg_pc += 2;
+                    self.advance_pc();
                 }
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0x5000 => {
                 // 0x5XY0: skips the next instruction if VX === VY
                 if reg_vx == reg_vy {
-                    self.reg_pc += 2;
+                    self.advance_pc();
                 }
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0x6000 => {
                 // 0x6XNN: sets VX to NN
                 self.set_reg_vx(instruction, nn);
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0x7000 => {
                 // 0x7XNN: Adds NN to VX (carry flag is not changed)
                 self.set_reg_vx(instruction, reg_vx.wrapping_add(nn));
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0x8000 => {
                 match instruction & 0x000F {
                     0x0000 => {
                         // 0x8XY0: sets VX = VY
                         self.set_reg_vx(instruction, reg_vy);
-                        self.reg_pc += 2;
+                        self.advance_pc();
                     },
                     0x0001 => {
                         // 0x8XY1: bitwise OR -> VX | VY, store in VX
                         self.set_reg_vx(instruction, reg_vx | reg_vy);
-                        self.reg_pc += 2;
+                        self.reset_vf_after_logic();
+                        self.advance_pc();
                     },
                     0x0002 => {
                         // 0x8XY2: bitwise AND -> VX & VY
                         self.set_reg_vx(instruction, reg_vx & reg_vy);
-                        self.reg_pc += 2;
+                        self.reset_vf_after_logic();
+                        self.advance_pc();
                     },
                     0x0003 => {
                         // 0x8XY3: XOR -> VX XOR VY
                         self.set_reg_vx(instruction, reg_vx ^ reg_vy);
-                        self.reg_pc += 2;
+                        self.reset_vf_after_logic();
+                        self.advance_pc();
                     },
                     0x0004 => {
                         // 0x8XY4: adds VY to VX. VF is set to 1 when there's a carry
@@ -225,7 +667,7 @@ impl Cpu {
                             self.reg_gpr[0xF] = 0;
                         }
                         self.set_reg_vx(instruction, reg_vx.wrapping_add(reg_vy));
-                        self.reg_pc += 2;
+                        self.advance_pc();
 
                     },
                     0x0005 => {
@@ -236,14 +678,16 @@ impl Cpu {
                         } else {
                             self.reg_gpr[0xF] = 0;
                         }
-                        self.set_reg_vx(instruction, reg_vx - reg_vy);
-                        self.reg_pc += 2;
+                        self.set_reg_vx(instruction, reg_vx.wrapping_sub(reg_vy));
+                        self.advance_pc();
                     },
                     0x0006 => {
-                        // 0x8XY6: stores the LSB of VX in VF and then shifts VX to the right by 1
-                        self.reg_gpr[0xF] = reg_vx & 1;
-                        self.set_reg_vx(instruction, reg_vx >> 1);
-                        self.reg_pc += 2;
+                        // 0x8XY6: stores the LSB of VX in VF and then shifts VX to the right by 1,
+                        // with the shift-vy quirk VY shifted into VX
+                        let value = if self.quirks.shift_vy { reg_vy } else { reg_vx };
+                        self.reg_gpr[0xF] = value & 1;
+                        self.set_reg_vx(instruction, value >> 1);
+                        self.advance_pc();
                     },
                     0x0007 => {
                         // 0x8XY7: sets VX to VY minus VX.
@@ -253,41 +697,44 @@ impl Cpu {
                         } else {
                             self.reg_gpr[0xF] = 0;
                         }
-                        self.set_reg_vy(instruction, reg_vy - reg_vx);
-                        self.reg_pc += 2;
+                        self.set_reg_vx(instruction, reg_vy.wrapping_sub(reg_vx));
+                        self.advance_pc();
                     },
                     0x000E => {
-                        // 0x8XYE: stores the MSB of VX in VF and then shifts VX to the left by 1
-                        self.reg_gpr[0xF] = (reg_vx >> 3) & 1;
-                        self.set_reg_vx(instruction, reg_vx << 1);
-                        self.reg_pc += 2;
+                        // 0x8XYE: stores the MSB of VX in VF and then shifts VX to the left by 1,
+                        // with the shift-vy quirk VY shifted into VX
+                        let value = if self.quirks.shift_vy { reg_vy } else { reg_vx };
+                        self.reg_gpr[0xF] = (value >> 7) & 1;
+                        self.set_reg_vx(instruction, value << 1);
+                        self.advance_pc();
                     },
-                    _ => println!("Invalid opcode {}", instruction)
+                    _ => return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc })
                 }
             },
             0x9000 => {
                 // 0x9XY0: skips the next instruction if VX doesn't equal VY
                 if reg_vx != reg_vy {
-                    self.reg_pc += 2;
+                    self.advance_pc();
                 }
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0xA000 => {
                 // 0xANNN: sets I to the address NNN
                 self.reg_i = nnn;
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0xB000 => {
-                // 0xBNNN: jumps to the address NNN plus V0
-                self.reg_pc = nnn + reg_v0;
+                // 0xBNNN: jumps to the address NNN plus V0, or with the
+                // jump-vx quirk to XNN plus VX
+                let offset = if self.quirks.jump_vx { reg_vx as u16 } else { reg_v0 };
+                self.reg_pc = nnn + offset;
             },
             0xC000 => {
                 // 0xCXNN: sets VX to the result of a bitwise AND operation
                 // on a random number (Typically: 0 to 255) and NN
-                let mut rng = rand::thread_rng();
-                let rand_num: u8 = rng.gen();
+                let rand_num = self.rng.next_byte();
                 self.set_reg_vx(instruction, rand_num & nn as u8);
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0xD000 => {
                 // 0xDXYN: draws a sprite at coordinate (VX, VY), has a width of 8 pixels and
@@ -302,118 +749,279 @@ impl Cpu {
                 for y_line in 0..height {
                     // get one byte of sprite data from the mem address in the i register
                     // pixel = ram.memory[(self.reg_i + y_line as u16) as usize];
-                    pixel = ram.read_byte(self.reg_i + y_line as u16);
+                    pixel = self.read_byte(ram, self.reg_i.wrapping_add(y_line as u16))?;
                     for x_line in 0..8 {
                         if (pixel & (0x0080 >> x_line)) != 0 {
-                            let pos_x: u32 = (x as u32 + x_line as u32) % WIDTH as u32;
-                            let pos_y: u32 = (y as u32 + y_line as u32) % HEIGHT as u32;
-                            if self.gfx[(pos_x + (pos_y * 64 as u32)) as usize] == PX_ON {
+                            // the sprite's position always wraps, clipping only
+                            // cuts off the part hanging over the edge
+                            let pos_x = x as usize % WIDTH + x_line as usize;
+                            let pos_y = y as usize % HEIGHT + y_line as usize;
+                            if self.quirks.clip_sprites && (pos_x >= WIDTH || pos_y >= HEIGHT) {
+                                continue;
+                            }
+                            let pos_x = pos_x % WIDTH;
+                            let pos_y = pos_y % HEIGHT;
+                            if self.gfx.toggle(pos_x, pos_y) {
                                 self.reg_gpr[0xF] = 1;
                             }
-                            self.gfx[(pos_x + (pos_y * 64 as u32)) as usize] ^= PX_ON;
                         }
                     }
                 }
-                self.reg_pc += 2;
+                self.advance_pc();
             },
             0xE000 => {
                 match instruction & 0x000F {
                     0x000E => {
                         // 0xEX9E: skips the next instruction if the key stored in VX is pressed
-                        if self.keys[reg_vx as usize] != 0 {
-                            self.reg_pc += 2;
+                        if self.keys[self.key_index(reg_vx)?] != 0 {
+                            self.advance_pc();
                         }
-                        self.reg_pc += 2;
+                        self.advance_pc();
                     },
                     0x0001 => {
                         // 0xEXA1: skips the next instruction if the key stored in VX isn't pressed
-                        if self.keys[reg_vx as usize] == 0 {
-                            self.reg_pc += 2;
+                        if self.keys[self.key_index(reg_vx)?] == 0 {
+                            self.advance_pc();
                         }
-                        self.reg_pc += 2;
+                        self.advance_pc();
                     },
-                    _ => println!("Invalid opcode! {:#X}", instruction)
+                    _ => return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc })
                 }
             },
             0xF000 => {
                 match instruction & 0x000F {
+                    0x0001 if extension == Some(spec::OP_LD_MS) => {
+                        // 0xFXF1 (rust8 extension): VX = low byte of the
+                        // millisecond clock, see millis
+                        self.set_reg_vx(instruction, self.millis() as u8);
+                        self.advance_pc();
+                    },
                     0x0007 => {
                         // 0xFX07: the value of DT is placed in VX
                         self.set_reg_vx(instruction, self.reg_dt);
-                        self.reg_pc += 2;
+                        self.advance_pc();
                     },
                     0x000A => {
                         // 0xFX0A: wait for key press, store the value of key into VX
-                        // keypad logic
-                        let mut key_pressed = false;
-                        for i in 0..self.keys.len() {
-                            if self.keys[i] != 0 {
-                                key_pressed = true;
-                                self.set_reg_vx(instruction, i as u8);
-                                break;
+                        // like the original interpreter the key has to be released
+                        // again, otherwise one press would satisfy several FX0A
+                        let waited = *self.key_wait_frames.get_or_insert(0);
+                        if let Some((frames, action)) = self.key_wait_timeout {
+                            if waited >= frames {
+                                match action {
+                                    KeyWaitTimeout::Press(key) => {
+                                        self.set_reg_vx(instruction, key);
+                                        self.waiting_key = None;
+                                        self.key_wait_frames = None;
+                                        self.advance_pc();
+                                    },
+                                    KeyWaitTimeout::Abort => {
+                                        self.halt = Some(format!("FX0A at {:04X} got no key within {} frames", self.reg_pc, frames));
+                                    }
+                                }
+                                return Ok(());
                             }
                         }
-
-                        // if no key is pressed, try again
-                        if !key_pressed {
-                            return;
+                        match self.waiting_key {
+                            None => {
+                                if let Some(key) = self.keys.iter().position(|&k| k != 0) {
+                                    self.waiting_key = Some(key as u8);
+                                }
+                            },
+                            Some(key) => {
+                                if self.keys[key as usize] == 0 {
+                                    self.set_reg_vx(instruction, key);
+                                    self.waiting_key = None;
+                                    self.key_wait_frames = None;
+                                    self.advance_pc();
+                                }
+                            }
                         }
-
-                        self.reg_pc += 2;
                     },
                     0x0005 => {
                         match instruction & 0x00FF {
                             0x0015 => {
                                 // 0xFX15: set DT to VX
                                 self.reg_dt = reg_vx;
-                                self.reg_pc += 2;
+                                self.advance_pc();
                             },
                             0x0055 => {
                                 // 0xFX55: store registers V0 -> VX in memory starting at
                                 // location I
                                 let x = (instruction & 0x0F00) >> 8;
                                 for index in 0..=x {
-                                    ram.write_byte(self.reg_i + index, self.reg_gpr[index as usize]);
+                                    self.write_byte(ram, self.reg_i.wrapping_add(index), self.reg_gpr[index as usize])?;
+                                }
+                                if self.quirks.load_store_increment {
+                                    self.reg_i = self.reg_i.wrapping_add(x + 1);
                                 }
+                                self.advance_pc();
                             },
                             0x0065 => {
                                 // 0xFX65: read registers V0 -> Vx from memory starting at
                                 // location I
                                 let x = (instruction & 0x0F00) >> 8;
                                 for index in 0..=x {
-                                    self.set_reg_vx(instruction, ram.read_byte(self.reg_i + index));
+                                    self.reg_gpr[index as usize] = self.read_byte(ram, self.reg_i.wrapping_add(index))?;
+                                }
+                                if self.quirks.load_store_increment {
+                                    self.reg_i = self.reg_i.wrapping_add(x + 1);
                                 }
+                                self.advance_pc();
                             },
-                            _ => println!("Invalid opcode! {:#X}", instruction)
+                            _ => return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc })
                         }
                     },
                     0x0008 => {
                         // 0xFX18: set ST to VX
                         self.reg_st = reg_vx;
-                        self.reg_pc += 2;
+                        self.advance_pc();
                     },
                     0x000E => {
                         // 0xFX1E: set I = I + VX
-                        self.reg_i += reg_vx as u16;
-                        self.reg_pc += 2;
+                        self.reg_i = self.reg_i.wrapping_add(reg_vx as u16);
+                        self.advance_pc();
                     },
                     0x0009 => {
                         // 0xFX29: set I = location of sprite for digit VX
-                        self.reg_i = self.gfx[reg_vx as usize] as u16;
-                        self.reg_pc += 2;
+                        self.reg_i = spec::FONT_BASE + (reg_vx & 0x0F) as u16 * spec::FONT_SPRITE_BYTES as u16;
+                        self.advance_pc();
                     },
                     0x0003 => {
                         // 0xFX33: store BCD representation of VX in memory locations
                         // I, I+1, I+2
-                        ram.write_byte(self.reg_i, reg_vx / 100);
-                        ram.write_byte(self.reg_i + 1, (reg_vx / 10) % 10);
-                        ram.write_byte(self.reg_i + 2, (reg_vx % 100) % 10);
-                        self.reg_pc += 2;
+                        self.write_byte(ram, self.reg_i, reg_vx / 100)?;
+                        self.write_byte(ram, self.reg_i.wrapping_add(1), (reg_vx / 10) % 10)?;
+                        self.write_byte(ram, self.reg_i.wrapping_add(2), (reg_vx % 100) % 10)?;
+                        self.advance_pc();
                     },
-                    _ => println!("Invalid opcode! {:#X}", instruction)
+                    _ => return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc })
                 }
             }
-            _ => println!("Invalid opcode! {:#X}", instruction)
+            _ => return Err(Chip8Error::InvalidOpcode { opcode: instruction, pc: self.reg_pc })
         }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+// one line of scalar registers, the Vx registers in two rows of eight, then
+// the live part of the stack and the held keys
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PC {:04X}  I {:04X}  SP {:X}  DT {:02X}  ST {:02X}",
+            self.reg_pc, self.reg_i, self.sp, self.reg_dt, self.reg_st)?;
+        for (half, regs) in self.reg_gpr.chunks(8).enumerate() {
+            let cells: Vec<String> = regs.iter().enumerate()
+                .map(|(i, v)| format!("V{:X} {:02X}", half * 8 + i, v))
+                .collect();
+            writeln!(f, "{}", cells.join("  "))?;
+        }
+        let depth = (self.sp as usize).min(STACK_DEPTH);
+        let stack: Vec<String> = self.stack[..depth].iter().map(|v| format!("{:03X}", v)).collect();
+        writeln!(f, "stack [{}]", stack.join(" "))?;
+        let keys: String = self.keys.iter().map(|&k| if k != 0 { '#' } else { '.' }).collect();
+        write!(f, "keys  {}", keys)
+    }
+}
+
+// one write to ram, by the instruction at pc
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RamWrite {
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+    pub pc: u16
+}
+
+// e.g. "3A0 00->05 by 2F4"
+impl fmt::Display for RamWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X} {:02X}->{:02X} by {:03X}", self.addr, self.old, self.new, self.pc)
+    }
+}
+
+// how many instructions MachineDump shows on each side of PC
+const DUMP_CONTEXT: u16 = 3;
+
+// the cpu state followed by a disassembly of the code around PC, PC marked
+// with '>'. this is the one format for state in logs and error reports
+pub struct MachineDump<'a> {
+    cpu: &'a Cpu,
+    ram: &'a Ram
+}
+
+impl<'a> fmt::Display for MachineDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.cpu)?;
+        let memory = self.ram.memory();
+        let pc = self.cpu.reg_pc;
+        let start = pc.saturating_sub(2 * DUMP_CONTEXT);
+        let end = pc.saturating_add(2 * DUMP_CONTEXT + 1);
+        for addr in (start..end).step_by(2) {
+            let addr_index = addr as usize;
+            if addr_index + 1 >= memory.len() {
+                break;
+            }
+            let instruction = (memory[addr_index] as u16) << 8 | memory[addr_index + 1] as u16;
+            let marker = if addr == pc { '>' } else { ' ' };
+            write!(f, "\n{} {:04X}  {:04X}  {}", marker, addr, instruction, spec::disassemble(instruction))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Extensions;
+    use crate::chip8::Chip8;
+    use crate::error::Chip8Error;
+    use crate::spec;
+
+    #[test]
+    fn errors_are_returned_and_halt_the_machine() {
+        // 8XYF isn't an instruction
+        let mut chip8 = Chip8::with_rom(&[0x81, 0x2F]).unwrap();
+        assert_eq!(chip8.step(), Err(Chip8Error::InvalidOpcode { opcode: 0x812F, pc: 0x200 }));
+        assert_eq!(chip8.cpu.halted(), Some("Invalid opcode 812F at 0200"));
+
+        // CALL 200 over and over
+        let mut chip8 = Chip8::with_rom(&[0x22, 0x00]).unwrap();
+        let error = (0..32).find_map(|_| chip8.step().err());
+        assert_eq!(error, Some(Chip8Error::StackOverflow { opcode: 0x2200, pc: 0x200 }));
+    }
+
+    #[test]
+    fn the_stack_keeps_whole_return_addresses() {
+        // RET with nothing on the stack
+        let mut chip8 = Chip8::with_rom(&[0x00, 0xEE]).unwrap();
+        assert_eq!(chip8.step(), Err(Chip8Error::StackUnderflow { opcode: 0x00EE, pc: 0x200 }));
+
+        // returns above 0FF come back to where they were called from
+        let mut chip8 = Chip8::with_rom(&[0x13, 0x00]).unwrap();
+        chip8.ram.write_byte(0x300, 0x24);
+        chip8.ram.write_byte(0x400, 0x00);
+        chip8.ram.write_byte(0x401, 0xEE);
+        (0..3).for_each(|_| chip8.step().unwrap());
+        assert_eq!(chip8.cpu.reg_pc(), 0x302);
+    }
+
+    #[test]
+    fn the_millisecond_clock_needs_rust8_extensions() {
+        // LD V0, MS; JP 200
+        let rom = [0xF0, 0xF1, 0x12, 0x00];
+        let mut chip8 = Chip8::with_rom(&rom).unwrap();
+        assert_eq!(chip8.step(), Err(Chip8Error::InvalidOpcode { opcode: 0xF0F1, pc: 0x200 }));
+
+        let mut chip8 = Chip8::with_rom(&rom).unwrap();
+        chip8.cpu.set_extensions(Some(Extensions::Rust8));
+        chip8.cpu.set_strict(true);
+        chip8.set_instructions_per_frame(10);
+        for _ in 0..3 {
+            chip8.run_frame(&[false; 16]);
+        }
+        // 3 frames and a tenth of the next
+        chip8.step().unwrap();
+        assert_eq!(chip8.cpu.reg_gpr()[0], 51);
+        assert_eq!(spec::disassemble(0xF3F1), "LD V3, MS");
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+// how dark each effect makes the pixels it touches, out of 256
+const SCANLINE_SHADE: u32 = 160;
+const GRID_SHADE: u32 = 192;
+// how dark the corners get, out of 256, fading in from the middle
+const VIGNETTE_SHADE: f32 = 96.0;
+
+// crt style effects for the scaled up game. none of them change what the
+// rom drew, only how it looks in the window
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct CrtEffects {
+    // every other line of the window darker
+    pub scanlines: bool,
+    // a darker line between game pixels, needs a scale of 3 or more
+    pub grid: bool,
+    // darker towards the corners
+    pub vignette: bool
+}
+
+impl CrtEffects {
+    // a list of scanlines, grid and vignette, or none
+    pub fn parse(text: &str) -> Option<CrtEffects> {
+        let mut effects = CrtEffects::default();
+        if text == "none" {
+            return Some(effects);
+        }
+        for name in text.split(',') {
+            match name.trim() {
+                "scanlines" => effects.scanlines = true,
+                "grid" => effects.grid = true,
+                "vignette" => effects.vignette = true,
+                _ => return None
+            }
+        }
+        Some(effects)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CrtEffects::default()
+    }
+}
+
+// the post processing stage, run over the game area after scaling. the
+// effects are worked out once per size into a shade per window pixel, so a
+// frame only costs a multiply per pixel
+pub struct Crt {
+    effects: CrtEffects,
+    // area width and height, then the frame width and height it was for
+    size: (usize, usize, usize, usize),
+    shades: Vec<u32>
+}
+
+impl Crt {
+    pub fn new(effects: CrtEffects) -> Crt {
+        Crt {
+            effects,
+            size: (0, 0, 0, 0),
+            shades: Vec::new()
+        }
+    }
+
+    pub fn effects(&self) -> CrtEffects {
+        self.effects
+    }
+
+    // darkens the game area of pixels, a window stride pixels wide. area is
+    // x, y, width, height and resolution the size of the scaled up frame
+    pub fn apply(&mut self, pixels: &mut [u32], stride: usize, area: (usize, usize, usize, usize), resolution: (usize, usize)) {
+        let (area_x, area_y, area_w, area_h) = area;
+        let size = (area_w, area_h, resolution.0, resolution.1);
+        if size != self.size {
+            self.size = size;
+            self.shades = shades(self.effects, size);
+        }
+        for y in 0..area_h {
+            let start = area_x + (area_y + y) * stride;
+            let line = &mut pixels[start..start + area_w];
+            for (pixel, &shade) in line.iter_mut().zip(&self.shades[y * area_w..]) {
+                *pixel = darken(*pixel, shade);
+            }
+        }
+    }
+}
+
+fn shades(effects: CrtEffects, size: (usize, usize, usize, usize)) -> Vec<u32> {
+    let (area_w, area_h, frame_w, frame_h) = size;
+    let mut shades = vec![256; area_w * area_h];
+    // a grid only leaves something of the pixel with at least 3 lines of it
+    let grid = effects.grid && area_w >= frame_w * 3 && area_h >= frame_h * 3;
+    for y in 0..area_h {
+        // the last line of a game pixel is the one before the next starts
+        let row_edge = (y + 1) * frame_h / area_h != y * frame_h / area_h;
+        for x in 0..area_w {
+            let shade = &mut shades[y * area_w + x];
+            if effects.scanlines && y % 2 == 1 {
+                *shade = *shade * SCANLINE_SHADE / 256;
+            }
+            let column_edge = (x + 1) * frame_w / area_w != x * frame_w / area_w;
+            if grid && (row_edge || column_edge) {
+                *shade = *shade * GRID_SHADE / 256;
+            }
+            if effects.vignette {
+                // 0 in the middle, 1 in the corners
+                let dx = (x as f32 + 0.5) / area_w as f32 * 2.0 - 1.0;
+                let dy = (y as f32 + 0.5) / area_h as f32 * 2.0 - 1.0;
+                let distance = (dx * dx + dy * dy) / 2.0;
+                let vignette = 256.0 - VIGNETTE_SHADE * distance * distance;
+                *shade = *shade * vignette as u32 / 256;
+            }
+        }
+    }
+    shades
+}
+
+fn darken(color: u32, shade: u32) -> u32 {
+    let red = ((color >> 16) & 0xFF) * shade / 256;
+    let green = ((color >> 8) & 0xFF) * shade / 256;
+    let blue = (color & 0xFF) * shade / 256;
+    red << 16 | green << 8 | blue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Crt,
+        CrtEffects
+    };
+
+    #[test]
+    fn effects_darken_lines_and_edges() {
+        assert_eq!(CrtEffects::parse("none"), Some(CrtEffects::default()));
+        assert_eq!(CrtEffects::parse("bloom"), None);
+        let effects = CrtEffects::parse("scanlines, grid").unwrap();
+        assert!(effects.scanlines && effects.grid && !effects.vignette);
+
+        // a 2x1 frame scaled 4 times: scanlines on odd lines, the grid on
+        // the last row and column of each game pixel
+        let mut crt = Crt::new(effects);
+        let mut pixels = vec![0xFFFFFF; 8 * 4];
+        crt.apply(&mut pixels, 8, (0, 0, 8, 4), (2, 1));
+        assert_eq!(pixels[0], 0xFFFFFF);
+        assert_eq!(pixels[3], 0xBFBFBF);
+        assert_eq!(pixels[8], 0x9F9F9F);
+        assert_eq!(pixels[3 * 8], 0x777777);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::romdb;
+
+// the machine's screen as lit and unlit pixels. how they look is up to
+// whoever shows them, so the core doesn't know about host colors
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: vec![false; width * height]
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // row by row, true for a lit pixel
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+
+    pub fn lit(&self, x: usize, y: usize) -> bool {
+        self.pixels[x + y * self.width]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        self.pixels[x + y * self.width] = on;
+    }
+
+    // flips a pixel the way sprites are drawn, returns true if it was lit
+    pub fn toggle(&mut self, x: usize, y: usize) -> bool {
+        let pixel = &mut self.pixels[x + y * self.width];
+        let was_lit = *pixel;
+        *pixel = !was_lit;
+        was_lit
+    }
+
+    pub fn clear(&mut self) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = false;
+        }
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.pixels.iter().all(|&on| !on)
+    }
+
+    // the same picture always has the same sha1
+    pub fn sha1(&self) -> String {
+        let pixels: Vec<u8> = self.pixels.iter().map(|&lit| lit as u8).collect();
+        romdb::sha1(&pixels)
+    }
+}
+
+// something that shows frames to the user, a window, a terminal, a canvas
+pub trait Display {
+    fn present(&mut self, frame: &Frame);
+    // frames after this have this many pixels
+    fn set_resolution(&mut self, width: usize, height: usize);
+    // blank the game, e.g. between roms
+    fn clear(&mut self);
+}
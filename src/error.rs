@@ -0,0 +1,42 @@
+use std::fmt;
+
+// what can go wrong loading or running a rom. the machine stops on any of
+// them, frontends decide how to tell the user
+#[derive(Clone, Debug, PartialEq)]
+pub enum Chip8Error {
+    RomTooLarge { size: usize, max: usize },
+    // a CALL with every stack slot already in use
+    StackOverflow { opcode: u16, pc: u16 },
+    // a RET with nothing to return to
+    StackUnderflow { opcode: u16, pc: u16 },
+    InvalidOpcode { opcode: u16, pc: u16 },
+    OutOfBoundsAccess { addr: usize, pc: u16 },
+    // strict mode only: a write below the program, where the interpreter
+    // and font live
+    ReservedWrite { addr: u16, pc: u16 },
+    // strict mode only: EX9E or EXA1 on a VX that isn't a key
+    InvalidKey { key: u8, pc: u16 }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::RomTooLarge { size, max } => write!(f, "Rom is {} bytes, at most {} fit in memory", size, max),
+            Chip8Error::StackOverflow { opcode, pc } => write!(f, "Stack overflow: {:04X} at {:04X} with the stack full", opcode, pc),
+            Chip8Error::StackUnderflow { opcode, pc } => write!(f, "Stack underflow: {:04X} at {:04X} with the stack empty", opcode, pc),
+            Chip8Error::InvalidOpcode { opcode, pc } => write!(f, "Invalid opcode {:04X} at {:04X}", opcode, pc),
+            Chip8Error::OutOfBoundsAccess { addr, pc } => write!(f, "Out of bounds access to {:04X} at {:04X}", addr, pc),
+            Chip8Error::ReservedWrite { addr, pc } => write!(f, "Write to reserved memory {:04X} at {:04X}", addr, pc),
+            Chip8Error::InvalidKey { key, pc } => write!(f, "Key {:02X} doesn't exist, at {:04X}", key, pc)
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// most of the crate reports errors as strings
+impl From<Chip8Error> for String {
+    fn from(error: Chip8Error) -> String {
+        error.to_string()
+    }
+}
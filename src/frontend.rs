@@ -0,0 +1,682 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use rust8::chip8;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use rust8::chip8::Chip8;
+use rust8::cpu::Cpu;
+use rust8::display::Frame;
+use rust8::input::KeyLayout;
+use rust8::keymap::KeyMap;
+use rust8::profile::Profile;
+use rust8::present::Palette;
+use rust8::ram::Ram;
+use rust8::rng::SeededRng;
+use rust8::netplay::{
+    NetplayClient,
+    NetplayHost
+};
+use rust8::recording::Recording;
+use rust8::replay::{
+    Replay,
+    ReplayPlayer
+};
+use rust8::romdb;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use rust8::split;
+use rust8::screenshot::{
+    self,
+    Image
+};
+use rust8::timing::Speed;
+
+use crate::minifb_frontend;
+#[cfg(feature = "sdl")]
+use crate::sdl;
+#[cfg(feature = "tui")]
+use crate::tui;
+use crate::options::Options;
+use crate::shutdown::Shutdown;
+
+// how far - and = can take the window scale while a rom runs
+pub const MIN_RUNTIME_SCALE: usize = 2;
+pub const MAX_RUNTIME_SCALE: usize = 16;
+
+// runs past the start of a freshly loaded rom for --skip-frames and
+// --fast-boot. frames are counted in instructions, so this takes the same
+// machine time however fast the host is
+pub fn skip_boot(cpu: &mut Cpu, ram: &mut Ram, profile: &Profile, options: &Options) {
+    let (frames, until_input) = match options.boot_skip() {
+        Some(skip) => skip,
+        None => return
+    };
+    match chip8::fast_forward(cpu, ram, frames, frame_instructions(profile), until_input) {
+        Ok(skipped) => println!("Skipped {} frames", skipped),
+        Err(e) => println!("{}, stopping the fast boot", e)
+    }
+    // the first frame shown shouldn't list the skipped frames' writes
+    cpu.clear_journal();
+}
+
+// the rom's file name without its extension, for naming captures
+fn rom_name(rom_path: &str) -> String {
+    Path::new(rom_path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+// saves a screenshot taken with F12 into --screenshot-dir, named after
+// the rom
+pub fn save_screenshot(image: &Image, options: &Options, rom_path: &str) {
+    let saved = screenshot::timestamped_path(&options.screenshot_dir, &rom_name(rom_path), SystemTime::now())
+        .and_then(|path| {
+            let path = path.to_string_lossy().into_owned();
+            image.write_png(&path).map(|()| path)
+        });
+    match saved {
+        Ok(path) => println!("Saved screenshot {}", path),
+        Err(e) => println!("{}", e)
+    }
+}
+
+// shift+F12 starts a recording into --screenshot-dir, or ends the one
+// going. scale is the window's, used unless --record-scale is given
+pub fn toggle_recording(recording: &mut Option<Recording>, options: &Options, rom_path: &str, scale: usize) {
+    if let Some(video) = recording.take() {
+        finish_recording(video);
+        return;
+    }
+    let scale = options.record_scale.unwrap_or(scale);
+    match Recording::start(&options.screenshot_dir, &rom_name(rom_path), options.record_format, scale, options.record_fps, SystemTime::now()) {
+        Ok(video) => {
+            println!("Recording to {}, shift+F12 stops", video.path().display());
+            *recording = Some(video);
+        },
+        Err(e) => println!("{}", e)
+    }
+}
+
+pub fn finish_recording(video: Recording) {
+    let frames = video.frames();
+    match video.finish() {
+        Ok(path) => println!("Saved {} frames to {}", frames, path.display()),
+        Err(e) => println!("{}", e)
+    }
+}
+
+// adds the frame just shown to the recording if there is one, ending it
+// if it can't go on
+pub fn record_frame(recording: &mut Option<Recording>, frame: &Frame, palette: &Palette) {
+    let failed = match recording {
+        Some(video) => video.capture(frame, palette).err(),
+        None => None
+    };
+    if let Some(e) = failed {
+        println!("{}, stopping the recording", e);
+        if let Some(video) = recording.take() {
+            finish_recording(video);
+        }
+    }
+}
+
+// --record-replay, --replay or netplay, going on the machine running now.
+// all of them fix how frames run, see instructions_per_frame
+pub enum ReplayRun {
+    Recording {
+        replay: Replay,
+        path: String,
+        // the cpu's frame count when the replay started, so frames line up
+        // again after rewinding
+        first_frame: u64
+    },
+    Playing {
+        player: ReplayPlayer,
+        first_frame: u64,
+        // --record-replay given too, where the replay is recorded on to
+        // once it's taken over or finishes
+        rerecord: Option<String>
+    },
+    // --netplay-host, RND seeded from --seed or at random if a start
+    // finds it isn't
+    Hosting {
+        host: NetplayHost,
+        rom_sha1: String,
+        seed: Option<u64>,
+        instructions_per_frame: usize
+    },
+    // --netplay-join
+    Following(Box<NetplayClient>)
+}
+
+impl ReplayRun {
+    // frames run this many instructions while a replay goes, whatever the
+    // speed. timers tick once a frame, and keys are read once at its start
+    pub fn instructions_per_frame(&self) -> usize {
+        match self {
+            ReplayRun::Recording { replay, .. } => replay.instructions_per_frame,
+            ReplayRun::Playing { player, .. } => player.replay().instructions_per_frame,
+            ReplayRun::Hosting { instructions_per_frame, .. } => *instructions_per_frame,
+            ReplayRun::Following(client) => client.instructions_per_frame()
+        }
+    }
+
+    // a netplay client ran the last frame on a guess. it's kept from
+    // rewind, scores, achievements and recordings, which a rollback
+    // couldn't take back
+    pub fn guessing(&self) -> bool {
+        matches!(self, ReplayRun::Following(client) if client.guessing())
+    }
+
+    // what to read the keys from instead of the keyboard
+    pub fn player(&mut self) -> Option<&mut ReplayPlayer> {
+        match self {
+            ReplayRun::Playing { player, .. } => Some(player),
+            ReplayRun::Following(client) => Some(client.player()),
+            _ => None
+        }
+    }
+}
+
+// the instructions per frame to run, fixed while a replay goes
+pub fn speed(replay: Option<&ReplayRun>, profile: &Profile) -> Speed {
+    match replay {
+        Some(run) => Speed::PerFrame(run.instructions_per_frame()),
+        None => profile.speed()
+    }
+}
+
+// the profile's speed as a number of instructions, for running frames
+// without a clock to go by
+fn frame_instructions(profile: &Profile) -> usize {
+    match profile.speed() {
+        Speed::PerFrame(instructions) => instructions,
+        Speed::Unlimited => chip8::DEFAULT_INSTRUCTIONS_PER_FRAME
+    }
+}
+
+// starts --record-replay, --replay or netplay on a freshly booted
+// machine running the rom with rom_sha1. a recording keeps --seed, or
+// picks one. with both --replay and --record-replay the replay plays
+// until it's taken over, then records on
+pub fn start_replay(cpu: &mut Cpu, ram: &mut Ram, profile: &Profile, rom_sha1: &str, options: &Options) -> Option<ReplayRun> {
+    let first_frame = cpu.frames();
+    let started = if let Some(path) = &options.replay {
+        Replay::load(Path::new(path))
+            .and_then(|replay| replay.restart(cpu, ram, Some(rom_sha1)).map(|()| replay))
+            .map(|replay| {
+                println!("Playing {} frame replay {}", replay.len(), path);
+                ReplayRun::Playing {
+                    player: ReplayPlayer::new(replay),
+                    first_frame,
+                    rerecord: options.record_replay.clone()
+                }
+            })
+    } else if let Some(path) = &options.record_replay {
+        let seed = options.seed.unwrap_or_else(rand::random);
+        Replay::record_from(cpu, ram, rom_sha1, seed, frame_instructions(profile)).map(|replay| {
+            println!("Recording a replay to {} with seed {}", path, seed);
+            ReplayRun::Recording {
+                replay,
+                path: path.clone(),
+                first_frame
+            }
+        })
+    } else if let Some(addr) = &options.netplay_host {
+        NetplayHost::listen(addr).map(|mut host| {
+            host.set_spectators(options.netplay_spectators);
+            println!("Netplay host listening on {}", host.local_addr());
+            ReplayRun::Hosting {
+                host,
+                rom_sha1: rom_sha1.to_string(),
+                seed: options.seed,
+                instructions_per_frame: frame_instructions(profile)
+            }
+        })
+    } else if let Some(addr) = &options.netplay_join {
+        println!("Joining netplay host {}", addr);
+        NetplayClient::connect(addr, cpu, ram).map(|mut client| {
+            client.set_delay(options.netplay_delay);
+            client.set_rollback(options.netplay_rollback);
+            println!("Watching {}", addr);
+            ReplayRun::Following(Box::new(client))
+        })
+    } else {
+        return None;
+    };
+    started.map_err(|e| println!("{}", e)).ok()
+}
+
+// starts a netplay host's clients from the machine as it is now, every
+// one of them over or only the ones that just joined. RND only gets a seed
+// if it has none, clients can't follow it otherwise
+fn start_clients(host: &mut NetplayHost, cpu: &mut Cpu, ram: &Ram, rom_sha1: &str, seed: Option<u64>, instructions_per_frame: usize, everyone: bool) {
+    if cpu.rng_seed().is_none() {
+        cpu.set_rng(Box::new(SeededRng::new(seed.unwrap_or_else(rand::random))));
+    }
+    let started = if everyone {
+        host.start(cpu, ram, rom_sha1, instructions_per_frame)
+    } else {
+        host.start_joined(cpu, ram, rom_sha1, instructions_per_frame)
+    };
+    if let Err(e) = started {
+        println!("{}", e);
+    }
+}
+
+// false while a netplay client waits for the host's next frame. a client
+// whose host has gone hands the machine over to the keyboard
+pub fn replay_ready(run: &mut Option<ReplayRun>, cpu: &mut Cpu, ram: &mut Ram) -> bool {
+    let ready = match run {
+        Some(ReplayRun::Following(client)) => client.ready(cpu, ram),
+        _ => return true
+    };
+    ready.unwrap_or_else(|e| {
+        println!("{}, the keyboard has the machine", e);
+        *run = None;
+        true
+    })
+}
+
+// call at the end of every frame. a finished playback says whether it
+// stayed in step and hands over to the keyboard, as does a netplay client
+// that went out of step
+pub fn replay_frame(run: &mut Option<ReplayRun>, cpu: &mut Cpu, ram: &mut Ram) {
+    let ended = match run {
+        Some(ReplayRun::Recording { replay, .. }) => {
+            replay.record(cpu, ram);
+            None
+        },
+        Some(ReplayRun::Playing { player, .. }) => {
+            player.next_frame();
+            if player.finished() {
+                Some(player.replay().check(cpu, ram).map(|()| String::from("Replay finished")).unwrap_or_else(|e| e))
+            } else {
+                None
+            }
+        },
+        Some(ReplayRun::Hosting { host, rom_sha1, seed, instructions_per_frame }) => {
+            host.send_frame(cpu, ram);
+            if host.joined() {
+                start_clients(host, cpu, ram, rom_sha1, *seed, *instructions_per_frame, false);
+            }
+            None
+        },
+        Some(ReplayRun::Following(client)) => client.end_frame(cpu, ram).err(),
+        None => None
+    };
+    if let Some(message) = ended {
+        if let Some(ReplayRun::Playing { rerecord: Some(_), .. }) = run {
+            println!("{}", message);
+            take_over_replay(run);
+        } else {
+            println!("{}, the keyboard has the machine", message);
+            *run = None;
+        }
+    }
+}
+
+// takes over a replay being played: the frames after this one are
+// dropped and the keyboard records on from here to --record-replay
+pub fn take_over_replay(run: &mut Option<ReplayRun>) {
+    match run.take() {
+        Some(ReplayRun::Playing { player, first_frame, rerecord: Some(path) }) => {
+            let frame = player.frame();
+            let mut replay = player.into_replay();
+            replay.truncate(frame);
+            println!("Re-recording from frame {} to {}", frame, path);
+            *run = Some(ReplayRun::Recording {
+                replay,
+                path,
+                first_frame
+            });
+        },
+        Some(playing @ ReplayRun::Playing { .. }) => {
+            println!("Re-recording a replay needs --record-replay to save to");
+            *run = Some(playing);
+        },
+        other => *run = other
+    }
+}
+
+// after a state is saved: a replay recording or playing notes where it
+// was, so loading the state again carries the replay on from there
+pub fn replay_saved(run: &mut Option<ReplayRun>, cpu: &Cpu, ram: &Ram) {
+    match run {
+        Some(ReplayRun::Recording { replay, .. }) => replay.anchor(replay.len(), cpu, ram),
+        Some(ReplayRun::Playing { player, .. }) => {
+            let frame = player.frame();
+            player.replay_mut().anchor(frame, cpu, ram);
+        },
+        _ => ()
+    }
+}
+
+// after a state is loaded: one saved during the replay carries it on from
+// that frame, a recording dropping the frames after it. any other state is
+// a jump, see replay_jumped
+pub fn replay_loaded(run: &mut Option<ReplayRun>, cpu: &mut Cpu, ram: &mut Ram, rom_sha1: &str) {
+    let resumed = match run {
+        Some(ReplayRun::Recording { replay, first_frame, .. }) => replay.resume(cpu, ram).inspect(|&frame| {
+            replay.truncate(frame);
+            *first_frame = cpu.frames().saturating_sub(frame as u64);
+        }),
+        Some(ReplayRun::Playing { player, first_frame, .. }) => player.replay().resume(cpu, ram).inspect(|&frame| {
+            player.seek(frame);
+            *first_frame = cpu.frames().saturating_sub(frame as u64);
+        }),
+        _ => None
+    };
+    match resumed {
+        Some(frame) => println!("Replay carries on from frame {}", frame),
+        None => replay_jumped(run, cpu, ram, rom_sha1)
+    }
+}
+
+// after rewinding: a recording forgets the frames rewound and a replay
+// plays them again. a netplay host starts its clients over from here, a
+// client stops following
+pub fn replay_rewound(run: &mut Option<ReplayRun>, cpu: &mut Cpu, ram: &mut Ram, rom_sha1: &str) {
+    match run {
+        Some(ReplayRun::Recording { replay, first_frame, .. }) => replay.truncate(cpu.frames().saturating_sub(*first_frame) as usize),
+        Some(ReplayRun::Playing { player, first_frame, .. }) => player.seek(cpu.frames().saturating_sub(*first_frame) as usize),
+        _ => replay_jumped(run, cpu, ram, rom_sha1)
+    }
+}
+
+// after the machine was put somewhere else, by loading a state or another
+// rom, which is now the one with rom_sha1: replays end, a netplay host
+// starts its clients over from here and a client stops following
+pub fn replay_jumped(run: &mut Option<ReplayRun>, cpu: &mut Cpu, ram: &mut Ram, rom_sha1: &str) {
+    match run {
+        Some(ReplayRun::Hosting { host, rom_sha1: hosted, seed, instructions_per_frame }) => {
+            *hosted = rom_sha1.to_string();
+            start_clients(host, cpu, ram, rom_sha1, *seed, *instructions_per_frame, true);
+        },
+        _ => {
+            if let Some(run) = run.take() {
+                finish_replay(run);
+            }
+        }
+    }
+}
+
+// after delete starts the rom over: replays start over with it and a
+// netplay host starts its clients over too
+pub fn restart_replay(run: &mut Option<ReplayRun>, cpu: &mut Cpu, ram: &mut Ram, profile: &Profile, rom_sha1: &str, options: &Options) {
+    match run {
+        Some(ReplayRun::Recording { .. }) | Some(ReplayRun::Playing { .. }) => {
+            if let Some(run) = run.take() {
+                finish_replay(run);
+            }
+            *run = start_replay(cpu, ram, profile, rom_sha1, options);
+        },
+        _ => replay_jumped(run, cpu, ram, rom_sha1)
+    }
+}
+
+// saves a recording, when the window closes or the replay ends early
+pub fn finish_replay(run: ReplayRun) {
+    match run {
+        ReplayRun::Recording { replay, path, .. } => match replay.save(Path::new(&path)) {
+            Ok(()) => println!("Saved {} frame replay to {}", replay.len(), path),
+            Err(e) => println!("{}", e)
+        },
+        ReplayRun::Following(_) => println!("Stopped following the netplay host"),
+        ReplayRun::Playing { .. } | ReplayRun::Hosting { .. } => ()
+    }
+}
+
+// a way of showing the emulator and taking its input, picked with
+// --frontend
+pub trait Frontend {
+    // checks the options and sets up whatever run needs
+    fn init(&mut self, _options: &Options) -> Result<(), String> {
+        Ok(())
+    }
+
+    // plays until the user quits or shutdown is requested
+    fn run(&mut self, options: &Options, shutdown: &Shutdown) -> Result<(), String>;
+
+    // called last, even when init or run failed
+    fn teardown(&mut self) {}
+}
+
+pub struct Registration {
+    pub name: &'static str,
+    create: fn() -> Box<dyn Frontend>
+}
+
+// every frontend this build has, the first is the default
+pub const FRONTENDS: &[Registration] = &[
+    Registration {
+        name: "minifb",
+        create: || Box::new(MinifbFrontend)
+    },
+    #[cfg(feature = "sdl")]
+    Registration {
+        name: "sdl",
+        create: || Box::new(SdlFrontend::default())
+    },
+    #[cfg(feature = "tui")]
+    Registration {
+        name: "tui",
+        create: || Box::new(TuiFrontend::default())
+    }
+];
+
+pub fn names() -> Vec<&'static str> {
+    FRONTENDS.iter().map(|frontend| frontend.name).collect()
+}
+
+pub fn find(name: &str) -> Option<&'static Registration> {
+    FRONTENDS.iter().find(|frontend| frontend.name == name)
+}
+
+// the whole life of the frontend called name
+pub fn run(name: &str, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+    let registration = find(name)
+        .ok_or_else(|| format!("Could not find frontend {}, this build has {}", name, names().join(", ")))?;
+    run_frontend((registration.create)().as_mut(), options, shutdown)
+}
+
+// init then run, tearing down afterwards even when either failed, since
+// init may have set up part of what teardown puts back
+fn run_frontend(frontend: &mut dyn Frontend, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+    let result = frontend.init(options).and_then(|()| frontend.run(options, shutdown));
+    frontend.teardown();
+    result
+}
+
+// the settings given on the command line, with the profile of the rom with
+// this sha1 and then the rom database filling in the rest. a broken profile
+// is reported and left out
+pub fn profile(options: &Options, sha1: &str) -> Profile {
+    let saved = Profile::path(sha1).map_or_else(|| Ok(Profile::default()), |path| Profile::load(&path));
+    let saved = saved.unwrap_or_else(|e| {
+        println!("{}", e);
+        Profile::default()
+    });
+    options.profile().or(saved).or(Profile::known(sha1))
+}
+
+// the window title, naming the rom when the rom database knows it
+pub fn caption(base: &str, sha1: &str) -> String {
+    match romdb::lookup(sha1) {
+        Some(info) => format!("{} - {}", base, romdb::describe(info)),
+        None => base.to_string()
+    }
+}
+
+// the profile's keymap preset if it has one, otherwise the layout the rom
+// database says the rom uses
+fn key_preset(rom_path: &str, profile: &Profile) -> KeyMap {
+    if let Some(name) = &profile.keymap {
+        match KeyMap::preset(name) {
+            Some(map) => return map,
+            None => println!("No keymap preset {}, using the rom's usual layout", name)
+        }
+    }
+    let layout = std::fs::read(rom_path).ok()
+        .and_then(|data| romdb::lookup(&romdb::sha1(&data)))
+        .map(|info| info.layout)
+        .unwrap_or(KeyLayout::Standard);
+    KeyMap::for_layout(layout)
+}
+
+// the preset's keys with the user's keymap files on top. a broken file is
+// reported and the preset's keys used as they are
+pub fn key_map(rom_path: &str, profile: &Profile) -> KeyMap {
+    let preset = key_preset(rom_path, profile);
+    let sha1 = std::fs::read(rom_path).map(|data| romdb::sha1(&data)).unwrap_or_default();
+    KeyMap::load(preset.clone(), &KeyMap::paths(&sha1)).unwrap_or_else(|e| {
+        println!("{}", e);
+        preset
+    })
+}
+
+// what changes with the rom besides the machine
+#[cfg(any(feature = "sdl", feature = "tui"))]
+pub struct LoadedRom {
+    pub sha1: String,
+    pub profile: Profile,
+    pub keymap: KeyMap,
+    pub caption: String
+}
+
+// resets chip8 with the rom at rom_path, or with its program'th program,
+// set up by the options and the rom's profile. on error chip8 keeps running
+// what it had. for the frontends that play a single rom
+#[cfg(any(feature = "sdl", feature = "tui"))]
+pub fn load(chip8: &mut Chip8, rom_path: &str, program: Option<usize>, options: &Options) -> Result<LoadedRom, String> {
+    let mut rom = std::fs::read(rom_path).map_err(|e| format!("Could not read {}: {}", rom_path, e))?;
+    let sha1 = romdb::sha1(&rom);
+    if let Some(program) = program {
+        rom = split::select(&rom, program)?;
+    }
+    chip8.reset(&rom)?;
+    chip8.ram.set_policy(options.memory_policy());
+    chip8.cpu.set_strict(options.strict);
+    chip8.cpu.set_extensions(options.extensions);
+    chip8.cpu.set_invalid_opcode_policy(options.invalid_opcodes);
+    if let Some(frames) = options.key_wait_timeout {
+        chip8.cpu.set_key_wait_timeout(frames, options.key_wait_action());
+    }
+    if let Some(seed) = options.seed {
+        chip8.cpu.set_rng(Box::new(SeededRng::new(seed)));
+    }
+    let profile = profile(options, &sha1);
+    profile.apply(&mut chip8.cpu);
+    skip_boot(&mut chip8.cpu, &mut chip8.ram, &profile, options);
+    Ok(LoadedRom {
+        keymap: key_map(rom_path, &profile),
+        caption: caption("chip-8 rust", &sha1),
+        sha1,
+        profile
+    })
+}
+
+struct MinifbFrontend;
+
+impl Frontend for MinifbFrontend {
+    fn run(&mut self, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+        minifb_frontend::run(options, shutdown)
+    }
+}
+
+// sdl has proper key up events and a resizable window, but only plays a
+// single rom
+#[cfg(feature = "sdl")]
+#[derive(Default)]
+struct SdlFrontend {
+    rom: Option<String>
+}
+
+#[cfg(feature = "sdl")]
+impl Frontend for SdlFrontend {
+    fn init(&mut self, options: &Options) -> Result<(), String> {
+        let rom = options.rom.as_ref()
+            .ok_or_else(|| String::from("The sdl frontend needs a rom, it doesn't support --kiosk, --watch-dir or the rom browser"))?;
+        self.rom = Some(rom.clone());
+        Ok(())
+    }
+
+    fn run(&mut self, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+        match &self.rom {
+            Some(rom) => sdl::run(rom, options, shutdown),
+            None => Err(String::from("The sdl frontend wasn't initialized"))
+        }
+    }
+}
+
+// plays in the terminal, e.g. over ssh. the terminal is set up in init so
+// teardown can always put it back
+#[cfg(feature = "tui")]
+#[derive(Default)]
+struct TuiFrontend {
+    rom: Option<String>,
+    terminal: Option<tui::Terminal>
+}
+
+#[cfg(feature = "tui")]
+impl Frontend for TuiFrontend {
+    fn init(&mut self, options: &Options) -> Result<(), String> {
+        let rom = options.rom.as_ref()
+            .ok_or_else(|| String::from("The tui frontend needs a rom, it doesn't support --kiosk, --watch-dir or the rom browser"))?;
+        self.rom = Some(rom.clone());
+        self.terminal = Some(tui::Terminal::enter()?);
+        Ok(())
+    }
+
+    fn run(&mut self, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+        match (&self.rom, &mut self.terminal) {
+            (Some(rom), Some(terminal)) => tui::run(rom, terminal, options, shutdown),
+            _ => Err(String::from("The tui frontend wasn't initialized"))
+        }
+    }
+
+    fn teardown(&mut self) {
+        if let Some(terminal) = self.terminal.take() {
+            terminal.leave();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{
+        run_frontend,
+        Frontend
+    };
+    use crate::options::Options;
+    use crate::shutdown::Shutdown;
+
+    // a frontend whose init fails, e.g. with no terminal to set up
+    struct Failing {
+        torn_down: bool
+    }
+
+    impl Frontend for Failing {
+        fn init(&mut self, _options: &Options) -> Result<(), String> {
+            Err(String::from("Could not set up the terminal"))
+        }
+
+        fn run(&mut self, _options: &Options, _shutdown: &Shutdown) -> Result<(), String> {
+            panic!("ran without init");
+        }
+
+        fn teardown(&mut self) {
+            self.torn_down = true;
+        }
+    }
+
+    #[test]
+    fn frontends_are_torn_down_when_init_fails() {
+        let mut frontend = Failing {
+            torn_down: false
+        };
+        let options = Options::parse_from(["rust8"]);
+        let shutdown = Shutdown::new();
+        assert_eq!(run_frontend(&mut frontend, &options, &shutdown), Err(String::from("Could not set up the terminal")));
+        assert!(frontend.torn_down);
+    }
+}
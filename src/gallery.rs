@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use rust8::audio::SilentSink;
+use rust8::chip8::{
+    Chip8,
+    DEFAULT_INSTRUCTIONS_PER_FRAME
+};
+use rust8::rng::SeededRng;
+use rust8::romdb;
+use rust8::screenshot;
+
+use crate::kiosk;
+use crate::shutdown::Shutdown;
+
+// what RND is seeded with unless --seed says otherwise
+pub const DEFAULT_SEED: u64 = 0;
+
+// one rom of the list, and how long to run it before taking its picture
+struct GalleryRom {
+    path: PathBuf,
+    frames: usize
+}
+
+// what a rom looked like at the end of its run
+pub struct GalleryEntry {
+    pub title: String,
+    pub png: String,
+    // sha1 of the final frame, the same picture always has the same one
+    pub frame_sha1: String,
+    // why the rom stopped early, if it did
+    pub halted: Option<String>
+}
+
+// reads the rom list, one rom per line with an optional frame count after
+// it, e.g. "roms/pong.ch8 600". paths are relative to the list, # starts a
+// comment
+fn read_list(list: &str, default_frames: usize) -> Result<Vec<GalleryRom>, String> {
+    let text = fs::read_to_string(list)
+        .map_err(|e| format!("Could not read gallery list {}: {}", list, e))?;
+    let base = Path::new(list).parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+
+    let mut roms = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (path, frames) = match words[..] {
+            [] => continue,
+            [path] => (path, default_frames),
+            [path, frames] => (path, frames.parse().map_err(|e| format!("Could not parse line {} of {}: {}", number + 1, list, e))?),
+            _ => return Err(format!("Could not parse line {} of {}: needs ROM [FRAMES]", number + 1, list))
+        };
+        roms.push(GalleryRom {
+            path: base.join(path),
+            frames
+        });
+    }
+    Ok(roms)
+}
+
+// runs every rom of the list headless with no input for a fixed number of
+// frames and writes DIR/index.html showing each one's final frame. nothing
+// depends on the host's speed, so the same build gives the same pictures
+// and comparing two galleries shows what a release changed. every rom gets
+// RND seeded the same way. on shutdown the index only has the roms done so
+// far
+pub fn build(list: &str, out: &str, default_frames: usize, scale: usize, seed: u64, shutdown: &Shutdown) -> Result<Vec<GalleryEntry>, String> {
+    let roms = read_list(list, default_frames)?;
+    fs::create_dir_all(out)
+        .map_err(|e| format!("Could not create {}: {}", out, e))?;
+
+    let mut entries = Vec::new();
+    for (i, rom) in roms.iter().enumerate() {
+        if shutdown.requested() {
+            break;
+        }
+        let data = fs::read(&rom.path)
+            .map_err(|e| format!("Could not read {}: {}", rom.path.display(), e))?;
+        let mut chip8 = Chip8::with_rom_quirks(&data).map_err(|e| format!("Could not load {}: {}", rom.path.display(), e))?;
+        chip8.cpu.set_rng(Box::new(SeededRng::new(seed)));
+        let halted = run(&mut chip8, rom.frames).err();
+
+        // numbered so two roms with the same name don't overwrite each other
+        let stem = rom.path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let png = format!("{:03}-{}.png", i + 1, stem);
+        screenshot::write_png(&Path::new(out).join(&png).to_string_lossy(), chip8.cpu.gfx(), scale)?;
+
+        let title = romdb::lookup(&romdb::sha1(&data))
+            .map(|info| info.title.to_string())
+            .unwrap_or_else(|| kiosk::rom_title(&rom.path));
+        entries.push(GalleryEntry {
+            title,
+            png,
+            frame_sha1: chip8.cpu.gfx().sha1(),
+            halted
+        });
+    }
+
+    let index = Path::new(out).join("index.html");
+    fs::write(&index, html(&entries, &roms))
+        .map_err(|e| format!("Could not write {}: {}", index.display(), e))?;
+    Ok(entries)
+}
+
+fn run(chip8: &mut Chip8, frames: usize) -> Result<(), String> {
+    let mut audio = SilentSink;
+    for _ in 0..frames {
+        for _ in 0..DEFAULT_INSTRUCTIONS_PER_FRAME {
+            chip8.step()?;
+        }
+        chip8.cpu.tick_timers(&mut audio);
+    }
+    Ok(())
+}
+
+fn html(entries: &[GalleryEntry], roms: &[GalleryRom]) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>rust8 gallery</title>\n",
+        "<style>\n",
+        "body { background: #222; color: #ddd; font-family: monospace; }\n",
+        ".gallery { display: flex; flex-wrap: wrap; gap: 16px; }\n",
+        "figure { margin: 0; }\n",
+        "img { image-rendering: pixelated; }\n",
+        ".halted { color: #f66; }\n",
+        "</style>\n</head>\n<body>\n<div class=\"gallery\">\n"
+    ));
+    for (entry, rom) in entries.iter().zip(roms) {
+        html.push_str(&format!("<figure>\n<img src=\"{}\" alt=\"{}\">\n<figcaption>{}<br>{} frames, {}",
+            escape(&entry.png), escape(&entry.title), escape(&entry.title), rom.frames, &entry.frame_sha1[..8]));
+        if let Some(reason) = &entry.halted {
+            html.push_str(&format!("<br><span class=\"halted\">{}</span>", escape(reason)));
+        }
+        html.push_str("</figcaption>\n</figure>\n");
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
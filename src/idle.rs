@@ -0,0 +1,79 @@
+use std::time::{
+    Duration,
+    Instant
+};
+
+use crate::display::Frame;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum IdleAction {
+    Pause,
+    Snapshot,
+    Both
+}
+
+impl IdleAction {
+    pub fn parse(name: &str) -> Option<IdleAction> {
+        match name {
+            "pause" => Some(IdleAction::Pause),
+            "snapshot" => Some(IdleAction::Snapshot),
+            "both" => Some(IdleAction::Both),
+            _ => None
+        }
+    }
+
+    pub fn pauses(&self) -> bool {
+        *self != IdleAction::Snapshot
+    }
+
+    pub fn snapshots(&self) -> bool {
+        *self != IdleAction::Pause
+    }
+}
+
+// notices when nobody has touched the keypad and nothing has been drawn
+// for a while, e.g. a kiosk left sitting on a demo rom
+pub struct IdleDetector {
+    timeout: Duration,
+    last_activity: Instant,
+    last_keys: [u8; 16],
+    last_frame: Option<Frame>,
+    idle: bool
+}
+
+impl IdleDetector {
+    pub fn new(timeout: Duration) -> IdleDetector {
+        IdleDetector {
+            timeout,
+            last_activity: Instant::now(),
+            last_keys: [0; 16],
+            last_frame: None,
+            idle: false
+        }
+    }
+
+    // feed the current keypad and frame, returns true once when the machine
+    // goes idle
+    pub fn update(&mut self, keys: &[u8; 16], frame: &Frame) -> bool {
+        let now = Instant::now();
+        let key_held = keys.iter().any(|&k| k != 0);
+        if key_held || *keys != self.last_keys || self.last_frame.as_ref() != Some(frame) {
+            self.last_activity = now;
+            self.last_keys = *keys;
+            self.last_frame = Some(frame.clone());
+            self.idle = false;
+            return false;
+        }
+
+        if !self.idle && now.duration_since(self.last_activity) >= self.timeout {
+            self.idle = true;
+            return true;
+        }
+        false
+    }
+
+    pub fn reset(&mut self) {
+        self.last_activity = Instant::now();
+        self.idle = false;
+    }
+}
\ No newline at end of file
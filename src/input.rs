@@ -0,0 +1,509 @@
+use std::time::{
+    Duration,
+    Instant
+};
+
+// something the keypad can be read from. poll marks the keys that are held
+// right now and leaves the others alone, so several sources can be combined
+pub trait InputSource {
+    fn poll(&mut self, keys: &mut [u8; 16]);
+}
+
+#[cfg(test)]
+pub mod scripted {
+    use super::InputSource;
+
+    // plays back key presses and releases at fixed steps, one step per poll
+    pub struct ScriptedInput {
+        events: Vec<(u64, usize, bool)>,
+        step: u64,
+        held: [bool; 16]
+    }
+
+    impl Default for ScriptedInput {
+        fn default() -> ScriptedInput {
+            ScriptedInput::new()
+        }
+    }
+
+    impl ScriptedInput {
+        pub fn new() -> ScriptedInput {
+            ScriptedInput {
+                events: Vec::new(),
+                step: 0,
+                held: [false; 16]
+            }
+        }
+
+        pub fn press(mut self, step: u64, key: usize) -> ScriptedInput {
+            self.events.push((step, key, true));
+            self
+        }
+
+        pub fn release(mut self, step: u64, key: usize) -> ScriptedInput {
+            self.events.push((step, key, false));
+            self
+        }
+    }
+
+    impl InputSource for ScriptedInput {
+        fn poll(&mut self, keys: &mut [u8; 16]) {
+            // events are applied in the order they were scripted
+            for &(step, key, pressed) in &self.events {
+                if step == self.step {
+                    self.held[key] = pressed;
+                }
+            }
+            self.step += 1;
+
+            for (key, &held) in self.held.iter().enumerate() {
+                if held {
+                    keys[key] = 1;
+                }
+            }
+        }
+    }
+}
+
+// accessibility mode for players who can't hold keys: tapping a key
+// latches it down until it's tapped again. the rom still just sees keys
+// held or not
+pub struct StickyKeys {
+    latched: [bool; 16],
+    // physical keys on the last update, to spot taps
+    last: [u8; 16]
+}
+
+impl Default for StickyKeys {
+    fn default() -> StickyKeys {
+        StickyKeys::new()
+    }
+}
+
+impl StickyKeys {
+    pub fn new() -> StickyKeys {
+        StickyKeys {
+            latched: [false; 16],
+            last: [0; 16]
+        }
+    }
+
+    // feed the keys physically held right now, a key going down toggles it
+    pub fn update(&mut self, physical: &[u8; 16]) {
+        for (key, latched) in self.latched.iter_mut().enumerate() {
+            if physical[key] != 0 && self.last[key] == 0 {
+                *latched = !*latched;
+            }
+        }
+        self.last = *physical;
+    }
+
+    pub fn latched(&self) -> [bool; 16] {
+        self.latched
+    }
+}
+
+// the latched keys, for the cpu to read in place of the physical ones
+impl InputSource for StickyKeys {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        for (key, &latched) in self.latched.iter().enumerate() {
+            if latched {
+                keys[key] = 1;
+            }
+        }
+    }
+}
+
+// smooths out shaky or flaky key presses. a key has to stay down (or up)
+// for debounce before the rom sees the change, and once the rom sees it
+// down it stays down for at least min_hold. only timing changes, the rom
+// still sees plain held keys
+pub struct KeyFilter {
+    debounce: Duration,
+    min_hold: Duration,
+    // physical state and when it last changed
+    raw: [bool; 16],
+    raw_since: [Option<Instant>; 16],
+    // what the rom sees and when the key went down for it
+    pressed: [bool; 16],
+    pressed_at: [Option<Instant>; 16]
+}
+
+impl KeyFilter {
+    pub fn new(debounce: Duration, min_hold: Duration) -> KeyFilter {
+        KeyFilter {
+            debounce,
+            min_hold,
+            raw: [false; 16],
+            raw_since: [None; 16],
+            pressed: [false; 16],
+            pressed_at: [None; 16]
+        }
+    }
+
+    // feed the keys physically held at now
+    pub fn update(&mut self, physical: &[u8; 16], now: Instant) {
+        for (key, &held) in physical.iter().enumerate() {
+            let down = held != 0;
+            if down != self.raw[key] {
+                self.raw[key] = down;
+                self.raw_since[key] = Some(now);
+            }
+            if self.raw[key] == self.pressed[key] {
+                continue;
+            }
+
+            let stable = self.raw_since[key].is_none_or(|since| now.duration_since(since) >= self.debounce);
+            let held_long_enough = self.pressed_at[key].is_none_or(|at| now.duration_since(at) >= self.min_hold);
+            if stable && (self.raw[key] || held_long_enough) {
+                self.pressed[key] = self.raw[key];
+                self.pressed_at[key] = if self.raw[key] { Some(now) } else { None };
+            }
+        }
+    }
+}
+
+// the filtered keys, for the cpu to read in place of the physical ones
+impl InputSource for KeyFilter {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        for (key, &pressed) in self.pressed.iter().enumerate() {
+            if pressed {
+                keys[key] = 1;
+            }
+        }
+    }
+}
+
+// which keyboard keys drive the keypad. two player games like pong put
+// the second player on the right column of the keypad (C up, D down), which
+// is awkward to share, so TwoPlayer also puts those on the arrow keys and
+// numpad 8 / 2
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyLayout {
+    Standard,
+    TwoPlayer
+}
+
+impl KeyLayout {
+    pub fn parse(name: &str) -> Option<KeyLayout> {
+        match name {
+            "standard" => Some(KeyLayout::Standard),
+            "two-player" => Some(KeyLayout::TwoPlayer),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        Instant
+    };
+
+    use super::{
+        InputSource,
+        KeyFilter,
+        StickyKeys
+    };
+    use super::scripted::ScriptedInput;
+    use crate::macros::{
+        Macro,
+        MacroPlayer
+    };
+    use crate::audio::mock::MockAudioSink;
+    use crate::cpu::{
+        Cpu,
+        KeyWaitTimeout
+    };
+    use crate::ram::Ram;
+    use crate::PROGRAM_START_ADDR;
+
+    fn machine(program: &[u16]) -> (Cpu, Ram) {
+        let mut ram = Ram::new();
+        for (i, opcode) in program.iter().enumerate() {
+            let addr = PROGRAM_START_ADDR + 2 * i as u16;
+            ram.write_byte(addr, (opcode >> 8) as u8);
+            ram.write_byte(addr + 1, *opcode as u8);
+        }
+        (Cpu::new(), ram)
+    }
+
+    // polls the input then runs one instruction, like the main loop
+    fn step(cpu: &mut Cpu, ram: &mut Ram, input: &mut dyn InputSource) {
+        cpu.handle_keypress(input);
+        cpu.run_instruction(ram).unwrap();
+    }
+
+    #[test]
+    fn scripted_input_follows_the_script() {
+        let mut input = ScriptedInput::new().press(1, 0xA).release(3, 0xA);
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            let mut keys = [0; 16];
+            input.poll(&mut keys);
+            seen.push(keys[0xA]);
+        }
+        assert_eq!(seen, vec![0, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn ex9e_skips_only_while_the_key_is_held() {
+        // V1 = 5, skip if key V1 down
+        let (mut cpu, mut ram) = machine(&[0x6105, 0xE19E]);
+        let mut input = ScriptedInput::new().press(1, 5);
+        step(&mut cpu, &mut ram, &mut input);
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 6);
+
+        let (mut cpu, mut ram) = machine(&[0x6105, 0xE19E]);
+        let mut input = ScriptedInput::new().press(0, 5).release(1, 5);
+        step(&mut cpu, &mut ram, &mut input);
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 4);
+    }
+
+    #[test]
+    fn exa1_skips_only_while_the_key_is_up() {
+        // V1 = 5, skip if key V1 up
+        let (mut cpu, mut ram) = machine(&[0x6105, 0xE1A1]);
+        let mut input = ScriptedInput::new();
+        step(&mut cpu, &mut ram, &mut input);
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 6);
+
+        let (mut cpu, mut ram) = machine(&[0x6105, 0xE1A1]);
+        let mut input = ScriptedInput::new().press(1, 5);
+        step(&mut cpu, &mut ram, &mut input);
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 4);
+    }
+
+    #[test]
+    fn other_keys_do_not_count_for_ex9e() {
+        let (mut cpu, mut ram) = machine(&[0x6105, 0xE19E]);
+        let mut input = ScriptedInput::new().press(0, 4).press(0, 6);
+        step(&mut cpu, &mut ram, &mut input);
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 4);
+    }
+
+    #[test]
+    fn set_key_drives_the_keypad_without_an_input_source() {
+        let (mut cpu, mut ram) = machine(&[0xF30A]);
+        cpu.set_key(7, true);
+        cpu.run_instruction(&mut ram).unwrap();
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR);
+
+        cpu.set_key(7, false);
+        cpu.run_instruction(&mut ram).unwrap();
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+        assert_eq!(cpu.save_state(&ram).v[3], 7);
+    }
+
+    #[test]
+    fn macro_plays_back_one_recorded_frame_per_frame() {
+        let mut recorded = Macro::new();
+        let mut keys = [0u8; 16];
+        recorded.record(&keys);
+        keys[0xA] = 1;
+        recorded.record(&keys);
+
+        let mut player = MacroPlayer::new(recorded);
+        let mut seen = Vec::new();
+        while !player.finished() {
+            // the player's own keys stay held on top
+            let mut keys = [0u8; 16];
+            keys[1] = 1;
+            player.poll(&mut keys);
+            seen.push((keys[1], keys[0xA]));
+            player.next_frame();
+        }
+        assert_eq!(seen, vec![(1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn fx0a_waits_for_press_and_release() {
+        let (mut cpu, mut ram) = machine(&[0xF30A]);
+        let mut input = ScriptedInput::new().press(3, 7).release(6, 7);
+
+        // nothing pressed yet, and then held: still waiting
+        for _ in 0..6 {
+            step(&mut cpu, &mut ram, &mut input);
+            assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR);
+        }
+
+        // released: V3 gets the key and execution moves on
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+        assert_eq!(cpu.save_state(&ram).v[3], 7);
+    }
+
+    #[test]
+    fn fx0a_held_key_does_not_satisfy_the_next_wait() {
+        let (mut cpu, mut ram) = machine(&[0xF10A, 0xF20A]);
+        let mut input = ScriptedInput::new()
+            .press(0, 2).release(1, 2)
+            .press(2, 9);
+
+        step(&mut cpu, &mut ram, &mut input);
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+
+        // key 9 stays down, so the second wait never finishes
+        for _ in 0..10 {
+            step(&mut cpu, &mut ram, &mut input);
+        }
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+    }
+
+    #[test]
+    fn fx0a_reports_the_key_that_was_pressed_first() {
+        let (mut cpu, mut ram) = machine(&[0xF40A]);
+        let mut input = ScriptedInput::new()
+            .press(0, 0xB)
+            .press(1, 0x2)
+            .release(2, 0x2)
+            .release(3, 0xB);
+
+        for _ in 0..3 {
+            step(&mut cpu, &mut ram, &mut input);
+            assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR);
+        }
+        step(&mut cpu, &mut ram, &mut input);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+        assert_eq!(cpu.save_state(&ram).v[4], 0xB);
+    }
+
+    // one frame: the timers tick after every step here
+    fn frame(cpu: &mut Cpu, ram: &mut Ram, input: &mut dyn InputSource, audio: &mut MockAudioSink) {
+        step(cpu, ram, input);
+        cpu.tick_timers(audio);
+    }
+
+    #[test]
+    fn fx0a_timeout_presses_the_configured_key() {
+        let (mut cpu, mut ram) = machine(&[0xF50A]);
+        cpu.set_key_wait_timeout(4, KeyWaitTimeout::Press(0xC));
+        let mut input = ScriptedInput::new();
+        let mut audio = MockAudioSink::new();
+
+        for _ in 0..4 {
+            frame(&mut cpu, &mut ram, &mut input, &mut audio);
+            assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR);
+        }
+        frame(&mut cpu, &mut ram, &mut input, &mut audio);
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+        assert_eq!(cpu.save_state(&ram).v[5], 0xC);
+        assert_eq!(cpu.halted(), None);
+    }
+
+    #[test]
+    fn fx0a_timeout_can_abort() {
+        let (mut cpu, mut ram) = machine(&[0xF50A]);
+        cpu.set_key_wait_timeout(2, KeyWaitTimeout::Abort);
+        let mut input = ScriptedInput::new();
+        let mut audio = MockAudioSink::new();
+
+        for _ in 0..2 {
+            frame(&mut cpu, &mut ram, &mut input, &mut audio);
+        }
+        assert_eq!(cpu.halted(), None);
+        frame(&mut cpu, &mut ram, &mut input, &mut audio);
+        assert!(cpu.halted().is_some());
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR);
+    }
+
+    #[test]
+    fn fx0a_timeout_restarts_for_each_wait() {
+        let (mut cpu, mut ram) = machine(&[0xF10A, 0xF20A]);
+        cpu.set_key_wait_timeout(5, KeyWaitTimeout::Abort);
+        let mut input = ScriptedInput::new().press(2, 1).release(3, 1);
+        let mut audio = MockAudioSink::new();
+
+        for _ in 0..4 {
+            frame(&mut cpu, &mut ram, &mut input, &mut audio);
+        }
+        assert_eq!(cpu.reg_pc(), PROGRAM_START_ADDR + 2);
+
+        // the second wait gets its own five frames
+        for _ in 0..5 {
+            frame(&mut cpu, &mut ram, &mut input, &mut audio);
+            assert_eq!(cpu.halted(), None);
+        }
+        frame(&mut cpu, &mut ram, &mut input, &mut audio);
+        assert!(cpu.halted().is_some());
+    }
+
+    #[test]
+    fn sticky_keys_toggle_on_each_tap() {
+        let mut sticky = StickyKeys::new();
+        let mut held = [0u8; 16];
+        let mut keys = [0u8; 16];
+
+        // down then up leaves the key latched
+        held[5] = 1;
+        sticky.update(&held);
+        held[5] = 0;
+        sticky.update(&held);
+        sticky.poll(&mut keys);
+        assert_eq!(keys[5], 1);
+
+        // holding it doesn't toggle it again every update
+        held[5] = 1;
+        sticky.update(&held);
+        sticky.update(&held);
+        keys = [0; 16];
+        sticky.poll(&mut keys);
+        assert_eq!(keys[5], 0);
+        assert!(!sticky.latched().iter().any(|&latched| latched));
+    }
+
+    fn filtered(filter: &mut KeyFilter) -> bool {
+        let mut keys = [0u8; 16];
+        filter.poll(&mut keys);
+        keys[3] != 0
+    }
+
+    #[test]
+    fn key_filter_ignores_bounces_shorter_than_debounce() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut filter = KeyFilter::new(Duration::from_millis(30), Duration::from_millis(0));
+        let mut held = [0u8; 16];
+
+        // a 10ms blip never reaches the rom
+        held[3] = 1;
+        filter.update(&held, ms(0));
+        held[3] = 0;
+        filter.update(&held, ms(10));
+        filter.update(&held, ms(50));
+        assert!(!filtered(&mut filter));
+
+        // a steady press does, once it has lasted 30ms
+        held[3] = 1;
+        filter.update(&held, ms(100));
+        filter.update(&held, ms(120));
+        assert!(!filtered(&mut filter));
+        filter.update(&held, ms(130));
+        assert!(filtered(&mut filter));
+    }
+
+    #[test]
+    fn key_filter_holds_quick_taps_for_min_hold() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut filter = KeyFilter::new(Duration::from_millis(0), Duration::from_millis(100));
+        let mut held = [0u8; 16];
+
+        held[3] = 1;
+        filter.update(&held, ms(0));
+        held[3] = 0;
+        filter.update(&held, ms(20));
+        assert!(filtered(&mut filter));
+        filter.update(&held, ms(99));
+        assert!(filtered(&mut filter));
+        filter.update(&held, ms(100));
+        assert!(!filtered(&mut filter));
+    }
+}
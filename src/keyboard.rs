@@ -0,0 +1,43 @@
+use minifb::{
+    Key,
+    Window
+};
+
+use rust8::input::InputSource;
+use rust8::keymap::KeyMap;
+
+// the window's keyboard as a keypad, through a keymap
+pub struct Keyboard<'a> {
+    window: &'a Window,
+    keymap: &'a KeyMap
+}
+
+impl<'a> Keyboard<'a> {
+    pub fn new(window: &'a Window, keymap: &'a KeyMap) -> Keyboard<'a> {
+        Keyboard {
+            window,
+            keymap
+        }
+    }
+}
+
+// the keymap's name for a key, minifb's own except "1" for Key1
+fn key_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    match name.strip_prefix("Key") {
+        Some(digit) if digit.len() == 1 => digit.to_string(),
+        _ => name
+    }
+}
+
+impl<'a> InputSource for Keyboard<'a> {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        if let Some(keys_received) = self.window.get_keys() {
+            for k in keys_received {
+                if let Some(key) = self.keymap.get(&key_name(k)) {
+                    keys[key] = 1;
+                }
+            }
+        }
+    }
+}
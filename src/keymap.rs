@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::input::KeyLayout;
+
+// the left side of a qwerty keyboard, laid out like the keypad:
+//   1 2 3 4        1 2 3 C
+//   Q W E R   ->   4 5 6 D
+//   A S D F        7 8 9 E
+//   Z X C V        A 0 B F
+const QWERTY: [(&str, usize); 16] = [
+    ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xC),
+    ("Q", 0x4), ("W", 0x5), ("E", 0x6), ("R", 0xD),
+    ("A", 0x7), ("S", 0x8), ("D", 0x9), ("F", 0xE),
+    ("Z", 0xA), ("X", 0x0), ("C", 0xB), ("V", 0xF)
+];
+
+// which keypad key each host key presses. host keys go by frontend
+// independent names: letters, digits, Up, Down, Left, Right, Space, Enter
+// and NumPad0 to NumPad9
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyMap {
+    keys: BTreeMap<String, usize>
+}
+
+// a keymap file, e.g. {"preset": "arrows", "keys": {"Space": "5"}}. the
+// preset replaces what came before, keys are then added on top and "none"
+// unbinds one
+#[derive(Deserialize)]
+struct KeyMapFile {
+    preset: Option<String>,
+    #[serde(default)]
+    keys: BTreeMap<String, String>
+}
+
+// the host key names the frontends send that aren't a single letter or
+// digit
+const NAMED_KEYS: [&str; 16] = [
+    "Up", "Down", "Left", "Right", "Space", "Enter",
+    "NumPad0", "NumPad1", "NumPad2", "NumPad3", "NumPad4",
+    "NumPad5", "NumPad6", "NumPad7", "NumPad8", "NumPad9"
+];
+
+// a host key name as the frontends spell it, so "space" and "q" match
+// Space and Q, or None if no frontend sends it
+fn host_key(name: &str) -> Option<String> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase().to_string()),
+        _ => NAMED_KEYS.iter().find(|key| key.eq_ignore_ascii_case(name)).map(|key| key.to_string())
+    }
+}
+
+// the preset a layout starts from
+pub fn preset_name(layout: KeyLayout) -> &'static str {
+    match layout {
+        KeyLayout::Standard => "standard",
+        KeyLayout::TwoPlayer => "two-player"
+    }
+}
+
+impl KeyMap {
+    // standard is QWERTY above. two-player adds the arrows and numpad 8
+    // and 2 for the right paddle on C and D. arrows adds the arrows and
+    // space for games steering with 2 4 6 8 and firing with 5
+    pub fn preset(name: &str) -> Option<KeyMap> {
+        let mut map = KeyMap {
+            keys: QWERTY.iter().map(|&(host, key)| (host.to_string(), key)).collect()
+        };
+        let extra: &[(&str, usize)] = match name {
+            "standard" => &[],
+            "two-player" => &[("Up", 0xC), ("NumPad8", 0xC), ("Down", 0xD), ("NumPad2", 0xD)],
+            "arrows" => &[("Up", 0x2), ("Left", 0x4), ("Right", 0x6), ("Down", 0x8), ("Space", 0x5)],
+            _ => return None
+        };
+        for &(host, key) in extra {
+            map.keys.insert(host.to_string(), key);
+        }
+        Some(map)
+    }
+
+    pub fn for_layout(layout: KeyLayout) -> KeyMap {
+        KeyMap::preset(preset_name(layout)).expect("Could not find key layout preset.")
+    }
+
+    pub fn get(&self, host: &str) -> Option<usize> {
+        self.keys.get(host).copied()
+    }
+
+    // the user's keymap for every rom, then the one for the rom with this
+    // sha1, both in the config directory
+    pub fn paths(sha1: &str) -> Vec<PathBuf> {
+        config::dir().map(|dir| vec![
+            dir.join("keymap.json"),
+            dir.join("keymaps").join(format!("{}.json", sha1))
+        ]).unwrap_or_default()
+    }
+
+    // base with the keymap files that exist applied in order
+    pub fn load(base: KeyMap, paths: &[PathBuf]) -> Result<KeyMap, String> {
+        let mut map = base;
+        for path in paths.iter().filter(|path| path.exists()) {
+            map.apply_file(path)?;
+        }
+        Ok(map)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        self.apply_json(&json)
+            .map_err(|e| format!("Could not load keymap {}: {}", path.display(), e))
+    }
+
+    fn apply_json(&mut self, json: &str) -> Result<(), String> {
+        let file: KeyMapFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if let Some(name) = &file.preset {
+            *self = KeyMap::preset(name).ok_or_else(|| format!("unknown preset {}", name))?;
+        }
+        for (name, key) in &file.keys {
+            let host = host_key(name).ok_or_else(|| format!("unknown host key {}", name))?;
+            if key == "none" {
+                self.keys.remove(&host);
+                continue;
+            }
+            let key = usize::from_str_radix(key, 16).ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(|| format!("{} needs a keypad key 0 to F or none, not {}", host, key))?;
+            self.keys.insert(host, key);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyMap;
+    use crate::input::KeyLayout;
+
+    #[test]
+    fn keymap_files_build_on_the_layouts_preset() {
+        let standard = KeyMap::for_layout(KeyLayout::Standard);
+        assert_eq!(standard.get("X"), Some(0x0));
+        assert_eq!(standard.get("Up"), None);
+        assert_eq!(KeyMap::for_layout(KeyLayout::TwoPlayer).get("NumPad2"), Some(0xD));
+
+        let mut map = standard.clone();
+        map.apply_json(r#"{"keys": {"space": "5", "x": "none"}}"#).unwrap();
+        assert_eq!(map.get("Space"), Some(0x5));
+        assert_eq!(map.get("X"), None);
+        assert_eq!(map.get("Q"), Some(0x4));
+
+        // a preset starts over from that preset
+        map.apply_json(r#"{"preset": "arrows"}"#).unwrap();
+        assert_eq!(map.get("X"), Some(0x0));
+        assert_eq!(map.get("Left"), Some(0x4));
+
+        assert!(map.apply_json(r#"{"keys": {"Space": "10"}}"#).is_err());
+        assert!(map.apply_json(r#"{"preset": "dvorak"}"#).is_err());
+        assert!(map.apply_json(r#"{"keys": {"Spacebar": "5"}}"#).is_err());
+        assert!(map.apply_json(r#"{"keys": {"numpad5": "5"}}"#).is_ok());
+        assert_eq!(map.get("NumPad5"), Some(0x5));
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::time::{
+    Duration,
+    Instant
+};
+
+pub enum KioskEvent {
+    // show the next rom in attract mode
+    NextRom,
+    // a visitor pressed a key, restart the current rom for them
+    StartPlaying
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Attract,
+    Playing
+}
+
+// attract mode for events and displays: cycles through every rom in a
+// directory and hands control to whoever walks up and presses a key
+pub struct Kiosk {
+    roms: Vec<PathBuf>,
+    index: usize,
+    mode: Mode,
+    rom_time: Duration,
+    mode_started: Instant,
+    last_input: Instant
+}
+
+impl Kiosk {
+    pub fn open(dir: &str, rom_time: Duration) -> Result<Kiosk, String> {
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("Could not read kiosk directory {}: {}", dir, e))?;
+
+        let mut roms: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_rom(path))
+            .collect();
+        roms.sort();
+
+        if roms.is_empty() {
+            return Err(format!("No roms found in {}", dir));
+        }
+
+        let now = Instant::now();
+        Ok(Kiosk {
+            roms,
+            index: 0,
+            mode: Mode::Attract,
+            rom_time,
+            mode_started: now,
+            last_input: now
+        })
+    }
+
+    pub fn current(&self) -> String {
+        self.roms[self.index].to_string_lossy().into_owned()
+    }
+
+    pub fn title(&self) -> String {
+        rom_title(&self.roms[self.index])
+    }
+
+    pub fn attracting(&self) -> bool {
+        self.mode == Mode::Attract
+    }
+
+    // call once per frame with whether any key is held
+    pub fn update(&mut self, key_held: bool) -> Option<KioskEvent> {
+        let now = Instant::now();
+        if key_held {
+            self.last_input = now;
+        }
+
+        match self.mode {
+            Mode::Attract => {
+                if key_held {
+                    self.mode = Mode::Playing;
+                    self.mode_started = now;
+                    return Some(KioskEvent::StartPlaying);
+                }
+                if now.duration_since(self.mode_started) >= self.rom_time {
+                    self.advance(now);
+                    return Some(KioskEvent::NextRom);
+                }
+            },
+            Mode::Playing => {
+                // the visitor walked away, go back to cycling
+                if now.duration_since(self.last_input) >= self.rom_time {
+                    self.mode = Mode::Attract;
+                    self.advance(now);
+                    return Some(KioskEvent::NextRom);
+                }
+            }
+        }
+        None
+    }
+
+    fn advance(&mut self, now: Instant) {
+        self.index = (self.index + 1) % self.roms.len();
+        self.mode_started = now;
+    }
+}
+
+// saved states and other emulator files live next to the roms
+pub fn is_rom(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    !name.starts_with('.') && !name.ends_with(".json")
+}
+
+pub fn rom_title(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().replace('_', " ").to_uppercase())
+        .unwrap_or_default()
+}
\ No newline at end of file
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use crate::config;
+
+// a named stretch of memory, start and end included
+#[derive(Clone, PartialEq, Debug)]
+pub struct Region {
+    pub name: String,
+    pub start: u16,
+    pub end: u16
+}
+
+impl Region {
+    // ADDR or START-END, in hex
+    fn parse(name: &str, text: &str) -> Option<Region> {
+        let (start, end) = match text.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (text, text)
+        };
+        let start = u16::from_str_radix(start.trim(), 16).ok()?;
+        let end = u16::from_str_radix(end.trim(), 16).ok()?;
+        if end < start {
+            return None;
+        }
+        Some(Region {
+            name: name.to_string(),
+            start,
+            end
+        })
+    }
+
+    fn len(&self) -> u16 {
+        self.end - self.start + 1
+    }
+}
+
+// names for what a rom keeps where in memory, in address order. kept in
+// the config directory under the rom's sha1 and written by hand, e.g.
+// {"lives": "3A0", "ball_x": "3A1", "sprite_table": "400-43F"}
+// the debugger shows these instead of bare addresses. code has no labels
+// of its own yet, this is only for data
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MemoryLabels {
+    regions: Vec<Region>
+}
+
+impl MemoryLabels {
+    // where the labels for the rom with this sha1 are kept
+    pub fn path(sha1: &str) -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("labels").join(format!("{}.json", sha1)))
+    }
+
+    // no file means no labels
+    pub fn load(path: &Path) -> Result<MemoryLabels, String> {
+        let file: BTreeMap<String, String> = config::load_json(path)?;
+        let mut labels = MemoryLabels::default();
+        for (name, range) in &file {
+            let region = Region::parse(name, range)
+                .ok_or_else(|| format!("Could not parse {}: {} needs ADDR or START-END in hex, not {}", path.display(), name, range))?;
+            labels.regions.push(region);
+        }
+        labels.regions.sort_by_key(|region| (region.start, region.end));
+        Ok(labels)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    // the smallest region addr is in, so a field inside a labelled table
+    // gets its own name
+    pub fn region(&self, addr: u16) -> Option<&Region> {
+        self.regions.iter()
+            .filter(|region| (region.start..=region.end).contains(&addr))
+            .min_by_key(|region| region.len())
+    }
+
+    // "lives", or "sprite_table+3" for an address inside a longer region
+    pub fn name(&self, addr: u16) -> Option<String> {
+        self.region(addr).map(|region| match addr - region.start {
+            0 => region.name.clone(),
+            offset => format!("{}+{:X}", region.name, offset)
+        })
+    }
+
+    // the name with the address after it, or just the address
+    pub fn describe(&self, addr: u16) -> String {
+        match self.name(addr) {
+            Some(name) => format!("{} ({:03X})", name, addr),
+            None => format!("{:03X}", addr)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MemoryLabels,
+        Region
+    };
+
+    #[test]
+    fn addresses_take_the_name_of_the_smallest_region() {
+        let labels = MemoryLabels {
+            regions: vec![
+                Region::parse("player", "3A0-3AF").unwrap(),
+                Region::parse("lives", "3A0").unwrap()
+            ]
+        };
+        assert_eq!(Region::parse("backwards", "3AF-3A0"), None);
+        assert_eq!(labels.name(0x3A0).as_deref(), Some("lives"));
+        assert_eq!(labels.name(0x3AB).as_deref(), Some("player+B"));
+        assert_eq!(labels.describe(0x3AB), "player+B (3AB)");
+        assert_eq!(labels.describe(0x200), "200");
+    }
+}
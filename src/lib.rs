@@ -0,0 +1,58 @@
+pub mod spec;
+pub mod ram;
+pub mod cpu;
+pub mod error;
+pub mod opcode_log;
+pub mod display;
+pub mod state;
+pub mod remote;
+pub mod idle;
+pub mod overlay;
+pub mod present;
+pub mod bezel;
+pub mod phosphor;
+pub mod crt;
+pub mod animation;
+pub mod sink;
+pub mod screenshot;
+pub mod recording;
+pub mod audio;
+pub mod input;
+pub mod keymap;
+pub mod rng;
+pub mod quirks;
+pub mod quirk_report;
+pub mod profile;
+pub mod map;
+pub mod romdiff;
+pub mod breakpoint;
+pub mod session;
+pub mod labels;
+pub mod ram_search;
+pub mod chip8;
+pub mod romdb;
+pub mod timing;
+pub mod rewind;
+pub mod autosave;
+pub mod config;
+pub mod macros;
+pub mod replay;
+pub mod netplay;
+pub mod score;
+pub mod stats;
+pub mod achievement;
+pub mod split;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+pub use spec::{
+    NUM_GPR,
+    RAM_SIZE,
+    WIDTH,
+    HEIGHT,
+    PROGRAM_START_ADDR
+};
+
+// default colors for unlit and lit pixels
+pub const PX_OFF: u32 = 0;
+pub const PX_ON: u32 = 0xFFFFFF;
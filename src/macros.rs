@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::config;
+use crate::input::InputSource;
+
+// a recorded stretch of keypad input, e.g. the presses that get past a
+// title screen, played back on top of whatever the player is holding.
+// one entry per frame, bit n set while key n is down
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct Macro {
+    frames: Vec<u16>
+}
+
+impl Macro {
+    pub fn new() -> Macro {
+        Macro::default()
+    }
+
+    // adds one frame's keys
+    pub fn record(&mut self, keys: &[u8; 16]) {
+        let mut bits = 0;
+        for (key, &held) in keys.iter().enumerate() {
+            if held != 0 {
+                bits |= 1 << key;
+            }
+        }
+        self.frames.push(bits);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // the keys held in the nth frame
+    pub fn held(&self, frame: usize) -> Option<[bool; 16]> {
+        let bits = *self.frames.get(frame)?;
+        let mut keys = [false; 16];
+        for (key, held) in keys.iter_mut().enumerate() {
+            *held = bits & (1 << key) != 0;
+        }
+        Some(keys)
+    }
+
+    // keeps the first len frames, e.g. after rewinding
+    pub fn truncate(&mut self, len: usize) {
+        self.frames.truncate(len);
+    }
+
+    // where the macro for the rom with this sha1 is kept, see romdb::sha1
+    pub fn path(sha1: &str) -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("macros").join(format!("{}.json", sha1)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Could not serialize macro: {}", e))?;
+        fs::write(path, json)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Macro, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+    }
+}
+
+// plays a macro back a frame at a time. poll merges the current frame's
+// keys, next_frame moves on
+pub struct MacroPlayer {
+    recorded: Macro,
+    frame: usize
+}
+
+impl MacroPlayer {
+    pub fn new(recorded: Macro) -> MacroPlayer {
+        MacroPlayer {
+            recorded,
+            frame: 0
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.recorded.len()
+    }
+}
+
+impl InputSource for MacroPlayer {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        if let Some(held) = self.recorded.held(self.frame) {
+            for (key, &down) in keys.iter_mut().zip(&held) {
+                if down {
+                    *key = 1;
+                }
+            }
+        }
+    }
+}
@@ -1,87 +1,381 @@
-mod ram;
-mod cpu;
-
-use std::fs::File;
-use std::io::Read;
-use minifb::{
-    Window,
-    WindowOptions,
-    Scale
+mod options;
+mod kiosk;
+mod watch;
+mod tutorial;
+mod sweep;
+mod catalog;
+mod gallery;
+mod keyboard;
+mod frontend;
+mod shutdown;
+mod minifb_frontend;
+mod browser;
+#[cfg(feature = "sdl")]
+mod sdl;
+#[cfg(feature = "tui")]
+mod tui;
+
+use rust8::breakpoint::BreakpointGroups;
+use rust8::chip8::Chip8;
+use rust8::labels::MemoryLabels;
+use rust8::map;
+use rust8::profile::Profile;
+use rust8::quirk_report;
+use rust8::recording::{
+    RecordFormat,
+    Recording
+};
+use rust8::replay::{
+    self,
+    Replay
+};
+use rust8::session::DebugSession;
+use rust8::romdb;
+use rust8::romdiff;
+use rust8::split;
+use rust8::stats::{
+    self,
+    UsageStats
+};
+use options::{
+    Command,
+    GroupAction,
+    Options
 };
-use rodio::Sink;
+use shutdown::Shutdown;
 
-use ram::Ram;
-use cpu::Cpu;
+// how long `map` runs a rom to see what it touches, about 5 seconds of play
+const MAP_INSTRUCTIONS: usize = 20000;
+// frames a sweep lets FX0A wait by default
+const SWEEP_KEY_WAIT: u32 = 300;
 
-const NUM_GPR: usize = 16;
-const RAM_SIZE: usize = 4096;
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-const TIMER_DEFAULT: usize = 8;
-const PX_OFF: u32 = 0;
-const PX_ON: u32 = 0xFFFFFF;
-const PROGRAM_START_ADDR: u16 = 0x0200;
+// applies action to the rom's breakpoint groups, then lists them
+fn breakpoint_groups(rom: &str, action: &Option<GroupAction>) -> Result<(), String> {
+    let data = std::fs::read(rom).map_err(|e| format!("Could not read {}: {}", rom, e))?;
+    let path = BreakpointGroups::path(&romdb::sha1(&data))
+        .ok_or_else(|| String::from("Could not find a config directory, set HOME or XDG_CONFIG_HOME"))?;
+    let mut groups = BreakpointGroups::load(&path)?;
+    match action {
+        Some(GroupAction::Add { group, breakpoints }) => groups.add(group, breakpoints),
+        Some(GroupAction::Remove { group }) => groups.remove(group)?,
+        Some(GroupAction::Enable { group }) => groups.set_enabled(group, true)?,
+        Some(GroupAction::Disable { group }) => groups.set_enabled(group, false)?,
+        None => ()
+    }
+    if action.is_some() {
+        groups.save(&path)?;
+    }
 
-fn load_rom(data: &Vec<u8>, ram: &mut Ram) {
-    for i in 0..data.len() {
-        ram.write_byte(PROGRAM_START_ADDR + i as u16, data[i]);
+    if groups.groups().is_empty() {
+        println!("No breakpoint groups for {}", rom);
     }
+    for (name, group) in groups.groups() {
+        let breakpoints: Vec<String> = group.breakpoints.iter().map(|breakpoint| breakpoint.to_string()).collect();
+        let state = if group.enabled { "on" } else { "off" };
+        println!("{} ({}): {}", name, state, breakpoints.join(" "));
+    }
+    Ok(())
 }
 
-fn main() {
-    let mut file = File::open("data/breakout").expect("Could not open file.");
-    let mut data = Vec::<u8>::new();
-    file.read_to_end(&mut data).expect("Could not read file.");
-
-    let mut ram = Ram::new();
-    let mut cpu = Cpu::new();
-
-    // load rom into Chip8
-    load_rom(&data, &mut ram);
-
-    // setup audio
-    let audio_device = rodio::default_output_device().unwrap();
-    let audio_sink = Sink::new(&audio_device);
-    let audio_source = rodio::source::SineWave::new(440);
-    audio_sink.append(audio_source);
-    audio_sink.pause();
-
-    let mut runloop_time = TIMER_DEFAULT;
-
-    let mut window = Window::new(
-        &format!("chip-8 rust"),
-        WIDTH,
-        HEIGHT,
-        WindowOptions {
-            scale: Scale::X8,
-            ..WindowOptions::default()
+// saves the command line's settings into the rom's profile, or clears it,
+// then shows it
+fn rom_profile(rom: &str, options: &Options, save: bool, clear: bool) -> Result<(), String> {
+    let data = std::fs::read(rom).map_err(|e| format!("Could not read {}: {}", rom, e))?;
+    let path = Profile::path(&romdb::sha1(&data))
+        .ok_or_else(|| String::from("Could not find a config directory, set HOME or XDG_CONFIG_HOME"))?;
+    let mut profile = Profile::load(&path)?;
+    if save {
+        profile = options.profile().or(profile);
+        profile.save(&path)?;
+    }
+    if clear && path.exists() {
+        std::fs::remove_file(&path).map_err(|e| format!("Could not remove {}: {}", path.display(), e))?;
+        profile = Profile::default();
+    }
+
+    if profile.is_empty() {
+        println!("No settings kept for {}", rom);
+        return Ok(());
+    }
+    if let Some(speed) = profile.speed {
+        println!("speed: {}", speed);
+    }
+    if let Some(quirks) = profile.quirks {
+        println!("quirks: {}", quirks);
+    }
+    if let Some(color) = profile.foreground {
+        println!("foreground: {:06X}", color);
+    }
+    if let Some(color) = profile.background {
+        println!("background: {:06X}", color);
+    }
+    if let Some(keymap) = &profile.keymap {
+        println!("keymap: {}", keymap);
+    }
+    Ok(())
+}
+
+// lists what the rom's debug session holds, after clearing it if asked
+fn debug_session(rom: &str, clear: bool) -> Result<(), String> {
+    let data = std::fs::read(rom).map_err(|e| format!("Could not read {}: {}", rom, e))?;
+    let path = DebugSession::path(&romdb::sha1(&data))
+        .ok_or_else(|| String::from("Could not find a config directory, set HOME or XDG_CONFIG_HOME"))?;
+    if clear && path.exists() {
+        std::fs::remove_file(&path).map_err(|e| format!("Could not remove {}: {}", path.display(), e))?;
+    }
+    let session = DebugSession::load(&path)?;
+
+    if session.is_empty() {
+        println!("No debug session for {}", rom);
+        return Ok(());
+    }
+    let watches: Vec<String> = session.watches.iter().map(|watch| watch.to_string()).collect();
+    let mut panels = Vec::new();
+    if session.layout.registers {
+        panels.push("registers");
+    }
+    if session.layout.watches {
+        panels.push("watches");
+    }
+    println!("watches: {}", watches.join(" "));
+    println!("open panels: {}", panels.join(" "));
+    Ok(())
+}
+
+// lists the rom's memory labels and the file they come from
+fn memory_labels(rom: &str) -> Result<(), String> {
+    let data = std::fs::read(rom).map_err(|e| format!("Could not read {}: {}", rom, e))?;
+    let path = MemoryLabels::path(&romdb::sha1(&data))
+        .ok_or_else(|| String::from("Could not find a config directory, set HOME or XDG_CONFIG_HOME"))?;
+    let labels = MemoryLabels::load(&path)?;
+
+    if labels.is_empty() {
+        println!("No memory labels for {}, add some to {}", rom, path.display());
+        println!("e.g. {{\"lives\": \"3A0\", \"sprite_table\": \"400-43F\"}}");
+        return Ok(());
+    }
+    println!("From {}", path.display());
+    for region in labels.regions() {
+        if region.start == region.end {
+            println!("{:03X}      {}", region.start, region.name);
+        } else {
+            println!("{:03X}-{:03X}  {}", region.start, region.end, region.name);
         }
-    ).unwrap();
+    }
+    Ok(())
+}
 
-    window.limit_update_rate(Some(std::time::Duration::from_micros(2083)));
+// a machine for playing replays headless, set up by the options as a run
+// would be. replays that kept their own settings put those in instead
+fn replay_machine(options: &Options) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.ram.set_policy(options.memory_policy());
+    chip8.cpu.set_strict(options.strict);
+    chip8.cpu.set_extensions(options.extensions);
+    chip8.cpu.set_invalid_opcode_policy(options.invalid_opcodes);
+    if let Some(frames) = options.key_wait_timeout {
+        chip8.cpu.set_key_wait_timeout(frames, options.key_wait_action());
+    }
+    chip8
+}
+
+fn play_replay(file: &str, options: &Options) -> Result<(), String> {
+    let replay = Replay::load(std::path::Path::new(file))?;
+    let mut chip8 = replay_machine(options);
+    replay::play(&mut chip8, &replay)?;
+    println!("Played {} frames in step with the recording, ending at {}", replay.len(), chip8.save_state().checksum());
+    Ok(())
+}
+
+// plays a replay headless, recording every frame it shows into out: a
+// gif, an mp4, or a directory of pngs when out has no extension. the
+// colors are the rom's, from its profile, as a run would show them
+fn render_replay(file: &str, out: &str, options: &Options) -> Result<(), String> {
+    let replay = Replay::load(std::path::Path::new(file))?;
+    let out = std::path::Path::new(out);
+    let format = match out.extension().and_then(|extension| extension.to_str()) {
+        Some("gif") => RecordFormat::Gif,
+        Some("mp4") => RecordFormat::Mp4,
+        None => RecordFormat::Png,
+        Some(_) => return Err(format!("Could not render to {}: give a .gif, a .mp4, or a directory for pngs", out.display()))
+    };
+    let mut chip8 = replay_machine(options);
+    let palette = frontend::profile(options, &replay.rom_sha1).palette(options.palette());
+    let mut video = Recording::to(out, format, options.record_scale.unwrap_or(options.scale), options.record_fps)?;
+    replay::play_frames(&mut chip8, &replay, |chip8| video.capture(chip8.cpu.gfx(), &palette))?;
+    let frames = video.frames();
+    let path = video.finish()?;
+    println!("Rendered {} frames of {} to {}", frames, file, path.display());
+    Ok(())
+}
 
-    while window.is_open() && (cpu.reg_pc() as usize) <= RAM_SIZE {
-        cpu.run_instruction(&mut ram);
+// turns usage stats on or off, then lists the roms played, latest first
+fn usage_stats(enable: bool, disable: bool) -> Result<(), String> {
+    let path = UsageStats::path()
+        .ok_or_else(|| String::from("Could not find a config directory, set HOME or XDG_CONFIG_HOME"))?;
+    if enable && !path.exists() {
+        UsageStats::default().save(&path)?;
+        println!("Keeping play time in {}", path.display());
+    }
+    if disable && path.exists() {
+        std::fs::remove_file(&path).map_err(|e| format!("Could not remove {}: {}", path.display(), e))?;
+        println!("Stopped keeping play time and deleted {}", path.display());
+    }
 
-        cpu.handle_keypress(&window);
+    let stats = match UsageStats::enabled(&path)? {
+        Some(stats) => stats,
+        None => {
+            println!("Usage stats are off, `rust8 stats --enable` turns them on");
+            return Ok(());
+        }
+    };
+    let recent = stats.recent();
+    if recent.is_empty() {
+        println!("No roms played yet");
+    }
+    for (sha1, rom) in recent {
+        let title = romdb::lookup(sha1).map(|info| info.title).unwrap_or(&rom.path);
+        let plays = if rom.plays == 1 { "play" } else { "plays" };
+        println!("{:24} {} {:4} {}  {}", title, stats::format_seconds(rom.seconds), rom.plays, plays, rom.path);
+    }
+    Ok(())
+}
 
-        // reset timers
-        if runloop_time == 0 {
-            if cpu.reg_dt() > 0 {
-                cpu.set_reg_dt(cpu.reg_dt() - 1);
+// the subcommands that don't open the emulator window
+fn run_command(command: &Command, options: &Options, shutdown: &Shutdown) {
+    match command {
+        Command::Tutorial => tutorial::run(),
+        Command::Breakpoints { rom, action } => {
+            if let Err(e) = breakpoint_groups(rom, action) {
+                println!("{}", e);
+            }
+        },
+        Command::Profile { rom, save, clear } => {
+            if let Err(e) = rom_profile(rom, options, *save, *clear) {
+                println!("{}", e);
+            }
+        },
+        Command::Session { rom, clear } => {
+            if let Err(e) = debug_session(rom, *clear) {
+                println!("{}", e);
+            }
+        },
+        Command::Labels { rom } => {
+            if let Err(e) = memory_labels(rom) {
+                println!("{}", e);
+            }
+        },
+        Command::Stats { enable, disable } => {
+            if let Err(e) = usage_stats(*enable, *disable) {
+                println!("{}", e);
+            }
+        },
+        Command::Sweep { dir, frames } => {
+            // roms stuck on FX0A count as failures unless told which key to press
+            let key_wait = (options.key_wait_timeout.unwrap_or(SWEEP_KEY_WAIT), options.key_wait_action());
+            let snapshots = options.halt_snapshots.as_deref().map(|dir| sweep::HaltSnapshots {
+                dir,
+                scale: options.screenshot_scale
+            });
+            let results = sweep::sweep(dir, *frames, key_wait, options.unsafe_speedups, snapshots, shutdown).expect("Could not run sweep.");
+            let failed = results.iter().filter(|result| result.outcome.is_err()).count();
+            println!("{} roms, {} ok, {} failed", results.len(), results.len() - failed, failed);
+            for result in &results {
+                if let Err(e) = &result.outcome {
+                    println!("  {}: {}", result.rom, e);
+                    if let Some(png) = &result.snapshot {
+                        println!("    ended up like {}", png);
+                    }
+                }
             }
-            if cpu.reg_st() > 0 {
-                audio_sink.play();
-                cpu.set_reg_st(cpu.reg_st() - 1);
-            } else if cpu.reg_st() == 0 {
-                audio_sink.pause();
+        },
+        Command::Catalog { dir } => {
+            let entries = catalog::build(dir).expect("Could not build catalog.");
+            for entry in &entries {
+                let known = if entry.known { "" } else { " (unknown)" };
+                let best = entry.high_score.map(|best| format!(", high score {}", best)).unwrap_or_default();
+                println!("{} {}{}{}", entry.sha1, entry.title, known, best);
+                if entry.files.len() > 1 {
+                    println!("    {} copies: {}", entry.files.len(), entry.files.join(", "));
+                }
             }
+            let path = std::path::Path::new(dir).join("catalog.json");
+            let path = path.to_string_lossy();
+            catalog::write(&path, &entries).expect("Could not write catalog.");
+            println!("Wrote {}", path);
+        },
+        Command::Gallery { list, out, frames, scale } => {
+            let entries = gallery::build(list, out, *frames, *scale, options.seed.unwrap_or(gallery::DEFAULT_SEED), shutdown).expect("Could not build gallery.");
+            for entry in &entries {
+                match &entry.halted {
+                    Some(reason) => println!("{} {}: {}", &entry.frame_sha1[..8], entry.title, reason),
+                    None => println!("{} {}", &entry.frame_sha1[..8], entry.title)
+                }
+            }
+            println!("Wrote {}", std::path::Path::new(out).join("index.html").display());
+        },
+        Command::Split { file, out } => {
+            let data = std::fs::read(file).expect("Could not read file.");
+            let programs = split::split(&data);
+            let path = std::path::Path::new(file);
+            let dir = match out {
+                Some(out) => std::path::PathBuf::from(out),
+                None => path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default()
+            };
+            std::fs::create_dir_all(&dir).expect("Could not create output directory.");
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            for (i, program) in programs.iter().enumerate() {
+                let rom = dir.join(format!("{}.{}.ch8", stem, i + 1));
+                std::fs::write(&rom, &program.data).expect("Could not write rom.");
+                println!("{}: {} bytes at offset {:X} -> {}", i + 1, program.data.len(), program.offset, rom.display());
+            }
+            println!("{} programs, run one with --program N", programs.len());
+        },
+        Command::Map { rom } => {
+            let data = std::fs::read(rom).expect("Could not read file.");
+            match map::describe(rom, &data, MAP_INSTRUCTIONS) {
+                Ok(map) => println!("{}", map),
+                Err(e) => println!("{}", e)
+            }
+        },
+        Command::Replay { file } => {
+            if let Err(e) = play_replay(file, options) {
+                println!("{}", e);
+            }
+        },
+        Command::Render { file, out } => {
+            if let Err(e) = render_replay(file, out, options) {
+                println!("{}", e);
+            }
+        },
+        Command::QuirkReport { rom, frames } => {
+            let data = std::fs::read(rom).expect("Could not read rom.");
+            match quirk_report::describe(&data, *frames, options.seed.unwrap_or(gallery::DEFAULT_SEED)) {
+                Ok(report) => println!("{}", report),
+                Err(e) => println!("{}", e)
+            }
+        },
+        Command::Romdiff { a, b } => {
+            let a_data = std::fs::read(a).expect("Could not read first rom.");
+            let b_data = std::fs::read(b).expect("Could not read second rom.");
+            println!("{}", romdiff::describe(a, &a_data, b, &b_data));
+        }
+    }
+}
 
-            window.update_with_buffer(&cpu.gfx(), WIDTH, HEIGHT).unwrap();
+fn main() {
+    let options = Options::from_args();
+    let shutdown = Shutdown::install().expect("Could not install signal handlers.");
 
-            runloop_time = TIMER_DEFAULT;
-        } else {
-            runloop_time -= 1;
-        }
+    if let Some(command) = &options.command {
+        run_command(command, &options, &shutdown);
+    } else if let Err(e) = frontend::run(&options.frontend, &options, &shutdown) {
+        println!("{}", e);
+        std::process::exit(1);
     }
-}
\ No newline at end of file
+
+    if let Some(code) = shutdown.exit_code() {
+        println!("Interrupted");
+        std::process::exit(code);
+    }
+}
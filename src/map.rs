@@ -0,0 +1,145 @@
+use crate::audio::SilentSink;
+use crate::cpu::Cpu;
+use crate::ram::Ram;
+use crate::spec::{
+    FONT,
+    FONT_BASE,
+    FONT_SPRITE_BYTES,
+    MAX_ROM_SIZE,
+    PROGRAM_START_ADDR,
+    RAM_SIZE,
+    STACK_DEPTH
+};
+
+// instructions between timer ticks, same as the main loop
+const INSTRUCTIONS_PER_TICK: usize = 8;
+
+// what a short run of the rom touched
+struct Observed {
+    instructions: usize,
+    code: Vec<bool>,
+    read: Vec<bool>,
+    written: Vec<bool>,
+    deepest_stack: u8,
+    halt: Option<String>
+}
+
+// the layout of memory for a rom: the fixed areas, where the rom sits, and
+// which parts it ran, read and wrote during a run of `instructions`
+// instructions with no keys held
+pub fn describe(rom_name: &str, rom: &[u8], instructions: usize) -> Result<String, String> {
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(format!("{} is {} bytes, at most {} fit in memory", rom_name, rom.len(), MAX_ROM_SIZE));
+    }
+
+    let font_end = FONT_BASE as usize + FONT.len() * FONT_SPRITE_BYTES;
+    let rom_start = PROGRAM_START_ADDR as usize;
+    let rom_end = rom_start + rom.len();
+
+    let mut lines = vec![
+        format!("memory map for {} (CHIP-8, {} bytes)", rom_name, RAM_SIZE),
+        region(FONT_BASE as usize, font_end, "font", &format!("hex digit sprites 0-F, {} bytes each", FONT_SPRITE_BYTES)),
+        region(font_end, rom_start, "reserved", "interpreter area, unused by rust8")
+    ];
+    if rom.is_empty() {
+        lines.push(String::from("         rom       empty"));
+    } else {
+        lines.push(region(rom_start, rom_end, "rom", &format!("{} bytes", rom.len())));
+    }
+    if rom_end < RAM_SIZE {
+        lines.push(region(rom_end, RAM_SIZE, "free", &format!("{} bytes", RAM_SIZE - rom_end)));
+    }
+
+    let observed = observe(rom, instructions);
+    lines.push(String::new());
+    lines.push(format!("observed over {} instructions with no input:", observed.instructions));
+    lines.push(format!("code     {}", ranges(&observed.code)));
+    lines.push(format!("read     {}", ranges(&observed.read)));
+    lines.push(format!("written  {}", ranges(&observed.written)));
+    lines.push(format!("stack    deepest {} of {} entries, kept outside ram", observed.deepest_stack, STACK_DEPTH));
+    if let Some(halt) = observed.halt {
+        lines.push(format!("halted   {}", halt));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn region(start: usize, end: usize, name: &str, what: &str) -> String {
+    format!("{:03X}-{:03X}  {:<8}  {}", start, end - 1, name, what)
+}
+
+fn observe(rom: &[u8], instructions: usize) -> Observed {
+    let mut ram = Ram::new();
+    for (i, &byte) in rom.iter().enumerate() {
+        ram.write_byte(PROGRAM_START_ADDR + i as u16, byte);
+    }
+    let mut cpu = Cpu::new();
+    let mut audio = SilentSink;
+    ram.start_trace();
+
+    let mut observed = Observed {
+        instructions: 0,
+        code: vec![false; RAM_SIZE],
+        read: vec![false; RAM_SIZE],
+        written: vec![false; RAM_SIZE],
+        deepest_stack: 0,
+        halt: None
+    };
+
+    while observed.instructions < instructions {
+        let pc = cpu.reg_pc() as usize;
+        if let Err(e) = cpu.run_instruction(&mut ram) {
+            observed.halt = Some(e.to_string());
+            break;
+        }
+        if let Some(reason) = cpu.halted() {
+            observed.halt = Some(reason.to_string());
+            break;
+        }
+
+        observed.instructions += 1;
+        observed.code[pc] = true;
+        observed.code[pc + 1] = true;
+        for (addr, write) in ram.take_trace() {
+            let addr = addr as usize;
+            if write {
+                observed.written[addr] = true;
+            } else if addr != pc && addr != pc + 1 {
+                observed.read[addr] = true;
+            }
+        }
+        observed.deepest_stack = observed.deepest_stack.max(cpu.sp());
+
+        if observed.instructions.is_multiple_of(INSTRUCTIONS_PER_TICK) {
+            cpu.tick_timers(&mut audio);
+        }
+    }
+
+    observed
+}
+
+// "200-2A3, 2B0" style list of the marked addresses
+fn ranges(marked: &[bool]) -> String {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (addr, &on) in marked.iter().chain(std::iter::once(&false)).enumerate() {
+        match (on, start) {
+            (true, None) => start = Some(addr),
+            (false, Some(from)) => {
+                if from == addr - 1 {
+                    spans.push(format!("{:03X}", from));
+                } else {
+                    spans.push(format!("{:03X}-{:03X}", from, addr - 1));
+                }
+                start = None;
+            },
+            _ => ()
+        }
+    }
+
+    if spans.is_empty() {
+        String::from("none")
+    } else {
+        spans.join(", ")
+    }
+}
@@ -0,0 +1,1180 @@
+use std::fs::File;
+use std::io::Read;
+use std::thread;
+use std::time::{
+    Instant,
+    SystemTime
+};
+use minifb::{
+    Key,
+    KeyRepeat,
+    Window,
+    WindowOptions,
+    Scale
+};
+
+use rust8::ram::Ram;
+use rust8::chip8;
+use rust8::cpu::Cpu;
+use rust8::state::State;
+use rust8::remote::RemoteInput;
+use rust8::input::StickyKeys;
+use rust8::romdb;
+use rust8::idle::IdleDetector;
+use rust8::overlay::{
+    self,
+    Overlay
+};
+use rust8::display::Display;
+use rust8::present::Screen;
+use rust8::animation::Moment;
+use rust8::bezel::Bezel;
+use rust8::crt::Crt;
+use rust8::phosphor::Phosphor;
+use rust8::recording::Recording;
+use rust8::sink::{
+    Compositor,
+    RawSink
+};
+use rust8::screenshot::{
+    self,
+    Image,
+    ScreenshotSeries
+};
+use rust8::split;
+use rust8::breakpoint::{
+    Breakpoint,
+    BreakpointGroups,
+    FirstOpcodeBreak,
+    FrameBreakpoints
+};
+use rust8::timing::{
+    FramePacer,
+    Rate,
+    TimerClock,
+    Timing,
+    FRAME_TIME
+};
+use rust8::rewind::Rewind;
+use rust8::autosave::Autosave;
+use rust8::rng::SeededRng;
+use rust8::profile::Profile;
+use rust8::labels::MemoryLabels;
+use rust8::ram_search::{
+    Change,
+    RamSearch
+};
+use rust8::session::{
+    DebugSession,
+    Watch
+};
+use rust8::score::HighScores;
+use rust8::stats::UsageStats;
+use rust8::achievement::Achievements;
+use rust8::macros::{
+    Macro,
+    MacroPlayer
+};
+use rust8::audio::{
+    AudioSink,
+    RodioSink
+};
+use crate::browser;
+use crate::kiosk::{
+    self,
+    Kiosk,
+    KioskEvent
+};
+use crate::options::Options;
+use crate::keyboard::Keyboard;
+use crate::watch::DirWatcher;
+use crate::frontend::{
+    self,
+    key_map,
+    ReplayRun
+};
+use crate::shutdown::Shutdown;
+
+use rust8::PX_ON;
+use rust8::spec::KEYPAD_LAYOUT;
+
+// how far back holding backspace can rewind, 10 seconds of frames
+const REWIND_FRAMES: usize = 600;
+// keys drawn on the keypad overlays that aren't latched or held are dimmed
+const KEY_UNLIT: u32 = 0x606060;
+// the window title, and how often --state-checksum adds to it
+const TITLE: &str = "chip-8 rust";
+const CHECKSUM_FRAMES: u64 = 60;
+// how long an unlocked achievement stays on screen, 3 seconds
+const NOTIFICATION_FRAMES: u32 = 180;
+
+fn state_path(rom_path: &str) -> String {
+    format!("{}.state.json", rom_path)
+}
+
+fn quick_state_path(rom_path: &str) -> String {
+    format!("{}.state", rom_path)
+}
+
+fn idle_state_path(rom_path: &str) -> String {
+    format!("{}.idle.state.json", rom_path)
+}
+
+fn rom_sha1(rom_path: &str) -> Result<String, String> {
+    let rom = std::fs::read(rom_path).map_err(|e| format!("Could not read {}: {}", rom_path, e))?;
+    Ok(romdb::sha1(&rom))
+}
+
+// writes out the high scores if a game beat its best since the last save
+fn save_high_scores(high_scores: &HighScores, changed: &mut bool) {
+    if !*changed {
+        return;
+    }
+    if let Some(path) = HighScores::path() {
+        match high_scores.save(&path) {
+            Ok(()) => println!("Saved high scores to {}", path.display()),
+            Err(e) => println!("{}", e)
+        }
+    }
+    *changed = false;
+}
+
+// the usage stats if the user turned them on with `rust8 stats --enable`
+fn load_stats() -> Option<UsageStats> {
+    match UsageStats::enabled(&UsageStats::path()?) {
+        Ok(stats) => stats,
+        Err(e) => {
+            println!("{}", e);
+            None
+        }
+    }
+}
+
+// adds the time since the player started the rom to its play time, when
+// stats are on. roms nobody started, e.g. kiosk mode showing them off, are
+// no play at all
+fn record_play(stats: &mut Option<UsageStats>, sha1: &str, rom_path: &str, played_since: Option<Instant>) {
+    let (stats, path, played_since) = match (stats, UsageStats::path(), played_since) {
+        (Some(stats), Some(path), Some(played_since)) => (stats, path, played_since),
+        _ => return
+    };
+    stats.record(sha1, rom_path, played_since.elapsed(), SystemTime::now());
+    if let Err(e) = stats.save(&path) {
+        println!("{}", e);
+    }
+}
+
+// with --halt-snapshots, keeps a picture and the state of where the rom
+// stopped
+fn save_halt_snapshot(options: &Options, rom_path: &str, cpu: &Cpu, ram: &Ram) {
+    if let Some(dir) = &options.halt_snapshots {
+        let name = std::path::Path::new(rom_path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        match screenshot::write_halt_snapshot(dir, &name, cpu.gfx(), &cpu.save_state(ram), options.screenshot_scale) {
+            Ok(png) => println!("Saved {} and its state", png),
+            Err(e) => println!("{}", e)
+        }
+    }
+}
+
+// the rom's debug session from last time, showing the watches given on the
+// command line as well
+fn load_session(options: &Options, sha1: &str) -> DebugSession {
+    let mut session = match DebugSession::path(sha1).map(|path| DebugSession::load(&path)) {
+        Some(Ok(session)) => session,
+        Some(Err(e)) => {
+            println!("{}", e);
+            DebugSession::default()
+        },
+        None => DebugSession::default()
+    };
+    if !session.breakpoints.is_empty() {
+        move_session_breakpoints(&mut session, sha1);
+    }
+    if !session.is_empty() {
+        println!("Restored debug session: {} watches", session.watches.len());
+    }
+    session.set_given_watches(&options.watch);
+    session
+}
+
+// breakpoints kept by older sessions become the rom's "session" breakpoint
+// group, so there's only the one place they're kept
+fn move_session_breakpoints(session: &mut DebugSession, sha1: &str) {
+    let path = match BreakpointGroups::path(sha1) {
+        Some(path) => path,
+        None => return
+    };
+    let moved = BreakpointGroups::load(&path).and_then(|mut groups| {
+        groups.add("session", &session.breakpoints);
+        groups.save(&path)
+    });
+    match moved {
+        Ok(()) => {
+            println!("Moved the session's breakpoints into breakpoint group session");
+            session.breakpoints.clear();
+            save_session(session, sha1);
+        },
+        Err(e) => println!("{}", e)
+    }
+}
+
+// the rom's memory labels, none if there's no file or it's broken
+fn load_labels(sha1: &str) -> MemoryLabels {
+    match MemoryLabels::path(sha1).map(|path| MemoryLabels::load(&path)) {
+        Some(Ok(labels)) => labels,
+        Some(Err(e)) => {
+            println!("{}", e);
+            MemoryLabels::default()
+        },
+        None => MemoryLabels::default()
+    }
+}
+
+fn save_session(session: &DebugSession, sha1: &str) {
+    let path = match DebugSession::path(sha1) {
+        Some(path) => path,
+        None => return
+    };
+    // no need for a file until there's something in it
+    if session.is_empty() && !path.exists() {
+        return;
+    }
+    if let Err(e) = session.save(&path) {
+        println!("{}", e);
+    }
+}
+
+// the command line's breakpoints and the rom's enabled breakpoint groups
+fn load_breakpoints(options: &Options, sha1: &str) -> Vec<Breakpoint> {
+    let mut breakpoints = options.breakpoints();
+    let groups = match BreakpointGroups::path(sha1).map(|path| BreakpointGroups::load(&path)) {
+        Some(Ok(groups)) => groups,
+        Some(Err(e)) => {
+            println!("{}", e);
+            return breakpoints;
+        },
+        None => return breakpoints
+    };
+    let enabled: Vec<&str> = groups.groups().iter()
+        .filter(|(_, group)| group.enabled)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !enabled.is_empty() {
+        println!("Breakpoint groups: {}", enabled.join(", "));
+    }
+    breakpoints.extend(groups.enabled());
+    breakpoints
+}
+
+// the rom's achievements, if it has a trigger file in the config directory
+fn load_achievements(sha1: &str) -> Option<Achievements> {
+    let path = Achievements::path(sha1).filter(|path| path.exists())?;
+    match Achievements::load(&path) {
+        Ok(achievements) => {
+            println!("Loaded {} achievements from {}", achievements.len(), path.display());
+            Some(achievements)
+        },
+        Err(e) => {
+            println!("{}", e);
+            None
+        }
+    }
+}
+
+// the rom's macro file in the config directory, by the rom's contents so
+// renamed copies share it
+fn macro_path(rom_path: &str) -> Result<std::path::PathBuf, String> {
+    Macro::path(&rom_sha1(rom_path)?).ok_or_else(|| "Could not find a config directory, set HOME or XDG_CONFIG_HOME".to_string())
+}
+
+// the rom loaded into a new machine, set up by the rom's profile which is
+// returned for the rest of its settings
+fn boot(rom_path: &str, options: &Options) -> Result<(Cpu, Ram, Profile), String> {
+    let mut file = File::open(rom_path).map_err(|e| format!("Could not open {}: {}", rom_path, e))?;
+    let mut data = Vec::<u8>::new();
+    file.read_to_end(&mut data).map_err(|e| format!("Could not read {}: {}", rom_path, e))?;
+    let profile = frontend::profile(options, &romdb::sha1(&data));
+    if let Some(program) = options.program {
+        data = split::select(&data, program)?;
+    }
+
+    let mut ram = Ram::new();
+    ram.set_policy(options.memory_policy());
+    let mut cpu = Cpu::new();
+    cpu.set_strict(options.strict);
+    cpu.set_extensions(options.extensions);
+    cpu.set_invalid_opcode_policy(options.invalid_opcodes);
+    // cheap enough to always keep, cleared every frame
+    cpu.start_journal();
+    if let Some(frames) = options.key_wait_timeout {
+        cpu.set_key_wait_timeout(frames, options.key_wait_action());
+    }
+    if let Some(seed) = options.seed {
+        cpu.set_rng(Box::new(SeededRng::new(seed)));
+    }
+
+    // load rom into Chip8
+    chip8::load_rom(&mut ram, &data)?;
+    profile.apply(&mut cpu);
+    frontend::skip_boot(&mut cpu, &mut ram, &profile, options);
+
+    Ok((cpu, ram, profile))
+}
+
+// the game's window, the size of screen
+fn open_window(caption: &str, screen: &Screen) -> Result<Window, String> {
+    let mut window = Window::new(
+        caption,
+        screen.width,
+        screen.height,
+        WindowOptions {
+            scale: Scale::X1,
+            ..WindowOptions::default()
+        }
+    ).map_err(|e| format!("Could not open window: {}", e))?;
+    window.limit_update_rate(Some(FRAME_TIME));
+    Ok(window)
+}
+
+fn draw_kiosk_banner(screen: &mut Screen, title: &str) {
+    let (area_x, area_y, area_w, area_h) = screen.game_area();
+    let scale = if area_w >= 384 { 2 } else { 1 };
+    let band_height = overlay::CELL_HEIGHT * scale + 4 * scale;
+    let y = area_y + area_h - band_height;
+
+    let mut text = format!("{} - PRESS ANY KEY TO PLAY", title);
+    if Overlay::text_width(&text, scale) > area_w {
+        text = title.to_string();
+    }
+    let x = area_x + area_w.saturating_sub(Overlay::text_width(&text, scale)) / 2;
+
+    let mut overlay = Overlay::new(&mut screen.pixels, screen.width, screen.height);
+    overlay.shade_rect(area_x, y, area_w, band_height);
+    overlay.draw_text(x, y + 2 * scale, &text, PX_ON, scale);
+}
+
+// a message across the top of the game, e.g. an unlocked achievement
+fn draw_notification(screen: &mut Screen, text: &str) {
+    let (area_x, area_y, area_w, _) = screen.game_area();
+    let scale = if area_w >= 384 { 2 } else { 1 };
+    let band_height = overlay::CELL_HEIGHT * scale + 4 * scale;
+    let x = area_x + area_w.saturating_sub(Overlay::text_width(text, scale)) / 2;
+
+    let mut overlay = Overlay::new(&mut screen.pixels, screen.width, screen.height);
+    overlay.shade_rect(area_x, area_y, area_w, band_height);
+    overlay.draw_text(x, area_y + 2 * scale, text, PX_ON, scale);
+}
+
+// V0-VF, I, PC and the timers in the top right corner of the game
+fn draw_registers(screen: &mut Screen, cpu: &Cpu) {
+    let v = cpu.reg_gpr();
+    let mut lines = vec![
+        format!("PC {:03X}  I {:03X}", cpu.reg_pc(), cpu.reg_i()),
+        format!("DT {:02X}  ST {:02X}", cpu.reg_dt(), cpu.reg_st())
+    ];
+    for (row, regs) in v.chunks(4).enumerate() {
+        let cells: Vec<String> = regs.iter().enumerate()
+            .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value))
+            .collect();
+        lines.push(cells.join(" "));
+    }
+
+    let (area_x, area_y, area_w, _) = screen.game_area();
+    let width = lines.iter().map(|line| Overlay::text_width(line, 1)).max().unwrap_or(0) + 4;
+    let height = lines.len() * overlay::CELL_HEIGHT + 4;
+    let x = area_x + area_w.saturating_sub(width);
+
+    let mut overlay = Overlay::new(&mut screen.pixels, screen.width, screen.height);
+    overlay.shade_rect(x, area_y, width, height);
+    for (row, line) in lines.iter().enumerate() {
+        overlay.draw_text(x + 2, area_y + 2 + row * overlay::CELL_HEIGHT, line, PX_ON, 1);
+    }
+}
+
+// the watches in the top left corner
+fn draw_watches(screen: &mut Screen, watches: &[Watch], cpu: &Cpu, ram: &Ram, labels: &MemoryLabels) {
+    if watches.is_empty() {
+        return;
+    }
+    let lines: Vec<String> = watches.iter().map(|watch| watch.describe(cpu, ram, labels)).collect();
+
+    let (area_x, area_y, _, _) = screen.game_area();
+    let width = lines.iter().map(|line| Overlay::text_width(line, 1)).max().unwrap_or(0) + 4;
+    let height = lines.len() * overlay::CELL_HEIGHT + 4;
+
+    let mut overlay = Overlay::new(&mut screen.pixels, screen.width, screen.height);
+    overlay.shade_rect(area_x, area_y, width, height);
+    for (row, line) in lines.iter().enumerate() {
+        overlay.draw_text(area_x + 2, area_y + 2 + row * overlay::CELL_HEIGHT, line, PX_ON, 1);
+    }
+}
+
+// the debugger panels the session has open
+fn draw_panels(screen: &mut Screen, session: &DebugSession, labels: &MemoryLabels, cpu: &Cpu, ram: &Ram) {
+    if session.layout.registers {
+        draw_registers(screen, cpu);
+    }
+    if session.layout.watches {
+        draw_watches(screen, &session.all_watches(), cpu, ram, labels);
+    }
+}
+
+// the keypad in a bottom corner of the game with the given keys lit
+fn draw_keypad(screen: &mut Screen, lit: &[bool; 16], right: bool) {
+    let (area_x, area_y, area_w, area_h) = screen.game_area();
+    let size = 4 * overlay::CELL_WIDTH + 4;
+    let x = if right { area_x + area_w.saturating_sub(size) } else { area_x };
+    let y = area_y + area_h.saturating_sub(4 * overlay::CELL_HEIGHT + 4);
+
+    let mut overlay = Overlay::new(&mut screen.pixels, screen.width, screen.height);
+    overlay.shade_rect(x, y, size, 4 * overlay::CELL_HEIGHT + 4);
+    for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+        for (column, &key) in keys.iter().enumerate() {
+            let color = if lit[key as usize] { PX_ON } else { KEY_UNLIT };
+            let text = format!("{:X}", key);
+            overlay.draw_text(x + 2 + column * overlay::CELL_WIDTH, y + 2 + row * overlay::CELL_HEIGHT, &text, color, 1);
+        }
+    }
+}
+
+// what the program wrote to ram this frame, oldest first
+fn print_journal(cpu: &Cpu, labels: &MemoryLabels) {
+    let journal = cpu.journal();
+    if journal.is_empty() {
+        println!("No ram writes this frame");
+        return;
+    }
+    println!("Ram writes this frame:");
+    for write in journal {
+        match labels.name(write.addr) {
+            Some(name) => println!("  {} {}", write, name),
+            None => println!("  {}", write)
+        }
+    }
+}
+
+// how many candidates a ram search lists after each round
+const RAM_SEARCH_SHOWN: usize = 12;
+
+// insert starts a ram search, then each press says how the value being
+// looked for changed since the last one: page up increased, page down
+// decreased, home changed and end unchanged
+fn update_ram_search(window: &Window, search: &mut Option<RamSearch>, ram: &Ram, labels: &MemoryLabels) {
+    if window.is_key_pressed(Key::Insert, KeyRepeat::No) {
+        println!("Ram search started, page up/down for increased/decreased, home for changed, end for unchanged");
+        *search = Some(RamSearch::new(ram));
+        return;
+    }
+    let change = if window.is_key_pressed(Key::PageUp, KeyRepeat::No) {
+        Change::Increased
+    } else if window.is_key_pressed(Key::PageDown, KeyRepeat::No) {
+        Change::Decreased
+    } else if window.is_key_pressed(Key::Home, KeyRepeat::No) {
+        Change::Changed
+    } else if window.is_key_pressed(Key::End, KeyRepeat::No) {
+        Change::Unchanged
+    } else {
+        return;
+    };
+    let search = match search {
+        Some(search) => search,
+        None => {
+            println!("Press insert to start a ram search first");
+            return;
+        }
+    };
+    search.filter(ram, change);
+    let candidates = search.candidates();
+    println!("Ram search round {}: {} addresses {}", search.rounds(), candidates.len(), change);
+    for &addr in candidates.iter().take(RAM_SEARCH_SHOWN) {
+        println!("  {} = {:02X}", labels.describe(addr), search.value(addr));
+    }
+    match candidates.len() {
+        0 => println!("Nothing left, press insert to start again"),
+        n if n <= RAM_SEARCH_SHOWN => println!("Name them in the rom's labels file, or try --score ADDR:u8"),
+        n => println!("  and {} more", n - RAM_SEARCH_SHOWN)
+    }
+}
+
+// the rom to switch to, if kiosk mode moved on or restarted its rom for a
+// player, or a newer one turned up in the watched directory. true when it's
+// a player starting it
+fn next_rom(kiosk: &mut Option<Kiosk>, watcher: &mut Option<DirWatcher>, key_held: bool) -> Option<(String, bool)> {
+    if let Some(kiosk) = kiosk {
+        if let Some(event) = kiosk.update(key_held) {
+            let rom_path = kiosk.current();
+            let played = match event {
+                KioskEvent::NextRom => {
+                    println!("Kiosk showing {}", rom_path);
+                    false
+                },
+                KioskEvent::StartPlaying => {
+                    println!("Kiosk restarting {} for a player", rom_path);
+                    true
+                }
+            };
+            return Some((rom_path, played));
+        }
+    }
+    let path = watcher.as_mut()?.update()?;
+    println!("Loading {}", path);
+    Some((path, false))
+}
+
+// the window with everything: kiosk and watch modes, debugging keys, rewind,
+// macros, high scores and achievements. plays until the window is closed
+// or shutdown is requested
+pub fn run(options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+    let mut kiosk = options.kiosk.as_ref().map(|dir| {
+        Kiosk::open(dir, options.kiosk_time).expect("Could not start kiosk mode.")
+    });
+    let mut watcher = options.watch_dir.as_ref().map(|dir| {
+        DirWatcher::new(dir).expect("Could not watch directory.")
+    });
+    let newest = watcher.as_mut().and_then(|watcher| watcher.newest());
+    let mut rom_path = match (&kiosk, newest, &options.rom) {
+        (Some(kiosk), _, _) => kiosk.current(),
+        (None, Some(newest), _) => newest,
+        (None, None, Some(rom)) => rom.clone(),
+        (None, None, None) if watcher.is_some() => {
+            return Err(String::from("No roms in the watch directory yet, give a rom to start with"));
+        },
+        (None, None, None) => match browser::pick(&options.rom_dir, options.browse_sort, options.scale, shutdown)? {
+            Some(rom) => rom,
+            None => return Ok(())
+        }
+    };
+    let (mut cpu, mut ram, mut profile) = boot(&rom_path, options)?;
+    if options.resume {
+        match Autosave::latest(&rom_path) {
+            Some(path) => {
+                let path = path.to_string_lossy();
+                match State::load(&path).and_then(|state| cpu.load_state(&mut ram, &state)) {
+                    Ok(()) => println!("Resumed from {}", path),
+                    Err(e) => println!("{}", e)
+                }
+            },
+            None => println!("No autosave of {} to resume from", rom_path)
+        }
+    }
+    let mut sha1 = rom_sha1(&rom_path).unwrap_or_default();
+    let mut replay = frontend::start_replay(&mut cpu, &mut ram, &profile, &sha1, options);
+    // the next instruction starts a frame
+    let mut new_frame = true;
+    let mut autosave = options.autosave.map(|interval| Autosave::new(&rom_path, interval, options.autosave_keep));
+    let mut keymap = key_map(&rom_path, &profile);
+    let mut key_filter = options.key_filter();
+    let mut sticky_keys = if options.sticky_keys { Some(StickyKeys::new()) } else { None };
+    // games with a known score location have their best kept across runs
+    let mut high_scores = match HighScores::path() {
+        Some(path) => HighScores::load(&path).unwrap_or_else(|e| {
+            println!("{}", e);
+            HighScores::default()
+        }),
+        None => HighScores::default()
+    };
+    let mut high_scores_changed = false;
+    let mut caption = frontend::caption(TITLE, &sha1);
+    if let Some(spec) = options.score {
+        high_scores.configure(&sha1, spec);
+        high_scores_changed = true;
+    }
+    if let Some(game) = high_scores.get(&sha1) {
+        println!("High score {}", game.best);
+    }
+    let mut achievements = load_achievements(&sha1);
+    // text shown over the game and for how many more frames
+    let mut notification: Option<(String, u32)> = None;
+    // the timers count down at 60Hz by the clock, not by instructions run
+    let mut timer_clock = TimerClock::new(Instant::now());
+    // wall clock start of the current rom, reported with the frame and cycle counts
+    let mut started = Instant::now();
+    // only roms the player started count towards the usage stats, not the
+    // ones kiosk mode cycles through or the watched directory reloads
+    let mut played_since = if kiosk.is_some() { None } else { Some(started) };
+    let mut stats = load_stats();
+
+    // setup audio
+    let mut audio = RodioSink::new().expect("Could not open audio device.");
+    if let Some(hz) = options.beep_frequency {
+        audio.set_frequency(hz);
+    }
+
+    // external programs and hardware keypads can drive the keypad too
+    let mut external_inputs = Vec::new();
+    if let Some(addr) = &options.remote_input {
+        external_inputs.push(RemoteInput::listen(addr).expect("Could not start remote input."));
+    }
+    if let Some(path) = &options.keypad_serial {
+        external_inputs.push(RemoteInput::open_serial(path).expect("Could not open serial keypad."));
+    }
+    if let Some(path) = &options.keypad_midi {
+        external_inputs.push(RemoteInput::open_midi(path, options.midi_base_note).expect("Could not open midi keypad."));
+    }
+
+    let mut screenshot_series = options.screenshot_series.as_ref().map(|dir| {
+        let prefix = kiosk::rom_title(std::path::Path::new(&rom_path)).to_lowercase().replace(' ', "_");
+        ScreenshotSeries::new(dir, &prefix, options.stable_frames, options.screenshot_scale)
+            .expect("Could not start screenshot series.")
+    });
+
+    let mut idle_detector = options.idle_timeout.map(IdleDetector::new);
+    let mut paused = false;
+    // keys have to be let go once after pausing, so a key that was held
+    // when the machine paused doesn't resume it straight away
+    let mut resume_armed = false;
+    // delete was pressed, the rom starts over before the next instruction
+    let mut reset = false;
+    // toggled with `, see --slow-motion
+    let mut slow_motion = false;
+    let mut session = load_session(options, &sha1);
+    let mut labels = load_labels(&sha1);
+    let mut ram_search: Option<RamSearch> = None;
+    let mut breakpoints = FrameBreakpoints::new(load_breakpoints(options, &sha1));
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    let mut first_opcodes = if options.break_new_opcodes { Some(FirstOpcodeBreak::new()) } else { None };
+
+    // F8 starts and stops recording a macro, F10 plays it back
+    let mut recording: Option<Macro> = None;
+    // shift+F12 records what's shown, see --record-format
+    let mut video: Option<Recording> = None;
+    let mut playback: Option<MacroPlayer> = None;
+
+    let mut pacer = FramePacer::new(frontend::speed(replay.as_ref(), &profile));
+
+    let bezel = options.bezel.as_ref().map(|path| {
+        Bezel::load(path, options.bezel_cutout).expect("Could not load bezel.")
+    });
+    let mut screen = Screen::new(bezel, options.scale, options.border);
+    screen.set_palette(profile.palette(options.palette()));
+    if options.ghosting > 0.0 {
+        screen.set_phosphor(Some(Phosphor::new(options.ghosting)));
+    }
+    if let Some(effects) = options.palette_animation.filter(|effects| !effects.is_empty()) {
+        screen.set_palette_animation(Some(Box::new(effects)));
+    }
+    let crt = options.crt();
+    if !crt.is_empty() {
+        screen.set_crt(Some(Crt::new(crt)));
+    }
+
+    let mut window = open_window(&caption, &screen).unwrap();
+    let mut scale = options.scale;
+
+    // every presented frame goes through the compositor so it can also be
+    // mirrored elsewhere
+    let mut compositor = Compositor::new();
+    if options.mirror_window {
+        let mirror = Window::new(
+            "chip-8 rust (mirror)",
+            screen.width,
+            screen.height,
+            WindowOptions {
+                scale: Scale::X1,
+                ..WindowOptions::default()
+            }
+        ).expect("Could not open mirror window.");
+        compositor.add_mirror(Box::new(mirror));
+    }
+    if let Some(path) = &options.mirror_raw {
+        compositor.add_mirror(Box::new(RawSink::create(path, options.mirror_raw_format)?));
+    }
+
+    while window.is_open() && !shutdown.requested() {
+        // delete starts the rom over, paused or not. backspace is taken by
+        // rewind
+        if reset {
+            reset = false;
+            match boot(&rom_path, options) {
+                Ok(machine) => {
+                    cpu = machine.0;
+                    ram = machine.1;
+                    println!("Reset {}", rom_path);
+                    frontend::restart_replay(&mut replay, &mut cpu, &mut ram, &profile, &sha1, options);
+                    pacer = FramePacer::new(frontend::speed(replay.as_ref(), &profile));
+                    new_frame = true;
+                },
+                Err(e) => println!("{}, keeping the rom running", e)
+            }
+            breakpoints = FrameBreakpoints::new(load_breakpoints(options, &sha1));
+            rewind.clear();
+            if let Some(first_opcodes) = &mut first_opcodes {
+                first_opcodes.reset();
+            }
+            playback = None;
+            audio.stop_beep();
+            timer_clock.reset(Instant::now());
+        }
+
+        // a netplay client starts a frame once the host's keys for it are in
+        if new_frame && !frontend::replay_ready(&mut replay, &mut cpu, &mut ram) {
+            compositor.present(&mut window, &screen.pixels, screen.width, screen.height).unwrap();
+            continue;
+        }
+        // while a replay goes the keys only change between frames, the way
+        // Chip8::run_frame reads them, so it plays back the same
+        if let Some(player) = replay.as_mut().and_then(ReplayRun::player) {
+            if new_frame {
+                cpu.handle_keypress(player);
+            }
+        } else if replay.is_none() || new_frame {
+            cpu.handle_keypress(&mut Keyboard::new(&window, &keymap));
+            for input in &mut external_inputs {
+                cpu.merge_keypress(input);
+            }
+            if let Some(key_filter) = &mut key_filter {
+                key_filter.update(&cpu.keys(), Instant::now());
+                cpu.handle_keypress(key_filter);
+            }
+            // the rom sees the latched keys instead of the ones held down
+            if let Some(sticky_keys) = &mut sticky_keys {
+                sticky_keys.update(&cpu.keys());
+                cpu.handle_keypress(sticky_keys);
+            }
+            if let Some(player) = &mut playback {
+                cpu.merge_keypress(player);
+            }
+        }
+        new_frame = false;
+
+        if paused {
+            // any key wakes the machine back up
+            let key_held = cpu.keys().iter().any(|&k| k != 0);
+            if !key_held {
+                resume_armed = true;
+            }
+            // minifb keeps a key pressed until the window is next updated,
+            // so P, delete and F1 are read once per update, here while paused
+            // and at the end of a frame otherwise
+            if window.is_key_pressed(Key::P, KeyRepeat::No) || (key_held && resume_armed) {
+                println!("Resuming");
+                paused = false;
+                timer_clock.reset(Instant::now());
+                cpu.clear_journal();
+                if let Some(detector) = &mut idle_detector {
+                    detector.reset();
+                }
+            } else {
+                // F4 lists the ram writes made in the frame the machine paused in
+                if window.is_key_pressed(Key::F4, KeyRepeat::No) {
+                    print_journal(&cpu, &labels);
+                }
+                reset = window.is_key_pressed(Key::Delete, KeyRepeat::No);
+                if window.is_key_pressed(Key::F1, KeyRepeat::No) {
+                    frontend::take_over_replay(&mut replay);
+                }
+                update_ram_search(&window, &mut ram_search, &ram, &labels);
+                screen.present(cpu.gfx());
+                draw_panels(&mut screen, &session, &labels, &cpu, &ram);
+                compositor.present(&mut window, &screen.pixels, screen.width, screen.height).unwrap();
+                continue;
+            }
+        }
+
+        // holding backspace plays the last few seconds backwards, a frame at
+        // a time, and play carries on from wherever it's let go
+        if window.is_key_down(Key::Backspace) {
+            if pacer.step() {
+                if let Some((rewound_cpu, rewound_ram)) = rewind.pop() {
+                    cpu = rewound_cpu;
+                    ram = rewound_ram;
+                    frontend::replay_rewound(&mut replay, &mut cpu, &mut ram, &sha1);
+                }
+                new_frame = true;
+                audio.stop_beep();
+                screen.present(cpu.gfx());
+                compositor.present(&mut window, &screen.pixels, screen.width, screen.height).unwrap();
+                timer_clock.reset(Instant::now());
+            }
+            continue;
+        }
+
+        // slow motion shows each frame a few times before running the next.
+        // the window isn't updated meanwhile, so keys pressed in between are
+        // still there for the hotkeys at the end of the frame
+        if pacer.hold() {
+            thread::sleep(FRAME_TIME);
+            continue;
+        }
+
+        if let Some(first_opcodes) = &mut first_opcodes {
+            if let Some(hit) = first_opcodes.update(&cpu, &ram) {
+                println!("Break: {}\n{}", hit, cpu.dump(&ram));
+                println!("Paused, press a key to run it");
+                paused = true;
+                resume_armed = false;
+                audio.stop_beep();
+                continue;
+            }
+        }
+
+        // nothing changes until the delay timer ticks, so end the frame now
+        if options.unsafe_speedups && replay.is_none() && cpu.waiting_on_dt(&ram) {
+            pacer.finish_early();
+        }
+
+        if let Err(e) = cpu.run_instruction(&mut ram) {
+            println!("{}, stopping\n{}", e, cpu.dump(&ram));
+            // what the frame did leading up to a violation
+            if options.strict {
+                print_journal(&cpu, &labels);
+            }
+            save_halt_snapshot(options, &rom_path, &cpu, &ram);
+            break;
+        }
+        if let Some(reason) = cpu.halted() {
+            println!("{}, stopping\n{}", reason, cpu.dump(&ram));
+            save_halt_snapshot(options, &rom_path, &cpu, &ram);
+            break;
+        }
+
+        let frame_done = pacer.step();
+        // turbo runs several frames for each one shown, recordings keep them
+        // all. replays never run in turbo, so none of these is a guess
+        if !frame_done && pacer.machine_frame_ended() {
+            frontend::record_frame(&mut video, cpu.gfx(), &screen.palette());
+        }
+
+        // the end of a frame: timers, drawing and hotkeys
+        if frame_done {
+            new_frame = true;
+            // a replay's timers go by frames rather than the clock
+            let fixed_ticks = replay.as_ref().map(|_| 1);
+            let mut ticks = pacer.timer_ticks().or(fixed_ticks).unwrap_or_else(|| timer_clock.ticks(Instant::now()));
+            // skipping a delay loop needs DT to run out sooner too
+            if options.unsafe_speedups && cpu.waiting_on_dt(&ram) {
+                ticks = ticks.max(1);
+            }
+            for _ in 0..ticks {
+                cpu.tick_timers(&mut audio);
+            }
+            // stderr isn't buffered, so the lines survive a crash
+            for line in cpu.take_opcode_log() {
+                eprintln!("{}", line);
+            }
+            // a netplay frame run on a guess may yet be rolled back
+            let guessed = replay.as_ref().is_some_and(ReplayRun::guessing);
+            if !guessed {
+                rewind.push(&cpu, &ram);
+            }
+            frontend::replay_frame(&mut replay, &mut cpu, &mut ram);
+            if !guessed {
+                if let Some(score) = high_scores.update(&sha1, ram.memory()) {
+                    window.set_title(&format!("{} - high score {}", caption, score));
+                    high_scores_changed = true;
+                }
+                if let Some(achievements) = &mut achievements {
+                    for name in achievements.update(&cpu, &ram) {
+                        println!("Achievement unlocked: {}", name);
+                        notification = Some((format!("UNLOCKED: {}", name), NOTIFICATION_FRAMES));
+                    }
+                }
+            }
+            for input in &external_inputs {
+                input.report(Timing::of(&cpu, started));
+            }
+            if let Some(hit) = breakpoints.update(&cpu, &mut ram, &labels) {
+                println!("Break: {}\n{}", hit, cpu.dump(&ram));
+                println!("Paused, press a key to continue, F4 lists this frame's ram writes");
+                paused = true;
+                resume_armed = false;
+                audio.stop_beep();
+            }
+
+            // kiosk mode and the watched directory both move on to other
+            // roms, which are switched to the same way
+            let key_held = cpu.keys().iter().any(|&k| k != 0);
+            if let Some((path, played)) = next_rom(&mut kiosk, &mut watcher, key_held) {
+                // a rom that doesn't boot leaves the last one running as it was
+                match boot(&path, options) {
+                    Ok(machine) => {
+                        record_play(&mut stats, &sha1, &rom_path, played_since);
+                        save_high_scores(&high_scores, &mut high_scores_changed);
+                        save_session(&session, &sha1);
+                        rom_path = path;
+                        cpu = machine.0;
+                        ram = machine.1;
+                        profile = machine.2;
+                        screen.set_palette(profile.palette(options.palette()));
+                        sha1 = rom_sha1(&rom_path).unwrap_or_default();
+                        caption = frontend::caption(TITLE, &sha1);
+                        window.set_title(&caption);
+                        achievements = load_achievements(&sha1);
+                        notification = None;
+                        keymap = key_map(&rom_path, &profile);
+                        if let Some(autosave) = &mut autosave {
+                            autosave.set_rom(&rom_path);
+                        }
+                        started = Instant::now();
+                        played_since = if played { Some(started) } else { None };
+                        session = load_session(options, &sha1);
+                        labels = load_labels(&sha1);
+                        ram_search = None;
+                        frontend::replay_jumped(&mut replay, &mut cpu, &mut ram, &sha1);
+                        pacer = FramePacer::new(frontend::speed(replay.as_ref(), &profile));
+                        breakpoints = FrameBreakpoints::new(load_breakpoints(options, &sha1));
+                        rewind.clear();
+                        if let Some(first_opcodes) = &mut first_opcodes {
+                            first_opcodes.reset();
+                        }
+                        recording = None;
+                        playback = None;
+                        audio.stop_beep();
+                    },
+                    Err(e) => println!("{}, keeping the last rom running", e)
+                }
+            }
+
+            if options.state_checksum && cpu.frames().is_multiple_of(CHECKSUM_FRAMES) {
+                let checksum = cpu.save_state(&ram).checksum();
+                window.set_title(&format!("{} - frame {} state {}", caption, cpu.frames(), checksum));
+            }
+
+            if let Some(series) = &mut screenshot_series {
+                if let Some(path) = series.update(cpu.gfx()) {
+                    println!("Saved screenshot {}", path);
+                }
+            }
+
+            screen.animate(Moment {
+                frame: cpu.frames(),
+                sound: cpu.reg_st() > 0
+            });
+            screen.present(cpu.gfx());
+            if let Some(kiosk) = &kiosk {
+                if kiosk.attracting() {
+                    draw_kiosk_banner(&mut screen, &kiosk.title());
+                }
+            }
+            // tab held runs --turbo frames per frame drawn, ` toggles slow
+            // motion
+            if window.is_key_pressed(Key::Backquote, KeyRepeat::No) {
+                slow_motion = !slow_motion;
+                let text = if slow_motion { format!("SLOW MOTION 1/{}", options.slow_motion) } else { String::from("NORMAL SPEED") };
+                notification = Some((text, NOTIFICATION_FRAMES));
+            }
+            // turbo frames would run together, so replays go without
+            let rate = if window.is_key_down(Key::Tab) && replay.is_none() {
+                Rate::Turbo(options.turbo)
+            } else if slow_motion {
+                Rate::Slow(options.slow_motion)
+            } else {
+                Rate::Normal
+            };
+            if rate != pacer.rate() {
+                // the wall clock takes the timers back from where they are
+                timer_clock.reset(Instant::now());
+                pacer.set_rate(rate);
+            }
+
+            // - and = step the window scale, the window is opened again at
+            // the new size
+            let new_scale = if window.is_key_pressed(Key::Equal, KeyRepeat::No) && scale < frontend::MAX_RUNTIME_SCALE {
+                scale + 1
+            } else if window.is_key_pressed(Key::Minus, KeyRepeat::No) && scale > frontend::MIN_RUNTIME_SCALE {
+                scale - 1
+            } else {
+                scale
+            };
+            if new_scale != scale {
+                if screen.set_scale(new_scale, options.border) {
+                    scale = new_scale;
+                    screen.present(cpu.gfx());
+                    window = open_window(&caption, &screen)?;
+                    println!("Scale {}", scale);
+                } else {
+                    println!("The bezel sets the window size");
+                }
+            }
+            // F12 saves the display in the colors it's shown in, shift+F12
+            // starts and stops recording it
+            if window.is_key_pressed(Key::F12, KeyRepeat::No) {
+                if window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift) {
+                    frontend::toggle_recording(&mut video, options, &rom_path, scale);
+                } else {
+                    frontend::save_screenshot(&Image::new(cpu.gfx(), &screen.palette(), scale), options, &rom_path);
+                }
+            }
+            if !guessed {
+                frontend::record_frame(&mut video, cpu.gfx(), &screen.palette());
+            }
+            if window.is_key_pressed(Key::F11, KeyRepeat::No) {
+                println!("minifb windows can't go fullscreen, --frontend sdl can");
+            }
+
+            // F2 toggles the watch panel, F3 the registers
+            if window.is_key_pressed(Key::F2, KeyRepeat::No) {
+                session.layout.watches = !session.layout.watches;
+            }
+            if window.is_key_pressed(Key::F3, KeyRepeat::No) {
+                session.layout.registers = !session.layout.registers;
+            }
+            draw_panels(&mut screen, &session, &labels, &cpu, &ram);
+            // the latched sticky keys in the bottom left corner, the keys the
+            // frame just run saw held in the bottom right
+            if let Some(sticky_keys) = &sticky_keys {
+                draw_keypad(&mut screen, &sticky_keys.latched(), false);
+            }
+            if options.input_display {
+                draw_keypad(&mut screen, &cpu.keys().map(|key| key != 0), true);
+            }
+            notification = notification.filter(|&(_, frames_left)| frames_left > 0);
+            if let Some((text, frames_left)) = &mut notification {
+                draw_notification(&mut screen, text);
+                *frames_left -= 1;
+            }
+            compositor.present(&mut window, &screen.pixels, screen.width, screen.height).unwrap();
+
+            if let Some(detector) = &mut idle_detector {
+                if detector.update(&cpu.keys(), cpu.gfx()) {
+                    println!("No activity for {}s", options.idle_timeout.unwrap().as_secs());
+                    if options.idle_action.snapshots() {
+                        let path = idle_state_path(&rom_path);
+                        match cpu.save_state(&ram).export(&path) {
+                            Ok(()) => println!("Saved idle snapshot to {}", path),
+                            Err(e) => println!("{}", e)
+                        }
+                    }
+                    if options.idle_action.pauses() {
+                        println!("Pausing until a key is pressed");
+                        paused = true;
+                        resume_armed = false;
+                        audio.stop_beep();
+                    }
+                }
+            }
+
+            if let Some(autosave) = autosave.as_mut().filter(|autosave| !paused && autosave.due(Instant::now())) {
+                autosave.save(&cpu.save_state(&ram), Instant::now());
+            }
+
+            // delete starts the rom over, P pauses, and P or any keypad key
+            // resumes
+            reset = window.is_key_pressed(Key::Delete, KeyRepeat::No);
+            if window.is_key_pressed(Key::P, KeyRepeat::No) && !paused {
+                println!("Paused, press P or a key to continue");
+                paused = true;
+                resume_armed = false;
+                audio.stop_beep();
+            }
+            // F1 takes over a replay being played, paused or not, and records
+            // on from the next frame
+            if window.is_key_pressed(Key::F1, KeyRepeat::No) {
+                frontend::take_over_replay(&mut replay);
+            }
+
+            // F5 quick saves the machine, F9 puts it back exactly as it was
+            if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+                let path = quick_state_path(&rom_path);
+                match cpu.save_state(&ram).save(&path) {
+                    Ok(()) => {
+                        println!("Saved state to {}", path);
+                        frontend::replay_saved(&mut replay, &cpu, &ram);
+                    },
+                    Err(e) => println!("{}", e)
+                }
+            }
+            if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+                let path = quick_state_path(&rom_path);
+                match State::load(&path).and_then(|state| cpu.load_state(&mut ram, &state)) {
+                    Ok(()) => {
+                        println!("Loaded state from {}", path);
+                        frontend::replay_loaded(&mut replay, &mut cpu, &mut ram, &sha1);
+                    },
+                    Err(e) => println!("{}", e)
+                }
+            }
+
+            if let Some(keys) = recording.as_mut().filter(|_| !guessed) {
+                keys.record(&cpu.keys());
+            }
+            if let Some(player) = &mut playback {
+                player.next_frame();
+                if player.finished() {
+                    println!("Macro finished");
+                    playback = None;
+                }
+            }
+            if window.is_key_pressed(Key::F8, KeyRepeat::No) {
+                match recording.take() {
+                    None => {
+                        println!("Recording a macro, F8 again to stop");
+                        recording = Some(Macro::new());
+                        playback = None;
+                    }
+                    Some(keys) => {
+                        match macro_path(&rom_path).and_then(|path| keys.save(&path).map(|()| path)) {
+                            Ok(path) => println!("Saved {} frame macro to {}", keys.len(), path.display()),
+                            Err(e) => println!("{}", e)
+                        }
+                    }
+                }
+            }
+            if window.is_key_pressed(Key::F10, KeyRepeat::No) && recording.is_none() {
+                match macro_path(&rom_path).and_then(|path| Macro::load(&path)) {
+                    Ok(keys) => {
+                        println!("Playing {} frame macro", keys.len());
+                        playback = Some(MacroPlayer::new(keys));
+                    }
+                    Err(e) => println!("{}", e)
+                }
+            }
+
+            // F6 exports the machine state as json, F7 imports it again
+            if window.is_key_pressed(Key::F6, KeyRepeat::No) {
+                let path = state_path(&rom_path);
+                match cpu.save_state(&ram).export(&path) {
+                    Ok(()) => {
+                        println!("Exported state to {}", path);
+                        frontend::replay_saved(&mut replay, &cpu, &ram);
+                    },
+                    Err(e) => println!("{}", e)
+                }
+            }
+            if window.is_key_pressed(Key::F7, KeyRepeat::No) {
+                let path = state_path(&rom_path);
+                match State::import(&path).and_then(|state| cpu.load_state(&mut ram, &state)) {
+                    Ok(()) => {
+                        println!("Imported state from {}", path);
+                        frontend::replay_loaded(&mut replay, &mut cpu, &mut ram, &sha1);
+                    },
+                    Err(e) => println!("{}", e)
+                }
+            }
+            update_ram_search(&window, &mut ram_search, &ram, &labels);
+
+            // a paused machine keeps the frame's writes for F4
+            if !paused {
+                cpu.clear_journal();
+            }
+        }
+    }
+
+    // a macro still being recorded is kept, as if F8 had been pressed
+    if let Some(keys) = recording.take() {
+        match macro_path(&rom_path).and_then(|path| keys.save(&path).map(|()| path)) {
+            Ok(path) => println!("Saved {} frame macro to {}", keys.len(), path.display()),
+            Err(e) => println!("{}", e)
+        }
+    }
+    if let Some(video) = video.take() {
+        frontend::finish_recording(video);
+    }
+    for line in cpu.finish_opcode_log() {
+        eprintln!("{}", line);
+    }
+    if let Some(run) = replay.take() {
+        frontend::finish_replay(run);
+    }
+    // one last autosave of where the machine stopped
+    if let Some(mut autosave) = autosave {
+        autosave.save(&cpu.save_state(&ram), Instant::now());
+        autosave.finish();
+    }
+    audio.stop_beep();
+    save_high_scores(&high_scores, &mut high_scores_changed);
+    save_session(&session, &sha1);
+    record_play(&mut stats, &sha1, &rom_path, played_since);
+
+    Ok(())
+}
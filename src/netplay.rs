@@ -0,0 +1,858 @@
+use std::collections::VecDeque;
+use std::io::{
+    BufRead,
+    BufReader,
+    ErrorKind,
+    Write
+};
+use std::net::{
+    Shutdown,
+    SocketAddr,
+    TcpListener,
+    TcpStream,
+    ToSocketAddrs
+};
+use std::sync::mpsc::{
+    self,
+    Receiver,
+    RecvTimeoutError,
+    SyncSender,
+    TryRecvError,
+    TrySendError
+};
+use std::thread;
+use std::time::Duration;
+
+use crate::audio::SilentSink;
+use crate::chip8;
+use crate::cpu::Cpu;
+use crate::ram::Ram;
+use crate::replay::{
+    Replay,
+    ReplayPlayer
+};
+use crate::rng::SeededRng;
+use crate::state::State;
+
+// the first line a host sends, so clients know it speaks their protocol
+const GREETING: &str = "rust8 netplay 1";
+
+// sent in place of the greeting when every spectator slot is taken
+const FULL: &str = "rust8 netplay full";
+
+// frames between the checksums a host sends
+pub const CHECK_FRAMES: usize = 60;
+
+// how long a client waits to connect, then for the greeting and the start.
+// a paused host only lets clients in once it carries on, so joining one
+// gives up rather than hanging
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// lines waiting to go out to a client, about two seconds of frames. a
+// client that lets more pile up is dropped rather than holding the host's
+// game up
+const CLIENT_QUEUE: usize = 120;
+
+// what a host sends after the greeting, one per line:
+//   start {replay json}  start over from the replay's machine, see Replay
+//   keys 1A2F            the keys of the next frame, bit n set for key n
+//   check 120 3f2a9c01   the machine's checksum that many frames after its start
+enum Message {
+    Start(Box<Replay>),
+    Keys([u8; 16]),
+    Check(usize, String)
+}
+
+fn parse_message(line: &str) -> Result<Message, String> {
+    let invalid = || format!("Invalid netplay message: {}", line);
+    let (kind, rest) = line.split_once(' ').ok_or_else(invalid)?;
+    match kind {
+        "start" => serde_json::from_str(rest)
+            .map(|replay| Message::Start(Box::new(replay)))
+            .map_err(|e| format!("Invalid netplay start: {}", e)),
+        "keys" => {
+            let bits = u16::from_str_radix(rest, 16).map_err(|_| invalid())?;
+            let mut keys = [0; 16];
+            for (key, held) in keys.iter_mut().enumerate() {
+                *held = (bits >> key & 1) as u8;
+            }
+            Ok(Message::Keys(keys))
+        },
+        "check" => {
+            let (frame, checksum) = rest.split_once(' ').ok_or_else(invalid)?;
+            let frame = frame.parse().map_err(|_| invalid())?;
+            Ok(Message::Check(frame, checksum.to_string()))
+        },
+        _ => Err(invalid())
+    }
+}
+
+// the first of addr's addresses that takes the connection
+fn connect_to(addr: &str) -> Result<TcpStream, String> {
+    let mut error = format!("Could not connect to {}: no address found", addr);
+    let addrs = addr.to_socket_addrs()
+        .map_err(|e| format!("Could not connect to {}: {}", addr, e))?;
+    for socket in addrs {
+        match TcpStream::connect_timeout(&socket, HANDSHAKE_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = format!("Could not connect to {}: {}", addr, e)
+        }
+    }
+    Err(error)
+}
+
+fn key_bits(keys: &[u8; 16]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .filter(|&(_, &held)| held != 0)
+        .fold(0, |bits, (key, _)| bits | 1 << key)
+}
+
+// a joined client. a thread of its own writes the lines queued for it, so
+// a slow one can't hold the host up
+struct Client {
+    lines: SyncSender<String>,
+    // to cut the client off when it's dropped
+    stream: TcpStream,
+    // frames sent since its last start, None until it's had one
+    frames: Option<usize>
+}
+
+impl Client {
+    fn join(stream: TcpStream) -> Result<Client, String> {
+        let mut writer = stream.try_clone()
+            .map_err(|e| format!("Could not set up netplay client: {}", e))?;
+        let (lines, queued) = mpsc::sync_channel::<String>(CLIENT_QUEUE);
+        thread::spawn(move || {
+            for line in queued {
+                if writeln!(writer, "{}", line).is_err() {
+                    break;
+                }
+            }
+        });
+        let client = Client {
+            lines,
+            stream,
+            frames: None
+        };
+        if !client.send(GREETING) {
+            return Err(String::from("Netplay client left"));
+        }
+        Ok(client)
+    }
+
+    // queues line, false if the client went away or its queue is full,
+    // either way it's done with
+    fn send(&self, line: &str) -> bool {
+        match self.lines.try_send(line.to_string()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                println!("Netplay client couldn't keep up, dropping it");
+                let _ = self.stream.shutdown(Shutdown::Both);
+                false
+            },
+            Err(TrySendError::Disconnected(_)) => {
+                println!("Netplay client left");
+                false
+            }
+        }
+    }
+}
+
+// the player's side: streams every frame's keys to whoever joins, as many
+// spectators as there are slots for. a client that joins starts from the
+// machine as it is then, the others carry on
+pub struct NetplayHost {
+    // polled for clients by joined, it closes with the host
+    listener: TcpListener,
+    clients: Vec<Client>,
+    addr: SocketAddr,
+    // clients let in at once, any number if None
+    spectators: Option<usize>
+}
+
+impl NetplayHost {
+    pub fn listen(addr: &str) -> Result<NetplayHost, String> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|e| format!("Could not listen on {}: {}", addr, e))?;
+        let local = listener.local_addr()
+            .and_then(|local| listener.set_nonblocking(true).map(|()| local))
+            .map_err(|e| format!("Could not listen on {}: {}", addr, e))?;
+        Ok(NetplayHost {
+            listener,
+            clients: Vec::new(),
+            addr: local,
+            spectators: None
+        })
+    }
+
+    // turns away clients joining once this many are in
+    pub fn set_spectators(&mut self, spectators: Option<usize>) {
+        self.spectators = spectators;
+    }
+
+    // where clients connect, with the port picked if 0 was given
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    // takes in the clients that connected since the last call, true if
+    // there were any. they need a start before anything else, see
+    // start_joined
+    pub fn joined(&mut self) -> bool {
+        let mut joined = Vec::new();
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => joined.push(stream),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    println!("Could not let a netplay client in: {}", e);
+                    break;
+                }
+            }
+        }
+        let before = self.clients.len();
+        for mut stream in joined {
+            // the client's writer thread blocks, and a frame's keys should
+            // go out straight away
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_nodelay(true);
+            let peer = stream.peer_addr()
+                .map(|peer| format!(" from {}", peer))
+                .unwrap_or_default();
+            if self.spectators.is_some_and(|spectators| self.clients.len() >= spectators) {
+                println!("Netplay client{} turned away, every spectator slot is taken", peer);
+                let _ = writeln!(stream, "{}", FULL);
+                continue;
+            }
+            match Client::join(stream) {
+                Ok(client) => {
+                    println!("Netplay client joined{}", peer);
+                    self.clients.push(client);
+                },
+                Err(e) => println!("{}", e)
+            }
+        }
+        self.clients.len() > before
+    }
+
+    // starts every client over from the machine as it is now, which goes
+    // on untouched, e.g. when the host loads a state. RND has to be
+    // seeded for clients to follow it, see Replay::record_here
+    pub fn start(&mut self, cpu: &Cpu, ram: &Ram, rom_sha1: &str, instructions_per_frame: usize) -> Result<(), String> {
+        self.send_start(cpu, ram, rom_sha1, instructions_per_frame, true)
+    }
+
+    // starts the clients that joined since the last start from the
+    // machine as it is now, without disturbing the ones already watching
+    pub fn start_joined(&mut self, cpu: &Cpu, ram: &Ram, rom_sha1: &str, instructions_per_frame: usize) -> Result<(), String> {
+        self.send_start(cpu, ram, rom_sha1, instructions_per_frame, false)
+    }
+
+    fn send_start(&mut self, cpu: &Cpu, ram: &Ram, rom_sha1: &str, instructions_per_frame: usize, everyone: bool) -> Result<(), String> {
+        let replay = Replay::record_here(cpu, ram, rom_sha1, instructions_per_frame)?;
+        let json = serde_json::to_string(&replay)
+            .map_err(|e| format!("Could not serialize netplay start: {}", e))?;
+        let line = format!("start {}", json);
+        self.clients.retain_mut(|client| {
+            if client.frames.is_some() && !everyone {
+                return true;
+            }
+            client.frames = Some(0);
+            client.send(&line)
+        });
+        Ok(())
+    }
+
+    // call at the end of every frame, with the keys the frame ran with
+    pub fn send_frame(&mut self, cpu: &Cpu, ram: &Ram) {
+        let keys = format!("keys {:04X}", key_bits(&cpu.keys()));
+        // worked out once, for whichever clients have a check due
+        let mut checksum = None;
+        self.clients.retain_mut(|client| {
+            let frames = match client.frames.as_mut() {
+                Some(frames) => {
+                    *frames += 1;
+                    *frames
+                },
+                None => return true
+            };
+            if !frames.is_multiple_of(CHECK_FRAMES) {
+                return client.send(&keys);
+            }
+            let checksum = checksum.get_or_insert_with(|| cpu.save_state(ram).checksum());
+            let check = format!("check {} {}", frames, checksum);
+            client.send(&keys) && client.send(&check)
+        });
+    }
+}
+
+// the watching side: runs each frame once the host's keys for it have
+// arrived, so both machines go through exactly the same frames. keys that
+// come in unevenly can be smoothed over with a delay, and a rollback lets
+// the client run ahead of them on a guess
+pub struct NetplayClient {
+    messages: Receiver<Result<Message, String>>,
+    player: ReplayPlayer,
+    // frames of the replay whose keys came from the host, the ones after
+    // are guesses
+    confirmed: usize,
+    // checksums the host sent, and this machine's for the same frames,
+    // until both are in
+    checks: VecDeque<(usize, String)>,
+    sums: VecDeque<(usize, String)>,
+    // frames kept in hand before any run, see set_delay
+    delay: usize,
+    buffering: bool,
+    // frames that can run ahead on guesses, see set_rollback
+    rollback: usize,
+    // the machine before the first frame run on a guess, gone back to
+    // when a guess turns out wrong
+    guessed_from: Option<Guess>
+}
+
+// a machine saved before a guessed frame. saved states leave out RND and a
+// key FX0A waits on, so those are kept with it
+struct Guess {
+    frame: usize,
+    state: State,
+    seed: Option<u64>,
+    waiting_key: Option<u8>
+}
+
+impl NetplayClient {
+    // joins the host at addr, waiting for it to send the machine to start
+    // from, which cpu and ram are then set to. gives up after
+    // HANDSHAKE_TIMEOUT at each step
+    pub fn connect(addr: &str, cpu: &mut Cpu, ram: &mut Ram) -> Result<NetplayClient, String> {
+        let stream = connect_to(addr)?;
+        let _ = stream.set_nodelay(true);
+        let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        let mut reader = BufReader::new(stream);
+        let mut greeting = String::new();
+        match reader.read_line(&mut greeting) {
+            Ok(_) if greeting.trim_end() == GREETING => (),
+            Ok(_) if greeting.trim_end() == FULL => return Err(format!("{} has no room for another spectator", addr)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                return Err(format!("{} didn't let the client in, it may be paused", addr));
+            },
+            _ => return Err(format!("{} is not a rust8 netplay host", addr))
+        }
+        // a paused host sends nothing for as long as it likes
+        let _ = reader.get_ref().set_read_timeout(None);
+        let lines = reader.lines();
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in lines {
+                let message = line
+                    .map_err(|e| format!("Lost the netplay host: {}", e))
+                    .and_then(|line| parse_message(&line));
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let replay = match messages.recv_timeout(HANDSHAKE_TIMEOUT) {
+            Ok(Ok(Message::Start(replay))) => replay,
+            Ok(Err(e)) => return Err(e),
+            Err(RecvTimeoutError::Timeout) => return Err(format!("{} didn't start the machine, it may be paused", addr)),
+            _ => return Err(format!("{} didn't start the machine", addr))
+        };
+        replay.restart(cpu, ram, None)?;
+        Ok(NetplayClient::new(messages, *replay))
+    }
+
+    // starts over from replay's machine, with nothing from the host yet
+    fn start(&mut self, replay: Replay) {
+        self.player = ReplayPlayer::new(replay);
+        self.confirmed = 0;
+        self.checks.clear();
+        self.sums.clear();
+        self.buffering = self.delay > 0;
+        self.guessed_from = None;
+    }
+
+    fn new(messages: Receiver<Result<Message, String>>, replay: Replay) -> NetplayClient {
+        NetplayClient {
+            messages,
+            player: ReplayPlayer::new(replay),
+            confirmed: 0,
+            checks: VecDeque::new(),
+            sums: VecDeque::new(),
+            delay: 0,
+            buffering: false,
+            rollback: 0,
+            guessed_from: None
+        }
+    }
+
+    // keeps frames of the host's keys in hand before running any, and
+    // again whenever they run out, so a late frame doesn't hold play up.
+    // the client falls that many frames behind the host
+    pub fn set_delay(&mut self, frames: usize) {
+        self.delay = frames;
+        self.buffering = frames > 0;
+    }
+
+    // lets up to frames frames run ahead of the host's keys, guessing
+    // they're held as in the last frame that came. a wrong guess loads
+    // the machine as it was before it and runs the frames again
+    pub fn set_rollback(&mut self, frames: usize) {
+        self.rollback = frames;
+    }
+
+    pub fn instructions_per_frame(&self) -> usize {
+        self.player.replay().instructions_per_frame
+    }
+
+    // the host's keys, to read in place of the keyboard
+    pub fn player(&mut self) -> &mut ReplayPlayer {
+        &mut self.player
+    }
+
+    // takes in what the host has sent so far, starting over if it says
+    // to. true once the keys for the next frame are here, or guessed. an
+    // error means the host is gone and the frames it sent have all been
+    // run, or the machine went out of step with it
+    pub fn ready(&mut self, cpu: &mut Cpu, ram: &mut Ram) -> Result<bool, String> {
+        let mut wrong = false;
+        let gone = loop {
+            match self.messages.try_recv() {
+                Ok(Ok(Message::Start(replay))) => {
+                    replay.restart(cpu, ram, None)?;
+                    self.start(*replay);
+                    wrong = false;
+                },
+                Ok(Ok(Message::Keys(keys))) => wrong |= self.confirm(&keys),
+                Ok(Ok(Message::Check(frame, checksum))) => self.checks.push_back((frame, checksum)),
+                Ok(Err(e)) => return Err(e),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true
+            }
+        };
+        if wrong {
+            self.roll_back(cpu, ram)?;
+        }
+        if self.confirmed >= self.player.frame() {
+            self.guessed_from = None;
+        }
+        self.verify()?;
+
+        let frame = self.player.frame();
+        let buffered = self.confirmed.saturating_sub(frame);
+        if gone {
+            return match buffered {
+                0 => Err(String::from("The netplay host has gone")),
+                _ => Ok(true)
+            };
+        }
+        if self.buffering {
+            if buffered <= self.delay {
+                return Ok(false);
+            }
+            self.buffering = false;
+        }
+        if buffered > 0 {
+            return Ok(true);
+        }
+        if frame - self.confirmed < self.rollback {
+            self.guess(cpu, ram);
+            return Ok(true);
+        }
+        self.buffering = self.delay > 0;
+        Ok(false)
+    }
+
+    // whether the frames since the host's last keys ran on guesses, which
+    // a rollback may yet run again differently
+    pub fn guessing(&self) -> bool {
+        self.guessed_from.is_some()
+    }
+
+    // call at the end of every frame run, checks the machine against the
+    // host's when a checksum is due
+    pub fn end_frame(&mut self, cpu: &Cpu, ram: &Ram) -> Result<(), String> {
+        self.frame_ran(cpu, ram);
+        self.verify()
+    }
+
+    // takes in the host's keys for the next frame it hasn't sent, true if
+    // that frame was guessed wrong
+    fn confirm(&mut self, keys: &[u8; 16]) -> bool {
+        let replay = self.player.replay_mut();
+        let wrong = match replay.held(self.confirmed) {
+            Some(guess) => guess.iter().zip(keys).any(|(&guessed, &held)| guessed != (held != 0)),
+            None => false
+        };
+        if wrong || replay.len() <= self.confirmed {
+            replay.truncate(self.confirmed);
+            replay.push(keys);
+        }
+        self.confirmed += 1;
+        wrong
+    }
+
+    // the next frame runs ahead of the host's keys: the machine is saved
+    // before the first such frame, and its keys are the last ones the
+    // host sent
+    fn guess(&mut self, cpu: &Cpu, ram: &Ram) {
+        let frame = self.player.frame();
+        if self.guessed_from.is_none() {
+            self.guessed_from = Some(Guess {
+                frame,
+                state: cpu.save_state(ram),
+                seed: cpu.rng_seed(),
+                waiting_key: cpu.waiting_key()
+            });
+        }
+        let replay = self.player.replay_mut();
+        if replay.len() <= frame {
+            let mut keys = [0; 16];
+            if let Some(held) = self.confirmed.checked_sub(1).and_then(|last| replay.held(last)) {
+                for (key, &down) in keys.iter_mut().zip(&held) {
+                    *key = down as u8;
+                }
+            }
+            replay.push(&keys);
+        }
+    }
+
+    // loads the machine from before the first guess and runs the frames
+    // since again, with the host's keys where they're in now
+    fn roll_back(&mut self, cpu: &mut Cpu, ram: &mut Ram) -> Result<(), String> {
+        let from = match self.guessed_from.take() {
+            Some(from) => from,
+            None => return Ok(())
+        };
+        let to = self.player.frame();
+        cpu.load_state(ram, &from.state)?;
+        if let Some(seed) = from.seed {
+            cpu.set_rng(Box::new(SeededRng::new(seed)));
+        }
+        cpu.set_waiting_key(from.waiting_key);
+        self.player.seek(from.frame);
+        self.sums.retain(|&(frame, _)| frame <= from.frame);
+        let instructions_per_frame = self.instructions_per_frame();
+        while self.player.frame() < to && cpu.halted().is_none() {
+            if self.player.frame() >= self.confirmed {
+                self.guess(cpu, ram);
+            }
+            cpu.handle_keypress(&mut self.player);
+            chip8::run_frame(cpu, ram, instructions_per_frame, &mut SilentSink);
+            self.frame_ran(cpu, ram);
+        }
+        Ok(())
+    }
+
+    // notes the machine's checksum when the host will send its own
+    fn frame_ran(&mut self, cpu: &Cpu, ram: &Ram) {
+        self.player.next_frame();
+        let frame = self.player.frame();
+        if frame.is_multiple_of(CHECK_FRAMES) {
+            self.sums.push_back((frame, cpu.save_state(ram).checksum()));
+        }
+    }
+
+    // compares the checksums of frames run on the host's keys, not ones
+    // that may yet be rolled back
+    fn verify(&mut self) -> Result<(), String> {
+        while let Some((due, checksum)) = self.checks.front() {
+            if *due > self.confirmed || *due > self.player.frame() {
+                break;
+            }
+            while self.sums.front().is_some_and(|(frame, _)| frame < due) {
+                self.sums.pop_front();
+            }
+            if let Some((frame, sum)) = self.sums.front() {
+                if frame == due && sum != checksum {
+                    return Err(format!("Netplay went out of step with the host at frame {}", due));
+                }
+            }
+            self.checks.pop_front();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{
+        parse_message,
+        Message,
+        CHECK_FRAMES,
+        NetplayClient,
+        NetplayHost
+    };
+    use crate::chip8::Chip8;
+    use crate::cpu::Cpu;
+    use crate::ram::Ram;
+    use crate::replay::tests::{
+        keys,
+        ROM
+    };
+    use crate::replay::Replay;
+    use crate::rng::SeededRng;
+    use crate::romdb;
+    use crate::state::State;
+
+    // joins host and watches frames frames, then gives back where the
+    // machine ended up and the frames since the client's last start.
+    // machines can't move between threads, their states can
+    fn watch(host: &NetplayHost, frames: usize) -> thread::JoinHandle<(State, usize)> {
+        let addr = host.local_addr().to_string();
+        thread::spawn(move || {
+            let mut chip8 = Chip8::new();
+            let mut client = NetplayClient::connect(&addr, &mut chip8.cpu, &mut chip8.ram).unwrap();
+            chip8.set_instructions_per_frame(client.instructions_per_frame());
+            for _ in 0..frames {
+                while !client.ready(&mut chip8.cpu, &mut chip8.ram).unwrap() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                run_frame(&mut chip8, &mut client).unwrap();
+            }
+            (chip8.save_state(), client.player().frame())
+        })
+    }
+
+    // runs frames frames on the host at about the pace a real one goes,
+    // all at once would overrun the clients' queues
+    fn host_frames(host: &mut NetplayHost, chip8: &mut Chip8, frames: std::ops::Range<usize>) {
+        for frame in frames {
+            chip8.run_frame(&keys(frame));
+            host.send_frame(&chip8.cpu, &chip8.ram);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn clients_go_through_the_hosts_frames() {
+        let mut host = NetplayHost::listen("127.0.0.1:0").unwrap();
+        let watching = watch(&host, 120);
+
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        chip8.cpu.set_rng(Box::new(SeededRng::new(42)));
+        chip8.set_instructions_per_frame(9);
+        for frame in 0..30 {
+            chip8.run_frame(&keys(frame));
+        }
+        while !host.joined() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        // the client starts from the game as it is, the host carries on
+        let before = chip8.save_state();
+        host.start(&chip8.cpu, &chip8.ram, &romdb::sha1(&ROM), 9).unwrap();
+        assert_eq!(chip8.save_state(), before);
+        host_frames(&mut host, &mut chip8, 0..120);
+        assert_eq!(watching.join().unwrap().0, chip8.save_state());
+    }
+
+    #[test]
+    fn spectators_join_without_starting_the_others_over() {
+        let mut host = NetplayHost::listen("127.0.0.1:0").unwrap();
+        host.set_spectators(Some(2));
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        chip8.cpu.set_rng(Box::new(SeededRng::new(42)));
+        chip8.set_instructions_per_frame(9);
+        let sha1 = romdb::sha1(&ROM);
+
+        let first = watch(&host, 150);
+        while !host.joined() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        host.start_joined(&chip8.cpu, &chip8.ram, &sha1, 9).unwrap();
+        host_frames(&mut host, &mut chip8, 0..30);
+
+        // the second joins halfway through the first's checks
+        let second = watch(&host, 120);
+        while !host.joined() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        host.start_joined(&chip8.cpu, &chip8.ram, &sha1, 9).unwrap();
+
+        // a third finds every slot taken
+        let addr = host.local_addr().to_string();
+        let third = thread::spawn(move || NetplayClient::connect(&addr, &mut Cpu::new(), &mut Ram::new()).err());
+        while !third.is_finished() {
+            host.joined();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(third.join().unwrap(), Some(format!("{} has no room for another spectator", host.local_addr())));
+        assert_eq!(host.clients(), 2);
+
+        host_frames(&mut host, &mut chip8, 30..150);
+        assert_eq!(first.join().unwrap(), (chip8.save_state(), 150));
+        assert_eq!(second.join().unwrap(), (chip8.save_state(), 120));
+    }
+
+    #[test]
+    fn clients_that_fall_behind_are_dropped() {
+        let mut host = NetplayHost::listen("127.0.0.1:0").unwrap();
+        // connects but never reads
+        let _stalled = TcpStream::connect(host.local_addr()).unwrap();
+        while !host.joined() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        // lines big enough to fill the socket's buffers, then the queue
+        let line = "x".repeat(64 * 1024);
+        for _ in 0..10_000 {
+            if host.clients() == 0 {
+                break;
+            }
+            host.clients.retain(|client| client.send(&line));
+        }
+        assert_eq!(host.clients(), 0);
+    }
+
+    #[test]
+    fn clients_give_up_on_a_host_that_never_starts() {
+        let mut host = NetplayHost::listen("127.0.0.1:0").unwrap();
+        let addr = host.local_addr().to_string();
+        let joining = thread::spawn(move || NetplayClient::connect(&addr, &mut Cpu::new(), &mut Ram::new()).err());
+        while !host.joined() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(joining.join().unwrap(), Some(format!("{} didn't start the machine, it may be paused", host.local_addr())));
+    }
+
+    #[test]
+    fn hosts_stop_listening_when_dropped() {
+        let host = NetplayHost::listen("127.0.0.1:0").unwrap();
+        let addr = host.local_addr();
+        drop(host);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    // a client fed by the returned sender in place of a host, on a machine
+    // set up as the host's is
+    fn client(chip8: &mut Chip8) -> (mpsc::Sender<Result<Message, String>>, NetplayClient) {
+        chip8.cpu.set_rng(Box::new(SeededRng::new(42)));
+        chip8.set_instructions_per_frame(9);
+        let replay = Replay::record_here(&chip8.cpu, &chip8.ram, &romdb::sha1(&ROM), 9).unwrap();
+        let (host, messages) = mpsc::channel();
+        (host, NetplayClient::new(messages, replay))
+    }
+
+    // runs the frame the client is ready for, as a frontend would
+    fn run_frame(chip8: &mut Chip8, client: &mut NetplayClient) -> Result<(), String> {
+        let player = client.player();
+        let keys = player.replay().held(player.frame()).unwrap();
+        chip8.run_frame(&keys);
+        client.end_frame(&chip8.cpu, &chip8.ram)
+    }
+
+    fn send_keys(host: &mpsc::Sender<Result<Message, String>>, frame: usize) {
+        let mut held = [0; 16];
+        for (key, &down) in held.iter_mut().zip(&keys(frame)) {
+            *key = down as u8;
+        }
+        host.send(Ok(Message::Keys(held))).unwrap();
+    }
+
+    #[test]
+    fn clients_stop_when_out_of_step() {
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        let (host, mut client) = client(&mut chip8);
+        // the host's checksum when one is due isn't this machine's
+        for frame in 0..CHECK_FRAMES {
+            send_keys(&host, frame);
+        }
+        host.send(Ok(Message::Check(CHECK_FRAMES, String::from("00000000")))).unwrap();
+        for _ in 1..CHECK_FRAMES {
+            assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+            assert_eq!(run_frame(&mut chip8, &mut client), Ok(()));
+        }
+        assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+        assert_eq!(run_frame(&mut chip8, &mut client), Err(format!("Netplay went out of step with the host at frame {}", CHECK_FRAMES)));
+    }
+
+    #[test]
+    fn clients_keep_the_delay_in_hand() {
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        let (host, mut client) = client(&mut chip8);
+        client.set_delay(3);
+        for frame in 0..3 {
+            send_keys(&host, frame);
+            assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(false));
+        }
+        send_keys(&host, 3);
+        // then it runs what it has, and once that runs out it waits for
+        // the delay's worth again
+        for _ in 0..4 {
+            assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+            run_frame(&mut chip8, &mut client).unwrap();
+        }
+        assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(false));
+        for frame in 4..7 {
+            send_keys(&host, frame);
+            assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(false));
+        }
+        send_keys(&host, 7);
+        assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+    }
+
+    #[test]
+    fn clients_roll_back_wrong_guesses() {
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        let (host, mut client) = client(&mut chip8);
+        client.set_rollback(10);
+        for frame in 0..5 {
+            send_keys(&host, frame);
+        }
+        // five frames on the host's keys, ten on guesses, then it waits
+        for _ in 0..15 {
+            assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+            run_frame(&mut chip8, &mut client).unwrap();
+        }
+        assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(false));
+        assert!(client.guessing());
+
+        // most guesses were wrong, so once the keys come the frames run
+        // again and end up where the host's did
+        let mut live = Chip8::with_rom(&ROM).unwrap();
+        live.cpu.set_rng(Box::new(SeededRng::new(42)));
+        live.set_instructions_per_frame(9);
+        for frame in 0..15 {
+            live.run_frame(&keys(frame));
+        }
+        for frame in 5..CHECK_FRAMES {
+            send_keys(&host, frame);
+        }
+        assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+        assert_eq!(chip8.save_state(), live.save_state());
+        assert!(!client.guessing());
+
+        for frame in 15..CHECK_FRAMES {
+            live.run_frame(&keys(frame));
+        }
+        host.send(Ok(Message::Check(CHECK_FRAMES, live.save_state().checksum()))).unwrap();
+        for _ in 15..CHECK_FRAMES {
+            assert_eq!(client.ready(&mut chip8.cpu, &mut chip8.ram), Ok(true));
+            assert_eq!(run_frame(&mut chip8, &mut client), Ok(()));
+        }
+        assert_eq!(chip8.save_state(), live.save_state());
+    }
+
+    #[test]
+    fn bad_messages_are_errors() {
+        for line in &["", "hello", "hello there", "keys", "keys 1G", "keys 12345", "check 60", "check x 3f2a9c01", "start {"] {
+            assert!(parse_message(line).is_err(), "{}", line);
+        }
+        assert_eq!(parse_message("hello there").err(), Some(String::from("Invalid netplay message: hello there")));
+        match parse_message("keys 0003") {
+            Ok(Message::Keys(keys)) => assert_eq!(keys[..3], [1, 1, 0]),
+            _ => panic!("keys 0003 didn't parse")
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::quirks::Quirks;
+use crate::ram::Ram;
+use crate::spec::TIMER_HZ;
+
+// invalid opcodes logged per second of machine time, the rest are only
+// counted so a PC running through data doesn't fill the disk
+const LOG_PER_SECOND: u32 = 10;
+// memory shown before the opcode, the opcode and what follows make up the
+// other half
+const BYTES_BEFORE: usize = 4;
+
+// what an invalid opcode does
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InvalidOpcodePolicy {
+    // the machine stops with an error
+    Stop,
+    // the opcode is logged and stepped over
+    Skip
+}
+
+impl InvalidOpcodePolicy {
+    pub fn parse(name: &str) -> Option<InvalidOpcodePolicy> {
+        match name {
+            "stop" => Some(InvalidOpcodePolicy::Stop),
+            "skip" => Some(InvalidOpcodePolicy::Skip),
+            _ => None
+        }
+    }
+}
+
+// one skipped invalid opcode and what the machine looked like around it
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidOpcode {
+    pub opcode: u16,
+    pub pc: u16,
+    // the 8 bytes from 4 before pc, None past either end of memory
+    pub bytes: [Option<u8>; 8],
+    pub quirks: Quirks,
+    pub frame: u64
+}
+
+impl InvalidOpcode {
+    pub fn new(opcode: u16, pc: u16, ram: &Ram, quirks: Quirks, frame: u64) -> InvalidOpcode {
+        let mut bytes = [None; 8];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            let addr = (pc as usize + offset).checked_sub(BYTES_BEFORE);
+            *byte = addr.and_then(|addr| ram.memory().get(addr).copied());
+        }
+        InvalidOpcode {
+            opcode,
+            pc,
+            bytes,
+            quirks,
+            frame
+        }
+    }
+}
+
+// one line of key value pairs, the opcode's bytes in brackets, e.g.
+// "invalid_opcode pc=0234 opcode=812F bytes=A2,1E,60,00,[81,2F],60,00 quirks=vip frame=75"
+impl fmt::Display for InvalidOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().enumerate()
+            .map(|(offset, byte)| {
+                let hex = byte.map(|byte| format!("{:02X}", byte)).unwrap_or_else(|| String::from("--"));
+                match offset {
+                    BYTES_BEFORE => format!("[{}", hex),
+                    _ if offset == BYTES_BEFORE + 1 => format!("{}]", hex),
+                    _ => hex
+                }
+            })
+            .collect();
+        write!(f, "invalid_opcode pc={:04X} opcode={:04X} bytes={} quirks={} frame={}",
+            self.pc, self.opcode, bytes.join(","), self.quirks, self.frame)
+    }
+}
+
+// the lines to log for skipped invalid opcodes, at most LOG_PER_SECOND for
+// each second of machine time. a line after a second's last says how many
+// it left out
+#[derive(Clone, Debug, Default)]
+pub struct InvalidOpcodeLog {
+    second: u64,
+    logged: u32,
+    suppressed: u64,
+    pending: Vec<String>
+}
+
+impl InvalidOpcodeLog {
+    pub fn new() -> InvalidOpcodeLog {
+        InvalidOpcodeLog::default()
+    }
+
+    pub fn record(&mut self, entry: &InvalidOpcode) {
+        let second = entry.frame / TIMER_HZ as u64;
+        if second != self.second {
+            self.end_second();
+            self.second = second;
+            self.logged = 0;
+            self.suppressed = 0;
+        }
+        if self.logged < LOG_PER_SECOND {
+            self.logged += 1;
+            self.pending.push(entry.to_string());
+        } else {
+            self.suppressed += 1;
+        }
+    }
+
+    // the lines recorded since the last call, for the frontend to write out
+    // straight away. once frame is past the second being counted, what it
+    // left out comes too, rather than waiting on another invalid opcode
+    pub fn take(&mut self, frame: u64) -> Vec<String> {
+        if frame / TIMER_HZ as u64 != self.second {
+            self.end_second();
+        }
+        std::mem::take(&mut self.pending)
+    }
+
+    // every line left, what the second so far left out included, for when
+    // the machine stops
+    pub fn finish(&mut self) -> Vec<String> {
+        self.end_second();
+        std::mem::take(&mut self.pending)
+    }
+
+    fn end_second(&mut self) {
+        if self.suppressed > 0 {
+            self.pending.push(format!("invalid_opcode_suppressed count={} second={}", self.suppressed, self.second));
+            self.suppressed = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        InvalidOpcode,
+        InvalidOpcodeLog,
+        LOG_PER_SECOND
+    };
+    use crate::quirks::Quirks;
+    use crate::ram::Ram;
+
+    #[test]
+    fn a_runaway_pc_only_logs_a_few_lines_a_second() {
+        let ram = Ram::new();
+        let entry = InvalidOpcode::new(0xFFFF, 0x0FFE, &ram, Quirks::VIP, 0);
+        assert_eq!(entry.to_string(), "invalid_opcode pc=0FFE opcode=FFFF bytes=00,00,00,00,[00,00],--,-- quirks=vip frame=0");
+
+        let mut log = InvalidOpcodeLog::new();
+        for _ in 0..100 {
+            log.record(&entry);
+        }
+        assert_eq!(log.take(0).len(), LOG_PER_SECOND as usize);
+        assert!(log.take(59).is_empty());
+
+        // a second later the count of what was left out comes first
+        log.record(&InvalidOpcode::new(0xFFFF, 0x0200, &ram, Quirks::VIP, 60));
+        let lines = log.take(60);
+        assert_eq!(lines[0], "invalid_opcode_suppressed count=90 second=0");
+        assert!(lines[1].starts_with("invalid_opcode pc=0200"));
+    }
+
+    #[test]
+    fn what_was_left_out_is_logged_without_another_invalid_opcode() {
+        let ram = Ram::new();
+        let entry = InvalidOpcode::new(0xFFFF, 0x0FFE, &ram, Quirks::VIP, 0);
+        let mut log = InvalidOpcodeLog::new();
+        for _ in 0..15 {
+            log.record(&entry);
+        }
+        log.take(0);
+        // as the second ends
+        assert_eq!(log.take(60), vec![String::from("invalid_opcode_suppressed count=5 second=0")]);
+        assert!(log.take(61).is_empty());
+
+        // or as the machine stops part way through one
+        for _ in 0..12 {
+            log.record(&InvalidOpcode::new(0xFFFF, 0x0FFE, &ram, Quirks::VIP, 70));
+        }
+        log.take(70);
+        assert_eq!(log.finish(), vec![String::from("invalid_opcode_suppressed count=2 second=1")]);
+        assert!(log.finish().is_empty());
+    }
+}
@@ -0,0 +1,663 @@
+use std::time::Duration;
+
+use clap::{
+    Parser,
+    Subcommand
+};
+
+use rust8::idle::IdleAction;
+use rust8::bezel;
+use rust8::animation::PaletteEffects;
+use rust8::config;
+use rust8::crt::CrtEffects;
+use rust8::present::{
+    self,
+    Palette,
+    SCREEN_SCALE
+};
+use rust8::cpu::{
+    Extensions,
+    KeyWaitTimeout
+};
+use rust8::input::{
+    KeyFilter,
+    KeyLayout
+};
+use rust8::keymap;
+use rust8::opcode_log::InvalidOpcodePolicy;
+use rust8::profile::Profile;
+use rust8::quirks::Quirks;
+use rust8::recording::RecordFormat;
+use rust8::ram::MemoryPolicy;
+use rust8::session::Watch;
+use rust8::sink::PixelFormat;
+use rust8::score::ScoreSpec;
+use rust8::timing::Speed;
+use rust8::breakpoint::{
+    self,
+    Breakpoint,
+    Timer
+};
+
+use crate::browser::Sort;
+use crate::frontend;
+
+// the most --fast-boot skips when a rom never checks the keypad, a minute
+const FAST_BOOT_FRAMES: u64 = 3600;
+
+// emulator options read from the command line
+#[derive(Parser)]
+#[command(name = "rust8", version, about = "CHIP-8 interpreter", subcommand_negates_reqs = true)]
+pub struct Options {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // roms can also come from --kiosk or --watch-dir. with none of them the
+    // minifb frontend lets the user pick one from --rom-dir
+    #[arg(help = "Rom to run")]
+    pub rom: Option<String>,
+
+    #[arg(long, value_name = "DIR", default_value = ".", help = "Where the rom browser looks when no rom is given")]
+    pub rom_dir: String,
+
+    #[arg(long, default_value = "title", value_parser = parse_browse_sort, help = "List the rom browser by title, recent or most-played")]
+    pub browse_sort: Sort,
+
+    #[arg(long, default_value_t = SCREEN_SCALE, value_parser = parse_scale, help = "Window pixels per CHIP-8 pixel")]
+    pub scale: usize,
+
+    // speed, quirks, colors and layout fall back on the rom's profile, see
+    // `rust8 profile`
+    #[arg(long, value_parser = parse_speed, help = "Instructions run per 60Hz frame, or unlimited [default: 9]")]
+    pub speed: Option<Speed>,
+
+    #[arg(long, value_parser = parse_quirks, help = "none, vip, schip, or a list like shift-vy,clip-sprites")]
+    pub quirks: Option<Quirks>,
+
+    // one of frontend::FRONTENDS
+    #[arg(long, default_value = "minifb", value_parser = parse_frontend, help = "minifb, or sdl and tui in builds with them")]
+    pub frontend: String,
+
+    // known roms pick their own layout, see romdb
+    #[arg(long, value_parser = parse_layout, help = "standard or two-player, instead of the rom's usual layout")]
+    pub layout: Option<KeyLayout>,
+
+    #[arg(long, help = "Tapping a key latches it down until it's tapped again")]
+    pub sticky_keys: bool,
+
+    // the keys each frame ran with, for tutorials and streams
+    #[arg(long, help = "Show the keypad with the held keys lit in the corner of the game")]
+    pub input_display: bool,
+
+    // for files holding several programs, see `rust8 split`
+    #[arg(long, value_name = "N", help = "Run the Nth program of a multi-program file")]
+    pub program: Option<usize>,
+
+    // roms that run I off the end of memory usually expect it to wrap
+    #[arg(long, default_value = "checked", value_parser = parse_memory, help = "What accesses past 0FFF do: checked stops the rom, mirrored wraps, clamped sticks at 0FFF")]
+    pub memory: MemoryPolicy,
+
+    // skipped opcodes are logged to stderr as they happen
+    #[arg(long, default_value = "stop", value_parser = parse_invalid_opcodes, help = "What invalid opcodes do: stop stops the rom, skip logs and steps over them")]
+    pub invalid_opcodes: InvalidOpcodePolicy,
+
+    // for checking a rom will behave the same on other interpreters
+    #[arg(long, conflicts_with_all = ["memory", "invalid_opcodes"], help = "Stop on anything the spec leaves undefined, with a full report")]
+    pub strict: bool,
+
+    // non-standard opcodes for homebrew written for rust8, see
+    // spec::EXTENSION_OPCODES. off, roms behave like on any interpreter
+    #[arg(long, value_name = "SET", value_parser = parse_extensions, help = "Allow rust8's own opcodes (rust8: FXF1 reads a millisecond clock)")]
+    pub extensions: Option<Extensions>,
+
+    // remembered per rom in the config directory, so it's only needed once
+    #[arg(long, value_name = "ADDR:FORMAT", value_parser = parse_score, help = "Where the rom keeps its score, e.g. 3E0:bcd3, to track high scores")]
+    pub score: Option<ScoreSpec>,
+
+    // for tremors and flaky keyboards, see KeyFilter
+    #[arg(long, value_name = "MS", default_value_t = 0, help = "Milliseconds a key has to stay down or up before it counts")]
+    pub debounce: u64,
+    #[arg(long, value_name = "MS", default_value_t = 0, help = "Milliseconds a key stays down for the rom after a tap")]
+    pub min_hold: u64,
+
+    // address for the remote input socket, tcp:HOST:PORT or unix:PATH
+    #[arg(long, value_name = "ADDRESS", help = "Accept keypad commands on tcp:HOST:PORT or unix:PATH")]
+    pub remote_input: Option<String>,
+
+    // hardware keypads, a serial device speaking the remote input protocol
+    // and/or a raw midi device
+    #[arg(long, value_name = "DEVICE", help = "Serial keypad speaking the remote input protocol")]
+    pub keypad_serial: Option<String>,
+    #[arg(long, value_name = "DEVICE", help = "Raw midi device to use as a keypad")]
+    pub keypad_midi: Option<String>,
+    // C2, the first pad on most drum controllers
+    #[arg(long, default_value_t = 36, help = "Midi note for key 0, the next 15 notes are keys 1-F")]
+    pub midi_base_note: u8,
+
+    // what to do after this long without input or display changes
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, help = "Seconds without activity before going idle")]
+    pub idle_timeout: Option<Duration>,
+    #[arg(long, default_value = "both", value_parser = parse_idle_action, help = "pause, snapshot or both")]
+    pub idle_action: IdleAction,
+
+    // rolling saves next to the rom, so a crash or power cut loses little
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, help = "Save the machine every SECS seconds while playing")]
+    pub autosave: Option<Duration>,
+    #[arg(long, value_name = "N", default_value_t = 3, help = "How many autosaves to keep per rom")]
+    pub autosave_keep: usize,
+    #[arg(long, help = "Start from the rom's newest autosave")]
+    pub resume: bool,
+
+    // directory of roms to cycle through in attract mode, and how long each
+    // one runs before moving on
+    #[arg(long, value_name = "DIR", help = "Cycle through the roms in DIR in attract mode")]
+    pub kiosk: Option<String>,
+    #[arg(long, value_name = "SECS", default_value = "30", value_parser = parse_secs, help = "Seconds each kiosk rom runs")]
+    pub kiosk_time: Duration,
+
+    // run the newest rom in this directory, switching whenever a newer one
+    // is written
+    #[arg(long, value_name = "DIR", help = "Run the newest rom in DIR, switching when a newer one appears")]
+    pub watch_dir: Option<String>,
+
+    // png artwork drawn around the game, with the game placed inside the
+    // cutout rectangle
+    #[arg(long, value_name = "PNG", help = "Artwork to draw around the game")]
+    pub bezel: Option<String>,
+    #[arg(long, value_name = "WxH+X+Y", value_parser = parse_cutout, help = "Where the game goes in the bezel")]
+    pub bezel_cutout: Option<(usize, usize, usize, usize)>,
+
+    // colors for unlit and lit pixels and around the game, and the width
+    // of the border drawn when there's no bezel
+    #[arg(long = "palette", value_name = "NAME|FILE", value_parser = parse_palette, help = "classic, amber, green, lcd, octo, or a json palette file")]
+    pub base_palette: Option<Palette>,
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color, help = "Color of unlit pixels")]
+    pub background: Option<u32>,
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color, help = "Color of lit pixels")]
+    pub foreground: Option<u32>,
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color, help = "Color around the game")]
+    pub border_color: Option<u32>,
+    #[arg(long, value_name = "PIXELS", default_value_t = 0, help = "Border around the game when there's no bezel")]
+    pub border: usize,
+    // the colors turning round the color wheel, and the background
+    // lighting up with the buzzer
+    #[arg(long, value_name = "LIST", value_parser = parse_palette_animation, help = "Comma separated hue-shift[:SECONDS] and flash, or none")]
+    pub palette_animation: Option<PaletteEffects>,
+
+    // phosphor persistence, the fraction of brightness an unlit pixel keeps
+    // each frame. 0 turns ghosting off
+    #[arg(long, default_value_t = 0.0, value_parser = parse_ghosting, help = "Phosphor decay between 0 and 1")]
+    pub ghosting: f32,
+
+    // scanlines, a pixel grid and a vignette over the scaled up game.
+    // without it the user's crt.json in the config directory is used
+    #[arg(long = "crt", value_name = "LIST", value_parser = parse_crt, help = "Comma separated scanlines, grid and vignette, or none")]
+    pub crt_effects: Option<CrtEffects>,
+
+    // show the game in a second window too, e.g. on a projector
+    #[arg(long, help = "Show the game in a second window too")]
+    pub mirror_window: bool,
+    // for displays driven by another program, which reads the frames from
+    // a file or named pipe
+    #[arg(long, value_name = "PATH", help = "Write every frame's raw pixels to PATH too")]
+    pub mirror_raw: Option<String>,
+    #[arg(long, value_name = "FORMAT", default_value = "rgb565", value_parser = parse_pixel_format, help = "Pixels for --mirror-raw: argb8888, rgb565 or mono1")]
+    pub mirror_raw_format: PixelFormat,
+
+    // save a screenshot into this directory whenever the display has been
+    // unchanged for stable_frames frames
+    #[arg(long, value_name = "DIR", help = "Save a screenshot to DIR whenever the display settles")]
+    pub screenshot_series: Option<String>,
+    #[arg(long, default_value_t = 30, help = "Frames the display has to stay the same")]
+    pub stable_frames: usize,
+    #[arg(long, default_value_t = 1, help = "Screenshot pixels per CHIP-8 pixel")]
+    pub screenshot_scale: usize,
+    // runs the start of the rom without showing it, e.g. to get past a long
+    // intro. --fast-boot stops on its own once the rom checks the keypad
+    #[arg(long, value_name = "N", help = "Run the first N frames at full speed without showing them")]
+    pub skip_frames: Option<u64>,
+    #[arg(long, conflicts_with = "skip_frames", help = "Skip ahead until the rom first checks the keypad")]
+    pub fast_boot: bool,
+
+    // F12 saves what's on screen here, named after the rom and the time.
+    // shift+F12 starts and stops recording into it too
+    #[arg(long, value_name = "DIR", default_value = ".", help = "Where F12 saves screenshots and shift+F12 recordings")]
+    pub screenshot_dir: String,
+    #[arg(long, value_name = "FORMAT", default_value = "gif", value_parser = parse_record_format, help = "Record to a gif, an mp4 (needs ffmpeg) or a directory of pngs")]
+    pub record_format: RecordFormat,
+    #[arg(long, value_name = "FPS", default_value_t = 30, value_parser = parse_record_fps, help = "Frames a second kept when recording")]
+    pub record_fps: u32,
+    // the window scale unless given
+    #[arg(long, value_name = "N", value_parser = parse_scale, help = "Recording pixels per CHIP-8 pixel")]
+    pub record_scale: Option<usize>,
+    // also used by sweep, so its report shows where every failing rom stopped
+    #[arg(long, value_name = "DIR", help = "Save a screenshot and state to DIR when a rom halts or faults")]
+    pub halt_snapshots: Option<String>,
+
+    #[arg(long, value_name = "HZ", help = "Pitch of the buzzer")]
+    pub beep_frequency: Option<u32>,
+
+    // frames FX0A may wait for a key, then either press key_wait_key for
+    // the rom or stop the run. keeps unattended runs from hanging
+    #[arg(long, value_name = "FRAMES", help = "Frames FX0A waits for a key before giving up")]
+    pub key_wait_timeout: Option<u32>,
+    #[arg(long, value_name = "KEY", value_parser = parse_key, help = "Key to press when FX0A gives up, instead of stopping")]
+    pub key_wait_key: Option<u8>,
+
+    // pause at these frames or when a timer runs out
+    #[arg(long, value_name = "FRAME", help = "Pause at this frame")]
+    pub break_frame: Vec<u64>,
+    #[arg(long, value_name = "TIMER", value_parser = parse_timer, help = "Pause when dt or st runs out")]
+    pub break_timer: Vec<Timer>,
+    #[arg(long, value_name = "ADDR", value_parser = parse_addr, help = "Pause after a frame that writes this address")]
+    pub break_write: Vec<u16>,
+    #[arg(long, value_name = "ADDR", value_parser = parse_addr, help = "Pause after a frame that reads this address")]
+    pub break_read: Vec<u16>,
+    #[arg(long, help = "Pause before the first instruction of each opcode class")]
+    pub break_new_opcodes: bool,
+
+    // shown with F2 for this run, watches kept for every run go in the rom's
+    // debug session file
+    #[arg(long, value_name = "EXPR", value_parser = parse_watch, help = "Watch v0 to vf, i, pc, dt, st, [ADDR] or [i]")]
+    pub watch: Vec<Watch>,
+
+    // shortcuts that skip work the rom can't observe, but change how many
+    // instructions run per frame
+    #[arg(long, help = "Skip ahead through loops that only wait on the delay timer")]
+    pub unsafe_speedups: bool,
+
+    // tab held runs the machine faster, ` toggles slow motion
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = parse_factor, help = "Frames run for every one drawn while tab is held")]
+    pub turbo: u32,
+
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = parse_factor, help = "Times each frame is drawn in slow motion")]
+    pub slow_motion: u32,
+
+    // lets two people following the same inputs see whether their machines
+    // are still in step
+    #[arg(long, help = "Show a checksum of the machine state in the title every second")]
+    pub state_checksum: bool,
+
+    // together with the same inputs, the same seed gives the same run
+    #[arg(long, help = "Seed the random numbers RND gives so a run can be repeated exactly")]
+    pub seed: Option<u64>,
+
+    // the keys of every frame from boot, saved when the window closes. a
+    // replay carries its own machine, but refuses a rom other than the
+    // one it was recorded on. given both, the replay plays until F1 takes
+    // it over and then records on to --record-replay
+    #[arg(long, value_name = "FILE", help = "Record every frame's keys and the RND seed for exact playback")]
+    pub record_replay: Option<String>,
+    #[arg(long, value_name = "FILE", help = "Play back a replay instead of reading the keyboard")]
+    pub replay: Option<String>,
+
+    // one person plays, whoever joins watches the same game frame for
+    // frame. replays and netplay both fix the frames, so only one goes
+    #[arg(long, value_name = "HOST:PORT", conflicts_with_all = ["record_replay", "replay"], help = "Stream every frame's keys to netplay clients connecting here")]
+    pub netplay_host: Option<String>,
+    // spectators joining late start from the game as it is then, without
+    // holding up the ones already watching
+    #[arg(long, value_name = "N", requires = "netplay_host", help = "Let at most this many netplay clients watch at once")]
+    pub netplay_spectators: Option<usize>,
+    #[arg(long, value_name = "HOST:PORT", conflicts_with_all = ["record_replay", "replay", "netplay_host"], help = "Watch a netplay host's game, in step with it")]
+    pub netplay_join: Option<String>,
+    // for slow or uneven links. the delay keeps that many frames of the
+    // host's keys in hand, the rollback runs on guesses rather than wait
+    // and runs the frames again from a saved state when one was wrong
+    #[arg(long, value_name = "FRAMES", default_value_t = 0, requires = "netplay_join", help = "Frames of the host's keys to keep in hand before running them")]
+    pub netplay_delay: usize,
+    #[arg(long, value_name = "FRAMES", default_value_t = 0, requires = "netplay_join", help = "Frames to run ahead of the host's keys on a guess, rolling back when it's wrong")]
+    pub netplay_rollback: usize
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "List a rom's breakpoint groups, or change them")]
+    Breakpoints {
+        rom: String,
+        #[command(subcommand)]
+        action: Option<GroupAction>
+    },
+
+    #[command(about = "Show the settings kept for a rom, or change them")]
+    Profile {
+        rom: String,
+        // e.g. rust8 --speed 15 --quirks vip profile game.ch8 --save
+        #[arg(long, help = "Keep the --speed, --quirks, --foreground, --background and --layout given")]
+        save: bool,
+        #[arg(long, conflicts_with = "save", help = "Forget the rom's settings")]
+        clear: bool
+    },
+
+    #[command(about = "Show the debug session kept for a rom")]
+    Session {
+        rom: String,
+        #[arg(long, help = "Forget the session, starting the next run fresh")]
+        clear: bool
+    },
+
+    #[command(about = "Show the memory labels kept for a rom and where to edit them")]
+    Labels {
+        rom: String
+    },
+
+    // kept only in the config directory and only once enabled
+    #[command(about = "Show which roms were played and for how long")]
+    Stats {
+        #[arg(long, help = "Start keeping track of play time")]
+        enable: bool,
+        #[arg(long, conflicts_with = "enable", help = "Stop keeping track and delete what was kept")]
+        disable: bool
+    },
+
+    #[command(about = "Print the memory map of a rom")]
+    Map {
+        rom: String
+    },
+
+    #[command(about = "Play a replay headless and check it ends where the recording did")]
+    Replay {
+        file: String
+    },
+
+    // the same every time, unlike recording a run as it's played. at
+    // --record-scale, else --scale, and --record-fps
+    #[command(about = "Play a replay headless into a gif, an mp4 or a directory of pngs")]
+    Render {
+        file: String,
+        #[arg(short, long, value_name = "OUT", help = "A .gif, a .mp4 (needs ffmpeg), or a directory for numbered pngs")]
+        out: String
+    },
+
+    #[command(about = "Run a rom under every combination of quirks and report which ones matter")]
+    QuirkReport {
+        rom: String,
+        #[arg(long, default_value_t = 600, help = "Frames to run each combination for")]
+        frames: usize
+    },
+
+    #[command(about = "Show where two roms differ, in hex and disassembled")]
+    Romdiff {
+        a: String,
+        b: String
+    },
+
+    #[command(about = "Run a small demo one explained instruction at a time")]
+    Tutorial,
+
+    #[command(about = "Run every rom in a directory headless and report which halt")]
+    Sweep {
+        dir: String,
+        // a minute of play
+        #[arg(long, default_value_t = 3600, help = "Frames to run each rom for")]
+        frames: usize
+    },
+
+    #[command(about = "Write DIR/catalog.json listing the distinct roms in DIR")]
+    Catalog {
+        dir: String
+    },
+
+    #[command(about = "Write DIR/index.html showing how each rom of LIST looks after a fixed run")]
+    Gallery {
+        // one rom per line, optionally followed by its own frame count
+        list: String,
+        #[arg(long, value_name = "DIR", default_value = "gallery", help = "Where to write the pictures and index.html")]
+        out: String,
+        #[arg(long, default_value_t = 600, help = "Frames to run roms without their own count for")]
+        frames: usize,
+        #[arg(long, default_value_t = 4, value_parser = parse_scale, help = "Picture pixels per CHIP-8 pixel")]
+        scale: usize
+    },
+
+    #[command(about = "Write each program of a multi-program file to its own rom")]
+    Split {
+        file: String,
+        #[arg(long, value_name = "DIR", help = "Where to write the roms, next to FILE by default")]
+        out: Option<String>
+    }
+}
+
+// changes to a rom's breakpoint groups, kept in the config directory and
+// used every time the rom runs
+#[derive(Subcommand)]
+pub enum GroupAction {
+    #[command(about = "Add breakpoints to a group, making it if needed")]
+    Add {
+        group: String,
+        #[arg(required = true, value_parser = parse_breakpoint, help = "frame:N, dt, st, write:ADDR or read:ADDR")]
+        breakpoints: Vec<Breakpoint>
+    },
+    #[command(about = "Delete a group")]
+    Remove {
+        group: String
+    },
+    #[command(about = "Use a group's breakpoints when the rom runs")]
+    Enable {
+        group: String
+    },
+    #[command(about = "Keep a group but don't use its breakpoints")]
+    Disable {
+        group: String
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        Options::parse()
+    }
+
+    // --palette, else the user's palette.json in the config directory,
+    // else the default, with the single colors given on top
+    pub fn palette(&self) -> Palette {
+        let base = self.base_palette.unwrap_or_else(|| {
+            match config::dir().map(|dir| dir.join("palette.json")).filter(|path| path.exists()) {
+                Some(path) => Palette::load(&path).unwrap_or_else(|e| {
+                    println!("{}, using the default colors", e);
+                    Palette::default()
+                }),
+                None => Palette::default()
+            }
+        });
+        Palette {
+            off: self.background.unwrap_or(base.off),
+            on: self.foreground.unwrap_or(base.on),
+            border: self.border_color.unwrap_or(base.border),
+            ..base
+        }
+    }
+
+    // --crt, else the user's crt.json in the config directory, e.g.
+    // {"scanlines": true, "vignette": true}
+    pub fn crt(&self) -> CrtEffects {
+        self.crt_effects.unwrap_or_else(|| {
+            match config::dir().map(|dir| dir.join("crt.json")) {
+                Some(path) => config::load_json(&path).unwrap_or_else(|e| {
+                    println!("{}, leaving crt effects off", e);
+                    CrtEffects::default()
+                }),
+                None => CrtEffects::default()
+            }
+        })
+    }
+
+    // frames to skip at boot, and whether to stop as soon as the rom looks
+    // at the keypad
+    pub fn boot_skip(&self) -> Option<(u64, bool)> {
+        match (self.skip_frames, self.fast_boot) {
+            (Some(frames), _) => Some((frames, false)),
+            (None, true) => Some((FAST_BOOT_FRAMES, true)),
+            (None, false) => None
+        }
+    }
+
+    // the rom settings given on the command line, these win over the rom's
+    // profile
+    pub fn profile(&self) -> Profile {
+        Profile {
+            speed: self.speed,
+            quirks: self.quirks,
+            foreground: self.foreground,
+            background: self.background,
+            keymap: self.layout.map(|layout| keymap::preset_name(layout).to_string())
+        }
+    }
+
+    // checked whenever strict mode is on
+    pub fn memory_policy(&self) -> MemoryPolicy {
+        if self.strict {
+            return MemoryPolicy::Checked;
+        }
+        self.memory
+    }
+
+    pub fn key_wait_action(&self) -> KeyWaitTimeout {
+        match self.key_wait_key {
+            Some(key) => KeyWaitTimeout::Press(key),
+            None => KeyWaitTimeout::Abort
+        }
+    }
+
+    pub fn key_filter(&self) -> Option<KeyFilter> {
+        if self.debounce == 0 && self.min_hold == 0 {
+            return None;
+        }
+        Some(KeyFilter::new(Duration::from_millis(self.debounce), Duration::from_millis(self.min_hold)))
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        let frames = self.break_frame.iter().map(|&frame| Breakpoint::Frame(frame));
+        let timers = self.break_timer.iter().map(|&timer| Breakpoint::TimerExpired(timer));
+        let writes = self.break_write.iter().map(|&addr| Breakpoint::Write(addr));
+        let reads = self.break_read.iter().map(|&addr| Breakpoint::Read(addr));
+        frames.chain(timers).chain(writes).chain(reads).collect()
+    }
+}
+
+fn parse_scale(text: &str) -> Result<usize, String> {
+    text.parse()
+        .ok()
+        .filter(|scale| (1..=32).contains(scale))
+        .ok_or_else(|| String::from("needs a scale between 1 and 32"))
+}
+
+fn parse_invalid_opcodes(text: &str) -> Result<InvalidOpcodePolicy, String> {
+    InvalidOpcodePolicy::parse(text).ok_or_else(|| String::from("must be stop or skip"))
+}
+
+fn parse_palette(text: &str) -> Result<Palette, String> {
+    Palette::parse(text)
+}
+
+fn parse_palette_animation(text: &str) -> Result<PaletteEffects, String> {
+    PaletteEffects::parse(text).ok_or_else(|| String::from("needs none or a list of hue-shift, hue-shift:SECONDS and flash"))
+}
+
+fn parse_record_format(text: &str) -> Result<RecordFormat, String> {
+    RecordFormat::parse(text).ok_or_else(|| String::from("must be gif, mp4 or png"))
+}
+
+fn parse_record_fps(text: &str) -> Result<u32, String> {
+    text.parse()
+        .ok()
+        .filter(|fps| (1..=60).contains(fps))
+        .ok_or_else(|| String::from("needs a rate between 1 and 60"))
+}
+
+fn parse_crt(text: &str) -> Result<CrtEffects, String> {
+    CrtEffects::parse(text).ok_or_else(|| String::from("needs none or a list of scanlines, grid and vignette"))
+}
+
+fn parse_factor(text: &str) -> Result<u32, String> {
+    text.parse()
+        .ok()
+        .filter(|factor| (2..=64).contains(factor))
+        .ok_or_else(|| String::from("needs a number between 2 and 64"))
+}
+
+fn parse_speed(text: &str) -> Result<Speed, String> {
+    Speed::parse(text).ok_or_else(|| String::from("needs a number of instructions or unlimited"))
+}
+
+fn parse_quirks(text: &str) -> Result<Quirks, String> {
+    Quirks::parse(text).ok_or_else(|| String::from("needs none, vip, schip or a list of shift-vy, load-store-increment, jump-vx, logic-resets-vf and clip-sprites"))
+}
+
+fn parse_pixel_format(text: &str) -> Result<PixelFormat, String> {
+    PixelFormat::parse(text).ok_or_else(|| String::from("needs argb8888, rgb565 or mono1"))
+}
+
+fn parse_memory(text: &str) -> Result<MemoryPolicy, String> {
+    MemoryPolicy::parse(text).ok_or_else(|| String::from("needs checked, mirrored or clamped"))
+}
+
+fn parse_frontend(text: &str) -> Result<String, String> {
+    match frontend::find(text) {
+        Some(frontend) => Ok(frontend.name.to_string()),
+        None => Err(format!("must be one of {}", frontend::names().join(", ")))
+    }
+}
+
+fn parse_layout(text: &str) -> Result<KeyLayout, String> {
+    KeyLayout::parse(text).ok_or_else(|| String::from("must be standard or two-player"))
+}
+
+fn parse_extensions(text: &str) -> Result<Extensions, String> {
+    Extensions::parse(text).ok_or_else(|| String::from("must be rust8"))
+}
+
+fn parse_browse_sort(text: &str) -> Result<Sort, String> {
+    Sort::parse(text).ok_or_else(|| String::from("must be title, recent or most-played"))
+}
+
+fn parse_score(text: &str) -> Result<ScoreSpec, String> {
+    ScoreSpec::parse(text).ok_or_else(|| String::from("needs a hex address and bcd1-bcd8, u8 or u16"))
+}
+
+fn parse_secs(text: &str) -> Result<Duration, String> {
+    text.parse()
+        .map(Duration::from_secs)
+        .map_err(|_| String::from("needs a number of seconds"))
+}
+
+fn parse_idle_action(text: &str) -> Result<IdleAction, String> {
+    IdleAction::parse(text).ok_or_else(|| String::from("must be pause, snapshot or both"))
+}
+
+fn parse_cutout(text: &str) -> Result<(usize, usize, usize, usize), String> {
+    bezel::parse_cutout(text).ok_or_else(|| String::from("needs WIDTHxHEIGHT+X+Y"))
+}
+
+fn parse_color(text: &str) -> Result<u32, String> {
+    present::parse_color(text).ok_or_else(|| String::from("needs a RRGGBB color"))
+}
+
+fn parse_ghosting(text: &str) -> Result<f32, String> {
+    text.parse()
+        .ok()
+        .filter(|amount| (0.0..1.0).contains(amount))
+        .ok_or_else(|| String::from("needs a decay between 0 and 1"))
+}
+
+fn parse_key(text: &str) -> Result<u8, String> {
+    u8::from_str_radix(text, 16)
+        .ok()
+        .filter(|&key| key < 16)
+        .ok_or_else(|| String::from("needs a hex key 0-F"))
+}
+
+fn parse_addr(text: &str) -> Result<u16, String> {
+    breakpoint::parse_addr(text).ok_or_else(|| String::from("needs a hex address from 000 to FFF"))
+}
+
+fn parse_breakpoint(text: &str) -> Result<Breakpoint, String> {
+    Breakpoint::parse(text).ok_or_else(|| String::from("needs frame:N, dt, st, write:ADDR or read:ADDR"))
+}
+
+fn parse_watch(text: &str) -> Result<Watch, String> {
+    Watch::parse(text).ok_or_else(|| String::from("needs v0 to vf, i, pc, dt, st, [ADDR] or [i]"))
+}
+
+fn parse_timer(text: &str) -> Result<Timer, String> {
+    Timer::parse(text).ok_or_else(|| String::from("must be dt or st"))
+}
@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+// simulated phosphor persistence: lit pixels jump to full brightness and
+// unlit ones fade out exponentially instead of vanishing, which hides most
+// of the flicker from xor drawn sprites
+pub struct Phosphor {
+    // fraction of brightness left after one 60Hz frame, 0 disables ghosting
+    decay: f32,
+    levels: Vec<f32>,
+    last_update: Option<Instant>
+}
+
+impl Phosphor {
+    pub fn new(decay: f32) -> Phosphor {
+        Phosphor {
+            decay: decay.clamp(0.0, 0.99),
+            levels: Vec::new(),
+            last_update: None
+        }
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    // rewrites frame, drawn in the off and on colors, in place with the
+    // faded colors
+    pub fn apply(&mut self, frame: &mut [u32], off: u32, on: u32) {
+        let now = Instant::now();
+        // frames are presented at an uneven rate, so decay by elapsed time
+        let frames = match self.last_update {
+            Some(last) => now.duration_since(last).as_secs_f32() * 60.0,
+            None => 0.0
+        };
+        self.last_update = Some(now);
+        self.apply_frames(frame, off, on, frames);
+    }
+
+    // apply for callers that count frames themselves, e.g. where there's no
+    // clock like in the browser
+    pub fn apply_frames(&mut self, frame: &mut [u32], off: u32, on: u32, frames: f32) {
+        let factor = self.decay.powf(frames);
+
+        self.levels.resize(frame.len(), 0.0);
+        for (pixel, level) in frame.iter_mut().zip(self.levels.iter_mut()) {
+            *level = if *pixel == on { 1.0 } else { *level * factor };
+            *pixel = blend(off, on, *level);
+        }
+    }
+}
+
+fn blend(from: u32, to: u32, amount: f32) -> u32 {
+    let mut color = 0;
+    for shift in [0, 8, 16].iter() {
+        let a = ((from >> shift) & 0xFF) as f32;
+        let b = ((to >> shift) & 0xFF) as f32;
+        let c = (a + (b - a) * amount).round() as u32;
+        color |= c.min(0xFF) << shift;
+    }
+    color
+}
\ No newline at end of file
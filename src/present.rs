@@ -0,0 +1,376 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::animation::{
+    Moment,
+    PaletteAnimation
+};
+use crate::bezel::Bezel;
+use crate::crt::Crt;
+use crate::display::{
+    Display,
+    Frame
+};
+use crate::phosphor::Phosphor;
+use crate::WIDTH;
+use crate::HEIGHT;
+use crate::PX_OFF;
+use crate::PX_ON;
+
+// without a bezel the window shows the 64x32 display scaled up by this much
+// by default, drawn by us rather than minifb so overlays can use the full
+// resolution
+pub const SCREEN_SCALE: usize = 8;
+
+// colors the screen is drawn with. off is the unlit pixel color inside the
+// game, border fills the space around it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palette {
+    pub off: u32,
+    pub on: u32,
+    pub border: u32,
+    // xo-chip's second plane on its own and where both planes are lit. on
+    // is the first plane. unused until a rom can draw to two planes
+    pub plane2: u32,
+    pub both: u32
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            off: PX_OFF,
+            on: PX_ON,
+            border: PX_OFF,
+            plane2: 0xAAAAAA,
+            both: 0x555555
+        }
+    }
+}
+
+// a palette file, colors left out keep the default's, e.g.
+// {"off": "996600", "on": "FFCC00", "plane2": "FF6600", "both": "662200"}
+#[derive(Deserialize)]
+struct PaletteFile {
+    off: Option<String>,
+    on: Option<String>,
+    border: Option<String>,
+    plane2: Option<String>,
+    both: Option<String>
+}
+
+impl Palette {
+    // a palette for --palette: one of the presets, or a json palette file
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        match Palette::preset(text) {
+            Some(palette) => Ok(palette),
+            None => Palette::load(Path::new(text))
+        }
+    }
+
+    // classic is the default white on black
+    pub fn preset(name: &str) -> Option<Palette> {
+        let (off, on, plane2, both) = match name {
+            "classic" => return Some(Palette::default()),
+            "amber" => (0x1A0F00, 0xFFB000, 0xB36B00, 0x664000),
+            "green" => (0x001A08, 0x33FF66, 0x1F9940, 0x0D4D1F),
+            "lcd" => (0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F),
+            "octo" => (0x996600, 0xFFCC00, 0xFF6600, 0x662200),
+            _ => return None
+        };
+        Some(Palette {
+            off,
+            on,
+            border: off,
+            plane2,
+            both
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Palette, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read palette {}: {}", path.display(), e))?;
+        let file: PaletteFile = serde_json::from_str(&json)
+            .map_err(|e| format!("Could not parse palette {}: {}", path.display(), e))?;
+        let default = Palette::default();
+        let color = |text: &Option<String>, default: u32| match text {
+            Some(text) => parse_color(text).ok_or_else(|| format!("{} in {} isn't a RRGGBB color", text, path.display())),
+            None => Ok(default)
+        };
+        let off = color(&file.off, default.off)?;
+        Ok(Palette {
+            off,
+            on: color(&file.on, default.on)?,
+            // the border follows the background unless set
+            border: color(&file.border, off)?,
+            plane2: color(&file.plane2, default.plane2)?,
+            both: color(&file.both, default.both)?
+        })
+    }
+
+    // the color for a pixel by which planes are lit, bit 0 the first plane
+    // and bit 1 the second
+    pub fn color(&self, planes: u8) -> u32 {
+        match planes & 0b11 {
+            0 => self.off,
+            1 => self.on,
+            2 => self.plane2,
+            _ => self.both
+        }
+    }
+}
+
+// RRGGBB, with or without a leading #
+pub fn parse_color(text: &str) -> Option<u32> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// the pixels shown in the window, converted from the cpu framebuffer
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+    bezel: Option<Bezel>,
+    phosphor: Option<Phosphor>,
+    crt: Option<Crt>,
+    // the palette set, and the one frames are drawn in after animating it
+    base_palette: Palette,
+    palette: Palette,
+    animation: Option<Box<dyn PaletteAnimation>>,
+
+    // the part of the window the game is fitted into, the rest of it is
+    // border color: x, y, width, height
+    cutout: (usize, usize, usize, usize),
+
+    // size of the frames being presented, 64x32 unless changed
+    resolution: (usize, usize),
+
+    // the frame after filters, before scaling
+    frame: Vec<u32>,
+
+    // where the game ends up inside the window: x, y, width, height
+    game_area: (usize, usize, usize, usize)
+}
+
+impl Screen {
+    // without a bezel the game is drawn scale times its size, with border
+    // pixels of border color around it
+    pub fn new(bezel: Option<Bezel>, scale: usize, border: usize) -> Screen {
+        let (width, height, cutout) = match &bezel {
+            Some(bezel) => (bezel.width, bezel.height, bezel.cutout),
+            None => bordered(scale, border)
+        };
+
+        let pixels = match &bezel {
+            Some(bezel) => bezel.pixels.clone(),
+            None => vec![0; width * height]
+        };
+
+        Screen {
+            width,
+            height,
+            pixels,
+            bezel,
+            phosphor: None,
+            crt: None,
+            base_palette: Palette::default(),
+            palette: Palette::default(),
+            animation: None,
+            cutout,
+            resolution: (WIDTH, HEIGHT),
+            frame: Vec::new(),
+            game_area: letterbox(cutout)
+        }
+    }
+
+    // draws the game scale times its size from now on, the window has to
+    // follow. a bezel keeps the size of its artwork, so false with one
+    pub fn set_scale(&mut self, scale: usize, border: usize) -> bool {
+        if self.bezel.is_some() {
+            return false;
+        }
+        let (width, height, cutout) = bordered(scale, border);
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+        self.cutout = cutout;
+        self.game_area = letterbox(cutout);
+        true
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.base_palette = palette;
+        self.palette = palette;
+    }
+
+    // the colors the last frame was drawn in
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn set_palette_animation(&mut self, animation: Option<Box<dyn PaletteAnimation>>) {
+        self.animation = animation;
+        self.palette = self.base_palette;
+    }
+
+    // moves the palette animation on to moment, for the next present
+    pub fn animate(&mut self, moment: Moment) {
+        if let Some(animation) = &mut self.animation {
+            self.palette = animation.animate(&self.base_palette, moment);
+        }
+    }
+
+    pub fn set_phosphor(&mut self, phosphor: Option<Phosphor>) {
+        self.phosphor = phosphor;
+    }
+
+    pub fn set_crt(&mut self, crt: Option<Crt>) {
+        self.crt = crt;
+    }
+
+    pub fn game_area(&self) -> (usize, usize, usize, usize) {
+        self.game_area
+    }
+
+    // the artwork or border, with the letterbox bars inside the cutout
+    fn draw_surround(&mut self) {
+        // overlays may have drawn over the artwork and border last frame
+        match &self.bezel {
+            Some(bezel) => self.pixels.copy_from_slice(&bezel.pixels),
+            None => {
+                for pixel in self.pixels.iter_mut() {
+                    *pixel = self.palette.border;
+                }
+            }
+        }
+        let (cut_x, cut_y, cut_w, cut_h) = self.cutout;
+        for y in cut_y..cut_y + cut_h {
+            let start = cut_x + y * self.width;
+            for pixel in &mut self.pixels[start..start + cut_w] {
+                *pixel = self.palette.border;
+            }
+        }
+    }
+
+    // scales self.frame into the game area, then runs the crt effects over
+    // it
+    fn draw_frame(&mut self) {
+        let (frame_w, frame_h) = self.resolution;
+        let (area_x, area_y, area_w, area_h) = self.game_area;
+        for y in 0..area_h {
+            let row = &self.frame[(y * frame_h / area_h) * frame_w..];
+            let start = area_x + (area_y + y) * self.width;
+            let line = &mut self.pixels[start..start + area_w];
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = row[x * frame_w / area_w];
+            }
+        }
+        if let Some(crt) = &mut self.crt {
+            crt.apply(&mut self.pixels, self.width, self.game_area, self.resolution);
+        }
+    }
+}
+
+// the minifb frontend shows frames through a Screen, which it then draws
+// overlays on and hands to its windows
+impl Display for Screen {
+    fn present(&mut self, frame: &Frame) {
+        if (frame.width(), frame.height()) != self.resolution {
+            self.set_resolution(frame.width(), frame.height());
+        }
+        self.draw_surround();
+
+        let palette = self.palette;
+        self.frame.clear();
+        self.frame.extend(frame.pixels().iter().map(|&on| palette.color(on as u8)));
+        if let Some(phosphor) = &mut self.phosphor {
+            phosphor.apply(&mut self.frame, palette.off, palette.on);
+        }
+        self.draw_frame();
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.resolution = (width, height);
+        // the old levels are for pixels that no longer line up
+        if let Some(phosphor) = &mut self.phosphor {
+            *phosphor = Phosphor::new(phosphor.decay());
+        }
+    }
+
+    fn clear(&mut self) {
+        self.draw_surround();
+        let (width, height) = self.resolution;
+        self.frame.clear();
+        self.frame.resize(width * height, self.palette.off);
+        self.draw_frame();
+    }
+}
+
+// width, height and cutout of a screen without a bezel
+fn bordered(scale: usize, border: usize) -> (usize, usize, (usize, usize, usize, usize)) {
+    let width = WIDTH * scale + 2 * border;
+    let height = HEIGHT * scale + 2 * border;
+    (width, height, (border, border, WIDTH * scale, HEIGHT * scale))
+}
+
+// largest 2:1 area that fits the cutout, centered in it
+fn letterbox(cutout: (usize, usize, usize, usize)) -> (usize, usize, usize, usize) {
+    let (x, y, w, h) = cutout;
+    let (area_w, area_h) = if w * HEIGHT > h * WIDTH {
+        (h * WIDTH / HEIGHT, h)
+    } else {
+        (w, w * HEIGHT / WIDTH)
+    };
+    (x + (w - area_w) / 2, y + (h - area_h) / 2, area_w, area_h)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{
+        parse_color,
+        Palette
+    };
+
+    #[test]
+    fn palettes_come_from_presets_or_files() {
+        assert_eq!(Palette::preset("classic"), Some(Palette::default()));
+        let amber = Palette::preset("amber").unwrap();
+        assert_eq!((amber.off, amber.on, amber.border), (0x1A0F00, 0xFFB000, 0x1A0F00));
+        assert_eq!([amber.color(0), amber.color(1), amber.color(2), amber.color(3)], [0x1A0F00, 0xFFB000, 0xB36B00, 0x664000]);
+        assert_eq!(Palette::preset("Amber"), None);
+        assert_eq!(Palette::parse("octo"), Ok(Palette::preset("octo").unwrap()));
+
+        let path = std::env::temp_dir().join(format!("rust8-palette-{}.json", std::process::id()));
+        fs::write(&path, r##"{"off": "#102030", "on": "FFCC00"}"##).unwrap();
+        let loaded = Palette::parse(&path.to_string_lossy());
+        fs::write(&path, r#"{"on": "yellow"}"#).unwrap();
+        let bad_color = Palette::load(&path);
+        fs::write(&path, "{").unwrap();
+        let bad_json = Palette::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        // colors left out keep the default's, the border follows off
+        let loaded = loaded.unwrap();
+        assert_eq!((loaded.off, loaded.on, loaded.border), (0x102030, 0xFFCC00, 0x102030));
+        assert_eq!(loaded.plane2, Palette::default().plane2);
+        assert!(bad_color.unwrap_err().starts_with("yellow in "));
+        assert!(bad_json.unwrap_err().starts_with("Could not parse palette"));
+        assert!(Palette::parse("no-such-palette").unwrap_err().starts_with("Could not read palette no-such-palette"));
+    }
+
+    #[test]
+    fn colors_are_six_hex_digits() {
+        assert_eq!(parse_color("FFB000"), Some(0xFFB000));
+        assert_eq!(parse_color("#0f380f"), Some(0x0F380F));
+        assert_eq!(parse_color("FFF"), None);
+        assert_eq!(parse_color("+FFFFF"), None);
+        assert_eq!(parse_color("GGGGGG"), None);
+    }
+}
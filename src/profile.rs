@@ -0,0 +1,157 @@
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::config;
+use crate::cpu::Cpu;
+use crate::present::Palette;
+use crate::quirks::Quirks;
+use crate::romdb;
+use crate::timing::Speed;
+
+// instructions per frame when neither the command line nor a profile says
+pub const DEFAULT_SPEED: Speed = Speed::PerFrame(9);
+
+// settings for one rom, kept in the config directory under the rom's sha1
+// and used every time it's loaded. whatever isn't set is left to the
+// command line and the defaults, e.g.
+// {"speed": "15", "quirks": "vip", "foreground": "33FF66"}
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<Quirks>,
+    #[serde(with = "color", skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<u32>,
+    #[serde(with = "color", skip_serializing_if = "Option::is_none")]
+    pub background: Option<u32>,
+    // a keymap preset, see KeyMap::preset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<String>
+}
+
+impl Profile {
+    // where the profile for the rom with this sha1 is kept
+    pub fn path(sha1: &str) -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("profiles").join(format!("{}.json", sha1)))
+    }
+
+    // no file yet means an empty profile
+    pub fn load(path: &Path) -> Result<Profile, String> {
+        config::load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        config::save_json(path, self)
+    }
+
+    // what the rom database says about the rom with this sha1, for under
+    // the user's own profile
+    pub fn known(sha1: &str) -> Profile {
+        Profile {
+            quirks: romdb::lookup(sha1).map(|info| info.quirks),
+            ..Profile::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Profile::default()
+    }
+
+    // this profile's settings, with other's filling in the ones it leaves out
+    pub fn or(self, other: Profile) -> Profile {
+        Profile {
+            speed: self.speed.or(other.speed),
+            quirks: self.quirks.or(other.quirks),
+            foreground: self.foreground.or(other.foreground),
+            background: self.background.or(other.background),
+            keymap: self.keymap.or(other.keymap)
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed.unwrap_or(DEFAULT_SPEED)
+    }
+
+    // the pixel colors of base replaced by the profile's
+    pub fn palette(&self, base: Palette) -> Palette {
+        Palette {
+            off: self.background.unwrap_or(base.off),
+            on: self.foreground.unwrap_or(base.on),
+            ..base
+        }
+    }
+
+    // the parts of the profile the machine itself needs, call after loading
+    // the rom
+    pub fn apply(&self, cpu: &mut Cpu) {
+        cpu.set_quirks(self.quirks.unwrap_or_default());
+    }
+}
+
+// colors are written RRGGBB like on the command line
+mod color {
+    use serde::{
+        de::Error,
+        Deserialize,
+        Deserializer,
+        Serializer
+    };
+
+    use crate::present;
+
+    pub fn serialize<S: Serializer>(color: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        match color {
+            Some(color) => serializer.serialize_str(&format!("{:06X}", color)),
+            None => serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(text) => present::parse_color(&text)
+                .map(Some)
+                .ok_or_else(|| D::Error::custom(format!("{} isn't a RRGGBB color", text))),
+            None => Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+    use crate::quirks::Quirks;
+    use crate::timing::Speed;
+
+    #[test]
+    fn profiles_fill_in_what_the_command_line_leaves_out() {
+        let rom: Profile = serde_json::from_str(r#"{"speed": "15", "quirks": "vip", "foreground": "33ff66"}"#).unwrap();
+        assert_eq!(rom.speed, Some(Speed::PerFrame(15)));
+        assert_eq!(rom.quirks, Some(Quirks::VIP));
+        assert_eq!(rom.foreground, Some(0x33FF66));
+
+        let command_line = Profile {
+            speed: Some(Speed::Unlimited),
+            ..Profile::default()
+        };
+        let profile = command_line.or(rom.clone());
+        assert_eq!(profile.speed(), Speed::Unlimited);
+        assert_eq!(profile.quirks, Some(Quirks::VIP));
+
+        // written back the same way it was read
+        let json = serde_json::to_string(&rom).unwrap();
+        assert_eq!(serde_json::from_str::<Profile>(&json).unwrap(), rom);
+        assert_eq!(serde_json::to_string(&Profile::default()).unwrap(), "{}");
+
+        assert!(serde_json::from_str::<Profile>(r#"{"quirks": "cosmac"}"#).is_err());
+        assert_eq!(Quirks::parse("shift-vy,clip-sprites").unwrap().to_string(), "shift-vy,clip-sprites");
+    }
+}
@@ -0,0 +1,137 @@
+use crate::audio::SilentSink;
+use crate::chip8::{
+    Chip8,
+    DEFAULT_INSTRUCTIONS_PER_FRAME
+};
+use crate::quirks::{
+    Quirks,
+    NAMES
+};
+use crate::rng::SeededRng;
+
+// how often the screen is sampled while a rom runs
+const SAMPLE_FRAMES: usize = 30;
+
+// what a run looked like: the screen's sha1 at each sample and why the rom
+// stopped, if it did
+#[derive(PartialEq)]
+struct Outcome {
+    samples: Vec<String>,
+    halted: Option<String>
+}
+
+impl Outcome {
+    fn distinct_frames(&self) -> usize {
+        let mut samples = self.samples.clone();
+        samples.sort();
+        samples.dedup();
+        samples.len()
+    }
+
+    fn summary(&self) -> String {
+        match &self.halted {
+            Some(reason) => format!("stops: {}", reason),
+            None => {
+                let screens = self.distinct_frames();
+                format!("runs, {} different {}", screens, if screens == 1 { "screen" } else { "screens" })
+            }
+        }
+    }
+}
+
+// runs rom headless with no input under every combination of quirks,
+// seeding RND the same way each time, then reports which quirks change
+// what the rom does and which combination it most likely wants. the guess
+// favours runs that don't stop and that show the most different screens,
+// since a wrong quirk usually crashes a rom or freezes it
+pub fn describe(rom: &[u8], frames: usize, seed: u64) -> Result<String, String> {
+    let runs: Vec<(Quirks, Outcome)> = Quirks::all().into_iter()
+        .map(|quirks| run(rom, quirks, frames, seed).map(|outcome| (quirks, outcome)))
+        .collect::<Result<_, String>>()?;
+    let outcome = |quirks: Quirks| runs.iter()
+        .find(|(run_quirks, _)| *run_quirks == quirks)
+        .map(|(_, outcome)| outcome)
+        .expect("Could not find quirk combination.");
+
+    let mut lines = vec![format!("{} quirk combinations run for {} frames", runs.len(), frames)];
+    let all_same = runs.iter().all(|(_, run_outcome)| run_outcome == &runs[0].1);
+    if all_same {
+        lines.push(format!("They all do the same: {}", runs[0].1.summary()));
+        lines.push(String::from("No quirk matters this far in, without input. Try more frames if the rom waits on a key"));
+        return Ok(lines.join("\n"));
+    }
+
+    lines.push(String::new());
+    for name in NAMES.iter() {
+        // a quirk matters if flipping it alone ever changes the outcome
+        let matters = runs.iter().any(|(quirks, run_outcome)| outcome(quirks.toggled(name)) != run_outcome);
+        lines.push(format!("{:22} {}", name, if matters { "changes the outcome" } else { "no effect" }));
+    }
+
+    lines.push(String::new());
+    for (name, quirks) in [("none", Quirks::NONE), ("vip", Quirks::VIP), ("schip", Quirks::SCHIP)] {
+        lines.push(format!("{:22} {}", name, outcome(quirks).summary()));
+    }
+
+    let presets = [Quirks::NONE, Quirks::VIP, Quirks::SCHIP];
+    let likely = runs.iter()
+        .filter(|(_, run_outcome)| run_outcome.halted.is_none())
+        .max_by_key(|(quirks, run_outcome)| {
+            let quirks_on = NAMES.iter().filter(|name| quirks.get(name) == Some(true)).count();
+            (run_outcome.distinct_frames(), presets.contains(quirks), usize::MAX - quirks_on)
+        });
+    lines.push(String::new());
+    match likely {
+        Some((quirks, _)) => lines.push(format!("Likely intended: --quirks {}", quirks)),
+        None => lines.push(String::from("Every combination stops the rom, the problem is likely not a quirk"))
+    }
+    Ok(lines.join("\n"))
+}
+
+fn run(rom: &[u8], quirks: Quirks, frames: usize, seed: u64) -> Result<Outcome, String> {
+    let mut chip8 = Chip8::with_rom(rom)?;
+    chip8.cpu.set_quirks(quirks);
+    chip8.cpu.set_rng(Box::new(SeededRng::new(seed)));
+
+    let mut audio = SilentSink;
+    let mut samples = Vec::new();
+    for frame in 1..=frames {
+        for _ in 0..DEFAULT_INSTRUCTIONS_PER_FRAME {
+            // the error is kept as the halt reason
+            if chip8.step().is_err() || chip8.cpu.halted().is_some() {
+                break;
+            }
+        }
+        if chip8.cpu.halted().is_some() {
+            break;
+        }
+        chip8.cpu.tick_timers(&mut audio);
+        if frame % SAMPLE_FRAMES == 0 {
+            samples.push(chip8.cpu.gfx().sha1());
+        }
+    }
+    samples.push(chip8.cpu.gfx().sha1());
+    Ok(Outcome {
+        samples,
+        halted: chip8.cpu.halted().map(String::from)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+
+    #[test]
+    fn reports_the_quirks_that_change_a_rom() {
+        // LD V0, 5; LD V1, 3; SHR V0, V1; LD F, V0; DRW V2, V2, 5; JP 20A
+        // draws 2 normally and 1 when VY is shifted
+        let rom = [0x60, 0x05, 0x61, 0x03, 0x80, 0x16, 0xF0, 0x29, 0xD2, 0x25, 0x12, 0x0A];
+        let report = describe(&rom, 60, 0).unwrap();
+        assert!(report.contains("shift-vy               changes the outcome"));
+        assert!(report.contains("jump-vx                no effect"));
+
+        // JP 200 does the same whatever the quirks
+        let report = describe(&[0x12, 0x00], 60, 0).unwrap();
+        assert!(report.contains("They all do the same"));
+    }
+}
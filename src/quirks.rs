@@ -0,0 +1,147 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+// behaviours interpreters disagree on. roms are written against one
+// interpreter and can misbehave on another, so these pick which one to be.
+// all off is how rust8 has always run
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY into VX like the COSMAC VIP, instead of
+    // shifting VX in place
+    pub shift_vy: bool,
+    // FX55 and FX65 leave I just past the last register like the VIP
+    pub load_store_increment: bool,
+    // BNNN jumps to XNN plus VX like the SCHIP, instead of NNN plus V0
+    pub jump_vx: bool,
+    // 8XY1, 8XY2 and 8XY3 clear VF like the VIP
+    pub logic_resets_vf: bool,
+    // sprites are cut off at the edges of the screen instead of wrapping
+    pub clip_sprites: bool
+}
+
+// the names each quirk goes by in a list
+pub const NAMES: [&str; 5] = ["shift-vy", "load-store-increment", "jump-vx", "logic-resets-vf", "clip-sprites"];
+
+impl Quirks {
+    // how rust8 has always run
+    pub const NONE: Quirks = Quirks {
+        shift_vy: false,
+        load_store_increment: false,
+        jump_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false
+    };
+
+    // how the original COSMAC VIP interpreter behaves
+    pub const VIP: Quirks = Quirks {
+        shift_vy: true,
+        load_store_increment: true,
+        jump_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true
+    };
+
+    // how the SCHIP interpreter on the HP48 behaves, which most later
+    // games were written against
+    pub const SCHIP: Quirks = Quirks {
+        shift_vy: false,
+        load_store_increment: false,
+        jump_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true
+    };
+
+    // none, vip, schip, or quirk names separated by commas, e.g.
+    // "shift-vy,clip-sprites"
+    pub fn parse(text: &str) -> Option<Quirks> {
+        match text {
+            "none" => return Some(Quirks::NONE),
+            "vip" => return Some(Quirks::VIP),
+            "schip" => return Some(Quirks::SCHIP),
+            _ => ()
+        }
+        let mut quirks = Quirks::default();
+        for name in text.split(',') {
+            *quirks.flag_mut(name.trim())? = true;
+        }
+        Some(quirks)
+    }
+
+    // every combination of the quirks, for trying a rom under each
+    pub fn all() -> Vec<Quirks> {
+        (0..1 << NAMES.len()).map(|bits: usize| {
+            let mut quirks = Quirks::default();
+            for (i, name) in NAMES.iter().enumerate() {
+                *quirks.flag_mut(name).expect("Could not find quirk.") = bits & (1 << i) != 0;
+            }
+            quirks
+        }).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        NAMES.iter().position(|&known| known == name).map(|i| self.flags()[i])
+    }
+
+    // these quirks with the one called name flipped
+    pub fn toggled(mut self, name: &str) -> Quirks {
+        if let Some(flag) = self.flag_mut(name) {
+            *flag = !*flag;
+        }
+        self
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift-vy" => Some(&mut self.shift_vy),
+            "load-store-increment" => Some(&mut self.load_store_increment),
+            "jump-vx" => Some(&mut self.jump_vx),
+            "logic-resets-vf" => Some(&mut self.logic_resets_vf),
+            "clip-sprites" => Some(&mut self.clip_sprites),
+            _ => None
+        }
+    }
+
+    fn flags(&self) -> [bool; 5] {
+        [self.shift_vy, self.load_store_increment, self.jump_vx, self.logic_resets_vf, self.clip_sprites]
+    }
+}
+
+// the shortest name parse reads back: a preset name or the list
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Quirks::default() {
+            return write!(f, "none");
+        }
+        if *self == Quirks::VIP {
+            return write!(f, "vip");
+        }
+        if *self == Quirks::SCHIP {
+            return write!(f, "schip");
+        }
+        let names: Vec<&str> = NAMES.iter().zip(self.flags().iter())
+            .filter(|(_, &on)| on)
+            .map(|(&name, _)| name)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+impl TryFrom<String> for Quirks {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Quirks, String> {
+        Quirks::parse(&text).ok_or_else(|| format!("Could not parse quirks {}", text))
+    }
+}
+
+impl From<Quirks> for String {
+    fn from(quirks: Quirks) -> String {
+        quirks.to_string()
+    }
+}
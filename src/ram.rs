@@ -1,50 +1,134 @@
-use crate::RAM_SIZE;
+use serde::{
+    Deserialize,
+    Serialize
+};
 
+use crate::spec::{
+    FONT,
+    FONT_BASE,
+    FONT_SPRITE_BYTES,
+    RAM_SIZE
+};
+
+// what happens to accesses past the end of memory
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MemoryPolicy {
+    // the access is an error and the machine stops
+    Checked,
+    // addresses wrap around, like interpreters that only decode 12 bits
+    Mirrored,
+    // addresses stick at the last byte
+    Clamped
+}
+
+impl MemoryPolicy {
+    pub fn parse(name: &str) -> Option<MemoryPolicy> {
+        match name {
+            "checked" => Some(MemoryPolicy::Checked),
+            "mirrored" => Some(MemoryPolicy::Mirrored),
+            "clamped" => Some(MemoryPolicy::Clamped),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Ram {
-    memory: [u8; RAM_SIZE]
+    memory: [u8; RAM_SIZE],
+    policy: MemoryPolicy,
+
+    // every access as (address, was a write), only while tracing
+    trace: Option<Vec<(u16, bool)>>
+}
+
+impl Default for Ram {
+    fn default() -> Ram {
+        Ram::new()
+    }
 }
 
 impl Ram {
     pub fn new() -> Ram {
         let mut ram = Ram {
-            memory: [0; RAM_SIZE]
+            memory: [0; RAM_SIZE],
+            policy: MemoryPolicy::Checked,
+            trace: None
         };
 
-        let sprites: [[u8; 5]; 16] = [
-            [0xF0, 0x90, 0x90, 0x90, 0xF0],
-            [0x20, 0x60, 0x20, 0x20, 0x70],
-            [0xF0, 0x10, 0xF0, 0x80, 0xF0],
-            [0xF0, 0x10, 0xF0, 0x10, 0xF0],
-            [0x90 ,0x90, 0xF0, 0x10, 0x10],
-            [0xF0, 0x80, 0xF0, 0x10, 0xF0],
-            [0xF0, 0x80, 0xF0, 0x90, 0xF0],
-            [0xF0, 0x10, 0x20, 0x40, 0x40],
-            [0xF0, 0x90, 0xF0, 0x90, 0xF0],
-            [0xF0, 0x90, 0xF0, 0x10, 0xF0],
-            [0xF0, 0x90, 0xF0, 0x90, 0x90],
-            [0xE0, 0x90, 0xE0, 0x90, 0xE0],
-            [0xF0, 0x80, 0x80, 0x80, 0xF0],
-            [0xE0, 0x90, 0x90, 0x90, 0xE0],
-            [0xF0, 0x80, 0xF0, 0x80, 0xF0],
-            [0xF0, 0x80, 0xF0, 0x80, 0x80]
-        ];
-
-        let mut i = 0;
-        for sprite in sprites.iter() {
-            for ch in sprite {
-                ram.memory[i] = *ch;
-                i += 1;
-            }
+        let mut addr = FONT_BASE as usize;
+        for sprite in FONT.iter() {
+            ram.memory[addr..addr + FONT_SPRITE_BYTES].copy_from_slice(sprite);
+            addr += FONT_SPRITE_BYTES;
         }
 
         ram
     }
 
-    pub fn read_byte(&mut self, addr: u16) -> u8 {
-        return self.memory[addr as usize];
+    pub fn policy(&self) -> MemoryPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: MemoryPolicy) {
+        self.policy = policy;
+    }
+
+    // where addr lands under the policy, None if it's out of bounds and
+    // that's an error
+    pub fn resolve(&self, addr: u16) -> Option<u16> {
+        if (addr as usize) < RAM_SIZE {
+            return Some(addr);
+        }
+        match self.policy {
+            MemoryPolicy::Checked => None,
+            MemoryPolicy::Mirrored => Some(addr % RAM_SIZE as u16),
+            MemoryPolicy::Clamped => Some(RAM_SIZE as u16 - 1)
+        }
+    }
+
+    // None when the address is out of bounds, see resolve
+    pub fn read_byte(&mut self, addr: u16) -> Option<u8> {
+        let addr = self.resolve(addr)?;
+        if let Some(trace) = &mut self.trace {
+            trace.push((addr, false));
+        }
+        Some(self.memory[addr as usize])
     }
 
-    pub fn write_byte(&mut self, addr: u16, value: u8) {
+    // false when the address is out of bounds and nothing was written
+    pub fn write_byte(&mut self, addr: u16, value: u8) -> bool {
+        let addr = match self.resolve(addr) {
+            Some(addr) => addr,
+            None => return false
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.push((addr, true));
+        }
         self.memory[addr as usize] = value;
+        true
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn load_memory(&mut self, data: &[u8]) {
+        self.memory.copy_from_slice(data);
     }
-}
\ No newline at end of file
+
+    // starts recording accesses, see take_trace
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
+    pub fn tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    // the accesses recorded since the last call
+    pub fn take_trace(&mut self) -> Vec<(u16, bool)> {
+        match &mut self.trace {
+            Some(trace) => std::mem::take(trace),
+            None => Vec::new()
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::ram::Ram;
+
+// how a byte has to have changed since the last snapshot to stay a
+// candidate
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Change {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased
+}
+
+impl Change {
+    fn keeps(self, old: u8, new: u8) -> bool {
+        match self {
+            Change::Changed => new != old,
+            Change::Unchanged => new == old,
+            Change::Increased => new > old,
+            Change::Decreased => new < old
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Changed => write!(f, "changed"),
+            Change::Unchanged => write!(f, "unchanged"),
+            Change::Increased => write!(f, "increased"),
+            Change::Decreased => write!(f, "decreased")
+        }
+    }
+}
+
+// finds where a rom keeps something by diffing ram while it's played: take
+// a snapshot, do the thing (lose a life), say how the value should have
+// changed, and repeat until only a few addresses are left. what's found
+// goes into --score, achievements or the rom's memory labels
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+    rounds: u32
+}
+
+impl RamSearch {
+    // every address is a candidate to start with
+    pub fn new(ram: &Ram) -> RamSearch {
+        let snapshot = ram.memory().to_vec();
+        RamSearch {
+            candidates: (0..snapshot.len() as u16).collect(),
+            snapshot,
+            rounds: 0
+        }
+    }
+
+    // keeps the candidates whose byte changed the way given since the last
+    // snapshot, then takes a new one for the next round
+    pub fn filter(&mut self, ram: &Ram, change: Change) {
+        let memory = ram.memory();
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&addr| change.keeps(snapshot[addr as usize], memory[addr as usize]));
+        self.snapshot = memory.to_vec();
+        self.rounds += 1;
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    // the byte at addr when the last snapshot was taken
+    pub fn value(&self, addr: u16) -> u8 {
+        self.snapshot[addr as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Change,
+        RamSearch
+    };
+    use crate::ram::Ram;
+
+    #[test]
+    fn rounds_narrow_down_to_what_changed_every_time() {
+        let mut ram = Ram::new();
+        ram.write_byte(0x3A0, 3);
+        let mut search = RamSearch::new(&ram);
+
+        // a life lost, with a counter elsewhere going up from 0
+        ram.write_byte(0x3A0, 2);
+        ram.write_byte(0x3B0, 9);
+        search.filter(&ram, Change::Decreased);
+        assert_eq!(search.candidates(), &[0x3A0]);
+
+        // nothing happens, then another life is lost
+        search.filter(&ram, Change::Unchanged);
+        ram.write_byte(0x3A0, 1);
+        search.filter(&ram, Change::Decreased);
+        assert_eq!(search.candidates(), &[0x3A0]);
+        assert_eq!((search.rounds(), search.value(0x3A0)), (3, 1));
+    }
+}
@@ -0,0 +1,284 @@
+use std::convert::TryFrom;
+use std::fs::{
+    self,
+    File
+};
+use std::io::{
+    BufWriter,
+    Write
+};
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::process::{
+    Child,
+    Command,
+    Stdio
+};
+use std::time::SystemTime;
+
+use gif::{
+    Encoder,
+    Repeat
+};
+
+use crate::display::Frame;
+use crate::present::Palette;
+use crate::screenshot::{
+    self,
+    Image
+};
+use crate::spec::TIMER_HZ;
+
+// how a recording is saved
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecordFormat {
+    // one animated gif
+    Gif,
+    // a video, encoded by ffmpeg which has to be installed
+    Mp4,
+    // a directory of numbered pngs, e.g. for a video encoder
+    Png
+}
+
+impl RecordFormat {
+    pub fn parse(name: &str) -> Option<RecordFormat> {
+        match name {
+            "gif" => Some(RecordFormat::Gif),
+            "mp4" => Some(RecordFormat::Mp4),
+            "png" => Some(RecordFormat::Png),
+            _ => None
+        }
+    }
+}
+
+enum Output {
+    // made with the first frame, which sets the gif's size
+    Gif(Option<Encoder<BufWriter<File>>>),
+    // ffmpeg, started with the first frame too, reading raw rgb frames
+    Mp4(Option<Child>),
+    Png
+}
+
+// the frames shown between starting and finishing a recording, kept at
+// fps frames a second of machine time
+pub struct Recording {
+    output: Output,
+    path: PathBuf,
+    scale: usize,
+    // keeps one frame in every this many
+    every: u64,
+    offered: u64,
+    kept: u64,
+    size: Option<(usize, usize)>
+}
+
+impl Recording {
+    // a recording into dir named after name and the time, like screenshots.
+    // fps goes up to the next rate that divides the 60Hz display evenly,
+    // e.g. 25 records at 30
+    pub fn start(dir: &str, name: &str, format: RecordFormat, scale: usize, fps: u32, now: SystemTime) -> Result<Recording, String> {
+        let path = screenshot::timestamped_path(dir, name, now)?;
+        let path = match format {
+            RecordFormat::Gif => path.with_extension("gif"),
+            RecordFormat::Mp4 => path.with_extension("mp4"),
+            RecordFormat::Png => path.with_extension("")
+        };
+        Recording::to(&path, format, scale, fps)
+    }
+
+    // a recording into the gif, the video or the directory of pngs at path
+    pub fn to(path: &Path, format: RecordFormat, scale: usize, fps: u32) -> Result<Recording, String> {
+        if format == RecordFormat::Png {
+            fs::create_dir_all(path)
+                .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+        }
+        let output = match format {
+            RecordFormat::Gif => Output::Gif(None),
+            RecordFormat::Mp4 => Output::Mp4(None),
+            RecordFormat::Png => Output::Png
+        };
+        Ok(Recording {
+            output,
+            path: path.to_path_buf(),
+            scale,
+            every: (TIMER_HZ / fps.clamp(1, TIMER_HZ)) as u64,
+            offered: 0,
+            kept: 0,
+            size: None
+        })
+    }
+
+    // the gif, the video or the directory of pngs
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.kept
+    }
+
+    // call with every frame shown, in the palette it's shown in. the
+    // display can't change resolution part way through, a gif or a video
+    // only has the one size
+    pub fn capture(&mut self, frame: &Frame, palette: &Palette) -> Result<(), String> {
+        let size = (frame.width(), frame.height());
+        if *self.size.get_or_insert(size) != size {
+            return Err(format!("Could not record to {}: the display changed resolution", self.path.display()));
+        }
+        let offered = self.offered;
+        self.offered += 1;
+        if !offered.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        let image = Image::new(frame, palette, self.scale);
+
+        match &mut self.output {
+            Output::Png => {
+                let png = self.path.join(format!("{:05}.png", self.kept));
+                image.write_png(&png.to_string_lossy())?;
+            },
+            Output::Gif(encoder) => {
+                if encoder.is_none() {
+                    *encoder = Some(gif_encoder(&self.path, &image)?);
+                }
+                let mut gif_frame = gif_frame(&image);
+                gif_frame.delay = delay(self.kept, self.every);
+                encoder.as_mut().unwrap().write_frame(&gif_frame)
+                    .map_err(|e| format!("Could not write {}: {}", self.path.display(), e))?;
+            },
+            Output::Mp4(ffmpeg) => {
+                if ffmpeg.is_none() {
+                    *ffmpeg = Some(start_ffmpeg(&self.path, &image, TIMER_HZ as u64 / self.every)?);
+                }
+                let rgb: Vec<u8> = image.pixels.iter()
+                    .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+                    .collect();
+                ffmpeg.as_mut().unwrap().stdin.as_mut().unwrap().write_all(&rgb)
+                    .map_err(|e| format!("Could not write {}: {}", self.path.display(), e))?;
+            }
+        }
+        self.kept += 1;
+        Ok(())
+    }
+
+    // ends the gif or the video, returning where the recording went
+    pub fn finish(self) -> Result<PathBuf, String> {
+        let path = self.path;
+        match self.output {
+            Output::Gif(Some(encoder)) => {
+                encoder.into_inner()
+                    .and_then(|mut file| file.flush())
+                    .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            },
+            Output::Mp4(Some(mut ffmpeg)) => {
+                // ffmpeg finishes the video once its input closes
+                drop(ffmpeg.stdin.take());
+                let status = ffmpeg.wait()
+                    .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+                if !status.success() {
+                    return Err(format!("Could not write {}: ffmpeg failed, {}", path.display(), status));
+                }
+            },
+            _ => ()
+        }
+        Ok(path)
+    }
+}
+
+fn gif_encoder(path: &Path, image: &Image) -> Result<Encoder<BufWriter<File>>, String> {
+    let file = File::create(path)
+        .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+    let too_big = || format!("Could not record to {}: {}x{} is too big for a gif", path.display(), image.width, image.height);
+    let width = u16::try_from(image.width).map_err(|_| too_big())?;
+    let height = u16::try_from(image.height).map_err(|_| too_big())?;
+    let mut encoder = Encoder::new(BufWriter::new(file), width, height, &[])
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    encoder.set_repeat(Repeat::Infinite)
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    Ok(encoder)
+}
+
+// ffmpeg encoding raw rgb frames the size of image from its stdin into the
+// video at path
+fn start_ffmpeg(path: &Path, image: &Image, fps: u64) -> Result<Child, String> {
+    Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .arg("-s").arg(format!("{}x{}", image.width, image.height))
+        .arg("-r").arg(fps.to_string())
+        .args(["-i", "-", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not start ffmpeg to record {}: {}", path.display(), e))
+}
+
+// palette pictures have a handful of colors, so they're indexed exactly
+// rather than quantized
+fn gif_frame(image: &Image) -> gif::Frame<'static> {
+    let mut colors: Vec<u32> = Vec::new();
+    let mut indices = Vec::with_capacity(image.pixels.len());
+    for &pixel in &image.pixels {
+        let index = match colors.iter().position(|&color| color == pixel) {
+            Some(index) => index,
+            None if colors.len() < 256 => {
+                colors.push(pixel);
+                colors.len() - 1
+            },
+            None => {
+                let rgb: Vec<u8> = image.pixels.iter()
+                    .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+                    .collect();
+                return gif::Frame::from_rgb_speed(image.width as u16, image.height as u16, &rgb, 10);
+            }
+        };
+        indices.push(index as u8);
+    }
+    let palette: Vec<u8> = colors.iter()
+        .flat_map(|&color| [(color >> 16) as u8, (color >> 8) as u8, color as u8])
+        .collect();
+    gif::Frame::from_palette_pixels(image.width as u16, image.height as u16, &indices, &palette, None)
+}
+
+// how long the nth kept frame shows for, in the hundredths of a second gifs
+// count in. rounding is spread over the frames so the gif keeps time
+fn delay(n: u64, every: u64) -> u16 {
+    let at = |n: u64| n * every * 100 / TIMER_HZ as u64;
+    (at(n + 1) - at(n)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::{
+        delay,
+        RecordFormat,
+        Recording
+    };
+    use crate::display::Frame;
+    use crate::present::Palette;
+
+    #[test]
+    fn recordings_keep_time_at_the_frame_rate_asked_for() {
+        // 3 frames at 20fps take 15 hundredths
+        let delays: Vec<u16> = (0..3).map(|n| delay(n, 3)).collect();
+        assert_eq!(delays, vec![5, 5, 5]);
+        // 30fps can't be exact, but 3 frames still last 10 hundredths
+        let delays: Vec<u16> = (0..3).map(|n| delay(n, 2)).collect();
+        assert_eq!(delays, vec![3, 3, 4]);
+
+        let dir = std::env::temp_dir().join("rust8-recording-test");
+        let mut recording = Recording::start(&dir.to_string_lossy(), "pong", RecordFormat::Gif, 2, 30, UNIX_EPOCH).unwrap();
+        let palette = Palette::default();
+        for _ in 0..5 {
+            recording.capture(&Frame::new(64, 32), &palette).unwrap();
+        }
+        assert_eq!(recording.frames(), 3);
+        assert!(recording.capture(&Frame::new(128, 64), &palette).is_err());
+        let path = recording.finish().unwrap();
+        assert!(std::fs::read(&path).unwrap().starts_with(b"GIF89a"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
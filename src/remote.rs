@@ -0,0 +1,425 @@
+use std::fs::File;
+use std::io::{
+    BufRead,
+    BufReader,
+    Read,
+    Write
+};
+use std::net::TcpListener;
+use std::sync::{
+    Arc,
+    Mutex
+};
+use std::thread;
+use std::time::{
+    Duration,
+    Instant
+};
+
+use crate::input::InputSource;
+use crate::timing::Timing;
+
+// keys held by remote clients, merged into the keypad every instruction
+struct RemoteKeys {
+    down: [bool; 16],
+    release_at: [Option<Instant>; 16],
+    // latest progress reported by the emulator, for the status command
+    timing: Option<Timing>
+}
+
+impl RemoteKeys {
+    fn new() -> Arc<Mutex<RemoteKeys>> {
+        Arc::new(Mutex::new(RemoteKeys {
+            down: [false; 16],
+            release_at: [None; 16],
+            timing: None
+        }))
+    }
+
+    fn run(&mut self, command: Command) {
+        match command {
+            Command::KeyDown(key) => self.down[key] = true,
+            Command::KeyUp(key) => {
+                self.down[key] = false;
+                self.release_at[key] = None;
+            },
+            Command::Press(key, duration) => {
+                self.release_at[key] = Some(Instant::now() + duration);
+            },
+            // answered by the client, nothing to change
+            Command::Status => ()
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    KeyDown(usize),
+    KeyUp(usize),
+    Press(usize, Duration),
+    Status
+}
+
+// minimal newline delimited input protocol, one command per line:
+//   keydown 5
+//   keyup 5
+//   press A 100ms
+//   status
+// every line is answered with "ok" or "error: <reason>", except status which
+// is answered with the run's progress, e.g. "frames 60 cycles 540 runtime_ms 1002"
+pub struct RemoteInput {
+    keys: Arc<Mutex<RemoteKeys>>
+}
+
+impl RemoteInput {
+    pub fn listen(addr: &str) -> Result<RemoteInput, String> {
+        let keys = RemoteKeys::new();
+
+        if let Some(tcp_addr) = addr.strip_prefix("tcp:") {
+            let listener = TcpListener::bind(tcp_addr)
+                .map_err(|e| format!("Could not listen on {}: {}", tcp_addr, e))?;
+            let shared = keys.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // low latency matters more than throughput here
+                    let _ = stream.set_nodelay(true);
+                    if let Ok(writer) = stream.try_clone() {
+                        spawn_client(stream, writer, shared.clone());
+                    }
+                }
+            });
+        } else if let Some(path) = addr.strip_prefix("unix:") {
+            listen_unix(path, keys.clone())?;
+        } else {
+            return Err(format!("Remote input address must start with tcp: or unix:, got {}", addr));
+        }
+
+        println!("Remote input listening on {}", addr);
+        Ok(RemoteInput { keys })
+    }
+
+    // hardware keypad on a serial port speaking the same line protocol,
+    // e.g. a microcontroller scanning a 4x4 matrix. the port speed has to be
+    // configured beforehand (stty -F /dev/ttyUSB0 9600 raw)
+    pub fn open_serial(path: &str) -> Result<RemoteInput, String> {
+        let port = File::open(path)
+            .map_err(|e| format!("Could not open serial keypad {}: {}", path, e))?;
+        let keys = RemoteKeys::new();
+        let shared = keys.clone();
+        let name = path.to_string();
+        thread::spawn(move || {
+            for line in BufReader::new(port).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_command(&line) {
+                    Ok(command) => shared.lock().unwrap().run(command),
+                    Err(e) => println!("Serial keypad {}: {}", name, e)
+                }
+            }
+        });
+
+        println!("Serial keypad reading from {}", path);
+        Ok(RemoteInput { keys })
+    }
+
+    // raw midi device (e.g. /dev/snd/midiC1D0), notes base_note..base_note+15
+    // map to keys 0-F so pad controllers work as a keypad
+    pub fn open_midi(path: &str, base_note: u8) -> Result<RemoteInput, String> {
+        let device = File::open(path)
+            .map_err(|e| format!("Could not open midi device {}: {}", path, e))?;
+        let keys = RemoteKeys::new();
+        let shared = keys.clone();
+        thread::spawn(move || {
+            let mut parser = MidiParser::new();
+            for byte in BufReader::new(device).bytes() {
+                let byte = match byte {
+                    Ok(byte) => byte,
+                    Err(_) => break
+                };
+                if let Some((note, down)) = parser.feed(byte) {
+                    if note >= base_note && note - base_note < 16 {
+                        let key = (note - base_note) as usize;
+                        let command = if down { Command::KeyDown(key) } else { Command::KeyUp(key) };
+                        shared.lock().unwrap().run(command);
+                    }
+                }
+            }
+        });
+
+        println!("Midi keypad reading from {}", path);
+        Ok(RemoteInput { keys })
+    }
+
+    // call once a frame so status commands see the current progress
+    pub fn report(&self, timing: Timing) {
+        self.keys.lock().unwrap().timing = Some(timing);
+    }
+}
+
+impl InputSource for RemoteInput {
+    fn poll(&mut self, held: &mut [u8; 16]) {
+        let now = Instant::now();
+        let mut keys = self.keys.lock().unwrap();
+        let keys = &mut *keys;
+        for (key, release_at) in keys.release_at.iter_mut().enumerate() {
+            let timed = match *release_at {
+                Some(at) if at > now => true,
+                Some(_) => {
+                    *release_at = None;
+                    false
+                },
+                None => false
+            };
+            if keys.down[key] || timed {
+                held[key] = 1;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn listen_unix(path: &str, keys: Arc<Mutex<RemoteKeys>>) -> Result<(), String> {
+    use std::os::unix::net::UnixListener;
+
+    // a socket left behind by a previous run would make bind fail
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format!("Could not listen on {}: {}", path, e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Ok(writer) = stream.try_clone() {
+                spawn_client(stream, writer, keys.clone());
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_path: &str, _keys: Arc<Mutex<RemoteKeys>>) -> Result<(), String> {
+    Err(String::from("Unix sockets are not supported on this platform"))
+}
+
+fn spawn_client<R, W>(reader: R, mut writer: W, keys: Arc<Mutex<RemoteKeys>>)
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static
+{
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let reply = match parse_command(&line) {
+                Ok(Command::Status) => match keys.lock().unwrap().timing {
+                    Some(timing) => format!("{}\n", timing),
+                    None => String::from("error: not running yet\n")
+                },
+                Ok(command) => {
+                    keys.lock().unwrap().run(command);
+                    String::from("ok\n")
+                },
+                Err(e) => format!("error: {}\n", e)
+            };
+            if writer.write_all(reply.as_bytes()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// just enough of the midi byte stream to pull out note on/off events,
+// including running status and skipping everything else
+struct MidiParser {
+    status: u8,
+    data: Vec<u8>,
+    in_sysex: bool
+}
+
+impl MidiParser {
+    fn new() -> MidiParser {
+        MidiParser {
+            status: 0,
+            data: Vec::new(),
+            in_sysex: false
+        }
+    }
+
+    // returns (note, pressed) once a complete note message has been read
+    fn feed(&mut self, byte: u8) -> Option<(u8, bool)> {
+        if byte >= 0xF8 {
+            // realtime messages can appear anywhere and carry no data
+            return None;
+        }
+        if byte >= 0x80 {
+            self.in_sysex = byte == 0xF0;
+            // system common messages cancel running status
+            self.status = if byte >= 0xF0 { 0 } else { byte };
+            self.data.clear();
+            return None;
+        }
+        if self.in_sysex || self.status == 0 {
+            return None;
+        }
+
+        self.data.push(byte);
+        let length = match self.status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            _ => 2
+        };
+        if self.data.len() < length {
+            return None;
+        }
+
+        let message = match self.status & 0xF0 {
+            // note on with velocity 0 is a note off
+            0x90 => Some((self.data[0], self.data[1] != 0)),
+            0x80 => Some((self.data[0], false)),
+            _ => None
+        };
+        self.data.clear();
+        message
+    }
+}
+
+fn parse_key(token: Option<&str>) -> Result<usize, String> {
+    let token = token.ok_or("missing key")?;
+    match usize::from_str_radix(token, 16) {
+        Ok(key) if key < 16 => Ok(key),
+        _ => Err(format!("invalid key '{}', expected 0-F", token))
+    }
+}
+
+fn parse_duration(token: Option<&str>) -> Result<Duration, String> {
+    let token = token.ok_or("missing duration")?;
+    let millis = token.strip_suffix("ms").unwrap_or(token);
+    millis.parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("invalid duration '{}', expected e.g. 100ms", token))
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut tokens = line.split_whitespace();
+    let command = match tokens.next() {
+        Some("keydown") => Command::KeyDown(parse_key(tokens.next())?),
+        Some("keyup") => Command::KeyUp(parse_key(tokens.next())?),
+        Some("press") => {
+            let key = parse_key(tokens.next())?;
+            Command::Press(key, parse_duration(tokens.next())?)
+        },
+        Some("status") => Command::Status,
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+        None => return Err(String::from("empty command"))
+    };
+
+    if tokens.next().is_some() {
+        return Err(String::from("too many arguments"));
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        parse_command,
+        Command,
+        MidiParser,
+        RemoteInput,
+        RemoteKeys
+    };
+
+    #[test]
+    fn commands_parse_or_say_what_is_wrong() {
+        assert_eq!(parse_command("keydown a"), Ok(Command::KeyDown(0xA)));
+        assert_eq!(parse_command("  keyup F "), Ok(Command::KeyUp(0xF)));
+        assert_eq!(parse_command("press 5 100ms"), Ok(Command::Press(5, Duration::from_millis(100))));
+        assert_eq!(parse_command("press 5 40"), Ok(Command::Press(5, Duration::from_millis(40))));
+        assert_eq!(parse_command("status"), Ok(Command::Status));
+
+        assert_eq!(parse_command(""), Err(String::from("empty command")));
+        assert_eq!(parse_command("jump 5"), Err(String::from("unknown command 'jump'")));
+        assert_eq!(parse_command("keydown"), Err(String::from("missing key")));
+        assert_eq!(parse_command("keydown 10"), Err(String::from("invalid key '10', expected 0-F")));
+        assert_eq!(parse_command("press 5"), Err(String::from("missing duration")));
+        assert_eq!(parse_command("press 5 soon"), Err(String::from("invalid duration 'soon', expected e.g. 100ms")));
+        assert_eq!(parse_command("keyup 5 6"), Err(String::from("too many arguments")));
+    }
+
+    #[test]
+    fn listening_needs_a_tcp_or_unix_address() {
+        let error = RemoteInput::listen("udp:127.0.0.1:9000").err();
+        assert_eq!(error, Some(String::from("Remote input address must start with tcp: or unix:, got udp:127.0.0.1:9000")));
+        assert!(RemoteInput::listen("tcp:not an address").is_err());
+    }
+
+    #[test]
+    fn midi_note_on_and_off_come_out_of_the_byte_stream() {
+        let mut parser = MidiParser::new();
+        let mut feed = |bytes: &[u8]| -> Vec<(u8, bool)> {
+            bytes.iter().filter_map(|&byte| parser.feed(byte)).collect()
+        };
+        // note on 60, then 62 by running status, then 60 off as velocity 0
+        assert_eq!(feed(&[0x90, 60, 100, 62, 90, 60, 0]), vec![(60, true), (62, true), (60, false)]);
+        // a clock tick in the middle of a message changes nothing
+        assert_eq!(feed(&[0x80, 62, 0xF8, 64]), vec![(62, false)]);
+        // sysex and program changes are skipped, and sysex ends running status
+        assert_eq!(feed(&[0xF0, 60, 100, 0xF7, 60, 100]), vec![]);
+        assert_eq!(feed(&[0xC0, 5, 0x90, 61, 1]), vec![(61, true)]);
+    }
+
+    #[test]
+    fn missing_keypad_devices_are_reported() {
+        let error = RemoteInput::open_serial("/nonexistent/ttyUSB0").err().unwrap();
+        assert!(error.starts_with("Could not open serial keypad /nonexistent/ttyUSB0"));
+        let error = RemoteInput::open_midi("/nonexistent/midiC1D0", 36).err().unwrap();
+        assert!(error.starts_with("Could not open midi device /nonexistent/midiC1D0"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn clients_get_a_reply_to_every_line() {
+        use std::io::{
+            BufRead,
+            BufReader,
+            Write
+        };
+        use std::os::unix::net::UnixStream;
+
+        use super::spawn_client;
+        use crate::cpu::Cpu;
+        use crate::input::InputSource;
+        use crate::timing::Timing;
+
+        let keys = RemoteKeys::new();
+        let (mut client, server) = UnixStream::pair().unwrap();
+        spawn_client(server.try_clone().unwrap(), server, keys.clone());
+        client.write_all(b"status\nkeydown 5\n\nkeydown X\n").unwrap();
+
+        let mut replies = BufReader::new(client.try_clone().unwrap()).lines();
+        let mut reply = || replies.next().unwrap().unwrap();
+        assert_eq!(reply(), "error: not running yet");
+        assert_eq!(reply(), "ok");
+        assert_eq!(reply(), "error: invalid key 'X', expected 0-F");
+
+        let mut input = RemoteInput { keys };
+        let mut held = [0; 16];
+        input.poll(&mut held);
+        assert_eq!(held[5], 1);
+
+        input.report(Timing::of(&Cpu::new(), std::time::Instant::now()));
+        client.write_all(b"status\n").unwrap();
+        assert!(reply().starts_with("frames 0 cycles 0"));
+    }
+}
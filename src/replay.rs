@@ -0,0 +1,520 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::chip8::Chip8;
+use crate::cpu::{
+    Cpu,
+    Extensions,
+    KeyWaitTimeout
+};
+use crate::input::InputSource;
+use crate::macros::Macro;
+use crate::opcode_log::InvalidOpcodePolicy;
+use crate::quirks::Quirks;
+use crate::ram::{
+    MemoryPolicy,
+    Ram
+};
+use crate::rng::SeededRng;
+use crate::state::State;
+
+// the rust8 replays are recorded by, kept in them
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// a run that plays back exactly: the machine it started from, what RND was
+// seeded with and the keys held in every frame after. the start state has
+// all of ram, rom included, so a replay needs nothing else to play.
+// frames are Chip8::run_frame's, the keys are read once at the start and
+// the timers tick once at the end
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    // the sha1 of the rom file recorded on and the rust8 that recorded it.
+    // replays from before they were kept have them empty
+    #[serde(default)]
+    pub rom_sha1: String,
+    #[serde(default)]
+    pub version: String,
+    pub seed: u64,
+    pub quirks: Quirks,
+    // the rest of how the machine was set up, None in replays from before
+    // it was kept
+    #[serde(default)]
+    settings: Option<Settings>,
+    pub instructions_per_frame: usize,
+    pub start: State,
+    // a key FX0A was waiting to see released at the start, which start
+    // leaves out
+    #[serde(default)]
+    waiting_key: Option<u8>,
+    inputs: Macro,
+    // checksum of the machine after the last frame, so a replay that went
+    // out of step can tell
+    end: Option<String>,
+    // where states were saved along the way, see anchor
+    #[serde(default)]
+    anchors: Vec<Anchor>
+}
+
+// what the machine was told beyond its quirks, which a rom can go
+// differently under just the same
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct Settings {
+    strict: bool,
+    extensions: Option<Extensions>,
+    memory_policy: MemoryPolicy,
+    invalid_opcodes: InvalidOpcodePolicy,
+    key_wait_timeout: Option<(u32, KeyWaitTimeout)>
+}
+
+impl Settings {
+    fn of(cpu: &Cpu, ram: &Ram) -> Settings {
+        Settings {
+            strict: cpu.strict(),
+            extensions: cpu.extensions(),
+            memory_policy: ram.policy(),
+            invalid_opcodes: cpu.invalid_opcode_policy(),
+            key_wait_timeout: cpu.key_wait_timeout()
+        }
+    }
+
+    fn apply(&self, cpu: &mut Cpu, ram: &mut Ram) {
+        cpu.set_strict(self.strict);
+        cpu.set_extensions(self.extensions);
+        ram.set_policy(self.memory_policy);
+        cpu.set_invalid_opcode_policy(self.invalid_opcodes);
+        match self.key_wait_timeout {
+            Some((frames, action)) => cpu.set_key_wait_timeout(frames, action),
+            None => cpu.clear_key_wait_timeout()
+        }
+    }
+}
+
+// the machine after some frame of a replay, kept when a state is saved
+// there. saved states leave out RND and a key FX0A waits on, so those are
+// kept here for loading the state to carry the replay on exactly
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Anchor {
+    frame: usize,
+    checksum: String,
+    seed: u64,
+    waiting_key: Option<u8>
+}
+
+impl Replay {
+    // a recording starting from where the machine is now. the machine is
+    // put back to its own saved state with RND seeded afresh, so it starts
+    // exactly as a replay of the recording will
+    pub fn record_from(cpu: &mut Cpu, ram: &mut Ram, rom_sha1: &str, seed: u64, instructions_per_frame: usize) -> Result<Replay, String> {
+        let replay = Replay {
+            rom_sha1: rom_sha1.to_string(),
+            version: VERSION.to_string(),
+            seed,
+            quirks: cpu.quirks(),
+            settings: Some(Settings::of(cpu, ram)),
+            instructions_per_frame,
+            start: cpu.save_state(ram),
+            waiting_key: None,
+            inputs: Macro::new(),
+            end: None,
+            anchors: Vec::new()
+        };
+        replay.restart(cpu, ram, None)?;
+        Ok(replay)
+    }
+
+    // a recording starting from where the machine is now, leaving it
+    // untouched. RND carries on as it is, so it has to be seeded already
+    pub fn record_here(cpu: &Cpu, ram: &Ram, rom_sha1: &str, instructions_per_frame: usize) -> Result<Replay, String> {
+        let seed = cpu.rng_seed().ok_or_else(|| String::from("RND isn't seeded, so it couldn't be played back"))?;
+        Ok(Replay {
+            rom_sha1: rom_sha1.to_string(),
+            version: VERSION.to_string(),
+            seed,
+            quirks: cpu.quirks(),
+            settings: Some(Settings::of(cpu, ram)),
+            instructions_per_frame,
+            start: cpu.save_state(ram),
+            waiting_key: cpu.waiting_key(),
+            inputs: Macro::new(),
+            end: None,
+            anchors: Vec::new()
+        })
+    }
+
+    // puts the machine back where the recording started, set up the way it
+    // was. a replay from another rust8, or recorded on a rom other than
+    // rom_sha1 when that's given, is refused since it would go out of
+    // step. an empty rom_sha1 is one that couldn't be worked out
+    pub fn restart(&self, cpu: &mut Cpu, ram: &mut Ram, rom_sha1: Option<&str>) -> Result<(), String> {
+        if !self.version.is_empty() && self.version != VERSION {
+            return Err(format!("Replay was recorded with rust8 {}, this is {}", self.version, VERSION));
+        }
+        match rom_sha1 {
+            Some(sha1) if !sha1.is_empty() && !self.rom_sha1.is_empty() && self.rom_sha1 != sha1 => {
+                return Err(format!("Replay was recorded on another rom, {} rather than {}", self.rom_sha1, sha1));
+            },
+            _ => ()
+        }
+        cpu.load_state(ram, &self.start)?;
+        cpu.set_waiting_key(self.waiting_key);
+        cpu.set_quirks(self.quirks);
+        if let Some(settings) = &self.settings {
+            settings.apply(cpu, ram);
+        }
+        cpu.set_rng(Box::new(SeededRng::new(self.seed)));
+        Ok(())
+    }
+
+    // call at the end of every frame recorded
+    pub fn record(&mut self, cpu: &Cpu, ram: &Ram) {
+        self.inputs.record(&cpu.keys());
+        self.end = Some(cpu.save_state(ram).checksum());
+    }
+
+    // adds a frame's keys from elsewhere, e.g. a netplay host
+    pub fn push(&mut self, keys: &[u8; 16]) {
+        self.inputs.record(keys);
+        self.end = None;
+    }
+
+    // forgets the frames after the first frames, when play is rewound or
+    // re-recorded, along with the anchors in them
+    pub fn truncate(&mut self, frames: usize) {
+        if frames < self.inputs.len() {
+            self.inputs.truncate(frames);
+            self.end = None;
+        }
+        self.anchors.retain(|anchor| anchor.frame <= frames);
+    }
+
+    // notes that the machine is where frame frames of the replay took it,
+    // as a state is saved. loading that state again can then carry the
+    // replay on from there, see resume
+    pub fn anchor(&mut self, frame: usize, cpu: &Cpu, ram: &Ram) {
+        let seed = match cpu.rng_seed() {
+            Some(seed) => seed,
+            None => return
+        };
+        self.anchors.retain(|anchor| anchor.frame != frame);
+        self.anchors.push(Anchor {
+            frame,
+            checksum: cpu.save_state(ram).checksum(),
+            seed,
+            waiting_key: cpu.waiting_key()
+        });
+        self.anchors.sort_by_key(|anchor| anchor.frame);
+    }
+
+    // the frame a machine just loaded from a saved state was anchored at,
+    // with RND and FX0A put back as they were then. None if the state
+    // wasn't saved during the replay
+    pub fn resume(&self, cpu: &mut Cpu, ram: &Ram) -> Option<usize> {
+        let checksum = cpu.save_state(ram).checksum();
+        let anchor = self.anchors.iter()
+            .rev()
+            .find(|anchor| anchor.checksum == checksum && anchor.frame <= self.len())?;
+        cpu.set_rng(Box::new(SeededRng::new(anchor.seed)));
+        cpu.set_waiting_key(anchor.waiting_key);
+        Some(anchor.frame)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    // the keys held in the nth frame
+    pub fn held(&self, frame: usize) -> Option<[bool; 16]> {
+        self.inputs.held(frame)
+    }
+
+    // whether a machine that played every frame ended up where the
+    // recording did
+    pub fn check(&self, cpu: &Cpu, ram: &Ram) -> Result<(), String> {
+        let checksum = cpu.save_state(ram).checksum();
+        match &self.end {
+            Some(end) if *end != checksum => Err(format!("Replay went out of step, it ended at {} instead of {}", checksum, end)),
+            _ => Ok(())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Could not serialize replay: {}", e))?;
+        fs::write(path, json)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let replay: Replay = serde_json::from_str(&json)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        if replay.instructions_per_frame == 0 {
+            return Err(format!("Could not parse {}: no instructions per frame", path.display()));
+        }
+        Ok(replay)
+    }
+}
+
+// feeds a replay's keys to the machine in place of the keyboard, a frame
+// at a time
+pub struct ReplayPlayer {
+    replay: Replay,
+    frame: usize
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer {
+            replay,
+            frame: 0
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn replay_mut(&mut self) -> &mut Replay {
+        &mut self.replay
+    }
+
+    pub fn into_replay(self) -> Replay {
+        self.replay
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // carries on from the nth frame, when play is rewound
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.replay.len()
+    }
+}
+
+impl InputSource for ReplayPlayer {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        if let Some(held) = self.replay.held(self.frame) {
+            for (key, &down) in keys.iter_mut().zip(&held) {
+                *key = down as u8;
+            }
+        }
+    }
+}
+
+// plays the whole replay on chip8 without a window, as fast as it goes,
+// then checks it ended where the recording did. replays from before the
+// machine's settings were kept, e.g. strictness, leave them to the caller
+pub fn play(chip8: &mut Chip8, replay: &Replay) -> Result<(), String> {
+    play_frames(chip8, replay, |_| Ok(()))
+}
+
+// play, handing the machine to after_frame as every frame ends, e.g. to
+// record what it showed. an error from it stops play
+pub fn play_frames<F>(chip8: &mut Chip8, replay: &Replay, mut after_frame: F) -> Result<(), String>
+where
+    F: FnMut(&Chip8) -> Result<(), String>
+{
+    replay.restart(&mut chip8.cpu, &mut chip8.ram, None)?;
+    chip8.set_instructions_per_frame(replay.instructions_per_frame);
+    for frame in 0..replay.len() {
+        chip8.run_frame(&replay.held(frame).unwrap_or_default());
+        if let Some(reason) = chip8.cpu.halted() {
+            return Err(format!("Replay stopped in frame {}: {}", frame, reason));
+        }
+        after_frame(chip8)?;
+    }
+    replay.check(&chip8.cpu, &chip8.ram)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        play,
+        play_frames,
+        Replay,
+        VERSION
+    };
+    use crate::chip8::Chip8;
+    use crate::cpu::{
+        Extensions,
+        KeyWaitTimeout
+    };
+    use crate::opcode_log::InvalidOpcodePolicy;
+    use crate::ram::MemoryPolicy;
+    use crate::romdb;
+
+    // waits for a key, then draws a random sprite at a random place
+    pub(crate) const ROM: [u8; 12] = [
+        0xF0, 0x0A, // LD V0, K
+        0xC1, 0x3F, // RND V1, 3F
+        0xC2, 0x1F, // RND V2, 1F
+        0xF0, 0x29, // LD F, V0
+        0xD1, 0x25, // DRW V1, V2, 5
+        0x12, 0x00  // JP 200
+    ];
+
+    // the keys a test run holds in a frame, one key every third frame
+    pub(crate) fn keys(frame: usize) -> [bool; 16] {
+        let mut keys = [false; 16];
+        keys[frame % 16] = frame.is_multiple_of(3);
+        keys
+    }
+
+    // ROM recorded for frames frames with the test keys, and the machine
+    // that ran them
+    fn recorded(frames: usize) -> (Chip8, Replay) {
+        let mut live = Chip8::with_rom(&ROM).unwrap();
+        let mut replay = Replay::record_from(&mut live.cpu, &mut live.ram, &romdb::sha1(&ROM), 42, 9).unwrap();
+        live.set_instructions_per_frame(9);
+        for frame in 0..frames {
+            live.run_frame(&keys(frame));
+            replay.record(&live.cpu, &live.ram);
+        }
+        (live, replay)
+    }
+
+    #[test]
+    fn replays_play_back_the_frames_recorded() {
+        let (live, mut replay) = recorded(60);
+        let mut chip8 = Chip8::new();
+        assert_eq!(play(&mut chip8, &replay), Ok(()));
+        assert_eq!(chip8.save_state(), live.save_state());
+
+        // another seed draws somewhere else
+        replay.seed = 43;
+        assert!(play(&mut chip8, &replay).is_err());
+    }
+
+    #[test]
+    fn replays_show_the_same_frames_every_time() {
+        let (live, replay) = recorded(60);
+        let shown = || {
+            let mut frames = Vec::new();
+            play_frames(&mut Chip8::new(), &replay, |chip8| {
+                frames.push(chip8.cpu.gfx().clone());
+                Ok(())
+            }).map(|()| frames)
+        };
+        let first = shown().unwrap();
+        assert_eq!(first.len(), 60);
+        assert_eq!(first.last(), Some(live.cpu.gfx()));
+        assert_eq!(shown(), Ok(first));
+
+        // e.g. a full disk stops it there
+        let mut seen = 0;
+        let stopped = play_frames(&mut Chip8::new(), &replay, |_| {
+            seen += 1;
+            match seen {
+                10 => Err(String::from("disk full")),
+                _ => Ok(())
+            }
+        });
+        assert_eq!((stopped, seen), (Err(String::from("disk full")), 10));
+    }
+
+    #[test]
+    fn replays_refuse_another_rom_or_rust8() {
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        let sha1 = romdb::sha1(&ROM);
+        let mut replay = Replay::record_from(&mut chip8.cpu, &mut chip8.ram, &sha1, 42, 9).unwrap();
+        assert_eq!(replay.restart(&mut chip8.cpu, &mut chip8.ram, Some(&sha1)), Ok(()));
+        let other = romdb::sha1(&[0x12, 0x00]);
+        assert_eq!(
+            replay.restart(&mut chip8.cpu, &mut chip8.ram, Some(&other)),
+            Err(format!("Replay was recorded on another rom, {} rather than {}", sha1, other))
+        );
+
+        replay.version = String::from("0.0.0");
+        assert_eq!(
+            replay.restart(&mut chip8.cpu, &mut chip8.ram, None),
+            Err(format!("Replay was recorded with rust8 0.0.0, this is {}", VERSION))
+        );
+
+        // replays from before either was kept play anywhere
+        replay.version.clear();
+        replay.rom_sha1.clear();
+        assert_eq!(replay.restart(&mut chip8.cpu, &mut chip8.ram, Some(&other)), Ok(()));
+    }
+
+    #[test]
+    fn replays_restart_with_the_settings_they_were_recorded_with() {
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        chip8.cpu.set_strict(true);
+        chip8.cpu.set_extensions(Some(Extensions::Rust8));
+        chip8.ram.set_policy(MemoryPolicy::Mirrored);
+        chip8.cpu.set_invalid_opcode_policy(InvalidOpcodePolicy::Skip);
+        chip8.cpu.set_key_wait_timeout(30, KeyWaitTimeout::Press(5));
+        let replay = Replay::record_from(&mut chip8.cpu, &mut chip8.ram, "", 42, 9).unwrap();
+
+        let mut other = Chip8::new();
+        other.cpu.set_key_wait_timeout(2, KeyWaitTimeout::Abort);
+        replay.restart(&mut other.cpu, &mut other.ram, None).unwrap();
+        assert!(other.cpu.strict());
+        assert_eq!(other.cpu.extensions(), Some(Extensions::Rust8));
+        assert_eq!(other.ram.policy(), MemoryPolicy::Mirrored);
+        assert_eq!(other.cpu.invalid_opcode_policy(), InvalidOpcodePolicy::Skip);
+        assert_eq!(other.cpu.key_wait_timeout(), Some((30, KeyWaitTimeout::Press(5))));
+
+        // and the other way, nothing set carries over either
+        let mut blank = Chip8::new();
+        let plain = Replay::record_from(&mut blank.cpu, &mut blank.ram, "", 42, 9).unwrap();
+        plain.restart(&mut other.cpu, &mut other.ram, None).unwrap();
+        assert!(!other.cpu.strict());
+        assert_eq!(other.ram.policy(), MemoryPolicy::Checked);
+        assert_eq!(other.cpu.key_wait_timeout(), None);
+    }
+
+    #[test]
+    fn replays_play_on_a_rom_whose_sha1_is_unknown() {
+        let mut chip8 = Chip8::with_rom(&ROM).unwrap();
+        let sha1 = romdb::sha1(&ROM);
+        let replay = Replay::record_from(&mut chip8.cpu, &mut chip8.ram, &sha1, 42, 9).unwrap();
+        assert_eq!(replay.restart(&mut chip8.cpu, &mut chip8.ram, Some("")), Ok(()));
+    }
+
+    #[test]
+    fn re_recording_carries_on_from_a_saved_state() {
+        let (mut live, mut replay) = recorded(30);
+        let saved = live.save_state();
+        replay.anchor(30, &live.cpu, &live.ram);
+        for frame in 30..60 {
+            live.run_frame(&keys(frame));
+            replay.record(&live.cpu, &live.ram);
+        }
+
+        // back to the state saved at frame 30, then different keys after it
+        live.load_state(&saved).unwrap();
+        assert_eq!(replay.resume(&mut live.cpu, &live.ram), Some(30));
+        replay.truncate(30);
+        for frame in 30..60 {
+            live.run_frame(&keys(frame + 1));
+            replay.record(&live.cpu, &live.ram);
+        }
+        let mut chip8 = Chip8::new();
+        assert_eq!(play(&mut chip8, &replay), Ok(()));
+        assert_eq!(chip8.save_state(), live.save_state());
+
+        // a state saved somewhere else isn't anchored
+        let mut other = Chip8::with_rom(&[0x12, 0x00]).unwrap();
+        assert_eq!(replay.resume(&mut other.cpu, &other.ram), None);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+use crate::ram::Ram;
+
+// the last few seconds of play, one snapshot per frame. snapshots are
+// plain copies of the machine, about 12KB each
+pub struct Rewind {
+    snapshots: VecDeque<(Cpu, Ram)>,
+    capacity: usize
+}
+
+impl Rewind {
+    // keeps at most capacity frames, dropping the oldest
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity
+        }
+    }
+
+    // call once per frame while playing
+    pub fn push(&mut self, cpu: &Cpu, ram: &Ram) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((cpu.clone(), ram.clone()));
+    }
+
+    // the most recent snapshot, removing it so the next call goes further back
+    pub fn pop(&mut self) -> Option<(Cpu, Ram)> {
+        self.snapshots.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // forgets everything, e.g. after loading another rom
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rewind;
+    use crate::chip8::Chip8;
+
+    // breakout mid-game
+    const BREAKOUT: &[u8] = include_bytes!("../tests/fixtures/breakout.state.json");
+
+    #[test]
+    fn rewind_steps_back_a_frame_at_a_time() {
+        let mut chip8 = Chip8::from_state(BREAKOUT).unwrap();
+        let mut rewind = Rewind::new(3);
+        let mut states = Vec::new();
+        for _ in 0..5 {
+            chip8.run_frame(&[false; 16]);
+            rewind.push(&chip8.cpu, &chip8.ram);
+            states.push(chip8.save_state());
+        }
+        assert_eq!(rewind.len(), 3);
+
+        // newest first, and the two oldest frames fell out of the buffer
+        for expected in states.iter().rev().take(3) {
+            let (cpu, ram) = rewind.pop().unwrap();
+            assert_eq!(&cpu.save_state(&ram), expected);
+        }
+        assert!(rewind.pop().is_none());
+    }
+}
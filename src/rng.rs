@@ -0,0 +1,96 @@
+use rand::Rng;
+
+// where CXNN gets its random numbers
+pub trait RngSource {
+    fn next_byte(&mut self) -> u8;
+
+    // a copy that carries on from the same point, cpus are cloned for rewind
+    fn box_clone(&self) -> Box<dyn RngSource>;
+
+    // the seed a SeededRng would carry on from this point with, None when
+    // the numbers can't be repeated
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Clone for Box<dyn RngSource> {
+    fn clone(&self) -> Box<dyn RngSource> {
+        self.box_clone()
+    }
+}
+
+// fresh randomness every time, what people playing expect
+#[derive(Clone, Copy)]
+pub struct ThreadRng;
+
+impl RngSource for ThreadRng {
+    fn next_byte(&mut self) -> u8 {
+        rand::thread_rng().gen()
+    }
+
+    fn box_clone(&self) -> Box<dyn RngSource> {
+        Box::new(*self)
+    }
+}
+
+// the same numbers for the same seed on every machine and every build, for
+// replays and tests. splitmix64, written out so it can't change under us
+#[derive(Clone, Copy)]
+pub struct SeededRng {
+    state: u64
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> SeededRng {
+        SeededRng {
+            state: seed
+        }
+    }
+}
+
+impl RngSource for SeededRng {
+    fn next_byte(&mut self) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) as u8
+    }
+
+    fn box_clone(&self) -> Box<dyn RngSource> {
+        Box::new(*self)
+    }
+
+    fn seed(&self) -> Option<u64> {
+        Some(self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        RngSource,
+        SeededRng
+    };
+
+    #[test]
+    fn seeded_rng_repeats_for_the_same_seed() {
+        let bytes = |seed| {
+            let mut rng = SeededRng::new(seed);
+            (0..16).map(|_| rng.next_byte()).collect::<Vec<u8>>()
+        };
+        assert_eq!(bytes(1), bytes(1));
+        assert_ne!(bytes(1), bytes(2));
+
+        // a clone carries on from where the original was
+        let mut rng: Box<dyn RngSource> = Box::new(SeededRng::new(7));
+        rng.next_byte();
+        let mut copy = rng.clone();
+        assert_eq!(rng.next_byte(), copy.next_byte());
+
+        // as does one seeded with where it got to
+        let mut reseeded = SeededRng::new(rng.seed().unwrap());
+        assert_eq!(rng.next_byte(), reseeded.next_byte());
+    }
+}
@@ -0,0 +1,92 @@
+// roms we know by the sha1 of their contents, so renamed copies still get
+// the right title, key layout and quirks
+
+use std::fmt;
+
+use crate::input::KeyLayout;
+use crate::quirks::Quirks;
+
+// the machine a rom was written for. only CHIP-8 instructions run, the
+// others' extensions stop the rom as invalid opcodes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP")
+        }
+    }
+}
+
+pub struct RomInfo {
+    pub sha1: &'static str,
+    pub title: &'static str,
+    pub author: &'static str,
+    pub layout: KeyLayout,
+    pub platform: Platform,
+    // what the rom was written against, used unless a profile says otherwise
+    pub quirks: Quirks
+}
+
+pub const ROMS: [RomInfo; 5] = [
+    RomInfo {
+        sha1: "193915dcde1365ae054c4eaa21a35baa27cd3356",
+        title: "Breakout",
+        author: "Carmelo Cortez",
+        layout: KeyLayout::Standard,
+        platform: Platform::Chip8,
+        quirks: Quirks::VIP
+    },
+    RomInfo {
+        sha1: "0ebc4b92c6059d6193565644fb00108161d03d23",
+        title: "Keypad Test",
+        author: "hap",
+        layout: KeyLayout::Standard,
+        platform: Platform::Chip8,
+        quirks: Quirks::NONE
+    },
+    RomInfo {
+        sha1: "a60611339661e3ab2d8af024ad1da5880a6f8665",
+        title: "Pong",
+        author: "Paul Vervalin",
+        layout: KeyLayout::TwoPlayer,
+        platform: Platform::Chip8,
+        quirks: Quirks::VIP
+    },
+    RomInfo {
+        sha1: "f100197f0f2f05b4f3c8c31ab9c2c3930d3e9571",
+        title: "Space Invaders",
+        author: "David Winter",
+        layout: KeyLayout::Standard,
+        platform: Platform::Chip8,
+        quirks: Quirks::SCHIP
+    },
+    RomInfo {
+        sha1: "f1cfcffe1937ed6dd6eeed1a7f85dfc777bda700",
+        title: "Opcode Test",
+        author: "corax89",
+        layout: KeyLayout::Standard,
+        platform: Platform::Chip8,
+        quirks: Quirks::NONE
+    }
+];
+
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+// "Pong (CHIP-8)"
+pub fn describe(info: &RomInfo) -> String {
+    format!("{} ({})", info.title, info.platform)
+}
+
+pub fn lookup(sha1: &str) -> Option<&'static RomInfo> {
+    ROMS.iter().find(|info| info.sha1 == sha1)
+}
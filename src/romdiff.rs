@@ -0,0 +1,93 @@
+use crate::spec::{
+    self,
+    PROGRAM_START_ADDR
+};
+
+// differences this close together are shown as one site
+const MERGE_GAP: usize = 4;
+
+// a run of differing bytes, as offsets into the roms
+struct Site {
+    start: usize,
+    end: usize
+}
+
+// every place two roms differ, each shown as the instructions covering it
+// in both, hex and disassembled, at the address they load to
+pub fn describe(a_name: &str, a: &[u8], b_name: &str, b: &[u8]) -> String {
+    let sites = sites(a, b);
+    let differing: usize = (0..a.len().max(b.len())).filter(|&i| a.get(i) != b.get(i)).count();
+
+    let mut lines = vec![
+        format!("a: {} ({} bytes)", a_name, a.len()),
+        format!("b: {} ({} bytes)", b_name, b.len())
+    ];
+    if sites.is_empty() {
+        lines.push(String::from("identical"));
+        return lines.join("\n");
+    }
+    let places = if sites.len() == 1 { "place" } else { "places" };
+    lines.push(format!("{} bytes differ in {} {}", differing, sites.len(), places));
+
+    for site in &sites {
+        // instructions sit on even addresses from the program start
+        let start = site.start & !1;
+        let end = (site.end + 1) & !1;
+        lines.push(String::new());
+        lines.push(format!("{:04X}-{:04X}", address(site.start), address(site.end - 1)));
+        for offset in (start..end).step_by(2) {
+            lines.push(format!("  a {}", instruction(a, offset)));
+            lines.push(format!("  b {}", instruction(b, offset)));
+        }
+    }
+    lines.join("\n")
+}
+
+fn sites(a: &[u8], b: &[u8]) -> Vec<Site> {
+    let mut sites: Vec<Site> = Vec::new();
+    for offset in 0..a.len().max(b.len()) {
+        if a.get(offset) == b.get(offset) {
+            continue;
+        }
+        match sites.last_mut() {
+            Some(site) if offset - site.end < MERGE_GAP => site.end = offset + 1,
+            _ => sites.push(Site {
+                start: offset,
+                end: offset + 1
+            })
+        }
+    }
+    sites
+}
+
+fn address(offset: usize) -> usize {
+    PROGRAM_START_ADDR as usize + offset
+}
+
+// "0234  6A 02  LD VA, 02", with -- for bytes past the end of the rom
+fn instruction(rom: &[u8], offset: usize) -> String {
+    match (rom.get(offset), rom.get(offset + 1)) {
+        (Some(&hi), Some(&lo)) => {
+            let word = (hi as u16) << 8 | lo as u16;
+            format!("{:04X}  {:02X} {:02X}  {}", address(offset), hi, lo, spec::disassemble(word))
+        },
+        (Some(&hi), None) => format!("{:04X}  {:02X} --", address(offset), hi),
+        _ => format!("{:04X}  -- --", address(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+
+    #[test]
+    fn shows_each_differing_site_in_both_roms() {
+        let a = [0x00, 0xE0, 0x6A, 0x02, 0x00, 0xE0, 0x00, 0xE0, 0x12, 0x00];
+        let b = [0x00, 0xE0, 0x6A, 0x03, 0x00, 0xE0, 0x00, 0xE0, 0x12, 0x00, 0xFF];
+        let diff = describe("a.ch8", &a, "b.ch8", &b);
+        assert!(diff.contains("2 bytes differ in 2 places"));
+        assert!(diff.contains("  a 0202  6A 02  LD VA, 02\n  b 0202  6A 03  LD VA, 03"));
+        assert!(diff.contains("  a 020A  -- --\n  b 020A  FF --"));
+        assert!(describe("a", &a, "a", &a).ends_with("identical"));
+    }
+}
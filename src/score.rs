@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::config;
+
+// longest bcd score we read, enough for any chip-8 game
+const MAX_BCD_DIGITS: usize = 8;
+
+// how a game keeps its score in ram
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScoreFormat {
+    // one decimal digit per byte, most significant first, as FX33 writes
+    Bcd(usize),
+    Byte,
+    // two bytes, big endian
+    Word
+}
+
+// where and how a game keeps its score, written like "3E0:bcd3"
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScoreSpec {
+    pub addr: u16,
+    pub format: ScoreFormat
+}
+
+impl ScoreSpec {
+    // ADDR:FORMAT with a hex address and bcdN, u8 or u16
+    pub fn parse(text: &str) -> Option<ScoreSpec> {
+        let (addr, format) = text.split_once(':')?;
+        let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16).ok()?;
+        let format = match format {
+            "u8" => ScoreFormat::Byte,
+            "u16" => ScoreFormat::Word,
+            _ => {
+                let digits = format.strip_prefix("bcd")?.parse().ok()?;
+                if !(1..=MAX_BCD_DIGITS).contains(&digits) {
+                    return None;
+                }
+                ScoreFormat::Bcd(digits)
+            }
+        };
+        Some(ScoreSpec {
+            addr,
+            format
+        })
+    }
+
+    // the score in memory, None while it doesn't hold a valid one (e.g.
+    // before the game has set it up)
+    pub fn read(&self, memory: &[u8]) -> Option<u32> {
+        let start = self.addr as usize;
+        match self.format {
+            ScoreFormat::Byte => memory.get(start).map(|&byte| byte as u32),
+            ScoreFormat::Word => {
+                let bytes = memory.get(start..start + 2)?;
+                Some((bytes[0] as u32) << 8 | bytes[1] as u32)
+            },
+            ScoreFormat::Bcd(digits) => {
+                let bytes = memory.get(start..start + digits)?;
+                bytes.iter().try_fold(0, |score, &digit| {
+                    if digit > 9 { None } else { Some(score * 10 + digit as u32) }
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for ScoreSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            ScoreFormat::Bcd(digits) => write!(f, "{:03X}:bcd{}", self.addr, digits),
+            ScoreFormat::Byte => write!(f, "{:03X}:u8", self.addr),
+            ScoreFormat::Word => write!(f, "{:03X}:u16", self.addr)
+        }
+    }
+}
+
+impl TryFrom<String> for ScoreSpec {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<ScoreSpec, String> {
+        ScoreSpec::parse(&text).ok_or_else(|| format!("Could not parse score {}", text))
+    }
+}
+
+impl From<ScoreSpec> for String {
+    fn from(spec: ScoreSpec) -> String {
+        spec.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct GameScore {
+    pub spec: ScoreSpec,
+    pub best: u32
+}
+
+// best scores by rom sha1, kept between sessions since the games
+// themselves forget them at power off
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct HighScores {
+    games: BTreeMap<String, GameScore>
+}
+
+impl HighScores {
+    pub fn path() -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("scores.json"))
+    }
+
+    // no file yet is the same as no scores
+    pub fn load(path: &Path) -> Result<HighScores, String> {
+        config::load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        config::save_json(path, self)
+    }
+
+    pub fn get(&self, sha1: &str) -> Option<&GameScore> {
+        self.games.get(sha1)
+    }
+
+    // starts tracking the rom's score, or moves it. a moved score starts
+    // over since the old best was read from somewhere else
+    pub fn configure(&mut self, sha1: &str, spec: ScoreSpec) {
+        match self.games.get_mut(sha1) {
+            Some(game) if game.spec == spec => (),
+            _ => {
+                self.games.insert(sha1.to_string(), GameScore {
+                    spec,
+                    best: 0
+                });
+            }
+        }
+    }
+
+    // reads the rom's score out of memory, returns it if it's a new best
+    pub fn update(&mut self, sha1: &str, memory: &[u8]) -> Option<u32> {
+        let game = self.games.get_mut(sha1)?;
+        let score = game.spec.read(memory)?;
+        if score <= game.best {
+            return None;
+        }
+        game.best = score;
+        Some(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        HighScores,
+        ScoreSpec
+    };
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn high_scores_follow_a_bcd_score_in_ram() {
+        // LD V1, 7B  LD I, 3E0  LD B, V1
+        let mut chip8 = Chip8::with_rom(&[0x61, 0x7B, 0xA3, 0xE0, 0xF1, 0x33]).unwrap();
+        let mut scores = HighScores::default();
+        scores.configure("rom", ScoreSpec::parse("3E0:bcd3").unwrap());
+        assert_eq!(scores.update("rom", chip8.ram.memory()), None);
+
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(scores.update("rom", chip8.ram.memory()), Some(123));
+        assert_eq!(scores.update("rom", chip8.ram.memory()), None);
+        assert_eq!(scores.get("rom").unwrap().best, 123);
+    }
+}
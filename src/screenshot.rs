@@ -0,0 +1,209 @@
+use std::fs::{
+    self,
+    File
+};
+use std::io::BufWriter;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH
+};
+
+use crate::display::Frame;
+use crate::present::Palette;
+use crate::state::State;
+
+// the display in a palette's colors, each pixel scaled to a scale x scale
+// block, see Chip8::framebuffer_image
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>
+}
+
+impl Image {
+    pub fn new(frame: &Frame, palette: &Palette, scale: usize) -> Image {
+        let scale = scale.max(1);
+        let width = frame.width() * scale;
+        let height = frame.height() * scale;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(palette.color(frame.lit(x / scale, y / scale) as u8));
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels
+        }
+    }
+
+    // writes the image as an rgb png
+    pub fn write_png(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Could not create {}: {}", path, e))?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut data = Vec::with_capacity(self.pixels.len() * 3);
+        for &pixel in &self.pixels {
+            data.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+        }
+
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+}
+
+// writes a frame as an rgb png in the default colors, each pixel scaled to
+// a scale x scale block
+pub fn write_png(path: &str, frame: &Frame, scale: usize) -> Result<(), String> {
+    Image::new(frame, &Palette::default(), scale).write_png(path)
+}
+
+// DIR/NAME-YYYYMMDD-HHMMSS.png, the time in utc, for screenshots taken by
+// hand. the directory is made if needed
+pub fn timestamped_path(dir: &str, name: &str, now: SystemTime) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Could not create {}: {}", dir, e))?;
+    let seconds = now.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+    let (year, month, day) = civil_date(seconds / 86400);
+    let time = seconds % 86400;
+    let file = format!("{}-{:04}{:02}{:02}-{:02}{:02}{:02}.png", name, year, month, day, time / 3600, time / 60 % 60, time % 60);
+    Ok(Path::new(dir).join(file))
+}
+
+// year, month and day of a count of days since 1970-01-01, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// saves where a machine ended up as DIR/NAME.png and DIR/NAME.state.json,
+// e.g. after it halted. returns the path of the png
+pub fn write_halt_snapshot(dir: &str, name: &str, frame: &Frame, state: &State, scale: usize) -> Result<String, String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Could not create {}: {}", dir, e))?;
+    let base = PathBuf::from(dir).join(name);
+    let png = format!("{}.png", base.to_string_lossy());
+    write_png(&png, frame, scale)?;
+    state.export(&format!("{}.state.json", base.to_string_lossy()))?;
+    Ok(png)
+}
+
+// saves a screenshot every time the display settles on a new picture, so
+// running through a game leaves behind one image per screen
+pub struct ScreenshotSeries {
+    dir: PathBuf,
+    prefix: String,
+    stable_frames: usize,
+    scale: usize,
+    last_frame: Option<Frame>,
+    unchanged: usize,
+    saved: Option<Frame>,
+    count: usize
+}
+
+impl ScreenshotSeries {
+    pub fn new(dir: &str, prefix: &str, stable_frames: usize, scale: usize) -> Result<ScreenshotSeries, String> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create {}: {}", dir, e))?;
+
+        Ok(ScreenshotSeries {
+            dir: PathBuf::from(dir),
+            prefix: prefix.to_string(),
+            stable_frames: stable_frames.max(1),
+            scale: scale.max(1),
+            last_frame: None,
+            unchanged: 0,
+            saved: None,
+            count: 0
+        })
+    }
+
+    // call once per frame, returns the path of a screenshot if one was taken
+    pub fn update(&mut self, frame: &Frame) -> Option<String> {
+        if self.last_frame.as_ref() == Some(frame) {
+            self.unchanged += 1;
+        } else {
+            self.unchanged = 0;
+            self.last_frame = Some(frame.clone());
+        }
+
+        // only once per stable period, and never twice for the same picture
+        if self.unchanged != self.stable_frames || self.saved.as_ref() == Some(frame) {
+            return None;
+        }
+        // a cleared screen between scenes isn't worth documenting
+        if frame.is_blank() {
+            return None;
+        }
+
+        let path = self.dir.join(format!("{}-{:04}.png", self.prefix, self.count));
+        let path = path.to_string_lossy().into_owned();
+        match write_png(&path, frame, self.scale) {
+            Ok(()) => {
+                self.count += 1;
+                self.saved = Some(frame.clone());
+                Some(path)
+            },
+            Err(e) => {
+                println!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::time::{
+        Duration,
+        UNIX_EPOCH
+    };
+
+    use super::{
+        timestamped_path,
+        Image
+    };
+    use crate::display::Frame;
+    use crate::present::Palette;
+
+    #[test]
+    fn images_use_the_palette_and_names_the_time() {
+        let mut frame = Frame::new(2, 1);
+        frame.set(1, 0, true);
+        let palette = Palette::preset("amber").unwrap();
+        let image = Image::new(&frame, &palette, 2);
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.pixels, vec![palette.off, palette.off, palette.on, palette.on, palette.off, palette.off, palette.on, palette.on]);
+
+        // the directory is made if it isn't there
+        let dir = std::env::temp_dir().join(format!("rust8-screenshot-{}", std::process::id()));
+        let now = UNIX_EPOCH + Duration::from_secs(1_772_282_096);
+        let path = timestamped_path(&dir.to_string_lossy(), "pong", now);
+        let made = dir.is_dir();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(made);
+        assert_eq!(path.unwrap().file_name(), Some(Path::new("pong-20260228-123456.png").as_os_str()));
+    }
+}
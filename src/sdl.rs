@@ -0,0 +1,388 @@
+use std::thread;
+use std::time::Instant;
+
+use sdl2::audio::{
+    AudioCallback,
+    AudioDevice,
+    AudioSpecDesired
+};
+use sdl2::event::{
+    Event,
+    WindowEvent
+};
+use sdl2::keyboard::{
+    Mod,
+    Scancode
+};
+use sdl2::video::FullscreenType;
+use sdl2::pixels::{
+    Color,
+    PixelFormatEnum
+};
+
+use rust8::audio::{
+    AudioSink,
+    DEFAULT_FREQUENCY
+};
+use rust8::chip8::Chip8;
+use rust8::timing::{
+    FramePacer,
+    Rate,
+    FRAME_TIME
+};
+use rust8::input::InputSource;
+use rust8::animation::{
+    Moment,
+    PaletteAnimation
+};
+use rust8::phosphor::Phosphor;
+use rust8::recording::Recording;
+use rust8::keymap::KeyMap;
+use rust8::present::Palette;
+use rust8::{
+    HEIGHT,
+    WIDTH
+};
+
+use crate::frontend::{
+    self,
+    load,
+    ReplayRun
+};
+use crate::options::Options;
+use crate::shutdown::Shutdown;
+
+const SAMPLE_RATE: i32 = 44100;
+
+// keypad state kept from sdl's key down and key up events, so a key
+// released between two frames is still seen as released
+struct HeldKeys {
+    held: [u8; 16],
+    keymap: KeyMap
+}
+
+impl HeldKeys {
+    // by position rather than by character, so the keymap's qwerty
+    // layout works on any keyboard
+    fn key(&self, scancode: Scancode) -> Option<usize> {
+        self.keymap.get(&key_name(scancode))
+    }
+
+    fn set(&mut self, scancode: Scancode, held: bool) {
+        if let Some(key) = self.key(scancode) {
+            self.held[key] = held as u8;
+        }
+    }
+}
+
+// the keymap's name for a scancode, sdl's own except "1" for Num1,
+// NumPad8 for Kp8 and Enter for Return
+fn key_name(scancode: Scancode) -> String {
+    let name = format!("{:?}", scancode);
+    if let Some(digit) = name.strip_prefix("Num").filter(|digit| digit.len() == 1) {
+        return digit.to_string();
+    }
+    if let Some(digit) = name.strip_prefix("Kp").filter(|digit| digit.len() == 1) {
+        return format!("NumPad{}", digit);
+    }
+    match name.as_str() {
+        "Return" => String::from("Enter"),
+        _ => name
+    }
+}
+
+impl InputSource for HeldKeys {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        for (key, &held) in self.held.iter().enumerate() {
+            if held != 0 {
+                keys[key] = 1;
+            }
+        }
+    }
+}
+
+// square wave generated on sdl's audio thread
+struct SquareWave {
+    step: f32,
+    phase: f32
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { 0.25 } else { -0.25 };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+// the buzzer is the audio device itself, paused while silent
+struct SdlSink {
+    device: AudioDevice<SquareWave>,
+    sample_rate: i32,
+    playing: bool
+}
+
+impl AudioSink for SdlSink {
+    fn start_beep(&mut self) {
+        if !self.playing {
+            self.device.resume();
+            self.playing = true;
+        }
+    }
+
+    fn stop_beep(&mut self) {
+        if self.playing {
+            self.device.pause();
+            self.playing = false;
+        }
+    }
+
+    fn set_frequency(&mut self, hz: u32) {
+        self.device.lock().step = hz as f32 / self.sample_rate as f32;
+    }
+}
+
+fn border_color(palette: &Palette) -> Color {
+    Color::RGB((palette.border >> 16) as u8, (palette.border >> 8) as u8, palette.border as u8)
+}
+
+// runs rom_path in an sdl window until it's closed, escape is pressed or
+// shutdown is requested. dropping a rom file on the window switches to it.
+// a plain player: the debugging, kiosk and recording features are only in
+// the minifb frontend
+pub fn run(rom_path: &str, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+    let mut chip8 = Chip8::new();
+    let loaded = load(&mut chip8, rom_path, options.program, options)?;
+    let mut replay = frontend::start_replay(&mut chip8.cpu, &mut chip8.ram, &loaded.profile, &loaded.sha1, options);
+
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let window = video.window(&loaded.caption, (WIDTH * options.scale) as u32, (HEIGHT * options.scale) as u32)
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|e| format!("Could not open window: {}", e))?;
+    let mut canvas = window.into_canvas()
+        .build()
+        .map_err(|e| format!("Could not create renderer: {}", e))?;
+    // the game keeps its shape when the window is resized, with borders
+    // around it in the border color
+    canvas.set_logical_size(WIDTH as u32, HEIGHT as u32)
+        .map_err(|e| format!("Could not scale renderer: {}", e))?;
+    let textures = canvas.texture_creator();
+    let mut texture = textures.create_texture_streaming(PixelFormatEnum::RGB888, WIDTH as u32, HEIGHT as u32)
+        .map_err(|e| format!("Could not create texture: {}", e))?;
+
+    let audio = sdl.audio()?;
+    let desired = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: None
+    };
+    let device = audio.open_playback(None, &desired, |spec| SquareWave {
+        step: DEFAULT_FREQUENCY as f32 / spec.freq as f32,
+        phase: 0.0
+    })?;
+    let sample_rate = device.spec().freq;
+    let mut sink = SdlSink {
+        device,
+        sample_rate,
+        playing: false
+    };
+    if let Some(hz) = options.beep_frequency {
+        sink.set_frequency(hz);
+    }
+
+    let mut palette = loaded.profile.palette(options.palette());
+    let mut animation = options.palette_animation.filter(|effects| !effects.is_empty());
+    // the palette the last frame was shown in, animations included
+    let mut shown = palette;
+    let mut pacer = FramePacer::new(frontend::speed(replay.as_ref(), &loaded.profile));
+    let mut keys = HeldKeys {
+        held: [0; 16],
+        keymap: loaded.keymap
+    };
+    let mut events = sdl.event_pump()?;
+    let mut pixels = vec![0u8; WIDTH * HEIGHT * 4];
+    // the frame in palette colors, faded by --ghosting
+    let mut colors = Vec::with_capacity(WIDTH * HEIGHT);
+    let mut phosphor = Some(options.ghosting).filter(|&decay| decay > 0.0).map(Phosphor::new);
+    // what delete starts over, the last rom dropped on the window
+    let mut current = (rom_path.to_string(), options.program);
+    let mut paused = false;
+    let mut slow_motion = false;
+    let mut scale = options.scale;
+    // F12 was pressed, saved once the frame's colors are known
+    let mut screenshot = false;
+    let mut video: Option<Recording> = None;
+
+    'running: while !shutdown.requested() {
+        let frame_start = Instant::now();
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::KeyDown { scancode: Some(Scancode::Escape), .. } => break 'running,
+                // P pauses and resumes, delete starts the rom over
+                Event::KeyDown { scancode: Some(Scancode::P), repeat: false, .. } => {
+                    paused = !paused;
+                    if paused {
+                        println!("Paused, press P to continue");
+                        sink.stop_beep();
+                    } else {
+                        println!("Resuming");
+                    }
+                },
+                // F11 toggles fullscreen, - and = step the window scale
+                Event::KeyDown { scancode: Some(Scancode::F11), repeat: false, .. } => {
+                    let window = canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off
+                    };
+                    window.set_fullscreen(fullscreen)?;
+                },
+                Event::KeyDown { scancode: Some(scancode @ (Scancode::Minus | Scancode::Equals)), repeat: false, .. } => {
+                    scale = match scancode {
+                        Scancode::Equals if scale < frontend::MAX_RUNTIME_SCALE => scale + 1,
+                        Scancode::Minus if scale > frontend::MIN_RUNTIME_SCALE => scale - 1,
+                        _ => scale
+                    };
+                    canvas.window_mut().set_size((WIDTH * scale) as u32, (HEIGHT * scale) as u32)
+                        .map_err(|e| format!("Could not resize window: {}", e))?;
+                },
+                // F12 takes a screenshot, shift+F12 starts and stops recording
+                Event::KeyDown { scancode: Some(Scancode::F12), keymod, repeat: false, .. } => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        frontend::toggle_recording(&mut video, options, &current.0, scale);
+                    } else {
+                        screenshot = true;
+                    }
+                },
+                // tab held runs --turbo frames per frame drawn, ` toggles slow motion
+                Event::KeyDown { scancode: Some(Scancode::Grave), repeat: false, .. } => slow_motion = !slow_motion,
+                Event::KeyDown { scancode: Some(Scancode::Delete), repeat: false, .. } => match load(&mut chip8, &current.0, current.1, options) {
+                    Ok(loaded) => {
+                        println!("Reset {}", current.0);
+                        sink.stop_beep();
+                        frontend::restart_replay(&mut replay, &mut chip8.cpu, &mut chip8.ram, &loaded.profile, &loaded.sha1, options);
+                        pacer = FramePacer::new(frontend::speed(replay.as_ref(), &loaded.profile));
+                    },
+                    Err(e) => println!("{}, keeping the rom running", e)
+                },
+                Event::KeyDown { scancode: Some(scancode), .. } => keys.set(scancode, true),
+                Event::KeyUp { scancode: Some(scancode), .. } => keys.set(scancode, false),
+                // sdl stops sending key ups once the window loses focus
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => keys.held = [0; 16],
+                Event::DropFile { filename, .. } => match load(&mut chip8, &filename, None, options) {
+                    Ok(loaded) => {
+                        println!("Loaded {}", filename);
+                        current = (filename, None);
+                        palette = loaded.profile.palette(options.palette());
+                        keys.keymap = loaded.keymap;
+                        frontend::replay_jumped(&mut replay, &mut chip8.cpu, &mut chip8.ram, &loaded.sha1);
+                        pacer = FramePacer::new(frontend::speed(replay.as_ref(), &loaded.profile));
+                        canvas.window_mut().set_title(&loaded.caption)
+                            .map_err(|e| format!("Could not set window title: {}", e))?;
+                        sink.stop_beep();
+                    },
+                    Err(e) => println!("{}, keeping the last rom running", e)
+                },
+                _ => ()
+            }
+        }
+
+        // turbo frames would run together, so replays go without
+        let rate = if events.keyboard_state().is_scancode_pressed(Scancode::Tab) && replay.is_none() {
+            Rate::Turbo(options.turbo)
+        } else if slow_motion {
+            Rate::Slow(options.slow_motion)
+        } else {
+            Rate::Normal
+        };
+        pacer.set_rate(rate);
+
+        // paused, the window keeps drawing but the machine and its timers
+        // stand still. slow motion holds each frame a few times, and a
+        // netplay client waits for the host's keys for the next one
+        let ran = !paused && frontend::replay_ready(&mut replay, &mut chip8.cpu, &mut chip8.ram) && !pacer.hold();
+        if ran {
+            match replay.as_mut().and_then(ReplayRun::player) {
+                Some(player) => chip8.cpu.handle_keypress(player),
+                None => chip8.cpu.handle_keypress(&mut keys)
+            };
+            // an error halts the machine, reported just below. turbo runs
+            // several frames for each one shown, recordings keep them all
+            loop {
+                if chip8.step().is_err() || pacer.step() {
+                    break;
+                }
+                if pacer.machine_frame_ended() {
+                    frontend::record_frame(&mut video, chip8.cpu.gfx(), &shown);
+                }
+            }
+            if let Some(reason) = chip8.cpu.halted() {
+                println!("{}, stopping\n{}", reason, chip8.cpu.dump(&chip8.ram));
+                break;
+            }
+            // stderr isn't buffered, so the lines survive a crash
+            for line in chip8.cpu.take_opcode_log() {
+                eprintln!("{}", line);
+            }
+            for _ in 0..pacer.timer_ticks().unwrap_or(1) {
+                chip8.cpu.tick_timers(&mut sink);
+            }
+            frontend::replay_frame(&mut replay, &mut chip8.cpu, &mut chip8.ram);
+        }
+
+        // the palette animated to this frame, see --palette-animation
+        shown = match &mut animation {
+            Some(animation) => animation.animate(&palette, Moment {
+                frame: chip8.cpu.frames(),
+                sound: chip8.cpu.reg_st() > 0
+            }),
+            None => palette
+        };
+        if screenshot {
+            frontend::save_screenshot(&chip8.framebuffer_image(&shown, scale), options, &current.0);
+            screenshot = false;
+        }
+        // recordings take the frames the machine ran rather than the ones
+        // drawn, and a netplay frame run on a guess may yet be rolled back
+        if ran && !replay.as_ref().is_some_and(ReplayRun::guessing) {
+            frontend::record_frame(&mut video, chip8.cpu.gfx(), &shown);
+        }
+        colors.clear();
+        colors.extend(chip8.cpu.gfx().pixels().iter().map(|&on| shown.color(on as u8)));
+        if let Some(phosphor) = &mut phosphor {
+            phosphor.apply(&mut colors, shown.off, shown.on);
+        }
+        // RGB888 is stored as 4 bytes per pixel, blue first
+        for (pixel, color) in pixels.chunks_mut(4).zip(&colors) {
+            pixel.copy_from_slice(&color.to_le_bytes());
+        }
+        texture.update(None, &pixels, WIDTH * 4)
+            .map_err(|e| format!("Could not update texture: {}", e))?;
+        canvas.set_draw_color(border_color(&shown));
+        canvas.clear();
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+
+        if let Some(rest) = FRAME_TIME.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+
+    if let Some(video) = video.take() {
+        frontend::finish_recording(video);
+    }
+    for line in chip8.cpu.finish_opcode_log() {
+        eprintln!("{}", line);
+    }
+    if let Some(run) = replay.take() {
+        frontend::finish_replay(run);
+    }
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::breakpoint::Breakpoint;
+use crate::config;
+use crate::cpu::Cpu;
+use crate::labels::MemoryLabels;
+use crate::ram::Ram;
+
+// a value shown in the watch panel while the rom runs
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Watch {
+    Register(u8),
+    Index,
+    Pc,
+    Delay,
+    Sound,
+    // the byte at this address
+    Byte(u16),
+    // the byte I points at
+    AtIndex
+}
+
+impl Watch {
+    // v0 to vf, i, pc, dt, st, [ADDR] with ADDR in hex, or [i]
+    pub fn parse(text: &str) -> Option<Watch> {
+        let text = text.to_lowercase();
+        match text.as_str() {
+            "i" => return Some(Watch::Index),
+            "pc" => return Some(Watch::Pc),
+            "dt" => return Some(Watch::Delay),
+            "st" => return Some(Watch::Sound),
+            "[i]" => return Some(Watch::AtIndex),
+            _ => ()
+        }
+        if let Some(addr) = text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            return u16::from_str_radix(addr, 16).ok().map(Watch::Byte);
+        }
+        let register = text.strip_prefix('v')?;
+        if register.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(register, 16).ok().map(Watch::Register)
+    }
+
+    // None when the address is outside ram
+    pub fn value(&self, cpu: &Cpu, ram: &Ram) -> Option<u16> {
+        let byte = |addr: u16| ram.memory().get(addr as usize).map(|&value| value as u16);
+        match *self {
+            Watch::Register(register) => Some(cpu.reg_gpr()[register as usize] as u16),
+            Watch::Index => Some(cpu.reg_i()),
+            Watch::Pc => Some(cpu.reg_pc()),
+            Watch::Delay => Some(cpu.reg_dt() as u16),
+            Watch::Sound => Some(cpu.reg_st() as u16),
+            Watch::Byte(addr) => byte(addr),
+            Watch::AtIndex => byte(cpu.reg_i())
+        }
+    }
+
+    // e.g. "V3 1F" or "[3A0] 00", labelled addresses by name as in
+    // "[lives] 03" or "[I] 00 sprite_table+3"
+    pub fn describe(&self, cpu: &Cpu, ram: &Ram, labels: &MemoryLabels) -> String {
+        let name = match *self {
+            Watch::Byte(addr) => labels.name(addr),
+            Watch::AtIndex => labels.name(cpu.reg_i()),
+            _ => None
+        };
+        match (self, self.value(cpu, ram), name) {
+            (Watch::Index, Some(value), _) | (Watch::Pc, Some(value), _) => format!("{} {:03X}", self, value),
+            (Watch::Byte(_), Some(value), Some(name)) => format!("[{}] {:02X}", name, value),
+            (Watch::AtIndex, Some(value), Some(name)) => format!("{} {:02X} {}", self, value, name),
+            (_, Some(value), _) => format!("{} {:02X}", self, value),
+            (_, None, _) => format!("{} --", self)
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::Register(register) => write!(f, "V{:X}", register),
+            Watch::Index => write!(f, "I"),
+            Watch::Pc => write!(f, "PC"),
+            Watch::Delay => write!(f, "DT"),
+            Watch::Sound => write!(f, "ST"),
+            Watch::Byte(addr) => write!(f, "[{:03X}]", addr),
+            Watch::AtIndex => write!(f, "[I]")
+        }
+    }
+}
+
+impl TryFrom<String> for Watch {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Watch, String> {
+        Watch::parse(&text).ok_or_else(|| format!("Could not parse watch {}", text))
+    }
+}
+
+impl From<Watch> for String {
+    fn from(watch: Watch) -> String {
+        watch.to_string()
+    }
+}
+
+// which debugger panels are open
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanelLayout {
+    pub registers: bool,
+    pub watches: bool
+}
+
+// what was being looked at in a rom last time: its watches and open
+// panels, kept in the config directory under the rom's sha1 so the next run
+// picks up where the last one stopped. breakpoints live with the rom's
+// breakpoint groups, and what's given on the command line is only for the
+// run it was given to
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugSession {
+    pub watches: Vec<Watch>,
+    pub layout: PanelLayout,
+    // sessions used to keep breakpoints too, read so they can be moved into
+    // a breakpoint group but never written back
+    #[serde(skip_serializing)]
+    pub breakpoints: Vec<Breakpoint>,
+    // the command line's --watch, shown along with the kept ones
+    #[serde(skip)]
+    given_watches: Vec<Watch>
+}
+
+impl DebugSession {
+    // where the session for the rom with this sha1 is kept
+    pub fn path(sha1: &str) -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("sessions").join(format!("{}.json", sha1)))
+    }
+
+    // no file yet means a fresh session
+    pub fn load(path: &Path) -> Result<DebugSession, String> {
+        config::load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        config::save_json(path, self)
+    }
+
+    // true when there's nothing worth keeping
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty() && self.layout == PanelLayout::default() && self.breakpoints.is_empty()
+    }
+
+    // shows these as well for this run, without keeping them
+    pub fn set_given_watches(&mut self, watches: &[Watch]) {
+        self.given_watches = watches.iter()
+            .filter(|watch| !self.watches.contains(watch))
+            .copied()
+            .collect();
+    }
+
+    // the kept watches followed by the command line's
+    pub fn all_watches(&self) -> Vec<Watch> {
+        self.watches.iter().chain(self.given_watches.iter()).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DebugSession,
+        Watch
+    };
+    use crate::breakpoint::Breakpoint;
+    use crate::cpu::Cpu;
+    use crate::labels::MemoryLabels;
+    use crate::ram::Ram;
+
+    #[test]
+    fn sessions_keep_watches_and_panels_but_not_the_command_line() {
+        let mut session = DebugSession {
+            watches: vec![Watch::Register(3), Watch::Byte(0x3A0)],
+            ..DebugSession::default()
+        };
+        session.set_given_watches(&[Watch::Byte(0x3A0), Watch::AtIndex]);
+        session.layout.watches = true;
+        assert_eq!(session.all_watches(), vec![Watch::Register(3), Watch::Byte(0x3A0), Watch::AtIndex]);
+
+        let json = serde_json::to_string(&session).unwrap();
+        assert_eq!(json, r#"{"watches":["V3","[3A0]"],"layout":{"registers":false,"watches":true}}"#);
+        let loaded = serde_json::from_str::<DebugSession>(&json).unwrap();
+        assert_eq!(loaded.all_watches(), vec![Watch::Register(3), Watch::Byte(0x3A0)]);
+
+        let mut given = DebugSession::default();
+        given.set_given_watches(&[Watch::Pc]);
+        assert!(given.is_empty());
+
+        // breakpoints from an older session are read but not written back
+        let old: DebugSession = serde_json::from_str(r#"{"breakpoints":["write:3A0"]}"#).unwrap();
+        assert_eq!(old.breakpoints, vec![Breakpoint::Write(0x3A0)]);
+        assert!(!serde_json::to_string(&old).unwrap().contains("breakpoints"));
+
+        let mut ram = Ram::new();
+        ram.write_byte(0x3A0, 0x1F);
+        let cpu = Cpu::new();
+        let labels = MemoryLabels::default();
+        assert_eq!(Watch::parse("[3a0]").unwrap().describe(&cpu, &ram, &labels), "[3A0] 1F");
+        assert_eq!(Watch::parse("pc").unwrap().describe(&cpu, &ram, &labels), "PC 200");
+        assert_eq!(Watch::Byte(0xFFFF).describe(&cpu, &ram, &labels), "[FFFF] --");
+        assert_eq!(Watch::parse("v10"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicUsize,
+    Ordering
+};
+
+use signal_hook::consts::{
+    SIGINT,
+    SIGTERM
+};
+use signal_hook::flag;
+
+// Ctrl+C or SIGTERM only asks to stop, the frontends and long running
+// commands check once a frame so recordings and scores get written out
+// before exiting. a second one exits straight away, for when that's stuck
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    signal: Arc<AtomicUsize>
+}
+
+impl Default for Shutdown {
+    fn default() -> Shutdown {
+        Shutdown::new()
+    }
+}
+
+impl Shutdown {
+    // one no signal reaches, e.g. for tests. install is the one that listens
+    pub fn new() -> Shutdown {
+        Shutdown {
+            requested: Arc::new(AtomicBool::new(false)),
+            signal: Arc::new(AtomicUsize::new(0))
+        }
+    }
+
+    pub fn install() -> Result<Shutdown, String> {
+        let shutdown = Shutdown::new();
+        for &signal in &[SIGINT, SIGTERM] {
+            // checked before the flag is set, so only fires the second time
+            flag::register_conditional_shutdown(signal, 128 + signal, Arc::clone(&shutdown.requested))
+                .and_then(|_| flag::register(signal, Arc::clone(&shutdown.requested)))
+                .and_then(|_| flag::register_usize(signal, Arc::clone(&shutdown.signal), signal as usize))
+                .map_err(|e| format!("Could not handle signal {}: {}", signal, e))?;
+        }
+        Ok(shutdown)
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    // what to exit with after stopping for a signal, 128 plus its number
+    // like a shell reports it
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.signal.load(Ordering::SeqCst) {
+            0 => None,
+            signal => Some(128 + signal as i32)
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::fs::File;
+use std::io::Write;
+
+#[cfg(feature = "desktop")]
+use minifb::Window;
+
+// how a sink wants the pixels of a frame, row by row
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelFormat {
+    // one u32 per pixel, 0x00RRGGBB, what the screen is drawn in
+    Argb8888,
+    // one u16 per pixel, 5 bits red, 6 green, 5 blue
+    Rgb565,
+    // one bit per pixel, set where the pixel is bright, the first pixel in
+    // the top bit. each row starts on a new byte
+    Mono1
+}
+
+impl PixelFormat {
+    pub fn parse(name: &str) -> Option<PixelFormat> {
+        match name {
+            "argb8888" => Some(PixelFormat::Argb8888),
+            "rgb565" => Some(PixelFormat::Rgb565),
+            "mono1" => Some(PixelFormat::Mono1),
+            _ => None
+        }
+    }
+}
+
+// a frame's pixels in one of the formats
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Pixels<'a> {
+    Argb8888(&'a [u32]),
+    Rgb565(&'a [u16]),
+    Mono1(&'a [u8])
+}
+
+// anything that wants to receive presented frames: windows, recorders,
+// streams. frames are the composed window pixels, overlays included
+pub trait DisplaySink {
+    // what present is given. sinks asking for the same format share one
+    // conversion per frame
+    fn format(&self) -> PixelFormat {
+        PixelFormat::Argb8888
+    }
+
+    fn present(&mut self, pixels: Pixels, width: usize, height: usize) -> Result<(), String>;
+}
+
+#[cfg(feature = "desktop")]
+impl DisplaySink for Window {
+    fn present(&mut self, pixels: Pixels, width: usize, height: usize) -> Result<(), String> {
+        match pixels {
+            Pixels::Argb8888(pixels) => self.update_with_buffer(pixels, width, height)
+                .map_err(|e| format!("Could not update window: {}", e)),
+            _ => Err(String::from("Could not update window: it only takes argb8888 pixels"))
+        }
+    }
+}
+
+// writes every frame's pixels as they come, little endian and nothing in
+// between, e.g. to a named pipe a led matrix or e-ink driver reads from
+pub struct RawSink<W> {
+    out: W,
+    format: PixelFormat,
+    bytes: Vec<u8>
+}
+
+impl<W: Write> RawSink<W> {
+    pub fn new(out: W, format: PixelFormat) -> RawSink<W> {
+        RawSink {
+            out,
+            format,
+            bytes: Vec::new()
+        }
+    }
+}
+
+impl RawSink<File> {
+    // opening a named pipe waits for its reader
+    pub fn create(path: &str, format: PixelFormat) -> Result<RawSink<File>, String> {
+        let file = File::create(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+        Ok(RawSink::new(file, format))
+    }
+}
+
+impl<W: Write> DisplaySink for RawSink<W> {
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn present(&mut self, pixels: Pixels, _width: usize, _height: usize) -> Result<(), String> {
+        self.bytes.clear();
+        match pixels {
+            Pixels::Argb8888(pixels) => self.bytes.extend(pixels.iter().flat_map(|pixel| pixel.to_le_bytes())),
+            Pixels::Rgb565(pixels) => self.bytes.extend(pixels.iter().flat_map(|pixel| pixel.to_le_bytes())),
+            Pixels::Mono1(pixels) => self.bytes.extend_from_slice(pixels)
+        }
+        self.out.write_all(&self.bytes)
+            .and_then(|()| self.out.flush())
+            .map_err(|e| format!("Could not write frame: {}", e))
+    }
+}
+
+pub fn to_rgb565(pixels: &[u32], out: &mut Vec<u16>) {
+    out.clear();
+    out.extend(pixels.iter().map(|&color| {
+        let red = (color >> 19) & 0x1F;
+        let green = (color >> 10) & 0x3F;
+        let blue = (color >> 3) & 0x1F;
+        (red << 11 | green << 5 | blue) as u16
+    }));
+}
+
+// bright is at least half way to white by luminance
+pub fn to_mono1(pixels: &[u32], width: usize, out: &mut Vec<u8>) {
+    out.clear();
+    for row in pixels.chunks(width) {
+        for byte in row.chunks(8) {
+            let bits = byte.iter().enumerate().fold(0u8, |bits, (bit, &color)| {
+                let luma = (299 * ((color >> 16) & 0xFF) + 587 * ((color >> 8) & 0xFF) + 114 * (color & 0xFF)) / 1000;
+                if luma >= 0x80 { bits | 0x80 >> bit } else { bits }
+            });
+            out.push(bits);
+        }
+    }
+}
+
+// hands every frame to the primary sink (the window the user interacts
+// with) and to any number of mirrors
+pub struct Compositor {
+    mirrors: Vec<Box<dyn DisplaySink>>,
+    // the frame in the other formats, reused from frame to frame
+    rgb565: Vec<u16>,
+    mono1: Vec<u8>
+}
+
+impl Default for Compositor {
+    fn default() -> Compositor {
+        Compositor::new()
+    }
+}
+
+// the frame being presented, converted to a format the first time a sink
+// asks for it
+struct Conversions<'a> {
+    argb8888: &'a [u32],
+    width: usize,
+    rgb565: &'a mut Vec<u16>,
+    mono1: &'a mut Vec<u8>,
+    converted: [bool; 2]
+}
+
+impl<'a> Conversions<'a> {
+    fn get(&mut self, format: PixelFormat) -> Pixels<'_> {
+        match format {
+            PixelFormat::Argb8888 => Pixels::Argb8888(self.argb8888),
+            PixelFormat::Rgb565 => {
+                if !self.converted[0] {
+                    to_rgb565(self.argb8888, self.rgb565);
+                    self.converted[0] = true;
+                }
+                Pixels::Rgb565(self.rgb565)
+            },
+            PixelFormat::Mono1 => {
+                if !self.converted[1] {
+                    to_mono1(self.argb8888, self.width, self.mono1);
+                    self.converted[1] = true;
+                }
+                Pixels::Mono1(self.mono1)
+            }
+        }
+    }
+}
+
+impl Compositor {
+    pub fn new() -> Compositor {
+        Compositor {
+            mirrors: Vec::new(),
+            rgb565: Vec::new(),
+            mono1: Vec::new()
+        }
+    }
+
+    pub fn add_mirror(&mut self, sink: Box<dyn DisplaySink>) {
+        self.mirrors.push(sink);
+    }
+
+    pub fn present(&mut self, primary: &mut dyn DisplaySink, pixels: &[u32], width: usize, height: usize) -> Result<(), String> {
+        let mut conversions = Conversions {
+            argb8888: pixels,
+            width,
+            rgb565: &mut self.rgb565,
+            mono1: &mut self.mono1,
+            converted: [false; 2]
+        };
+        // a broken mirror shouldn't take the game down with it
+        self.mirrors.retain_mut(|sink| match sink.present(conversions.get(sink.format()), width, height) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Dropping display mirror: {}", e);
+                false
+            }
+        });
+
+        primary.present(conversions.get(primary.format()), width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::{
+        Compositor,
+        DisplaySink,
+        PixelFormat,
+        Pixels,
+        RawSink
+    };
+
+    // keeps the format and pixels of the last frame it was given, a u32 per
+    // pixel or byte, where the test can still see them once the compositor
+    // owns it
+    struct Recorder {
+        format: PixelFormat,
+        last: Rc<RefCell<(Option<PixelFormat>, Vec<u32>)>>
+    }
+
+    impl Recorder {
+        fn new(format: PixelFormat) -> Recorder {
+            Recorder {
+                format,
+                last: Rc::new(RefCell::new((None, Vec::new())))
+            }
+        }
+    }
+
+    impl DisplaySink for Recorder {
+        fn format(&self) -> PixelFormat {
+            self.format
+        }
+
+        fn present(&mut self, pixels: Pixels, _width: usize, _height: usize) -> Result<(), String> {
+            *self.last.borrow_mut() = match pixels {
+                Pixels::Argb8888(pixels) => (Some(PixelFormat::Argb8888), pixels.to_vec()),
+                Pixels::Rgb565(pixels) => (Some(PixelFormat::Rgb565), pixels.iter().map(|&pixel| pixel as u32).collect()),
+                Pixels::Mono1(pixels) => (Some(PixelFormat::Mono1), pixels.iter().map(|&pixel| pixel as u32).collect())
+            };
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sinks_get_frames_in_the_format_they_ask_for() {
+        let mut primary = Recorder::new(PixelFormat::Argb8888);
+        let mirror = Recorder::new(PixelFormat::Rgb565);
+        let mirrored = mirror.last.clone();
+        let mut compositor = Compositor::new();
+        compositor.add_mirror(Box::new(mirror));
+
+        // a 10x2 frame, white then black then red on the first row
+        let mut frame = vec![0; 20];
+        frame[0] = 0xFFFFFF;
+        frame[2] = 0xFF0000;
+        frame[9] = 0xFFFFFF;
+        compositor.present(&mut primary, &frame, 10, 2).unwrap();
+        assert_eq!(*primary.last.borrow(), (Some(PixelFormat::Argb8888), frame.clone()));
+
+        let (format, pixels) = &*mirrored.borrow();
+        assert_eq!(*format, Some(PixelFormat::Rgb565));
+        assert_eq!(pixels.len(), 20);
+        assert_eq!(&pixels[..3], &[0xFFFF, 0x0000, 0xF800]);
+        assert_eq!(pixels[9], 0xFFFF);
+
+        let mut mono1 = Vec::new();
+        super::to_mono1(&frame, 10, &mut mono1);
+        // red is too dark to count as lit
+        assert_eq!(mono1, vec![0b1000_0000, 0b0100_0000, 0, 0]);
+    }
+
+    #[test]
+    fn raw_sinks_write_the_pixels_as_they_come() {
+        let frame = [0xFFFFFF, 0xFF0000, 0, 0, 0, 0, 0, 0, 0xFFFFFF];
+        let mut compositor = Compositor::new();
+        let mut rgb565 = RawSink::new(Vec::new(), PixelFormat::Rgb565);
+        compositor.present(&mut rgb565, &frame, 9, 1).unwrap();
+        assert_eq!(&rgb565.out[..4], &[0xFF, 0xFF, 0x00, 0xF8]);
+        assert_eq!(rgb565.out.len(), 18);
+
+        let mut mono1 = RawSink::new(Vec::new(), PixelFormat::Mono1);
+        compositor.present(&mut mono1, &frame, 9, 1).unwrap();
+        compositor.present(&mut mono1, &frame, 9, 1).unwrap();
+        assert_eq!(mono1.out, vec![0b1000_0000, 0b1000_0000, 0b1000_0000, 0b1000_0000]);
+
+        assert_eq!(PixelFormat::parse("mono1"), Some(PixelFormat::Mono1));
+        assert_eq!(PixelFormat::parse("rgb888"), None);
+    }
+}
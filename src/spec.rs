@@ -0,0 +1,180 @@
+// numbers that describe the chip-8 machine itself, shared with tools built on
+// top of rust8 (assemblers, visualizers) so they don't hardcode them
+
+// memory map
+pub const RAM_SIZE: usize = 4096;
+pub const FONT_BASE: u16 = 0x0000;
+pub const FONT_SPRITE_BYTES: usize = 5;
+pub const PROGRAM_START_ADDR: u16 = 0x0200;
+pub const MAX_ROM_SIZE: usize = RAM_SIZE - PROGRAM_START_ADDR as usize;
+
+// registers and stack
+pub const NUM_GPR: usize = 16;
+pub const STACK_DEPTH: usize = 16;
+
+// display, in pixels
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const SPRITE_WIDTH: usize = 8;
+
+// timers count down at this rate
+pub const TIMER_HZ: u32 = 60;
+
+// keypad, as laid out on the original hex keypad
+pub const NUM_KEYS: usize = 16;
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF]
+];
+
+// hex digit sprites 0-F, stored at FONT_BASE
+pub const FONT: [[u8; FONT_SPRITE_BYTES]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0],
+    [0x20, 0x60, 0x20, 0x20, 0x70],
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+    [0x90 ,0x90, 0xF0, 0x10, 0x10],
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x10, 0x20, 0x40, 0x40],
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x90, 0x90],
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0],
+    [0xF0, 0x80, 0x80, 0x80, 0xF0],
+    [0xE0, 0x90, 0x90, 0x90, 0xE0],
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x80, 0x80]
+];
+
+// opcodes. an instruction matches OP_X when (instruction & MASK_X) == OP_X,
+// the remaining nibbles are its operands (X, Y, N, NN or NNN)
+pub const MASK_EXACT: u16 = 0xFFFF;
+pub const MASK_NNN: u16 = 0xF000;
+pub const MASK_XNN: u16 = 0xF000;
+pub const MASK_XYN: u16 = 0xF00F;
+pub const MASK_X: u16 = 0xF0FF;
+
+pub const OP_CLS: u16 = 0x00E0;   // 00E0 clear the display
+pub const OP_RET: u16 = 0x00EE;   // 00EE return from subroutine
+pub const OP_JP: u16 = 0x1000;    // 1NNN jump to NNN
+pub const OP_CALL: u16 = 0x2000;  // 2NNN call subroutine at NNN
+pub const OP_SE_NN: u16 = 0x3000; // 3XNN skip if VX == NN
+pub const OP_SNE_NN: u16 = 0x4000; // 4XNN skip if VX != NN
+pub const OP_SE_VY: u16 = 0x5000; // 5XY0 skip if VX == VY
+pub const OP_LD_NN: u16 = 0x6000; // 6XNN VX = NN
+pub const OP_ADD_NN: u16 = 0x7000; // 7XNN VX += NN
+pub const OP_LD_VY: u16 = 0x8000; // 8XY0 VX = VY
+pub const OP_OR: u16 = 0x8001;    // 8XY1 VX |= VY
+pub const OP_AND: u16 = 0x8002;   // 8XY2 VX &= VY
+pub const OP_XOR: u16 = 0x8003;   // 8XY3 VX ^= VY
+pub const OP_ADD_VY: u16 = 0x8004; // 8XY4 VX += VY, VF = carry
+pub const OP_SUB: u16 = 0x8005;   // 8XY5 VX -= VY, VF = not borrow
+pub const OP_SHR: u16 = 0x8006;   // 8XY6 VX >>= 1, VF = shifted out bit
+pub const OP_SUBN: u16 = 0x8007;  // 8XY7 VX = VY - VX, VF = not borrow
+pub const OP_SHL: u16 = 0x800E;   // 8XYE VX <<= 1, VF = shifted out bit
+pub const OP_SNE_VY: u16 = 0x9000; // 9XY0 skip if VX != VY
+pub const OP_LD_I: u16 = 0xA000;  // ANNN I = NNN
+pub const OP_JP_V0: u16 = 0xB000; // BNNN jump to NNN + V0
+pub const OP_RND: u16 = 0xC000;   // CXNN VX = random & NN
+pub const OP_DRW: u16 = 0xD000;   // DXYN draw N rows of sprite at I to (VX, VY)
+pub const OP_SKP: u16 = 0xE09E;   // EX9E skip if key VX is down
+pub const OP_SKNP: u16 = 0xE0A1;  // EXA1 skip if key VX is up
+pub const OP_LD_DT: u16 = 0xF007; // FX07 VX = DT
+pub const OP_LD_KEY: u16 = 0xF00A; // FX0A wait for a key, VX = key
+pub const OP_SET_DT: u16 = 0xF015; // FX15 DT = VX
+pub const OP_SET_ST: u16 = 0xF018; // FX18 ST = VX
+pub const OP_ADD_I: u16 = 0xF01E; // FX1E I += VX
+pub const OP_FONT: u16 = 0xF029;  // FX29 I = address of the font sprite for VX
+pub const OP_BCD: u16 = 0xF033;   // FX33 store VX as BCD at I, I+1, I+2
+pub const OP_STORE: u16 = 0xF055; // FX55 store V0..=VX at I
+pub const OP_LOAD: u16 = 0xF065;  // FX65 load V0..=VX from I
+
+// every opcode with the mask used to recognise it
+pub const OPCODES: [(u16, u16); 34] = [
+    (MASK_EXACT, OP_CLS), (MASK_EXACT, OP_RET),
+    (MASK_NNN, OP_JP), (MASK_NNN, OP_CALL),
+    (MASK_XNN, OP_SE_NN), (MASK_XNN, OP_SNE_NN), (MASK_XYN, OP_SE_VY),
+    (MASK_XNN, OP_LD_NN), (MASK_XNN, OP_ADD_NN),
+    (MASK_XYN, OP_LD_VY), (MASK_XYN, OP_OR), (MASK_XYN, OP_AND), (MASK_XYN, OP_XOR),
+    (MASK_XYN, OP_ADD_VY), (MASK_XYN, OP_SUB), (MASK_XYN, OP_SHR), (MASK_XYN, OP_SUBN),
+    (MASK_XYN, OP_SHL), (MASK_XYN, OP_SNE_VY),
+    (MASK_NNN, OP_LD_I), (MASK_NNN, OP_JP_V0), (MASK_XNN, OP_RND), (MASK_NNN, OP_DRW),
+    (MASK_X, OP_SKP), (MASK_X, OP_SKNP),
+    (MASK_X, OP_LD_DT), (MASK_X, OP_LD_KEY), (MASK_X, OP_SET_DT), (MASK_X, OP_SET_ST),
+    (MASK_X, OP_ADD_I), (MASK_X, OP_FONT), (MASK_X, OP_BCD), (MASK_X, OP_STORE), (MASK_X, OP_LOAD)
+];
+
+// rust8's own opcodes, part of no chip-8 variant. they only run with
+// --extensions rust8, anywhere else they're unknown instructions
+pub const OP_LD_MS: u16 = 0xF0F1; // FXF1 VX = low byte of the millisecond clock
+
+pub const EXTENSION_OPCODES: [(u16, u16); 1] = [
+    (MASK_X, OP_LD_MS)
+];
+
+// the opcode an instruction is, if any
+pub fn opcode_of(instruction: u16) -> Option<u16> {
+    OPCODES.iter()
+        .find(|&&(mask, op)| instruction & mask == op)
+        .map(|&(_, op)| op)
+}
+
+// the extension opcode an instruction is, if any
+pub fn extension_opcode_of(instruction: u16) -> Option<u16> {
+    EXTENSION_OPCODES.iter()
+        .find(|&&(mask, op)| instruction & mask == op)
+        .map(|&(_, op)| op)
+}
+
+// assembly text for one instruction, in the usual Cowgod mnemonics
+pub fn disassemble(instruction: u16) -> String {
+    let x = (instruction & 0x0F00) >> 8;
+    let y = (instruction & 0x00F0) >> 4;
+    let n = instruction & 0x000F;
+    let nn = instruction & 0x00FF;
+    let nnn = instruction & 0x0FFF;
+
+    match opcode_of(instruction) {
+        Some(OP_CLS) => String::from("CLS"),
+        Some(OP_RET) => String::from("RET"),
+        Some(OP_JP) => format!("JP {:03X}", nnn),
+        Some(OP_CALL) => format!("CALL {:03X}", nnn),
+        Some(OP_SE_NN) => format!("SE V{:X}, {:02X}", x, nn),
+        Some(OP_SNE_NN) => format!("SNE V{:X}, {:02X}", x, nn),
+        Some(OP_SE_VY) => format!("SE V{:X}, V{:X}", x, y),
+        Some(OP_LD_NN) => format!("LD V{:X}, {:02X}", x, nn),
+        Some(OP_ADD_NN) => format!("ADD V{:X}, {:02X}", x, nn),
+        Some(OP_LD_VY) => format!("LD V{:X}, V{:X}", x, y),
+        Some(OP_OR) => format!("OR V{:X}, V{:X}", x, y),
+        Some(OP_AND) => format!("AND V{:X}, V{:X}", x, y),
+        Some(OP_XOR) => format!("XOR V{:X}, V{:X}", x, y),
+        Some(OP_ADD_VY) => format!("ADD V{:X}, V{:X}", x, y),
+        Some(OP_SUB) => format!("SUB V{:X}, V{:X}", x, y),
+        Some(OP_SHR) => format!("SHR V{:X}", x),
+        Some(OP_SUBN) => format!("SUBN V{:X}, V{:X}", x, y),
+        Some(OP_SHL) => format!("SHL V{:X}", x),
+        Some(OP_SNE_VY) => format!("SNE V{:X}, V{:X}", x, y),
+        Some(OP_LD_I) => format!("LD I, {:03X}", nnn),
+        Some(OP_JP_V0) => format!("JP V0, {:03X}", nnn),
+        Some(OP_RND) => format!("RND V{:X}, {:02X}", x, nn),
+        Some(OP_DRW) => format!("DRW V{:X}, V{:X}, {:X}", x, y, n),
+        Some(OP_SKP) => format!("SKP V{:X}", x),
+        Some(OP_SKNP) => format!("SKNP V{:X}", x),
+        Some(OP_LD_DT) => format!("LD V{:X}, DT", x),
+        Some(OP_LD_KEY) => format!("LD V{:X}, K", x),
+        Some(OP_SET_DT) => format!("LD DT, V{:X}", x),
+        Some(OP_SET_ST) => format!("LD ST, V{:X}", x),
+        Some(OP_ADD_I) => format!("ADD I, V{:X}", x),
+        Some(OP_FONT) => format!("LD F, V{:X}", x),
+        Some(OP_BCD) => format!("LD B, V{:X}", x),
+        Some(OP_STORE) => format!("LD [I], V{:X}", x),
+        Some(OP_LOAD) => format!("LD V{:X}, [I]", x),
+        _ => match extension_opcode_of(instruction) {
+            Some(OP_LD_MS) => format!("LD V{:X}, MS", x),
+            _ => format!("DW {:04X}", instruction)
+        }
+    }
+}
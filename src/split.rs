@@ -0,0 +1,122 @@
+// archives sometimes hold several programs in one file, either as HP48
+// string objects ("HPHP48-" then a prolog and length) one after another,
+// or plainly concatenated with zero padding between them
+
+// the HP48 binary header, followed by one version letter
+const HP48_MAGIC: &[u8] = b"HPHP48-";
+// magic, version letter, then the string prolog and length, 5 nibbles each
+const HP48_HEADER: usize = 8 + 5;
+// zero bytes that count as padding between programs rather than data.
+// archives pad to blocks of 256 or 512
+const MIN_PADDING: usize = 256;
+
+// one program found in a file
+#[derive(Clone, PartialEq, Debug)]
+pub struct Program {
+    pub offset: usize,
+    pub data: Vec<u8>
+}
+
+// the programs in data, in file order. a plain rom is one program
+pub fn split(data: &[u8]) -> Vec<Program> {
+    let headers = find_all(data, HP48_MAGIC);
+    if !headers.is_empty() {
+        return headers.iter().enumerate().map(|(i, &start)| {
+            let end = headers.get(i + 1).copied().unwrap_or(data.len());
+            let offset = (start + HP48_HEADER).min(end);
+            Program {
+                offset,
+                data: data[offset..end].to_vec()
+            }
+        }).collect();
+    }
+
+    let mut programs = Vec::new();
+    let mut start = 0;
+    let mut zeros = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == 0 {
+            zeros += 1;
+            continue;
+        }
+        if zeros >= MIN_PADDING && i - zeros > start {
+            programs.push(Program {
+                offset: start,
+                data: data[start..word_end(start, i - zeros)].to_vec()
+            });
+            start = i;
+        } else if zeros >= MIN_PADDING {
+            // padding before the first program
+            start = i;
+        }
+        zeros = 0;
+    }
+    let end = if zeros >= MIN_PADDING { word_end(start, data.len() - zeros) } else { data.len() };
+    if end > start || programs.is_empty() {
+        programs.push(Program {
+            offset: start,
+            data: data[start..end.max(start)].to_vec()
+        });
+    }
+    programs
+}
+
+// program number `index`, counting from 1 like `rust8 split` lists them
+pub fn select(data: &[u8], index: usize) -> Result<Vec<u8>, String> {
+    let programs = split(data);
+    index.checked_sub(1)
+        .and_then(|i| programs.get(i))
+        .map(|program| program.data.clone())
+        .ok_or_else(|| format!("Could not select program {}: the file holds {}", index, programs.len()))
+}
+
+// padding only comes off in whole words, so a program ending in an
+// opcode like 1200 keeps its last zero byte
+fn word_end(start: usize, end: usize) -> usize {
+    end + (end - start) % 2
+}
+
+fn find_all(data: &[u8], needle: &[u8]) -> Vec<usize> {
+    data.windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        select,
+        split,
+        MIN_PADDING
+    };
+
+    #[test]
+    fn splits_on_hp48_headers_and_padding() {
+        let mut archive = Vec::new();
+        archive.extend_from_slice(b"HPHP48-E\x2C\x2A\x20\x00\x00");
+        archive.extend_from_slice(&[0x00, 0xE0, 0x12, 0x00]);
+        archive.extend_from_slice(b"HPHP48-E\x2C\x2A\x20\x00\x00");
+        archive.extend_from_slice(&[0x60, 0x01]);
+        let programs = split(&archive);
+        assert_eq!(programs.len(), 2);
+        assert_eq!(programs[0].data, vec![0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(programs[1].offset, 30);
+        assert_eq!(programs[1].data, vec![0x60, 0x01]);
+
+        let mut padded = vec![0x12, 0x00, 0x00, 0x00];
+        padded.extend(vec![0; MIN_PADDING]);
+        padded.extend_from_slice(&[0x60, 0x01]);
+        padded.extend(vec![0; MIN_PADDING]);
+        let programs = split(&padded);
+        assert_eq!(programs.len(), 2);
+        assert_eq!(programs[0].data, vec![0x12, 0x00]);
+        assert_eq!(programs[1].data, vec![0x60, 0x01]);
+        assert_eq!(select(&padded, 2).unwrap(), vec![0x60, 0x01]);
+        assert!(select(&padded, 3).is_err());
+
+        // a short run of zeros is just data
+        assert_eq!(split(&[0x12, 0x00, 0x00, 0x60]).len(), 1);
+    }
+}
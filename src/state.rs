@@ -0,0 +1,307 @@
+use std::fs;
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::RAM_SIZE;
+use crate::WIDTH;
+use crate::HEIGHT;
+
+// 2 widened the stack to whole addresses
+pub const STATE_VERSION: u32 = 2;
+
+// bytes of ram per line in the json dump
+const RAM_ROW_BYTES: usize = 32;
+
+// human readable snapshot of the whole machine, meant for bug reports
+// and hand written test fixtures
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct State {
+    pub version: u32,
+
+    // registers
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub stack: [u16; 16],
+
+    // timers
+    pub dt: u8,
+    pub st: u8,
+
+    pub keys: [u8; 16],
+
+    // one string per display row, '#' for a lit pixel and '.' for an unlit one
+    pub display: Vec<String>,
+
+    // ram as rows of hex, RAM_ROW_BYTES per row
+    pub ram: Vec<String>
+}
+
+// version 1 as it was saved, before the stack held whole addresses. its
+// stack kept only the low byte of each return address, which is what a
+// machine loaded from it returns to
+#[derive(Serialize, Deserialize)]
+struct StateV1 {
+    version: u32,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    sp: u8,
+    stack: [u8; 16],
+    dt: u8,
+    st: u8,
+    keys: [u8; 16],
+    display: Vec<String>,
+    ram: Vec<String>
+}
+
+impl From<StateV1> for State {
+    fn from(old: StateV1) -> State {
+        State {
+            version: STATE_VERSION,
+            v: old.v,
+            i: old.i,
+            pc: old.pc,
+            sp: old.sp,
+            stack: old.stack.map(|addr| addr as u16),
+            dt: old.dt,
+            st: old.st,
+            keys: old.keys,
+            display: old.display,
+            ram: old.ram
+        }
+    }
+}
+
+// just the version of a json state, to know how to read the rest
+#[derive(Deserialize)]
+struct Version {
+    version: u32
+}
+
+impl State {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Could not serialize state.")
+    }
+
+    pub fn from_json(json: &str) -> Result<State, String> {
+        let version: Version = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid state json: {}", e))?;
+        let state = if version.version == 1 {
+            serde_json::from_str::<StateV1>(json).map(State::from)
+        } else {
+            serde_json::from_str::<State>(json)
+        };
+        state.map_err(|e| format!("Invalid state json: {}", e))?.checked()
+    }
+
+    // the same state in binary, for quick saves nobody reads by hand
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Could not serialize state.")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<State, String> {
+        // the version comes first whichever it is
+        let version: u32 = bincode::deserialize(bytes)
+            .map_err(|e| format!("Invalid state file: {}", e))?;
+        let state = if version == 1 {
+            bincode::deserialize::<StateV1>(bytes).map(State::from)
+        } else {
+            bincode::deserialize::<State>(bytes)
+        };
+        state.map_err(|e| format!("Invalid state file: {}", e))?.checked()
+    }
+
+    fn checked(self) -> Result<State, String> {
+        if self.version != STATE_VERSION {
+            return Err(format!("Unsupported state version {}", self.version));
+        }
+        if self.sp as usize > self.stack.len() {
+            return Err(format!("Invalid stack pointer {}", self.sp));
+        }
+        // make sure the payloads decode before anyone tries to load them
+        self.display_pixels()?;
+        self.ram_bytes()?;
+
+        Ok(self)
+    }
+
+    pub fn export(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_json())
+            .map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    pub fn import(path: &str) -> Result<State, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+        State::from_json(&json)
+    }
+
+    // short fingerprint of the whole machine, the same on any two machines
+    // in the same state
+    pub fn checksum(&self) -> String {
+        let digest = sha1_smol::Sha1::from(self.to_bytes()).digest().to_string();
+        digest[..8].to_string()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes())
+            .map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<State, String> {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+        State::from_bytes(&bytes)
+    }
+
+    pub fn display_pixels(&self) -> Result<Vec<bool>, String> {
+        if self.display.len() != HEIGHT {
+            return Err(format!("Display must have {} rows, found {}", HEIGHT, self.display.len()));
+        }
+
+        let mut pixels = Vec::with_capacity(WIDTH * HEIGHT);
+        for (y, row) in self.display.iter().enumerate() {
+            if row.chars().count() != WIDTH {
+                return Err(format!("Display row {} must be {} pixels wide", y, WIDTH));
+            }
+            for ch in row.chars() {
+                match ch {
+                    '#' => pixels.push(true),
+                    '.' => pixels.push(false),
+                    _ => return Err(format!("Invalid pixel '{}' in display row {}", ch, y))
+                }
+            }
+        }
+
+        Ok(pixels)
+    }
+
+    pub fn ram_bytes(&self) -> Result<Vec<u8>, String> {
+        // whitespace is ignored so hand edited dumps can be grouped freely
+        let hex: String = self.ram.concat().chars().filter(|c| !c.is_whitespace()).collect();
+        if !hex.is_ascii() || hex.len() != RAM_SIZE * 2 {
+            return Err(format!("Ram must hold {} bytes, found {} hex digits", RAM_SIZE, hex.len()));
+        }
+
+        let mut memory = Vec::with_capacity(RAM_SIZE);
+        for index in 0..RAM_SIZE {
+            let digits = &hex[index * 2..index * 2 + 2];
+            let byte = u8::from_str_radix(digits, 16)
+                .map_err(|_| format!("Invalid ram byte '{}' at {:#05X}", digits, index))?;
+            memory.push(byte);
+        }
+
+        Ok(memory)
+    }
+}
+
+pub fn encode_display(pixels: &[bool]) -> Vec<String> {
+    pixels.chunks(WIDTH)
+        .map(|row| row.iter().map(|&on| if on { '#' } else { '.' }).collect())
+        .collect()
+}
+
+pub fn encode_ram(memory: &[u8]) -> Vec<String> {
+    memory.chunks(RAM_ROW_BYTES)
+        .map(|row| row.iter().map(|b| format!("{:02x}", b)).collect())
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        State,
+        StateV1,
+        STATE_VERSION
+    };
+    use crate::chip8::Chip8;
+
+    const BREAKOUT: &str = include_str!("../tests/fixtures/breakout.state.json");
+
+    #[test]
+    fn version_1_states_load_with_a_wider_stack() {
+        let state = State::from_json(BREAKOUT).unwrap();
+        let old = StateV1 {
+            version: 1,
+            v: state.v,
+            i: state.i,
+            pc: state.pc,
+            sp: 1,
+            stack: [0x4E; 16],
+            dt: state.dt,
+            st: state.st,
+            keys: state.keys,
+            display: state.display.clone(),
+            ram: state.ram.clone()
+        };
+
+        let loaded = State::from_bytes(&bincode::serialize(&old).unwrap()).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        assert_eq!(loaded.stack, [0x04E; 16]);
+        assert_eq!(loaded.ram, state.ram);
+
+        let loaded = State::from_json(&serde_json::to_string(&old).unwrap()).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        assert_eq!((loaded.sp, loaded.stack[0]), (1, 0x04E));
+
+        // newer than this build knows
+        let newer = BREAKOUT.replace("\"version\": 2", "\"version\": 3");
+        assert_eq!(State::from_json(&newer).err(), Some(String::from("Unsupported state version 3")));
+    }
+
+    #[test]
+    fn binary_state_resumes_where_it_left_off() {
+        let mut chip8 = Chip8::from_state(BREAKOUT.as_bytes()).unwrap();
+        for _ in 0..5 {
+            chip8.run_frame(&[false; 16]);
+        }
+        let saved = State::from_bytes(&chip8.save_state().to_bytes()).unwrap();
+        assert_eq!(saved, chip8.save_state());
+
+        let mut resumed = Chip8::new();
+        resumed.load_state(&saved).unwrap();
+        assert_eq!(resumed.save_state(), chip8.save_state());
+        assert!(State::from_bytes(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn checksum_tells_states_apart() {
+        let mut chip8 = Chip8::from_state(BREAKOUT.as_bytes()).unwrap();
+        let same = Chip8::from_state(BREAKOUT.as_bytes()).unwrap();
+        let checksum = chip8.save_state().checksum();
+        assert_eq!(checksum.len(), 8);
+        assert_eq!(checksum, same.save_state().checksum());
+
+        chip8.step().unwrap();
+        assert_ne!(chip8.save_state().checksum(), checksum);
+    }
+
+    #[test]
+    fn exported_states_import_the_same() {
+        let state = State::from_json(BREAKOUT).unwrap();
+        let path = std::env::temp_dir().join(format!("rust8-state-{}.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        state.export(&path).unwrap();
+        let imported = State::import(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.unwrap(), state);
+        assert!(State::import(&path).unwrap_err().starts_with("Could not read"));
+    }
+
+    #[test]
+    fn malformed_json_states_are_refused() {
+        assert!(State::from_json("{").unwrap_err().starts_with("Invalid state json"));
+
+        let extra_row = BREAKOUT.replacen("\"display\": [", "\"display\": [\"#\", ", 1);
+        assert_eq!(State::from_json(&extra_row).err(), Some(String::from("Display must have 32 rows, found 33")));
+
+        let bad_pixel = BREAKOUT.replacen('#', "x", 1);
+        assert!(State::from_json(&bad_pixel).unwrap_err().starts_with("Invalid pixel 'x'"));
+
+        let bad_sp = BREAKOUT.replacen("\"sp\": 0", "\"sp\": 17", 1);
+        assert_eq!(State::from_json(&bad_sp).err(), Some(String::from("Invalid stack pointer 17")));
+    }
+}
@@ -0,0 +1,112 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::config;
+
+// how much one rom has been played
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct RomStats {
+    // where it was last played from, for opening it again
+    pub path: String,
+    pub plays: u32,
+    pub seconds: u64,
+    // seconds since the unix epoch
+    pub last_played: u64
+}
+
+// which roms were played and for how long, by rom sha1. only ever kept in
+// the config directory, and only once the user turns it on: the file
+// existing is the switch, see enabled
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct UsageStats {
+    roms: BTreeMap<String, RomStats>
+}
+
+impl UsageStats {
+    pub fn path() -> Option<PathBuf> {
+        config::dir().map(|dir| dir.join("stats.json"))
+    }
+
+    // the stats if the user has turned them on, None otherwise
+    pub fn enabled(path: &Path) -> Result<Option<UsageStats>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        UsageStats::load(path).map(Some)
+    }
+
+    pub fn load(path: &Path) -> Result<UsageStats, String> {
+        config::load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        config::save_json(path, self)
+    }
+
+    pub fn get(&self, sha1: &str) -> Option<&RomStats> {
+        self.roms.get(sha1)
+    }
+
+    // one more play of the rom with this sha1, ending now
+    pub fn record(&mut self, sha1: &str, rom_path: &str, played: Duration, now: SystemTime) {
+        let rom = self.roms.entry(sha1.to_string()).or_default();
+        rom.path = rom_path.to_string();
+        rom.plays += 1;
+        rom.seconds += played.as_secs();
+        rom.last_played = now.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+    }
+
+    // most recently played first
+    pub fn recent(&self) -> Vec<(&str, &RomStats)> {
+        let mut roms: Vec<(&str, &RomStats)> = self.roms.iter().map(|(sha1, rom)| (sha1.as_str(), rom)).collect();
+        roms.sort_by_key(|(_, rom)| Reverse(rom.last_played));
+        roms
+    }
+}
+
+// "1:02:03" for an hour, two minutes and three seconds
+pub fn format_seconds(seconds: u64) -> String {
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        UNIX_EPOCH
+    };
+
+    use super::{
+        format_seconds,
+        UsageStats
+    };
+
+    #[test]
+    fn plays_add_up_per_rom() {
+        let mut stats = UsageStats::default();
+        stats.record("aaa", "pong.ch8", Duration::from_secs(90), UNIX_EPOCH + Duration::from_secs(1000));
+        stats.record("bbb", "tetris.ch8", Duration::from_secs(30), UNIX_EPOCH + Duration::from_secs(2000));
+        stats.record("aaa", "roms/pong.ch8", Duration::from_secs(45), UNIX_EPOCH + Duration::from_secs(3000));
+
+        let pong = stats.get("aaa").unwrap();
+        assert_eq!((pong.plays, pong.seconds, pong.last_played), (2, 135, 3000));
+        assert_eq!(pong.path, "roms/pong.ch8");
+        let recent: Vec<&str> = stats.recent().iter().map(|(sha1, _)| *sha1).collect();
+        assert_eq!(recent, vec!["aaa", "bbb"]);
+        assert_eq!(format_seconds(3723), "1:02:03");
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering
+};
+
+use rayon::prelude::*;
+
+use rust8::audio::SilentSink;
+use rust8::chip8::{
+    Chip8,
+    DEFAULT_INSTRUCTIONS_PER_FRAME
+};
+use rust8::cpu::KeyWaitTimeout;
+use rust8::screenshot;
+
+use crate::kiosk;
+use crate::shutdown::Shutdown;
+
+pub struct SweepResult {
+    pub rom: String,
+    pub outcome: Result<(), String>,
+    // screenshot of where a failed rom stopped
+    pub snapshot: Option<String>
+}
+
+// what to do when a rom fails, besides reporting it
+pub struct HaltSnapshots<'a> {
+    pub dir: &'a str,
+    pub scale: usize
+}
+
+// runs every rom in dir headless for `frames` frames with no input and
+// reports which ones halted. the machines are independent so the roms run
+// in parallel, one per core. skip_dt_waits ends a frame early when a rom is
+// only waiting on the delay timer. with snapshots, each failing rom leaves
+// a screenshot and state behind. on shutdown the roms running stop with an
+// error and the rest are left out
+pub fn sweep(dir: &str, frames: usize, key_wait: (u32, KeyWaitTimeout), skip_dt_waits: bool, snapshots: Option<HaltSnapshots>, shutdown: &Shutdown) -> Result<Vec<SweepResult>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Could not read sweep directory {}: {}", dir, e))?;
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && kiosk::is_rom(path))
+        .collect();
+    roms.sort();
+
+    let total = roms.len();
+    let done = AtomicUsize::new(0);
+    let results = roms.par_iter()
+        .filter(|_| !shutdown.requested())
+        .map(|path| {
+            let rom = path.to_string_lossy().into_owned();
+            let mut snapshot = None;
+            let outcome = fs::read(path)
+                .map_err(|e| format!("Could not read {}: {}", rom, e))
+                .and_then(|data| Chip8::with_rom_quirks(&data).map_err(String::from))
+                .and_then(|mut chip8| {
+                    let outcome = run(&mut chip8, frames, key_wait, skip_dt_waits, shutdown);
+                    if let (Err(_), Some(snapshots)) = (&outcome, &snapshots) {
+                        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                        match screenshot::write_halt_snapshot(snapshots.dir, &name, chip8.cpu.gfx(), &chip8.save_state(), snapshots.scale) {
+                            Ok(png) => snapshot = Some(png),
+                            Err(e) => println!("{}", e)
+                        }
+                    }
+                    outcome
+                });
+
+            let finished = done.fetch_add(1, Ordering::SeqCst) + 1;
+            match &outcome {
+                Ok(()) => println!("[{}/{}] {}: ok", finished, total, rom),
+                Err(e) => println!("[{}/{}] {}: {}", finished, total, rom, e)
+            }
+            SweepResult {
+                rom,
+                outcome,
+                snapshot
+            }
+        })
+        .collect();
+
+    Ok(results)
+}
+
+fn run(chip8: &mut Chip8, frames: usize, key_wait: (u32, KeyWaitTimeout), skip_dt_waits: bool, shutdown: &Shutdown) -> Result<(), String> {
+    chip8.cpu.set_key_wait_timeout(key_wait.0, key_wait.1);
+    let mut audio = SilentSink;
+
+    for _ in 0..frames {
+        if shutdown.requested() {
+            return Err(String::from("Interrupted"));
+        }
+        for _ in 0..DEFAULT_INSTRUCTIONS_PER_FRAME {
+            if skip_dt_waits && chip8.cpu.waiting_on_dt(&chip8.ram) {
+                break;
+            }
+            chip8.step()?;
+            if let Some(reason) = chip8.cpu.halted() {
+                return Err(reason.to_string());
+            }
+        }
+        chip8.cpu.tick_timers(&mut audio);
+    }
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{
+    Duration,
+    Instant
+};
+
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::cpu::Cpu;
+use crate::spec::TIMER_HZ;
+
+// one 60Hz frame
+pub const FRAME_TIME: Duration = Duration::from_micros(16667);
+
+// most ticks made up in one go, so a stall (a slow frame, a pause) doesn't
+// make the timers jump ahead
+const MAX_CATCH_UP: u32 = 6;
+
+// how far a run has got, for external tools implementing timeouts, pacing
+// checks and statistics
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Timing {
+    pub frames: u64,
+    pub cycles: u64,
+    pub runtime: Duration
+}
+
+impl Timing {
+    // started is when the rom was booted
+    pub fn of(cpu: &Cpu, started: Instant) -> Timing {
+        Timing {
+            frames: cpu.frames(),
+            cycles: cpu.cycles(),
+            runtime: started.elapsed()
+        }
+    }
+}
+
+// one line of key value pairs, e.g. "frames 60 cycles 540 runtime_ms 1002"
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frames {} cycles {} runtime_ms {}", self.frames, self.cycles, self.runtime.as_millis())
+    }
+}
+
+// when DT and ST are due to count down: TIMER_HZ by the wall clock, however
+// many instructions run in between
+pub struct TimerClock {
+    period: Duration,
+    last: Instant,
+    // time passed that hasn't made up a whole tick yet
+    owed: Duration
+}
+
+impl TimerClock {
+    pub fn new(now: Instant) -> TimerClock {
+        TimerClock {
+            period: Duration::from_secs(1) / TIMER_HZ,
+            last: now,
+            owed: Duration::from_secs(0)
+        }
+    }
+
+    // forgets the time passed so far, e.g. after a pause
+    pub fn reset(&mut self, now: Instant) {
+        self.last = now;
+        self.owed = Duration::from_secs(0);
+    }
+
+    // how many ticks have come due since the last call
+    pub fn ticks(&mut self, now: Instant) -> u32 {
+        self.owed += now.saturating_duration_since(self.last);
+        self.last = now;
+        let ticks = self.owed.as_nanos() / self.period.as_nanos();
+        if ticks > MAX_CATCH_UP as u128 {
+            self.owed = Duration::from_secs(0);
+            return MAX_CATCH_UP;
+        }
+        self.owed -= self.period * ticks as u32;
+        ticks as u32
+    }
+}
+
+// how many instructions run between frames
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Speed {
+    PerFrame(usize),
+    // as many as fit in FRAME_TIME
+    Unlimited
+}
+
+impl Speed {
+    // a number of instructions per frame, or unlimited
+    pub fn parse(text: &str) -> Option<Speed> {
+        if text == "unlimited" {
+            return Some(Speed::Unlimited);
+        }
+        text.parse().ok().filter(|&speed| speed > 0).map(Speed::PerFrame)
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::PerFrame(instructions) => write!(f, "{}", instructions),
+            Speed::Unlimited => write!(f, "unlimited")
+        }
+    }
+}
+
+impl TryFrom<String> for Speed {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Speed, String> {
+        Speed::parse(&text).ok_or_else(|| format!("Could not parse speed {}", text))
+    }
+}
+
+impl From<Speed> for String {
+    fn from(speed: Speed) -> String {
+        speed.to_string()
+    }
+}
+
+// how fast the machine runs against the wall clock
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rate {
+    Normal,
+    // this many frames run for every one drawn
+    Turbo(u32),
+    // every frame is drawn this many times
+    Slow(u32)
+}
+
+// decides when enough instructions have run to draw the next frame
+pub struct FramePacer {
+    speed: Speed,
+    rate: Rate,
+    left: usize,
+    started: Instant,
+    finish_early: bool,
+    // times the last frame is still to be drawn again in slow motion
+    held: u32,
+    // the last step ended a frame of the machine's, see machine_frame_ended
+    machine_frame_ended: bool
+}
+
+impl FramePacer {
+    pub fn new(speed: Speed) -> FramePacer {
+        FramePacer {
+            speed,
+            rate: Rate::Normal,
+            left: FramePacer::per_frame(speed, Rate::Normal),
+            started: Instant::now(),
+            finish_early: false,
+            held: 0,
+            machine_frame_ended: false
+        }
+    }
+
+    // turbo multiplies the instructions per frame, unlimited already runs
+    // as many as fit
+    fn per_frame(speed: Speed, rate: Rate) -> usize {
+        match (speed, rate) {
+            (Speed::PerFrame(instructions), Rate::Turbo(frames)) => instructions * frames as usize,
+            (Speed::PerFrame(instructions), _) => instructions,
+            (Speed::Unlimited, _) => 0
+        }
+    }
+
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    // starts a new frame at the new rate
+    pub fn set_rate(&mut self, rate: Rate) {
+        if rate == self.rate {
+            return;
+        }
+        self.rate = rate;
+        self.left = FramePacer::per_frame(self.speed, rate);
+        self.held = 0;
+    }
+
+    // call before running an instruction, true while slow motion still has
+    // to draw the last frame again rather than run the next one
+    pub fn hold(&mut self) -> bool {
+        if self.held == 0 {
+            return false;
+        }
+        self.held -= 1;
+        true
+    }
+
+    // how many times the timers tick at the end of a frame, None to go by
+    // the wall clock. turbo and slow motion tick them per frame so they keep
+    // pace with the instructions
+    pub fn timer_ticks(&self) -> Option<u32> {
+        match self.rate {
+            Rate::Normal => None,
+            Rate::Turbo(frames) => Some(frames),
+            Rate::Slow(_) => Some(1)
+        }
+    }
+
+    // call after each instruction, true when it was the frame's last. the
+    // next frame starts straight away
+    pub fn step(&mut self) -> bool {
+        let done = match self.speed {
+            Speed::PerFrame(_) => {
+                self.left -= 1;
+                self.left == 0
+            },
+            Speed::Unlimited => self.started.elapsed() >= FRAME_TIME
+        };
+        self.machine_frame_ended = match (self.speed, self.rate) {
+            (Speed::PerFrame(instructions), Rate::Turbo(_)) => self.left.is_multiple_of(instructions),
+            _ => done
+        };
+        if !done && !self.finish_early {
+            return false;
+        }
+        self.machine_frame_ended = true;
+        self.left = FramePacer::per_frame(self.speed, self.rate);
+        self.started = Instant::now();
+        self.finish_early = false;
+        if let Rate::Slow(times) = self.rate {
+            self.held = times - 1;
+        }
+        true
+    }
+
+    // true when the last step ended one of the machine's frames. turbo runs
+    // several of them for every one drawn, this tells where they end
+    pub fn machine_frame_ended(&self) -> bool {
+        self.machine_frame_ended
+    }
+
+    // makes the next instruction the frame's last
+    pub fn finish_early(&mut self) {
+        self.finish_early = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        Instant
+    };
+
+    use super::{
+        FramePacer,
+        Rate,
+        Speed,
+        TimerClock,
+        MAX_CATCH_UP
+    };
+
+    #[test]
+    fn timer_clock_ticks_at_60hz_by_the_wall_clock() {
+        let start = Instant::now();
+        let mut clock = TimerClock::new(start);
+        let mut ticks = 0;
+        // a second of very uneven frames still makes 60 ticks
+        for ms in [1, 5, 40, 41, 120, 190, 191, 270, 333, 400, 480, 560, 640, 720, 800, 880, 960, 999, 1000].iter() {
+            ticks += clock.ticks(start + Duration::from_millis(*ms));
+        }
+        assert_eq!(ticks, 60);
+
+        // a long stall only catches up a little
+        assert_eq!(clock.ticks(start + Duration::from_secs(10)), MAX_CATCH_UP);
+        assert_eq!(clock.ticks(start + Duration::from_secs(10)), 0);
+    }
+
+    #[test]
+    fn frame_pacer_runs_the_configured_instructions_per_frame() {
+        let mut pacer = FramePacer::new(Speed::parse("3").unwrap());
+        let frames: Vec<bool> = (0..6).map(|_| pacer.step()).collect();
+        assert_eq!(frames, vec![false, false, true, false, false, true]);
+
+        pacer.step();
+        pacer.finish_early();
+        assert!(pacer.step());
+        assert!(!pacer.step());
+
+        assert_eq!(Speed::parse("unlimited"), Some(Speed::Unlimited));
+        assert_eq!(Speed::parse("0"), None);
+    }
+
+    #[test]
+    fn turbo_and_slow_motion_scale_frames() {
+        let mut pacer = FramePacer::new(Speed::parse("2").unwrap());
+        pacer.set_rate(Rate::Turbo(3));
+        let frames: Vec<(bool, bool)> = (0..6).map(|_| (pacer.step(), pacer.machine_frame_ended())).collect();
+        // three frames of the machine's for the one drawn
+        assert_eq!(frames, vec![(false, false), (false, true), (false, false), (false, true), (false, false), (true, true)]);
+        assert_eq!(pacer.timer_ticks(), Some(3));
+
+        pacer.set_rate(Rate::Slow(3));
+        assert!(!pacer.hold());
+        pacer.step();
+        assert!(pacer.step());
+        assert!(pacer.hold());
+        assert!(pacer.hold());
+        assert!(!pacer.hold());
+
+        pacer.set_rate(Rate::Normal);
+        assert_eq!(pacer.timer_ticks(), None);
+    }
+}
@@ -0,0 +1,340 @@
+use std::io::{
+    self,
+    Stdout,
+    Write
+};
+use std::thread;
+use std::time::{
+    Duration,
+    Instant
+};
+
+use crossterm::cursor::{
+    Hide,
+    MoveTo,
+    Show
+};
+use crossterm::event::{
+    self,
+    Event,
+    KeyCode,
+    KeyEvent,
+    KeyEventKind,
+    KeyModifiers,
+    KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags
+};
+use crossterm::style::Print;
+use crossterm::terminal::{
+    self,
+    Clear,
+    ClearType,
+    EnterAlternateScreen,
+    LeaveAlternateScreen
+};
+use crossterm::{
+    execute,
+    queue
+};
+
+use rust8::audio::AudioSink;
+use rust8::chip8::Chip8;
+use rust8::display::Frame;
+use rust8::input::InputSource;
+use rust8::keymap::KeyMap;
+use rust8::timing::{
+    FramePacer,
+    Rate,
+    FRAME_TIME
+};
+
+use crate::frontend::{
+    self,
+    load,
+    ReplayRun
+};
+use crate::options::Options;
+use crate::shutdown::Shutdown;
+
+// without release events a key counts as held for this long after the
+// terminal last sent it. a key held down flickers until the terminal
+// starts repeating it
+const KEY_HOLD: Duration = Duration::from_millis(300);
+
+// the terminal put in raw mode on the alternate screen, until leave
+pub struct Terminal {
+    out: Stdout,
+    // the terminal reports key releases, not only presses
+    releases: bool,
+    // lines that would garble the screen, e.g. the invalid opcode log,
+    // written to stderr once the terminal is put back
+    held_back: Vec<String>
+}
+
+impl Terminal {
+    pub fn enter() -> Result<Terminal, String> {
+        terminal::enable_raw_mode().map_err(|e| format!("Could not set up the terminal: {}", e))?;
+        let mut terminal = Terminal {
+            out: io::stdout(),
+            releases: terminal::supports_keyboard_enhancement().unwrap_or(false),
+            held_back: Vec::new()
+        };
+        let mut entered = execute!(terminal.out, EnterAlternateScreen, Hide);
+        if terminal.releases {
+            entered = entered.and_then(|()| execute!(terminal.out, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)));
+        }
+        match entered {
+            Ok(()) => Ok(terminal),
+            Err(e) => {
+                terminal.leave();
+                Err(format!("Could not set up the terminal: {}", e))
+            }
+        }
+    }
+
+    // puts the terminal back the way it was, as well as it can
+    pub fn leave(mut self) {
+        if self.releases {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        for line in self.held_back {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+// keypad state kept from the terminal's key events. most terminals only
+// send a key as it goes down and again as it repeats, so it's held until
+// KEY_HOLD after the last of those. ones that report releases hold it
+// until the release
+struct TerminalKeys {
+    pressed: [Option<Instant>; 16],
+    releases: bool,
+    keymap: KeyMap
+}
+
+impl TerminalKeys {
+    fn set(&mut self, event: &KeyEvent, now: Instant) {
+        if let Some(key) = key_name(event.code).and_then(|name| self.keymap.get(&name)) {
+            self.pressed[key] = match event.kind {
+                KeyEventKind::Release => None,
+                _ => Some(now)
+            };
+        }
+    }
+}
+
+impl InputSource for TerminalKeys {
+    fn poll(&mut self, keys: &mut [u8; 16]) {
+        let now = Instant::now();
+        for (key, pressed) in self.pressed.iter().enumerate() {
+            let held = match pressed {
+                Some(at) => self.releases || now.duration_since(*at) < KEY_HOLD,
+                None => false
+            };
+            if held {
+                keys[key] = 1;
+            }
+        }
+    }
+}
+
+// the keymap's name for a key: letters upper case, digits, Space, Enter
+// and the arrows. terminals send characters rather than positions, so
+// the keymap follows the keyboard's layout here
+fn key_name(code: KeyCode) -> Option<String> {
+    match code {
+        KeyCode::Char(' ') => Some(String::from("Space")),
+        KeyCode::Char(c) if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase().to_string()),
+        KeyCode::Enter => Some(String::from("Enter")),
+        KeyCode::Up => Some(String::from("Up")),
+        KeyCode::Down => Some(String::from("Down")),
+        KeyCode::Left => Some(String::from("Left")),
+        KeyCode::Right => Some(String::from("Right")),
+        _ => None
+    }
+}
+
+// the terminal bell, rung as a beep starts. it can't hold a note, so
+// beeps lose their length and pitch
+struct Bell {
+    ringing: bool
+}
+
+impl AudioSink for Bell {
+    fn start_beep(&mut self) {
+        if !self.ringing {
+            print!("\x07");
+            let _ = io::stdout().flush();
+            self.ringing = true;
+        }
+    }
+
+    fn stop_beep(&mut self) {
+        self.ringing = false;
+    }
+
+    fn set_frequency(&mut self, _hz: u32) {}
+}
+
+// the frame as text, two rows of pixels to a line with half blocks
+fn lines(frame: &Frame) -> Vec<String> {
+    (0..frame.height()).step_by(2).map(|y| {
+        (0..frame.width()).map(|x| {
+            let bottom = y + 1 < frame.height() && frame.lit(x, y + 1);
+            match (frame.lit(x, y), bottom) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█'
+            }
+        }).collect()
+    }).collect()
+}
+
+// draws the frame with the status line under it. clearing first gets rid
+// of what a resize left behind
+fn draw(out: &mut Stdout, frame: &Frame, status: &str, clear: bool) -> io::Result<()> {
+    if clear {
+        queue!(out, Clear(ClearType::All))?;
+    }
+    let lines = lines(frame);
+    for (row, line) in lines.iter().enumerate() {
+        queue!(out, MoveTo(0, row as u16), Print(line))?;
+    }
+    queue!(out, MoveTo(0, lines.len() as u16), Clear(ClearType::CurrentLine), Print(status))?;
+    out.flush()
+}
+
+// runs rom_path in the terminal until escape or ctrl+c is pressed or
+// shutdown is requested. a plain player like sdl, with the bell for sound
+pub fn run(rom_path: &str, terminal: &mut Terminal, options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+    let mut chip8 = Chip8::new();
+    let loaded = load(&mut chip8, rom_path, options.program, options)?;
+    let mut replay = frontend::start_replay(&mut chip8.cpu, &mut chip8.ram, &loaded.profile, &loaded.sha1, options);
+    let mut pacer = FramePacer::new(frontend::speed(replay.as_ref(), &loaded.profile));
+    let mut keys = TerminalKeys {
+        pressed: [None; 16],
+        releases: terminal.releases,
+        keymap: loaded.keymap
+    };
+    let mut bell = Bell {
+        ringing: false
+    };
+    let mut paused = false;
+    let mut slow_motion = false;
+    // shown in place of the keys on the status line, e.g. a failed reset
+    let mut notice: Option<String> = None;
+    // the frame and status line on screen, None when it needs drawing afresh
+    let mut shown: Option<(Frame, String)> = None;
+    let mut halted = None;
+
+    'running: while !shutdown.requested() {
+        let frame_start = Instant::now();
+        while event::poll(Duration::ZERO).map_err(|e| format!("Could not read the terminal: {}", e))? {
+            match event::read().map_err(|e| format!("Could not read the terminal: {}", e))? {
+                // raw mode keeps ctrl+c from interrupting, so it quits here
+                Event::Key(key) if key.code == KeyCode::Esc => break 'running,
+                Event::Key(key) if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) => break 'running,
+                // P pauses and resumes, delete starts the rom over
+                Event::Key(KeyEvent { code: KeyCode::Char('p'), kind: KeyEventKind::Press, .. }) => {
+                    paused = !paused;
+                    bell.stop_beep();
+                },
+                // ` toggles slow motion. terminals can't tell a held tab from
+                // a repeating one, so there's no turbo here
+                Event::Key(KeyEvent { code: KeyCode::Char('`'), kind: KeyEventKind::Press, .. }) => slow_motion = !slow_motion,
+                Event::Key(KeyEvent { code: KeyCode::Delete, kind: KeyEventKind::Press, .. }) => match load(&mut chip8, rom_path, options.program, options) {
+                    Ok(loaded) => {
+                        notice = None;
+                        bell.stop_beep();
+                        frontend::restart_replay(&mut replay, &mut chip8.cpu, &mut chip8.ram, &loaded.profile, &loaded.sha1, options);
+                        pacer = FramePacer::new(frontend::speed(replay.as_ref(), &loaded.profile));
+                    },
+                    Err(e) => notice = Some(format!("{}, keeping the rom running", e))
+                },
+                Event::Key(key) => keys.set(&key, frame_start),
+                Event::Resize(..) => shown = None,
+                _ => ()
+            }
+        }
+
+        pacer.set_rate(if slow_motion { Rate::Slow(options.slow_motion) } else { Rate::Normal });
+
+        // paused, the screen stays up but the machine and its timers stand
+        // still. slow motion holds each frame a few times, and a netplay
+        // client waits for the host's keys for the next one
+        if !paused && frontend::replay_ready(&mut replay, &mut chip8.cpu, &mut chip8.ram) && !pacer.hold() {
+            match replay.as_mut().and_then(ReplayRun::player) {
+                Some(player) => chip8.cpu.handle_keypress(player),
+                None => chip8.cpu.handle_keypress(&mut keys)
+            };
+            // an error halts the machine, reported just below
+            loop {
+                if chip8.step().is_err() || pacer.step() {
+                    break;
+                }
+            }
+            if let Some(reason) = chip8.cpu.halted() {
+                halted = Some(format!("{}, stopping\n{}", reason, chip8.cpu.dump(&chip8.ram)));
+                break;
+            }
+            // the lines would garble the screen, so they wait for the
+            // terminal to be put back
+            let skipped = chip8.cpu.take_opcode_log();
+            if !skipped.is_empty() {
+                notice = Some(String::from("Skipped invalid opcodes, listed on exit"));
+                terminal.held_back.extend(skipped);
+            }
+            for _ in 0..pacer.timer_ticks().unwrap_or(1) {
+                chip8.cpu.tick_timers(&mut bell);
+            }
+            frontend::replay_frame(&mut replay, &mut chip8.cpu, &mut chip8.ram);
+        }
+
+        let status = match &notice {
+            Some(notice) => notice.clone(),
+            None if paused => format!("{} - paused, p continues", loaded.caption),
+            None => format!("{} - esc quits, p pauses, delete resets", loaded.caption)
+        };
+        let screen = (chip8.cpu.gfx().clone(), status);
+        if shown.as_ref() != Some(&screen) {
+            draw(&mut terminal.out, &screen.0, &screen.1, shown.is_none())
+                .map_err(|e| format!("Could not draw to the terminal: {}", e))?;
+            shown = Some(screen);
+        }
+
+        if let Some(rest) = FRAME_TIME.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+
+    terminal.held_back.extend(chip8.cpu.finish_opcode_log());
+    if let Some(run) = replay.take() {
+        frontend::finish_replay(run);
+    }
+    match halted {
+        Some(reason) => Err(reason),
+        None => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lines;
+    use rust8::display::Frame;
+
+    #[test]
+    fn two_rows_of_pixels_go_to_a_line() {
+        let mut frame = Frame::new(4, 3);
+        frame.set(0, 0, true);
+        frame.set(1, 1, true);
+        frame.set(2, 0, true);
+        frame.set(2, 1, true);
+        frame.set(3, 2, true);
+        assert_eq!(lines(&frame), vec!["▀▄█ ", "   ▀"]);
+    }
+}
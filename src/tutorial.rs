@@ -0,0 +1,197 @@
+use minifb::{
+    Key,
+    KeyRepeat,
+    Window,
+    WindowOptions,
+    Scale
+};
+
+use rust8::audio::SilentSink;
+use rust8::cpu::Cpu;
+use rust8::overlay::{
+    self,
+    Overlay
+};
+use rust8::display::Display;
+use rust8::present::{
+    Screen,
+    SCREEN_SCALE
+};
+use rust8::ram::Ram;
+use rust8::spec::{
+    self,
+    PROGRAM_START_ADDR
+};
+use rust8::PX_ON;
+
+// a smiley that walks across the screen, small enough to follow one
+// instruction at a time
+const PROGRAM: [u16; 13] = [
+    0x00E0, // 200 CLS
+    0x6000, // 202 LD V0, 00      x
+    0x610C, // 204 LD V1, 0C      y
+    0xA21A, // 206 LD I, 21A      the sprite below
+    0xD018, // 208 DRW V0, V1, 8
+    0x620F, // 20A LD V2, 0F
+    0xF215, // 20C LD DT, V2      wait a quarter second
+    0xF307, // 20E LD V3, DT
+    0x3300, // 210 SE V3, 00
+    0x120E, // 212 JP 20E         until DT runs out
+    0xD018, // 214 DRW V0, V1, 8  drawing again erases it
+    0x7004, // 216 ADD V0, 04     step right
+    0x1208  // 218 JP 208
+];
+const SPRITE: [u8; 8] = [0x3C, 0x42, 0xA5, 0x81, 0xA5, 0x99, 0x42, 0x3C];
+
+// frames between instructions, two instructions a second
+const STEP_FRAMES: usize = 30;
+
+// what an instruction does, in words
+fn explain(instruction: u16) -> String {
+    let x = (instruction & 0x0F00) >> 8;
+    let y = (instruction & 0x00F0) >> 4;
+    let n = instruction & 0x000F;
+    let nn = instruction & 0x00FF;
+    let nnn = instruction & 0x0FFF;
+
+    match spec::opcode_of(instruction) {
+        Some(spec::OP_CLS) => String::from("Turn every pixel off"),
+        Some(spec::OP_RET) => String::from("Return to the address on top of the stack"),
+        Some(spec::OP_JP) => format!("Continue at {:03X}", nnn),
+        Some(spec::OP_CALL) => format!("Push PC on the stack and continue at {:03X}", nnn),
+        Some(spec::OP_SE_NN) => format!("Skip the next instruction if V{:X} is {:02X}", x, nn),
+        Some(spec::OP_SNE_NN) => format!("Skip the next instruction unless V{:X} is {:02X}", x, nn),
+        Some(spec::OP_SE_VY) => format!("Skip the next instruction if V{:X} equals V{:X}", x, y),
+        Some(spec::OP_LD_NN) => format!("Put {:02X} in V{:X}", nn, x),
+        Some(spec::OP_ADD_NN) => format!("Add {:02X} to V{:X}", nn, x),
+        Some(spec::OP_LD_VY) => format!("Copy V{:X} into V{:X}", y, x),
+        Some(spec::OP_OR) => format!("V{:X} = V{:X} OR V{:X}", x, x, y),
+        Some(spec::OP_AND) => format!("V{:X} = V{:X} AND V{:X}", x, x, y),
+        Some(spec::OP_XOR) => format!("V{:X} = V{:X} XOR V{:X}", x, x, y),
+        Some(spec::OP_ADD_VY) => format!("Add V{:X} to V{:X}, VF is the carry", y, x),
+        Some(spec::OP_SUB) => format!("Subtract V{:X} from V{:X}, VF is 1 without borrow", y, x),
+        Some(spec::OP_SHR) => format!("Shift V{:X} right, VF gets the bit shifted out", x),
+        Some(spec::OP_SUBN) => format!("V{:X} = V{:X} - V{:X}, VF is 1 without borrow", x, y, x),
+        Some(spec::OP_SHL) => format!("Shift V{:X} left, VF gets the bit shifted out", x),
+        Some(spec::OP_SNE_VY) => format!("Skip the next instruction unless V{:X} equals V{:X}", x, y),
+        Some(spec::OP_LD_I) => format!("Point I at address {:03X}", nnn),
+        Some(spec::OP_JP_V0) => format!("Continue at {:03X} plus V0", nnn),
+        Some(spec::OP_RND) => format!("Put a random number masked with {:02X} in V{:X}", nn, x),
+        Some(spec::OP_DRW) => format!("XOR the {} byte sprite at I onto the screen at (V{:X}, V{:X})", n, x, y),
+        Some(spec::OP_SKP) => format!("Skip the next instruction if key V{:X} is down", x),
+        Some(spec::OP_SKNP) => format!("Skip the next instruction if key V{:X} is up", x),
+        Some(spec::OP_LD_DT) => format!("Read the delay timer into V{:X}", x),
+        Some(spec::OP_LD_KEY) => format!("Wait for a key and put it in V{:X}", x),
+        Some(spec::OP_SET_DT) => format!("Start the delay timer at V{:X}, it counts down 60 times a second", x),
+        Some(spec::OP_SET_ST) => format!("Beep for V{:X} sixtieths of a second", x),
+        Some(spec::OP_ADD_I) => format!("Add V{:X} to I", x),
+        Some(spec::OP_FONT) => format!("Point I at the font sprite for the digit in V{:X}", x),
+        Some(spec::OP_BCD) => format!("Write V{:X} as three decimal digits at I", x),
+        Some(spec::OP_STORE) => format!("Copy V0 to V{:X} into memory at I", x),
+        Some(spec::OP_LOAD) => format!("Load V0 to V{:X} from memory at I", x),
+        _ => String::from("Not an instruction")
+    }
+}
+
+// the registers an instruction changed, e.g. "V0 00->04  PC 216->218"
+fn changes(before: &Cpu, after: &Cpu) -> String {
+    let mut changed = Vec::new();
+    let (v_before, v_after) = (before.reg_gpr(), after.reg_gpr());
+    for x in 0..v_before.len() {
+        if v_before[x] != v_after[x] {
+            changed.push(format!("V{:X} {:02X}->{:02X}", x, v_before[x], v_after[x]));
+        }
+    }
+    if before.reg_i() != after.reg_i() {
+        changed.push(format!("I {:03X}->{:03X}", before.reg_i(), after.reg_i()));
+    }
+    if before.reg_dt() != after.reg_dt() {
+        changed.push(format!("DT {:02X}->{:02X}", before.reg_dt(), after.reg_dt()));
+    }
+    if before.gfx() != after.gfx() {
+        changed.push(String::from("screen"));
+    }
+    changed.push(format!("PC {:03X}->{:03X}", before.reg_pc(), after.reg_pc()));
+    changed.join("  ")
+}
+
+fn draw_notes(screen: &mut Screen, lines: &[String]) {
+    let (area_x, area_y, area_w, _) = screen.game_area();
+    let band_height = lines.len() * overlay::CELL_HEIGHT + 4;
+
+    let mut overlay = Overlay::new(&mut screen.pixels, screen.width, screen.height);
+    overlay.shade_rect(area_x, area_y, area_w, band_height);
+    for (row, line) in lines.iter().enumerate() {
+        overlay.draw_text(area_x + 2, area_y + 2 + row * overlay::CELL_HEIGHT, line, PX_ON, 1);
+    }
+}
+
+// runs the bundled program slowly, explaining each instruction as it goes.
+// space pauses, the right arrow steps while paused
+pub fn run() {
+    let mut ram = Ram::new();
+    let mut addr = PROGRAM_START_ADDR;
+    for opcode in PROGRAM.iter() {
+        ram.write_byte(addr, (opcode >> 8) as u8);
+        ram.write_byte(addr + 1, *opcode as u8);
+        addr += 2;
+    }
+    for byte in SPRITE.iter() {
+        ram.write_byte(addr, *byte);
+        addr += 1;
+    }
+    let mut cpu = Cpu::new();
+    let mut audio = SilentSink;
+
+    let mut screen = Screen::new(None, SCREEN_SCALE, 0);
+    let mut window = Window::new(
+        "chip-8 rust tutorial",
+        screen.width,
+        screen.height,
+        WindowOptions {
+            scale: Scale::X1,
+            ..WindowOptions::default()
+        }
+    ).expect("Could not open tutorial window.");
+    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+
+    let mut notes = vec![
+        String::from("Each instruction runs on its own, with what it did shown here"),
+        String::from("SPACE pauses, RIGHT steps while paused")
+    ];
+    let mut paused = false;
+    let mut frames: usize = 0;
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            paused = !paused;
+        }
+        let step = if paused {
+            window.is_key_pressed(Key::Right, KeyRepeat::Yes)
+        } else {
+            frames += 1;
+            frames.is_multiple_of(STEP_FRAMES)
+        };
+
+        if step {
+            let pc = cpu.reg_pc();
+            let memory = ram.memory();
+            let instruction = (memory[pc as usize] as u16) << 8 | memory[pc as usize + 1] as u16;
+            let before = cpu.clone();
+            if let Err(e) = cpu.run_instruction(&mut ram) {
+                println!("{}", e);
+            }
+            notes = vec![
+                format!("{:03X}  {:04X}  {}", pc, instruction, spec::disassemble(instruction)),
+                explain(instruction),
+                changes(&before, &cpu)
+            ];
+        }
+        cpu.tick_timers(&mut audio);
+
+        screen.present(cpu.gfx());
+        draw_notes(&mut screen, &notes);
+        window.update_with_buffer(&screen.pixels, screen.width, screen.height)
+            .expect("Could not update window.");
+    }
+}
@@ -0,0 +1,239 @@
+use wasm_bindgen::prelude::*;
+
+use crate::animation::{
+    Moment,
+    PaletteAnimation,
+    PaletteEffects
+};
+use crate::audio::{
+    AudioSink,
+    CaptureSink
+};
+use crate::chip8::Chip8;
+use crate::spec::NUM_KEYS;
+use crate::phosphor::Phosphor;
+use crate::present::Palette;
+use crate::{
+    HEIGHT,
+    WIDTH
+};
+
+// sound kept between frames, plenty when the page ticks every frame
+const AUDIO_BUFFER_MS: u32 = 250;
+
+// how far ahead of what's playing sound is queued, in seconds. the page's
+// clock and the sound card's drift apart, and without a limit the queue
+// would creep ahead and the beeps lag further and further behind
+const MAX_AUDIO_LEAD: f64 = 0.1;
+
+// the parts of WebAudio the sink uses
+#[wasm_bindgen]
+extern "C" {
+    pub type AudioContext;
+
+    #[wasm_bindgen(method, getter, js_name = currentTime)]
+    fn current_time(this: &AudioContext) -> f64;
+    #[wasm_bindgen(method, getter, js_name = sampleRate)]
+    fn sample_rate(this: &AudioContext) -> f32;
+    #[wasm_bindgen(method, getter)]
+    fn destination(this: &AudioContext) -> JsValue;
+    #[wasm_bindgen(method, catch, js_name = createBuffer)]
+    fn create_buffer(this: &AudioContext, channels: u32, length: u32, sample_rate: f32) -> Result<AudioBuffer, JsValue>;
+    #[wasm_bindgen(method, js_name = createBufferSource)]
+    fn create_buffer_source(this: &AudioContext) -> AudioBufferSourceNode;
+
+    type AudioBuffer;
+
+    #[wasm_bindgen(method, getter)]
+    fn duration(this: &AudioBuffer) -> f64;
+    #[wasm_bindgen(method, catch, js_name = copyToChannel)]
+    fn copy_to_channel(this: &AudioBuffer, source: &[f32], channel: u32) -> Result<(), JsValue>;
+
+    type AudioBufferSourceNode;
+
+    #[wasm_bindgen(method, setter)]
+    fn set_buffer(this: &AudioBufferSourceNode, buffer: &AudioBuffer);
+    #[wasm_bindgen(method, catch)]
+    fn connect(this: &AudioBufferSourceNode, destination: &JsValue) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    fn start(this: &AudioBufferSourceNode, when: f64) -> Result<(), JsValue>;
+}
+
+// the buzzer played through the page's AudioContext. the square wave is
+// CaptureSink's, and each frame's samples are queued right after the last
+// batch so they play back to back
+struct WebAudioSink {
+    context: AudioContext,
+    capture: CaptureSink,
+    // when the queued sound runs out, in the context's time
+    queued_until: f64,
+    samples: Vec<f32>
+}
+
+impl WebAudioSink {
+    fn new(context: AudioContext) -> WebAudioSink {
+        let sample_rate = context.sample_rate() as u32;
+        WebAudioSink {
+            context,
+            capture: CaptureSink::new(sample_rate, (sample_rate * AUDIO_BUFFER_MS / 1000) as usize),
+            queued_until: 0.0,
+            samples: vec![0.0; (sample_rate * AUDIO_BUFFER_MS / 1000) as usize]
+        }
+    }
+
+    // hands the samples made since the last call to WebAudio
+    fn play(&mut self) -> Result<(), JsValue> {
+        let count = self.capture.drain(&mut self.samples);
+        if count == 0 {
+            return Ok(());
+        }
+        let buffer = self.context.create_buffer(1, count as u32, self.capture.sample_rate() as f32)?;
+        buffer.copy_to_channel(&self.samples[..count], 0)?;
+        let source = self.context.create_buffer_source();
+        source.set_buffer(&buffer);
+        source.connect(&self.context.destination())?;
+        // after a stall the queue has run dry, so start again from now
+        let now = self.context.current_time();
+        self.queued_until = self.queued_until.clamp(now, now + MAX_AUDIO_LEAD);
+        source.start(self.queued_until)?;
+        self.queued_until += buffer.duration();
+        Ok(())
+    }
+}
+
+impl AudioSink for WebAudioSink {
+    fn start_beep(&mut self) {
+        self.capture.start_beep();
+    }
+
+    fn stop_beep(&mut self) {
+        self.capture.stop_beep();
+    }
+
+    fn set_frequency(&mut self, hz: u32) {
+        self.capture.set_frequency(hz);
+    }
+}
+
+// the machine as seen from javascript. the page calls tick() once per
+// animation frame, draws frame_buffer() into a canvas and forwards the
+// keyboard through key_down/key_up. see web/ for a page that does this
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: Chip8,
+    // keys the page has reported as held
+    keys: [bool; NUM_KEYS],
+    audio: WebAudioSink,
+    palette: Palette,
+    animation: Option<PaletteEffects>,
+    phosphor: Option<Phosphor>,
+    // frame_buffer's pixels before they're turned into bytes
+    colors: Vec<u32>
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    // the buzzer plays through audio, the page's AudioContext
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], audio: AudioContext) -> Result<Emulator, JsValue> {
+        let chip8 = Chip8::with_rom_quirks(rom).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Emulator {
+            chip8,
+            keys: [false; NUM_KEYS],
+            audio: WebAudioSink::new(audio),
+            palette: Palette::default(),
+            animation: None,
+            phosphor: None,
+            colors: Vec::with_capacity(WIDTH * HEIGHT)
+        })
+    }
+
+    // starts over with another rom, e.g. one dropped on the page. on error
+    // the old one keeps running
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.chip8.reset(rom).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.chip8.use_known_quirks(rom);
+        self.keys = [false; NUM_KEYS];
+        self.audio.stop_beep();
+        Ok(())
+    }
+
+    // one of the palette presets, see Palette::preset
+    pub fn set_palette(&mut self, name: &str) -> Result<(), JsValue> {
+        self.palette = Palette::preset(name).ok_or_else(|| JsValue::from_str(&format!("No palette called {}", name)))?;
+        Ok(())
+    }
+
+    // palette animations, see PaletteEffects::parse. the page can change
+    // them at any time, e.g. "hue-shift:30,flash" or "none"
+    pub fn set_palette_animation(&mut self, effects: &str) -> Result<(), JsValue> {
+        let effects = PaletteEffects::parse(effects).ok_or_else(|| JsValue::from_str(&format!("No palette animation {}", effects)))?;
+        self.animation = Some(effects).filter(|effects| !effects.is_empty());
+        Ok(())
+    }
+
+    // runs one frame, see Chip8::run_frame, and queues the frame's sound
+    pub fn tick(&mut self) -> Result<(), JsValue> {
+        self.chip8.run_frame(&self.keys);
+        if self.chip8.beeping() {
+            self.audio.start_beep();
+        } else {
+            self.audio.stop_beep();
+        }
+        self.audio.play()
+    }
+
+    // unlit pixels fade out, keeping decay of their brightness each frame.
+    // 0 turns it off
+    pub fn set_ghosting(&mut self, decay: f32) {
+        self.phosphor = Some(decay).filter(|&decay| decay > 0.0).map(Phosphor::new);
+    }
+
+    // the display as WIDTH x HEIGHT rgba pixels, ready for an ImageData.
+    // call once per tick, ghosting fades a frame's worth each call
+    pub fn frame_buffer(&mut self) -> Vec<u8> {
+        let moment = Moment {
+            frame: self.chip8.cpu.frames(),
+            sound: self.chip8.beeping()
+        };
+        let palette = match &mut self.animation {
+            Some(animation) => animation.animate(&self.palette, moment),
+            None => self.palette
+        };
+        self.colors.clear();
+        self.colors.extend(self.chip8.cpu.gfx().pixels().iter().map(|&on| palette.color(on as u8)));
+        if let Some(phosphor) = &mut self.phosphor {
+            phosphor.apply_frames(&mut self.colors, palette.off, palette.on, 1.0);
+        }
+        let mut rgba = Vec::with_capacity(WIDTH * HEIGHT * 4);
+        for &color in &self.colors {
+            rgba.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xFF]);
+        }
+        rgba
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        if let Some(held) = self.keys.get_mut(key as usize) {
+            *held = true;
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        if let Some(held) = self.keys.get_mut(key as usize) {
+            *held = false;
+        }
+    }
+
+    // why the machine stopped, if it has
+    pub fn halted(&self) -> Option<String> {
+        self.chip8.cpu.halted().map(String::from)
+    }
+
+    pub fn width() -> usize {
+        WIDTH
+    }
+
+    pub fn height() -> usize {
+        HEIGHT
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{
+    Duration,
+    Instant,
+    SystemTime
+};
+
+use crate::kiosk;
+
+// how often the directory is listed
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// keeps an eye on a build directory and reports the newest rom whenever
+// one is written, so an assembler's output runs as soon as it's built
+pub struct DirWatcher {
+    dir: String,
+    last_poll: Instant,
+    // modification time of the last rom handed out
+    loaded: Option<SystemTime>,
+    // a newer rom seen on the last poll, loaded once it stops changing so
+    // half written files are skipped
+    pending: Option<(PathBuf, SystemTime, u64)>
+}
+
+impl DirWatcher {
+    pub fn new(dir: &str) -> Result<DirWatcher, String> {
+        fs::read_dir(dir)
+            .map_err(|e| format!("Could not read watch directory {}: {}", dir, e))?;
+
+        Ok(DirWatcher {
+            dir: dir.to_string(),
+            last_poll: Instant::now(),
+            loaded: None,
+            pending: None
+        })
+    }
+
+    // the newest rom right now, if the directory has any
+    pub fn newest(&mut self) -> Option<String> {
+        let (path, modified, _) = self.scan()?;
+        self.loaded = Some(modified);
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    // call once per frame, returns a rom to switch to when a newer one appears
+    pub fn update(&mut self) -> Option<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let newest = self.scan();
+        let newest = match newest {
+            Some(newest) if Some(newest.1) > self.loaded => newest,
+            _ => {
+                self.pending = None;
+                return None;
+            }
+        };
+
+        if self.pending.as_ref() == Some(&newest) {
+            self.pending = None;
+            self.loaded = Some(newest.1);
+            return Some(newest.0.to_string_lossy().into_owned());
+        }
+        self.pending = Some(newest);
+        None
+    }
+
+    // path, modification time and size of the most recently modified rom
+    fn scan(&self) -> Option<(PathBuf, SystemTime, u64)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Could not read watch directory {}: {}", self.dir, e);
+                return None;
+            }
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| kiosk::is_rom(&entry.path()))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_file() {
+                    return None;
+                }
+                Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+            })
+            .max_by_key(|&(_, modified, _)| modified)
+    }
+}
@@ -0,0 +1,120 @@
+// build the wasm package into web/pkg first:
+//   wasm-pack build --target web --no-default-features --out-dir web/pkg
+// then serve this directory, e.g. python3 -m http.server -d web
+import init, { Emulator } from "./pkg/rust8.js";
+
+// same layout as the desktop build, the left side of a qwerty keyboard
+const KEYS = {
+    "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xC,
+    "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xD,
+    "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xE,
+    "z": 0xA, "x": 0x0, "c": 0xB, "v": 0xF
+};
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+const status = document.getElementById("status");
+// display settings from the address, e.g.
+// index.html?palette=amber&ghosting=0.6&animate=hue-shift:30,flash
+const params = new URLSearchParams(location.search);
+let emulator = null;
+let looping = false;
+let audio = null;
+
+// the machine runs at 60 frames a second whatever rate the screen
+// refreshes at, so frames are run for the time that's gone by
+const FRAME_MS = 1000 / 60;
+// most frames made up in one go, so a tab left in the background doesn't
+// fast forward when it comes back
+const MAX_CATCH_UP = 6;
+let lastTime = null;
+let owed = 0;
+
+document.addEventListener("keydown", (event) => {
+    const key = KEYS[event.key.toLowerCase()];
+    if (emulator && key !== undefined) {
+        emulator.key_down(key);
+    }
+});
+document.addEventListener("keyup", (event) => {
+    const key = KEYS[event.key.toLowerCase()];
+    if (emulator && key !== undefined) {
+        emulator.key_up(key);
+    }
+});
+
+function frame(now) {
+    if (lastTime !== null) {
+        owed += now - lastTime;
+    }
+    lastTime = now;
+    let frames = Math.floor(owed / FRAME_MS);
+    owed -= frames * FRAME_MS;
+    if (frames > MAX_CATCH_UP) {
+        frames = MAX_CATCH_UP;
+        owed = 0;
+    }
+    for (let i = 0; i < frames && !emulator.halted(); i++) {
+        emulator.tick();
+    }
+    const image = new ImageData(new Uint8ClampedArray(emulator.frame_buffer()), Emulator.width(), Emulator.height());
+    context.putImageData(image, 0, 0);
+
+    const halt = emulator.halted();
+    if (halt) {
+        status.textContent = halt;
+        looping = false;
+        return;
+    }
+    requestAnimationFrame(frame);
+}
+
+async function loadRom(file) {
+    // browsers only allow sound to start from a user action like this one
+    audio = audio || new AudioContext();
+    const rom = new Uint8Array(await file.arrayBuffer());
+    try {
+        if (emulator) {
+            emulator.load_rom(rom);
+        } else {
+            emulator = new Emulator(rom, audio);
+            if (params.has("palette")) {
+                emulator.set_palette(params.get("palette"));
+            }
+            if (params.has("animate")) {
+                emulator.set_palette_animation(params.get("animate"));
+            }
+            emulator.set_ghosting(Number(params.get("ghosting") || 0));
+        }
+    } catch (error) {
+        status.textContent = error;
+        return;
+    }
+    status.textContent = "";
+    if (!looping) {
+        looping = true;
+        // the first frame runs straight away
+        lastTime = null;
+        owed = FRAME_MS;
+        requestAnimationFrame(frame);
+    }
+}
+
+document.getElementById("rom").addEventListener("change", (event) => {
+    const file = event.target.files[0];
+    if (file) {
+        loadRom(file);
+    }
+});
+
+// a rom file dropped on the screen replaces the running one
+canvas.addEventListener("dragover", (event) => event.preventDefault());
+canvas.addEventListener("drop", (event) => {
+    event.preventDefault();
+    const file = event.dataTransfer.files[0];
+    if (file) {
+        loadRom(file);
+    }
+});
+
+await init();